@@ -0,0 +1,155 @@
+use axum::body::HttpBody;
+use axum::http::{header, Response};
+use axum::Router;
+use tower_http::compression::predicate::{Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+
+/// Media types (or their prefixes) of data that is already compressed, such responses are sent as they are
+pub const COMPRESSED_MEDIA_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/zstd",
+    "application/x-7z-compressed",
+    "audio/",
+    "video/",
+    // Server-sent events must not be buffered by the compression
+    "text/event-stream",
+];
+
+/// Configuration of the response compression (see [apply_compression])
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Compress the responses if the client accepts it (by the Accept-Encoding header)
+    pub enabled: bool,
+    /// Responses with a known size below this number of bytes are not compressed
+    pub min_size: u16,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            enabled: true,
+            min_size: 1024,
+        }
+    }
+}
+
+impl CompressionConfig {
+    pub fn disabled() -> Self {
+        CompressionConfig {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+    pub fn with_min_size(mut self, min_size: u16) -> Self {
+        self.min_size = min_size;
+        self
+    }
+}
+
+/// Decides which responses are compressed: large enough and not of an already compressed media type
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionPredicate {
+    size: SizeAbove,
+}
+
+impl Predicate for CompressionPredicate {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        let compressed = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|content_type| {
+                COMPRESSED_MEDIA_TYPES
+                    .iter()
+                    .any(|media_type| content_type.starts_with(media_type))
+            });
+        !compressed && self.size.should_compress(response)
+    }
+}
+
+/// Compression layer for the configuration, None if the compression is disabled
+pub fn compression_layer(config: &CompressionConfig) -> Option<CompressionLayer<CompressionPredicate>> {
+    config.enabled.then(|| {
+        CompressionLayer::new().gzip(true).compress_when(CompressionPredicate {
+            size: SizeAbove::new(config.min_size),
+        })
+    })
+}
+
+/// Add the response compression to the router according to the configuration.
+/// Responses are gzip-compressed if the client accepts it (Accept-Encoding header).
+pub fn apply_compression<S: Clone + Send + Sync + 'static>(
+    router: Router<S>,
+    config: &CompressionConfig,
+) -> Router<S> {
+    match compression_layer(config) {
+        Some(layer) => router.layer(layer),
+        None => router,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    fn router(config: &CompressionConfig) -> Router {
+        let app = Router::new()
+            .route("/large.txt", get(|| async { "liquers ".repeat(1000) }))
+            .route("/small.txt", get(|| async { "liquers" }))
+            .route(
+                "/large.png",
+                get(|| async { ([(header::CONTENT_TYPE, "image/png")], vec![0u8; 10000]) }),
+            );
+        apply_compression(app, config)
+    }
+
+    async fn get_encoded(app: Router, uri: &str) -> (Option<String>, usize) {
+        let request = Request::builder()
+            .uri(uri)
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let encoding = response
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .map(|value| value.to_str().unwrap().to_owned());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (encoding, body.len())
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        let config = CompressionConfig::default();
+        let (encoding, length) = get_encoded(router(&config), "/large.txt").await;
+        assert_eq!(encoding.as_deref(), Some("gzip"));
+        assert!(length < 8000);
+
+        let (encoding, _) = get_encoded(router(&config), "/small.txt").await;
+        assert_eq!(encoding, None);
+        let (encoding, length) = get_encoded(router(&config), "/large.png").await;
+        assert_eq!(encoding, None);
+        assert_eq!(length, 10000);
+
+        let (encoding, length) =
+            get_encoded(router(&CompressionConfig::disabled()), "/large.txt").await;
+        assert_eq!(encoding, None);
+        assert_eq!(length, 8000);
+    }
+}
@@ -4,23 +4,126 @@ use std::sync::Arc;
 use axum::{
     body::Body,
     extract::{Path, State},
-    http::{header, Response, StatusCode},
+    http::{header, HeaderMap, HeaderValue, Response, StatusCode},
     response::IntoResponse,
 };
-use liquers_core::value::Value;
+use liquers_core::{
+    context::NGEnvironment, parse::parse_query_with_limits, plan::PlanBuilder, value::Value,
+};
 
 use crate::{
     environment::{async_evaluate, ServerEnvRef},
-    utils::CoreError,
+    utils::{request_id, CoreError, REQUEST_ID_HEADER},
 };
 
+/// Evaluate the query. The request id from the X-Request-Id header (or a generated one)
+/// is used as the trace id of the evaluation and returned in the X-Request-Id response header.
 #[axum::debug_handler]
 pub async fn evaluate_handler(
     Path(query): Path<String>,
     State(envref): State<ServerEnvRef>,
+    headers: HeaderMap,
 ) -> Response<Body> {
-    match async_evaluate(envref, query).await {
-        Ok(state) => default_value_response(&(*state.read().unwrap()), Some(&state.metadata.get_media_type())),
+    let request_id = request_id(&headers);
+    let limits = envref.0.read().await.get_query_limits().clone();
+    let mut response = match parse_query_with_limits(&query, &limits) {
+        Ok(query) => match async_evaluate(envref, &query, Some(request_id.clone())).await {
+            Ok(state) => default_value_response(&(*state.read().unwrap()), Some(&state.metadata.get_media_type())),
+            Err(e) => CoreError(e).into_response(),
+        },
         Err(e) => CoreError(e).into_response(),
+    };
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Compile the query into a plan and return the plan as JSON, without executing it.
+/// Any failure is a problem of the query (parsing, unknown command, invalid parameters),
+/// it is reported as a bad request; the error message contains the position in the query.
+pub async fn plan_handler(
+    Path(query): Path<String>,
+    State(envref): State<ServerEnvRef>,
+) -> Response<Body> {
+    let env = envref.0.read().await;
+    let plan = parse_query_with_limits(&query, env.get_query_limits())
+        .and_then(|query| PlanBuilder::new(query, env.get_command_metadata_registry()).build());
+    match plan {
+        Ok(plan) => match serde_json::to_string_pretty(&plan) {
+            Ok(json) => Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(json.into())
+                .unwrap(),
+            Err(e) => CoreError(liquers_core::error::Error::general_error(format!(
+                "Plan serialization failed: {}",
+                e
+            )))
+            .into_response(),
+        },
+        Err(e) => {
+            let error = CoreError(e);
+            let status = match error.status_code() {
+                StatusCode::INTERNAL_SERVER_ERROR => StatusCode::BAD_REQUEST,
+                status => status,
+            };
+            error.into_response_with_status(status)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::{ServerEnvironment, ServerEnvironmentType};
+    use liquers_core::context::NGEnvRef;
+
+    fn envref() -> ServerEnvRef {
+        let mut env: ServerEnvironmentType = ServerEnvironment::new();
+        liquers_core::library::register_commands(env.get_mut_command_executor()).unwrap();
+        NGEnvRef::new(env)
+    }
+
+    async fn body(response: Response<Body>) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_evaluate_request_id() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("abc-123"));
+        let response =
+            evaluate_handler(Path("from_json".to_owned()), State(envref()), headers).await;
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "abc-123");
+
+        let response =
+            evaluate_handler(Path("from_json".to_owned()), State(envref()), HeaderMap::new()).await;
+        assert!(!response.headers()[REQUEST_ID_HEADER].is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_plan_handler() {
+        let response =
+            plan_handler(Path("merge-first/distinct".to_owned()), State(envref())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let plan: serde_json::Value = serde_json::from_str(&body(response).await).unwrap();
+        let actions = plan["steps"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|step| step["Action"]["action_name"].as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(actions, vec!["merge", "distinct"]);
+    }
+
+    #[tokio::test]
+    async fn test_plan_handler_compile_error() {
+        let response = plan_handler(Path("distinct/nonexistent".to_owned()), State(envref())).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(body(response).await.contains("nonexistent"));
     }
 }
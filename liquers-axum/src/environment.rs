@@ -1,4 +1,6 @@
+use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use liquers_core::{
     cache::{Cache, NoCache},
@@ -6,14 +8,17 @@ use liquers_core::{
     commands::{CommandRegistry, NGCommandRegistry},
     context::{ArcEnvRef, Context, EnvRef, Environment, NGContext, NGEnvRef, NGEnvironment},
     error::Error,
+    parse::QueryLimits,
     interpreter::{AsyncPlanInterpreter, NGPlanInterpreter},
     query::{self, TryToQuery},
     state::State,
     store::{AsyncStore, NoAsyncStore, NoStore, Store},
-    value::ValueInterface,
+    value::{ValueDeserializerRegistry, ValueInterface},
 };
 use tokio::sync::RwLock;
 
+use crate::compression::CompressionConfig;
+
 /// Simple environment with configurable store and cache
 /// CommandRegistry is used as command executor as well as it is providing the command metadata registry.
 pub struct ServerEnvironment<V: ValueInterface> {
@@ -22,6 +27,11 @@ pub struct ServerEnvironment<V: ValueInterface> {
     async_store: Arc<Box<dyn AsyncStore>>,
     cache: Arc<Mutex<Box<dyn Cache<V>>>>,
     command_registry: NGCommandRegistry<NGEnvRef<Self>, V, NGContext<Self>>,
+    query_limits: QueryLimits,
+    value_deserializers: ValueDeserializerRegistry<V>,
+    config: Arc<BTreeMap<String, V>>,
+    metadata_flush_interval: Option<Duration>,
+    compression: CompressionConfig,
 }
 
 pub type ServerValue = liquers_core::value::Value;
@@ -36,6 +46,11 @@ impl<V: ValueInterface + 'static> ServerEnvironment<V> {
             cache: Arc::new(Mutex::new(Box::new(NoCache::new()))),
             #[cfg(feature = "async_store")]
             async_store: Arc::new(Box::new(NoAsyncStore)),
+            query_limits: QueryLimits::default(),
+            value_deserializers: ValueDeserializerRegistry::new(),
+            config: Arc::new(BTreeMap::new()),
+            metadata_flush_interval: None,
+            compression: CompressionConfig::default(),
         }
     }
     pub fn with_store(&mut self, store: Box<dyn Store>) -> &mut Self {
@@ -51,13 +66,53 @@ impl<V: ValueInterface + 'static> ServerEnvironment<V> {
         self.cache = Arc::new(Mutex::new(cache));
         self
     }
+    pub fn with_query_limits(&mut self, query_limits: QueryLimits) -> &mut Self {
+        self.query_limits = query_limits;
+        self
+    }
+    /// Limits applied to queries received by the server
+    pub fn get_query_limits(&self) -> &QueryLimits {
+        &self.query_limits
+    }
+    pub fn with_compression(&mut self, compression: CompressionConfig) -> &mut Self {
+        self.compression = compression;
+        self
+    }
+    /// Compression of the server responses (see [crate::compression::apply_compression])
+    pub fn get_compression(&self) -> &CompressionConfig {
+        &self.compression
+    }
+    /// Registry of deserializers of custom value types, e.g. to register a new type identifier
+    pub fn get_mut_value_deserializer_registry(&mut self) -> &mut ValueDeserializerRegistry<V> {
+        &mut self.value_deserializers
+    }
+    /// Set a configuration value available to the queries (see [NGEnvironment::get_config])
+    pub fn with_config_value(&mut self, name: &str, value: V) -> &mut Self {
+        Arc::make_mut(&mut self.config).insert(name.to_owned(), value);
+        self
+    }
+    /// Periodically write the metadata of running evaluations to the store (see [NGEnvironment::get_metadata_flush_interval])
+    /// The interval must be positive.
+    pub fn with_metadata_flush_interval(&mut self, interval: Duration) -> Result<&mut Self, Error> {
+        if interval.is_zero() {
+            return Err(Error::general_error(
+                "Metadata flush interval must be positive".to_owned(),
+            ));
+        }
+        self.metadata_flush_interval = Some(interval);
+        Ok(self)
+    }
 }
 
+/// Evaluate a query; the trace id (e.g. the request id) is attached to the log entries
+/// of the evaluation and of its dependencies
 pub async fn async_evaluate<E: NGEnvironment, Q: TryToQuery>(
     envref: NGEnvRef<E>,
     query: Q,
+    trace_id: Option<String>,
 ) -> Result<liquers_core::state::State<<E as NGEnvironment>::Value>, liquers_core::error::Error> {
     let mut pi = NGPlanInterpreter::new(envref);
+    pi.with_trace_id(trace_id);
     let query = query.try_to_query()?;
     pi.set_query(&query).await?;
     //println!("{:?}", pi.plan);
@@ -99,4 +154,16 @@ impl<V: ValueInterface> NGEnvironment for ServerEnvironment<V> {
     fn get_async_store(&self) -> Arc<Box<dyn AsyncStore>> {
         self.async_store.clone()
     }
+
+    fn get_value_deserializer_registry(&self) -> Option<&ValueDeserializerRegistry<V>> {
+        Some(&self.value_deserializers)
+    }
+
+    fn get_config(&self) -> Arc<BTreeMap<String, V>> {
+        self.config.clone()
+    }
+
+    fn get_metadata_flush_interval(&self) -> Option<Duration> {
+        self.metadata_flush_interval
+    }
 }
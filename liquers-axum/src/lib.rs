@@ -1,3 +1,4 @@
+pub mod compression;
 pub mod environment;
 pub mod core_handlers;
 pub mod store_handlers;
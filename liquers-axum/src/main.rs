@@ -1,9 +1,11 @@
+pub mod compression;
 pub mod core_handlers;
 pub mod environment;
 pub mod store_handlers;
 pub mod utils;
 pub mod value;
 
+use crate::compression::apply_compression;
 use crate::environment::ServerEnvironment;
 use core_handlers::evaluate_handler;
 use liquers_core::context::NGEnvRef;
@@ -13,18 +15,24 @@ use tokio::sync::RwLock;
 
 use axum::{routing::get, Router};
 use liquers_core::query::Key;
-use liquers_core::store::{AsyncStoreWrapper, FileStore};
+use liquers_core::store::{AsyncStoreWrapper, FileStore, ReadOnlyStore};
 
 #[tokio::main]
 async fn main() {
     //let hashmaptest: Arc<HashMap<String, String>> = Arc::new(HashMap::new());
     // build our application with a single route
 
+    // With --read-only, the store can't be modified through the server
+    let read_only = std::env::args().any(|arg| arg == "--read-only");
+
     let mut env: ServerEnvironment<Value> = ServerEnvironment::new();
-    env.with_async_store(Box::new(AsyncStoreWrapper(FileStore::new(
-        ".",
-        &Key::new(),
-    ))));
+    let store = AsyncStoreWrapper(FileStore::new(".", &Key::new()));
+    if read_only {
+        env.with_async_store(Box::new(ReadOnlyStore::new(store)));
+    } else {
+        env.with_async_store(Box::new(store));
+    }
+    let compression = env.get_compression().clone();
     let state = NGEnvRef::new(env);
 
     //    let store:Arc<Box<dyn AsyncStore>> = Arc::new(Box::new(AsyncStoreWrapper(FileStore::new(".", &Key::new()))));
@@ -32,6 +40,10 @@ async fn main() {
     let app = Router::new()
         .route("/", get(|| async { "Hello, World!" }))
         .route("/liquer/q/*query", get(evaluate_handler))
+        .route(
+            "/liquer/api/plan/*query",
+            get(crate::core_handlers::plan_handler),
+        )
         //.route("/liquer/submit/*query", get(submit_query))
         .route(
             "/liquer/api/store/data/*query",
@@ -73,11 +85,16 @@ async fn main() {
             "/liquer/api/store/listdir/*query",
             get(crate::store_handlers::listdir_handler),
         ) // TODO: support listdir_keys and listdir_keys_deep
+        .route(
+            "/liquer/api/store/walk/*query",
+            get(crate::store_handlers::walk_handler),
+        )
         .route(
             "/liquer/api/store/makedir/*query",
             get(crate::store_handlers::makedir_handler),
         )
         .with_state(state);
+    let app = apply_compression(app, &compression);
 
     // run it with hyper on localhost:3000
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
@@ -3,10 +3,11 @@ use std::sync::Arc;
 use axum::{
     body::Body,
     extract::{Path, State},
-    http::{header, Response, StatusCode},
+    http::{header, HeaderMap, Response, StatusCode},
     response::IntoResponse,
     Error,
 };
+use futures::{SinkExt, StreamExt};
 use liquers_core::{
     context::{Environment, NGEnvironment},
     metadata::{Metadata, MetadataRecord},
@@ -17,7 +18,7 @@ use tokio::sync::RwLock;
 
 use crate::{
     environment::ServerEnvRef,
-    utils::{CoreError, DataResultWrapper},
+    utils::{byte_range, CoreError, DataResultWrapper},
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -100,14 +101,66 @@ where
     }
 }
 
+/// Data from the store, streamed to the response body in chunks
+/// (see [AsyncStore::get_stream](liquers_core::store::AsyncStore::get_stream)),
+/// so that large resources are not loaded to memory at once.
+/// The content type and length are taken from the metadata and size known before the body is streamed.
+/// A single byte range requested by the Range header is streamed as 206 Partial Content
+/// (see [AsyncStore::get_range_stream](liquers_core::store::AsyncStore::get_range_stream)),
+/// a range starting beyond the end of the data is answered with 416 Range Not Satisfiable.
 #[axum::debug_handler]
 pub async fn store_data_handler(
     Path(query): Path<String>,
     State(env): State<ServerEnvRef>,
+    headers: HeaderMap,
 ) -> Response<Body> {
     let store = env.0.read().await.get_async_store();
-    match parse_key(&query) {
-        Ok(key) => DataResultWrapper(store.get(&key).await).into_response(),
+    let key = match parse_key(&query) {
+        Ok(key) => key,
+        Err(e) => return CoreError(e).into_response(),
+    };
+    if let Some((start, end)) = byte_range(&headers) {
+        let metadata = match store.get_metadata(&key).await {
+            Ok(metadata) => metadata,
+            Err(e) => return CoreError(e).into_response(),
+        };
+        return match store.get_range_stream(&key, start, end).await {
+            Ok((stream, size, length)) if start < length => Response::builder()
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_TYPE, metadata.get_media_type())
+                .header(header::ACCEPT_RANGES, "bytes")
+                .header(header::CONTENT_LENGTH, size)
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, start + size - 1, length),
+                )
+                .body(Body::from_stream(stream))
+                .unwrap(),
+            Ok((_, _, length)) => {
+                let mut response = CoreError(liquers_core::error::Error::range_not_satisfiable(
+                    &key, start, end, length,
+                ))
+                .into_response();
+                response.headers_mut().insert(
+                    header::CONTENT_RANGE,
+                    format!("bytes */{}", length).parse().unwrap(),
+                );
+                response
+            }
+            Err(e) => CoreError(e).into_response(),
+        };
+    }
+    match store.get_stream(&key).await {
+        Ok((stream, metadata, size)) => {
+            let mut response = Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, metadata.get_media_type())
+                .header(header::ACCEPT_RANGES, "bytes");
+            if let Some(size) = size {
+                response = response.header(header::CONTENT_LENGTH, size);
+            }
+            response.body(Body::from_stream(stream)).unwrap()
+        }
         Err(e) => CoreError(e).into_response(),
     }
 }
@@ -275,3 +328,117 @@ pub async fn makedir_handler(
         Err(e) => CoreError(e).into_response(),
     }
 }
+
+/// Stream all keys under a directory as NDJSON, one JSON-encoded key per line.
+/// Keys are sent as they are discovered by the store walk.
+/// An error terminates the stream with a line containing the serialized error.
+#[axum::debug_handler]
+pub async fn walk_handler(
+    Path(query): Path<String>,
+    State(env): State<ServerEnvRef>,
+) -> Response<Body> {
+    let store = env.0.read().await.get_async_store();
+    match parse_key(&query) {
+        Ok(key) => {
+            let (mut tx, rx) = futures::channel::mpsc::channel::<String>(64);
+            tokio::spawn(async move {
+                let mut keys = store.walk(&key);
+                while let Some(result) = keys.next().await {
+                    let (line, stop) = match result {
+                        Ok(k) => (serde_json::to_string(&k.encode()), false),
+                        Err(e) => (serde_json::to_string(&e), true),
+                    };
+                    let line = line.unwrap_or_else(|e| format!("\"{}\"", e));
+                    if tx.send(format!("{}\n", line)).await.is_err() || stop {
+                        break;
+                    }
+                }
+            });
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, "application/x-ndjson")
+                .body(Body::from_stream(rx.map(Ok::<_, std::io::Error>)))
+                .unwrap()
+        }
+        Err(e) => CoreError(e).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::{ServerEnvironment, ServerEnvironmentType};
+    use liquers_core::context::NGEnvRef;
+    use liquers_core::query::Key;
+    use liquers_core::store::{AsyncStoreWrapper, FileStore};
+
+    #[tokio::test]
+    async fn test_store_data_streamed() {
+        let path = std::env::temp_dir().join(format!("liquers_axum_stream_{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let data = (0..5_000_000).map(|i| (i % 253) as u8).collect::<Vec<_>>();
+        std::fs::write(path.join("large.bin"), &data).unwrap();
+        let mut env: ServerEnvironmentType = ServerEnvironment::new();
+        env.with_async_store(Box::new(AsyncStoreWrapper(FileStore::new(
+            path.to_str().unwrap(),
+            &Key::new(),
+        ))));
+        let envref = NGEnvRef::new(env);
+
+        let response = store_data_handler(
+            Path("large.bin".to_owned()),
+            State(envref.clone()),
+            HeaderMap::new(),
+        )
+        .await;
+        let status = response.status();
+        let length = response.headers().get(header::CONTENT_LENGTH).cloned();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await;
+        let missing = store_data_handler(
+            Path("missing.bin".to_owned()),
+            State(envref),
+            HeaderMap::new(),
+        )
+        .await;
+        std::fs::remove_dir_all(&path).unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(length.unwrap(), data.len().to_string().as_str());
+        assert_eq!(body.unwrap().as_ref(), data.as_slice());
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_store_data_range() {
+        let path = std::env::temp_dir().join(format!("liquers_axum_range_{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        std::fs::write(path.join("data.txt"), b"0123456789").unwrap();
+        let mut env: ServerEnvironmentType = ServerEnvironment::new();
+        env.with_async_store(Box::new(AsyncStoreWrapper(FileStore::new(
+            path.to_str().unwrap(),
+            &Key::new(),
+        ))));
+        let envref = NGEnvRef::new(env);
+        let request = |range: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::RANGE, range.parse().unwrap());
+            store_data_handler(Path("data.txt".to_owned()), State(envref.clone()), headers)
+        };
+
+        let partial = request("bytes=2-4").await;
+        let status = partial.status();
+        let content_range = partial.headers().get(header::CONTENT_RANGE).cloned();
+        let body = axum::body::to_bytes(partial.into_body(), usize::MAX).await;
+        let tail = request("bytes=7-").await;
+        let tail_range = tail.headers().get(header::CONTENT_RANGE).cloned();
+        let beyond = request("bytes=10-").await;
+        std::fs::remove_dir_all(&path).unwrap();
+
+        assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(content_range.unwrap(), "bytes 2-4/10");
+        assert_eq!(body.unwrap().as_ref(), b"234");
+        assert_eq!(tail_range.unwrap(), "bytes 7-9/10");
+        assert_eq!(beyond.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(beyond.headers().get(header::CONTENT_RANGE).unwrap(), "bytes */10");
+    }
+}
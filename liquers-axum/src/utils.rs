@@ -1,9 +1,48 @@
 use axum::body::Body;
 use axum::http::header;
+use axum::http::HeaderMap;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::response::Response;
+use liquers_core::error::ErrorType;
 use liquers_core::metadata::Metadata;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Header carrying the request id, used as the trace id of the evaluation
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Request id from the X-Request-Id header, a new unique id is generated if the header is missing
+pub fn request_id(headers: &HeaderMap) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_owned())
+        .unwrap_or_else(|| {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or_default();
+            format!("{:x}-{:x}", nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+        })
+}
+
+/// Byte range requested by the Range header as start and exclusive end (None means the end of the data).
+/// Only a single range with a start (`bytes=start-end` or `bytes=start-`) is supported,
+/// None is returned for other or invalid ranges, so that the whole data is served as HTTP allows.
+pub fn byte_range(headers: &HeaderMap) -> Option<(u64, Option<u64>)> {
+    let range = headers.get(header::RANGE)?.to_str().ok()?;
+    let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.trim().parse::<u64>().ok()?;
+    let end = end.trim();
+    if end.is_empty() {
+        return Some((start, None));
+    }
+    let last = end.parse::<u64>().ok()?;
+    (last >= start).then(|| (start, Some(last + 1)))
+}
 
 pub struct CoreError(pub liquers_core::error::Error);
 
@@ -13,17 +52,42 @@ impl From<liquers_core::error::Error> for CoreError {
     }
 }
 
-impl IntoResponse for CoreError {
-    fn into_response(self) -> Response<Body> {
-        // TODO: make error specific response
+impl CoreError {
+    /// HTTP status code corresponding to the error type
+    pub fn status_code(&self) -> StatusCode {
+        match self.0.error_type {
+            ErrorType::QueryTooLong => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorType::QueryTooComplex => StatusCode::BAD_REQUEST,
+            ErrorType::StoreNotConfigured => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorType::KeyNotFound => StatusCode::NOT_FOUND,
+            ErrorType::PermissionDenied => StatusCode::FORBIDDEN,
+            ErrorType::NotADirectory => StatusCode::BAD_REQUEST,
+            ErrorType::RangeNotSatisfiable => StatusCode::RANGE_NOT_SATISFIABLE,
+            ErrorType::ReadOnlyStore => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl CoreError {
+    /// Plain text error response with an explicit status code
+    pub fn into_response_with_status(self, status: StatusCode) -> Response<Body> {
         Response::builder()
-            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .status(status)
             .header(header::CONTENT_TYPE, "text/plain".to_owned())
             .body(format!("Error: {}", self.0).into())
             .unwrap()
     }
 }
 
+impl IntoResponse for CoreError {
+    fn into_response(self) -> Response<Body> {
+        // TODO: make error specific response for more error types
+        let status = self.status_code();
+        self.into_response_with_status(status)
+    }
+}
+
 pub struct DataResultWrapper(pub Result<(Vec<u8>, Metadata), liquers_core::error::Error>);
 
 impl From<Result<(Vec<u8>, Metadata), liquers_core::error::Error>> for DataResultWrapper {
@@ -72,4 +136,26 @@ impl<T> IntoResponse for StoreResultWrapper<T> where T: IntoResponse {
     }
 }
 
-*/
\ No newline at end of file
+*/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(value: &str) -> Option<(u64, Option<u64>)> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RANGE, value.parse().unwrap());
+        byte_range(&headers)
+    }
+
+    #[test]
+    fn test_byte_range() {
+        assert_eq!(byte_range(&HeaderMap::new()), None);
+        assert_eq!(range("bytes=0-499"), Some((0, Some(500))));
+        assert_eq!(range("bytes=100-"), Some((100, None)));
+        assert_eq!(range("bytes=-500"), None);
+        assert_eq!(range("bytes=0-1,5-6"), None);
+        assert_eq!(range("bytes=5-2"), None);
+        assert_eq!(range("items=0-1"), None);
+    }
+}
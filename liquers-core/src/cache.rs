@@ -6,12 +6,16 @@ use chrono::format;
 use crate::error::Error;
 use crate::state::State;
 use crate::value::ValueInterface;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::metadata::Metadata;
-use crate::query::Query;
+use crate::metadata::{Metadata, MetadataRecord, Status};
+use crate::command_metadata::CommandMetadataRegistry;
+use crate::query::{Key, Query};
+use crate::store::Store;
 
 /// Definition of Cache interface for binary data
 /// Cache is meant to temporarily store results of queries as values.
@@ -75,69 +79,263 @@ impl BinCache for NoBinCache {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct MemoryBinCache(HashMap<Query, (Arc<Metadata>, Option<Vec<u8>>)>);
+#[derive(Debug)]
+struct MemoryBinCacheEntry {
+    metadata: Arc<Metadata>,
+    data: Option<Vec<u8>>,
+    last_used: AtomicU64,
+    stored: Instant,
+}
+
+impl Clone for MemoryBinCacheEntry {
+    fn clone(&self) -> Self {
+        MemoryBinCacheEntry {
+            metadata: self.metadata.clone(),
+            data: self.data.clone(),
+            last_used: AtomicU64::new(self.last_used.load(Ordering::Relaxed)),
+            stored: self.stored,
+        }
+    }
+}
+
+/// Binary cache keeping the data in memory.
+/// Total size of the cached binaries can be limited by a memory budget (see [MemoryBinCache::with_max_bytes]):
+/// when the budget is exceeded, binaries of the least recently used entries are evicted.
+/// The metadata of the evicted entries are kept, so the results can be recomputed.
+/// Entries can expire after a time to live (see [MemoryBinCache::with_ttl]): expired entries are not returned
+/// (so the results are evaluated again) and they are dropped on the next write to the cache.
+/// Entries of results being evaluated (see [Status::is_in_flight]) never expire.
+#[derive(Debug)]
+pub struct MemoryBinCache {
+    entries: HashMap<Query, MemoryBinCacheEntry>,
+    clock: AtomicU64,
+    cached_bytes: usize,
+    max_bytes: Option<usize>,
+    ttl: Option<Duration>,
+    /// Writes of the entries in the order of time (only with a time to live), so that they expire in this order;
+    /// a write is outdated when the entry was removed or written again
+    writes: VecDeque<(Instant, Query)>,
+}
+
+impl Clone for MemoryBinCache {
+    fn clone(&self) -> Self {
+        MemoryBinCache {
+            entries: self.entries.clone(),
+            clock: AtomicU64::new(self.clock.load(Ordering::Relaxed)),
+            cached_bytes: self.cached_bytes,
+            max_bytes: self.max_bytes,
+            ttl: self.ttl,
+            writes: self.writes.clone(),
+        }
+    }
+}
 
 impl MemoryBinCache {
     pub fn new() -> Self {
-        MemoryBinCache(HashMap::new())
+        MemoryBinCache {
+            entries: HashMap::new(),
+            clock: AtomicU64::new(0),
+            cached_bytes: 0,
+            max_bytes: None,
+            ttl: None,
+            writes: VecDeque::new(),
+        }
+    }
+
+    /// Limit the total size of the cached binaries (in bytes)
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self.evict();
+        self
+    }
+
+    /// Memory budget for the cached binaries (in bytes), None if unlimited
+    pub fn max_bytes(&self) -> Option<usize> {
+        self.max_bytes
+    }
+
+    /// Total size of the cached binaries (in bytes)
+    pub fn cached_bytes(&self) -> usize {
+        self.cached_bytes
+    }
+
+    /// Expire the entries when the time since they were written exceeds the time to live
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        let mut writes = self
+            .entries
+            .iter()
+            .map(|(query, entry)| (entry.stored, query.clone()))
+            .collect::<Vec<_>>();
+        writes.sort_by_key(|(stored, _)| *stored);
+        self.writes = writes.into();
+        self
+    }
+
+    /// Time to live of the entries, None if the entries do not expire
+    pub fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    fn is_expired(&self, entry: &MemoryBinCacheEntry) -> bool {
+        self.ttl.is_some_and(|ttl| entry.stored.elapsed() >= ttl)
+            && !entry
+                .metadata
+                .metadata_record()
+                .is_some_and(|record| record.status.is_in_flight())
+    }
+
+    fn live_entry(&self, query: &Query) -> Option<&MemoryBinCacheEntry> {
+        self.entries
+            .get(query)
+            .filter(|entry| !self.is_expired(entry))
+    }
+
+    /// Drop the expired entries to reclaim the memory; returns the queries of the dropped entries.
+    /// Only the writes older than the time to live are visited.
+    pub fn remove_expired(&mut self) -> Vec<Query> {
+        let mut expired = Vec::new();
+        let Some(ttl) = self.ttl else {
+            return expired;
+        };
+        while self
+            .writes
+            .front()
+            .is_some_and(|(stored, _)| stored.elapsed() >= ttl)
+        {
+            let (stored, query) = self.writes.pop_front().unwrap();
+            // Entries being evaluated are written again when finished
+            let is_expired = self
+                .entries
+                .get(&query)
+                .is_some_and(|entry| entry.stored == stored && self.is_expired(entry));
+            if !is_expired {
+                continue;
+            }
+            if let Some(d) = self.entries.remove(&query).and_then(|entry| entry.data) {
+                self.cached_bytes -= d.len();
+            }
+            expired.push(query);
+        }
+        expired
+    }
+
+    /// Record the time of a write for the expiration
+    fn written(&mut self, query: &Query, stored: Instant) {
+        if self.ttl.is_some() {
+            self.writes.push_back((stored, query.clone()));
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Drop binaries of the least recently used entries until the cached bytes fit into the budget
+    fn evict(&mut self) {
+        let Some(max_bytes) = self.max_bytes else {
+            return;
+        };
+        while self.cached_bytes > max_bytes {
+            let oldest = self
+                .entries
+                .values_mut()
+                .filter(|entry| entry.data.is_some())
+                .min_by_key(|entry| entry.last_used.load(Ordering::Relaxed));
+            match oldest {
+                Some(entry) => {
+                    let data = entry.data.take().unwrap_or_default();
+                    self.cached_bytes -= data.len();
+                }
+                None => break,
+            }
+        }
     }
 }
 
 impl BinCache for MemoryBinCache {
     fn clear(&mut self) {
-        self.0.clear();
+        self.entries.clear();
+        self.writes.clear();
+        self.cached_bytes = 0;
     }
 
     fn get_metadata(&self, query: &Query) -> Option<Arc<Metadata>> {
-        if let Some((metadata, _)) = self.0.get(query) {
-            Some(metadata.clone())
-        } else {
-            None
-        }
+        self.live_entry(query).map(|entry| entry.metadata.clone())
     }
 
     fn set_metadata(&mut self, metadata: &Metadata) -> Result<(), Error> {
         let query = metadata.query()?;
-        if let Some((am, _)) = self.0.get_mut(&query) {
-            *am = Arc::new(metadata.clone());
+        self.remove_expired();
+        let last_used = self.tick();
+        let stored = Instant::now();
+        if let Some(entry) = self.entries.get_mut(&query) {
+            entry.metadata = Arc::new(metadata.clone());
+            entry.last_used.store(last_used, Ordering::Relaxed);
+            entry.stored = stored;
         } else {
-            self.0.insert(query, (Arc::new(metadata.clone()), None));
+            self.entries.insert(
+                query.clone(),
+                MemoryBinCacheEntry {
+                    metadata: Arc::new(metadata.clone()),
+                    data: None,
+                    last_used: AtomicU64::new(last_used),
+                    stored,
+                },
+            );
         }
+        self.written(&query, stored);
         Ok(())
     }
 
     fn set_binary(&mut self, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
         let query = metadata.query()?;
-        if let Some((am, d)) = self.0.get_mut(&query) {
-            *am = Arc::new(metadata.clone());
-            *d = Some(data.to_vec());
-        } else {
-            self.0
-                .insert(query, (Arc::new(metadata.clone()), Some(data.to_vec())));
+        self.remove_expired();
+        let last_used = self.tick();
+        let stored = Instant::now();
+        let previous = self.entries.insert(
+            query.clone(),
+            MemoryBinCacheEntry {
+                metadata: Arc::new(metadata.clone()),
+                data: Some(data.to_vec()),
+                last_used: AtomicU64::new(last_used),
+                stored,
+            },
+        );
+        self.written(&query, stored);
+        if let Some(d) = previous.and_then(|entry| entry.data) {
+            self.cached_bytes -= d.len();
         }
+        self.cached_bytes += data.len();
+        self.evict();
         Ok(())
     }
 
     fn remove(&mut self, query: &Query) -> Result<(), Error> {
-        self.0.remove(query);
+        if let Some(d) = self.entries.remove(query).and_then(|entry| entry.data) {
+            self.cached_bytes -= d.len();
+        }
         Ok(())
     }
 
     fn contains(&self, query: &Query) -> bool {
-        self.0.contains_key(query)
+        self.live_entry(query).is_some()
     }
 
     fn keys(&self) -> Vec<Query> {
-        self.0.keys().cloned().collect()
+        self.entries
+            .iter()
+            .filter(|(_, entry)| !self.is_expired(entry))
+            .map(|(query, _)| query.clone())
+            .collect()
     }
 
     fn get_binary(&self, query: &Query) -> Option<Vec<u8>> {
-        if let Some((_, data)) = self.0.get(query) {
-            data.clone()
-        } else {
-            None
+        let entry = self.live_entry(query)?;
+        if entry.data.is_some() {
+            entry.last_used.store(self.tick(), Ordering::Relaxed);
         }
+        entry.data.clone()
     }
 }
 
@@ -190,6 +388,12 @@ impl<V: ValueInterface> Cache<V> for NoCache<V> {
 
 pub struct SerializingCache<V: ValueInterface, BC: BinCache>(BC, PhantomData<V>);
 
+impl<V: ValueInterface, BC: BinCache> SerializingCache<V, BC> {
+    pub fn new(bin_cache: BC) -> Self {
+        SerializingCache(bin_cache, PhantomData)
+    }
+}
+
 impl<V: ValueInterface, BC: BinCache> BinCache for SerializingCache<V, BC> {
     fn clear(&mut self) {
         self.0.clear()
@@ -248,6 +452,202 @@ impl<V: ValueInterface, BC: BinCache> Cache<V> for SerializingCache<V, BC> {
     }
 }
 
+/// Default key prefix under which [PersistentBinCache] keeps the cached results in the store
+pub const DEFAULT_CACHE_PREFIX: &str = "_cache";
+
+/// Binary cache persisting the finished results into a store, so that they survive a restart.
+/// The results are cached in the inner cache (e.g. [MemoryBinCache]); the ready results (see [Status::Ready])
+/// are in addition written into the store under a prefix (see [DEFAULT_CACHE_PREFIX]),
+/// one key per query named by the hash of the encoded canonical query (see [PersistentBinCache::store_key]),
+/// together with the metadata, which contain the full query.
+/// When a result is not in the inner cache, it is read from the store.
+/// Results that are not ready anymore (e.g. expired by [invalidate_prefix](crate::interpreter::invalidate_prefix))
+/// are removed from the store.
+pub struct PersistentBinCache<BC: BinCache, S: Store> {
+    cache: BC,
+    store: S,
+    prefix: Key,
+    command_registry: Option<CommandMetadataRegistry>,
+}
+
+impl<BC: BinCache, S: Store> PersistentBinCache<BC, S> {
+    pub fn new(cache: BC, store: S) -> Self {
+        PersistentBinCache {
+            cache,
+            store,
+            prefix: Key::new().join(DEFAULT_CACHE_PREFIX),
+            command_registry: None,
+        }
+    }
+
+    /// Canonicalize the queries with the command metadata (see [Query::canonical]),
+    /// so that the equivalent queries share the result in the store
+    pub fn with_command_metadata_registry(mut self, cmr: CommandMetadataRegistry) -> Self {
+        self.command_registry = Some(cmr);
+        self
+    }
+
+    /// Keep the results in the store under a different key prefix
+    pub fn with_prefix(mut self, prefix: Key) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    pub fn prefix(&self) -> &Key {
+        &self.prefix
+    }
+
+    pub fn inner(&self) -> &BC {
+        &self.cache
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Key under which the result of the query is kept in the store.
+    /// The name is the hash of the encoded canonical query, so it is a valid file name for a query of any length.
+    /// Without a command metadata registry (or if the query can't be canonicalized),
+    /// the query is hashed as it is.
+    pub fn store_key(&self, query: &Query) -> Key {
+        let query = self
+            .command_registry
+            .as_ref()
+            .and_then(|cmr| query.canonical(cmr).ok())
+            .unwrap_or_else(|| query.clone());
+        self.prefix
+            .join(blake3::hash(query.encode().as_bytes()).to_hex().as_str())
+    }
+
+    fn is_ready(metadata: &Metadata) -> bool {
+        metadata
+            .metadata_record()
+            .is_some_and(|record| record.status == Status::Ready)
+    }
+
+    /// Write (or update) the result in the store when it is ready, otherwise remove it from the store.
+    /// Without data, only the metadata of a result already in the store are updated.
+    fn persist(&self, data: Option<&[u8]>, metadata: &Metadata) -> Result<(), Error> {
+        let key = self.store_key(&metadata.query()?);
+        match data {
+            Some(data) if Self::is_ready(metadata) => {
+                let _ = self.store.makedir(&self.prefix);
+                self.store.set(&key, data, metadata)
+            }
+            None if Self::is_ready(metadata) => {
+                if self.store.contains(&key)? {
+                    self.store.set_metadata(&key, metadata)?;
+                }
+                Ok(())
+            }
+            _ => {
+                if self.store.contains(&key)? {
+                    self.store.remove(&key)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<BC: BinCache, S: Store> BinCache for PersistentBinCache<BC, S> {
+    fn clear(&mut self) {
+        self.cache.clear();
+        for key in self.store.listdir_keys(&self.prefix).unwrap_or_default() {
+            let _ = self.store.remove(&key);
+        }
+    }
+
+    fn get_binary(&self, query: &Query) -> Option<Vec<u8>> {
+        self.cache
+            .get_binary(query)
+            .or_else(|| self.store.get_bytes(&self.store_key(query)).ok())
+    }
+
+    fn get_metadata(&self, query: &Query) -> Option<Arc<Metadata>> {
+        self.cache.get_metadata(query).or_else(|| {
+            self.store
+                .get_metadata(&self.store_key(query))
+                .ok()
+                .map(Arc::new)
+        })
+    }
+
+    fn set_binary(&mut self, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
+        self.cache.set_binary(data, metadata)?;
+        self.persist(Some(data), metadata)
+    }
+
+    fn set_metadata(&mut self, metadata: &Metadata) -> Result<(), Error> {
+        self.cache.set_metadata(metadata)?;
+        self.persist(None, metadata)
+    }
+
+    fn remove(&mut self, query: &Query) -> Result<(), Error> {
+        self.cache.remove(query)?;
+        let key = self.store_key(query);
+        if self.store.contains(&key)? {
+            self.store.remove(&key)?;
+        }
+        Ok(())
+    }
+
+    fn contains(&self, query: &Query) -> bool {
+        self.cache.contains(query) || self.store.contains(&self.store_key(query)).unwrap_or(false)
+    }
+
+    fn keys(&self) -> Vec<Query> {
+        let mut keys = self.cache.keys();
+        for key in self.store.listdir_keys(&self.prefix).unwrap_or_default() {
+            if let Ok(query) = self.store.get_metadata(&key).and_then(|metadata| metadata.query()) {
+                if !keys.contains(&query) {
+                    keys.push(query);
+                }
+            }
+        }
+        keys
+    }
+}
+
+/// Snapshot of the index of a cache, e.g. to avoid a cold start after a restart.
+/// The snapshot contains the metadata of the ready results that are stored in a store
+/// (i.e. have a key), but not the cached data: after [import_cache], the results are loaded from the store
+/// on the first request (see [evaluate_cached](crate::interpreter::evaluate_cached)).
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CacheSnapshot {
+    pub entries: Vec<MetadataRecord>,
+}
+
+/// Export the index of the cache, see [CacheSnapshot]
+pub fn export_cache<C: BinCache + ?Sized>(cache: &C) -> CacheSnapshot {
+    let mut entries = cache
+        .keys()
+        .iter()
+        .filter_map(|query| cache.get_metadata(query)?.metadata_record())
+        .filter(|record| record.status == Status::Ready && record.key.is_some())
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|record| record.query.encode());
+    CacheSnapshot { entries }
+}
+
+/// Import the index exported by [export_cache] into a cache.
+/// Only the metadata are imported; results already present in the cache are left alone.
+/// Returns the number of imported entries.
+pub fn import_cache<C: BinCache + ?Sized>(
+    cache: &mut C,
+    snapshot: &CacheSnapshot,
+) -> Result<usize, Error> {
+    let mut imported = 0;
+    for record in snapshot.entries.iter() {
+        if record.key.is_none() || cache.contains(&record.query) {
+            continue;
+        }
+        cache.set_metadata(&record.clone().into())?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
 #[cfg(test)]
 mod tests {
     use std::{sync::Mutex, thread, time::Duration};
@@ -277,6 +677,172 @@ mod tests {
         Ok(())
     }
     #[test]
+    fn test_memory_cache_budget() -> Result<(), Error> {
+        let mut cache = MemoryBinCache::new().with_max_bytes(10);
+        assert_eq!(cache.max_bytes(), Some(10));
+        let q1 = parse_query("-R/a")?;
+        let q2 = parse_query("-R/b")?;
+        let q3 = parse_query("-R/c")?;
+        cache.set_binary(b"1234", &Metadata::new().with_query(q1.to_owned()))?;
+        cache.set_binary(b"5678", &Metadata::new().with_query(q2.to_owned()))?;
+        assert_eq!(cache.cached_bytes(), 8);
+        cache.set_binary(b"9012", &Metadata::new().with_query(q3.to_owned()))?;
+        assert_eq!(cache.cached_bytes(), 8);
+        assert!(cache.get_binary(&q1).is_none());
+        assert!(cache.contains(&q1));
+        assert!(cache.get_metadata(&q1).is_some());
+
+        // Reading b makes c the least recently used
+        assert!(cache.get_binary(&q2).is_some());
+        cache.set_binary(b"abcd", &Metadata::new().with_query(q1.to_owned()))?;
+        assert!(cache.get_binary(&q2).is_some());
+        assert!(cache.get_binary(&q3).is_none());
+        assert_eq!(cache.cached_bytes(), 8);
+
+        cache.remove(&q2)?;
+        assert_eq!(cache.cached_bytes(), 4);
+        // The cache can be shared between threads
+        fn assert_sync<T: Sync>(_: &T) {}
+        assert_sync(&cache);
+        cache.clear();
+        assert_eq!(cache.cached_bytes(), 0);
+        Ok(())
+    }
+    #[test]
+    fn test_memory_cache_ttl() -> Result<(), Error> {
+        let mut cache = MemoryBinCache::new().with_ttl(Duration::from_millis(50));
+        let finished = parse_query("-R/a")?;
+        let running = parse_query("-R/b")?;
+        cache.set_binary(b"1234", &Metadata::new().with_query(finished.to_owned()))?;
+        let mut record = MetadataRecord::new();
+        record.with_query(running.to_owned()).with_status(Status::Evaluation);
+        cache.set_metadata(&record.into())?;
+        assert!(cache.get_binary(&finished).is_some());
+
+        std::thread::sleep(Duration::from_millis(80));
+        assert!(cache.get_binary(&finished).is_none());
+        assert!(!cache.contains(&finished));
+        // Results being evaluated do not expire
+        assert!(cache.contains(&running));
+        assert_eq!(cache.keys(), vec![running.clone()]);
+        assert_eq!(cache.cached_bytes(), 4);
+        assert_eq!(cache.remove_expired(), vec![finished.clone()]);
+        assert_eq!(cache.cached_bytes(), 0);
+        // Only the writes are visited; the running entry is tracked again when written
+        assert!(cache.writes.is_empty());
+        assert!(cache.remove_expired().is_empty());
+        assert!(cache.contains(&running));
+
+        cache.set_binary(b"5678", &Metadata::new().with_query(finished.to_owned()))?;
+        cache.set_binary(b"5678", &Metadata::new().with_query(finished.to_owned()))?;
+        assert_eq!(cache.get_binary(&finished), Some(b"5678".to_vec()));
+        assert_eq!(cache.writes.len(), 2);
+        std::thread::sleep(Duration::from_millis(80));
+        assert_eq!(cache.remove_expired(), vec![finished.clone()]);
+        assert!(cache.writes.is_empty());
+        Ok(())
+    }
+    #[test]
+    fn test_cache_snapshot() -> Result<(), Error> {
+        let mut cache = MemoryBinCache::new();
+        let stored = parse_query("-R/a/-/f")?;
+        let mut record = MetadataRecord::new();
+        record
+            .with_query(stored.clone())
+            .with_key(crate::parse::parse_key("results/a.json")?)
+            .with_status(Status::Ready);
+        cache.set_binary(b"1", &record.into())?;
+        let mut transient = MetadataRecord::new();
+        transient
+            .with_query(parse_query("-R/b/-/f")?)
+            .with_status(Status::Ready);
+        cache.set_binary(b"2", &transient.into())?;
+
+        let snapshot = export_cache(&cache);
+        assert_eq!(snapshot.entries.len(), 1);
+        assert_eq!(snapshot.entries[0].query, stored);
+        let snapshot: CacheSnapshot =
+            serde_json::from_str(&serde_json::to_string(&snapshot).unwrap()).unwrap();
+
+        let mut fresh = MemoryBinCache::new();
+        assert_eq!(import_cache(&mut fresh, &snapshot)?, 1);
+        assert!(fresh.contains(&stored));
+        assert!(fresh.get_binary(&stored).is_none());
+        assert_eq!(import_cache(&mut fresh, &snapshot)?, 0);
+        Ok(())
+    }
+    #[test]
+    fn test_persistent_cache() -> Result<(), Error> {
+        use crate::store::FileStore;
+
+        let path = std::env::temp_dir().join(format!("liquers_cache_test_{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let file_store = || FileStore::new(path.to_str().unwrap(), &Key::new());
+        let ready = parse_query("data/a.txt/-/f")?;
+        let running = parse_query("-R/b/-/f")?;
+        let mut cache = PersistentBinCache::new(MemoryBinCache::new(), file_store());
+        let mut record = MetadataRecord::new();
+        record.with_query(ready.clone()).with_status(Status::Ready);
+        cache.set_binary(b"123", &record.into())?;
+        let mut record = MetadataRecord::new();
+        record.with_query(running.clone()).with_status(Status::Evaluation);
+        cache.set_metadata(&record.into())?;
+        assert!(cache.store().contains(&cache.store_key(&ready))?);
+        assert!(!cache.store().contains(&cache.store_key(&running))?);
+
+        // After a restart, the ready result is read from the store
+        let mut cache = PersistentBinCache::new(MemoryBinCache::new(), file_store());
+        let encoded = |queries: Vec<Query>| queries.iter().map(Query::encode).collect::<Vec<_>>();
+        assert_eq!(encoded(cache.keys()), vec![ready.encode()]);
+        assert_eq!(cache.get_binary(&ready), Some(b"123".to_vec()));
+        let record = cache.get_metadata(&ready).unwrap().metadata_record().unwrap();
+        assert_eq!(record.query.encode(), ready.encode());
+        assert_eq!(record.status, Status::Ready);
+
+        // A result which is not ready anymore is dropped from the store
+        let mut expired = record.clone();
+        expired.with_status(Status::Expired);
+        cache.set_metadata(&expired.into())?;
+        let persisted = cache.store().contains(&cache.store_key(&ready))?;
+        assert!(!persisted);
+
+        // Queries far longer than a file name are persisted; the full query is kept in the metadata
+        let long = parse_query(&format!("-R/{}/-/f", "x".repeat(5000)))?;
+        let mut record = MetadataRecord::new();
+        record.with_query(long.clone()).with_status(Status::Ready);
+        cache.set_binary(b"456", &record.into())?;
+        let mut cache = PersistentBinCache::new(MemoryBinCache::new(), file_store());
+        assert_eq!(cache.get_binary(&long), Some(b"456".to_vec()));
+        assert!(encoded(cache.keys()).contains(&long.encode()));
+        cache.clear();
+        std::fs::remove_dir_all(&path).unwrap();
+        Ok(())
+    }
+
+    #[test]
+    fn test_persistent_cache_canonical_key() -> Result<(), Error> {
+        use crate::command_metadata::{ArgumentInfo, CommandMetadata};
+        use crate::store::MemoryStore;
+
+        let mut cmr = CommandMetadataRegistry::new();
+        let mut head = CommandMetadata::new("head");
+        head.with_argument(ArgumentInfo::integer_argument("n", false).with_default(10));
+        cmr.add_command(&head);
+        let mut cache = PersistentBinCache::new(MemoryBinCache::new(), MemoryStore::new(&Key::new()))
+            .with_command_metadata_registry(cmr);
+        let explicit = parse_query("data/a.csv/-/head-10")?;
+        let default = parse_query("data/a.csv/-/head")?;
+        assert_eq!(cache.store_key(&explicit), cache.store_key(&default));
+        assert_ne!(cache.store_key(&explicit), cache.store_key(&parse_query("data/a.csv/-/head-5")?));
+
+        let mut record = MetadataRecord::new();
+        record.with_query(explicit.clone()).with_status(Status::Ready);
+        cache.set_binary(b"rows", &record.into())?;
+        // Served from the store for the equivalent query
+        assert_eq!(cache.get_binary(&default), Some(b"rows".to_vec()));
+        Ok(())
+    }
+    #[test]
     fn test_memory_cache_threaded() -> Result<(), Error> {
         let key = parse_query("-R/key")?;
         let cache = MemoryBinCache::new();
@@ -1,12 +1,15 @@
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
+use std::sync::{Arc, Mutex};
 
 use crate::error::Error;
 use crate::query::{ActionParameter, Query};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tokio::sync::Semaphore;
 
 /// A structure holding a description of an identified issue with a command registry
 /// Issue can be either a warning or an error (when is_error is true)
@@ -31,10 +34,24 @@ impl CommandRegistryIssue {
         }
     }
     pub fn warning(realm: &str, namespace: &str, name: &str, message: String) -> Self {
-        CommandRegistryIssue::new(realm, name, namespace, false, message)
+        CommandRegistryIssue::new(realm, namespace, name, false, message)
     }
     pub fn error(realm: &str, namespace: &str, name: &str, message: String) -> Self {
-        CommandRegistryIssue::new(realm, name, namespace, true, message)
+        CommandRegistryIssue::new(realm, namespace, name, true, message)
+    }
+}
+
+impl Display for CommandRegistryIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} in command {}/{}/{}: {}",
+            if self.is_error { "Error" } else { "Warning" },
+            self.realm,
+            self.namespace,
+            self.name,
+            self.message
+        )
     }
 }
 
@@ -165,6 +182,12 @@ pub enum ArgumentType {
     FloatOption,
     #[serde(rename = "bool")]
     Boolean,
+    /// Time span like `30s` or `1h30m` (see [parse_duration])
+    #[serde(rename = "duration")]
+    Duration,
+    /// RFC 3339 date and time, e.g. `2024-01-31T12:00:00Z`
+    #[serde(rename = "datetime")]
+    DateTime,
     Enum(EnumArgument),
     #[serde(rename = "any")]
     Any,
@@ -180,6 +203,99 @@ impl ArgumentType {
             _ => false,
         }
     }
+
+    /// Argument type of a Rust type given by name (as produced by `stringify!` in the registration macros).
+    /// The scalar types `String`, `i64`, `f64` and `bool`, the optional numbers `Option<i64>` and `Option<f64>`,
+    /// `Duration` (`std::time::Duration`) and `DateTime<Utc>` (`chrono`) are recognized,
+    /// all other types are [ArgumentType::Any].
+    /// For vectors (`Vec<f64>`) and tuples (`(f64, f64)`) this is the type of the elements;
+    /// tuples of mixed types are [ArgumentType::Any].
+    pub fn from_type_name(type_name: &str) -> Self {
+        let type_name = type_name.split_whitespace().collect::<String>();
+        if let Some(element) = type_name
+            .strip_prefix("Vec<")
+            .and_then(|t| t.strip_suffix('>'))
+        {
+            return Self::from_type_name(element);
+        }
+        if let Some(elements) = type_name
+            .strip_prefix('(')
+            .and_then(|t| t.strip_suffix(')'))
+        {
+            let types = elements
+                .split(',')
+                .filter(|t| !t.is_empty())
+                .map(Self::from_type_name)
+                .collect::<Vec<_>>();
+            return match types.split_first() {
+                Some((first, rest))
+                    if rest
+                        .iter()
+                        .all(|t| std::mem::discriminant(t) == std::mem::discriminant(first)) =>
+                {
+                    first.clone()
+                }
+                _ => ArgumentType::Any,
+            };
+        }
+        match type_name.as_str() {
+            "String" => ArgumentType::String,
+            "i64" => ArgumentType::Integer,
+            "Option<i64>" => ArgumentType::IntegerOption,
+            "f64" => ArgumentType::Float,
+            "Option<f64>" => ArgumentType::FloatOption,
+            "bool" => ArgumentType::Boolean,
+            "Duration" | "time::Duration" | "std::time::Duration" => ArgumentType::Duration,
+            "DateTime<Utc>"
+            | "DateTime<chrono::Utc>"
+            | "chrono::DateTime<Utc>"
+            | "chrono::DateTime<chrono::Utc>" => ArgumentType::DateTime,
+            _ => ArgumentType::Any,
+        }
+    }
+}
+
+/// Parse a duration consisting of numbers with units, e.g. `30s`, `1h30m` or `1.5d`.
+/// Supported units are `ms`, `s`, `m`, `h`, `d` and `w`; a plain integer is a number of seconds.
+pub fn parse_duration(text: &str) -> Option<std::time::Duration> {
+    if let Ok(seconds) = text.parse::<u64>() {
+        return Some(std::time::Duration::from_secs(seconds));
+    }
+    if text.is_empty() {
+        return None;
+    }
+    let mut seconds = 0.0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let number_length = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        if number_length == 0 {
+            return None;
+        }
+        let number = rest[..number_length].parse::<f64>().ok()?;
+        rest = &rest[number_length..];
+        let unit_length = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let unit = match &rest[..unit_length] {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86400.0,
+            "w" => 604800.0,
+            _ => return None,
+        };
+        seconds += number * unit;
+        rest = &rest[unit_length..];
+    }
+    std::time::Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Parse an RFC 3339 date and time, e.g. `2024-01-31T12:00:00+01:00`, converted to UTC
+pub fn parse_datetime(text: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|t| t.with_timezone(&chrono::Utc))
 }
 
 impl Default for ArgumentType {
@@ -264,6 +380,9 @@ pub struct ArgumentInfo {
     pub multiple: bool,
     pub injected: bool,
     pub gui_info: ArgumentGUIInfo,
+    /// Free-form hints for the user interface, e.g. `unit` -> `meters` to label an input
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hints: BTreeMap<String, String>,
 }
 
 impl ArgumentInfo {
@@ -276,10 +395,32 @@ impl ArgumentInfo {
             multiple: false,
             injected: false,
             gui_info: ArgumentGUIInfo::TextField(40),
+            hints: BTreeMap::new(),
         }
     }
-    fn check(&self, _realm: &str, _namespace: &str, _name: &str) -> Vec<CommandRegistryIssue> {
-        let issues = Vec::new();
+    fn check(&self, realm: &str, namespace: &str, name: &str) -> Vec<CommandRegistryIssue> {
+        let mut issues = Vec::new();
+        if self.name.is_empty() {
+            issues.push(CommandRegistryIssue::error(
+                realm,
+                namespace,
+                name,
+                "Argument name is empty".to_string(),
+            ));
+        }
+        if let ArgumentType::Enum(e) = &self.argument_type {
+            if e.values.is_empty() && !e.others_allowed {
+                issues.push(CommandRegistryIssue::error(
+                    realm,
+                    namespace,
+                    name,
+                    format!(
+                        "Enum argument '{}' ({}) has no values and does not allow others",
+                        self.name, e.name
+                    ),
+                ));
+            }
+        }
         issues
     }
 
@@ -292,6 +433,31 @@ impl ArgumentInfo {
             multiple: false,
             injected: false,
             gui_info: ArgumentGUIInfo::TextField(40),
+            hints: BTreeMap::new(),
+        }
+    }
+    /// Argument of a Rust type given by name (see [ArgumentType::from_type_name]),
+    /// set up like by the constructor of the type, e.g. [ArgumentInfo::integer_argument].
+    /// Vectors (`Vec<f64>`) and tuples (`(f64, f64)`) are multiple arguments,
+    /// i.e. they take all the remaining parameters of the action,
+    /// so they must be the last argument (see [CommandMetadata::validate_arguments]).
+    pub fn typed_argument(name: &str, type_name: &str) -> Self {
+        let compact = type_name.split_whitespace().collect::<String>();
+        if compact.starts_with("Vec<") || compact.starts_with('(') {
+            return ArgumentInfo::argument(name)
+                .with_type(ArgumentType::from_type_name(type_name))
+                .set_multiple();
+        }
+        match ArgumentType::from_type_name(type_name) {
+            ArgumentType::String => ArgumentInfo::string_argument(name),
+            ArgumentType::Integer => ArgumentInfo::integer_argument(name, false),
+            ArgumentType::IntegerOption => ArgumentInfo::integer_argument(name, true),
+            ArgumentType::Float => ArgumentInfo::float_argument(name, false),
+            ArgumentType::FloatOption => ArgumentInfo::float_argument(name, true),
+            ArgumentType::Boolean => ArgumentInfo::boolean_argument(name),
+            ArgumentType::Duration => ArgumentInfo::duration_argument(name),
+            ArgumentType::DateTime => ArgumentInfo::datetime_argument(name),
+            argument_type => ArgumentInfo::argument(name).with_type(argument_type),
         }
     }
     pub fn string_argument(name: &str) -> Self {
@@ -303,6 +469,7 @@ impl ArgumentInfo {
             multiple: false,
             injected: false,
             gui_info: ArgumentGUIInfo::TextField(40),
+            hints: BTreeMap::new(),
         }
     }
     pub fn integer_argument(name: &str, option: bool) -> Self {
@@ -322,6 +489,7 @@ impl ArgumentInfo {
             multiple: false,
             injected: false,
             gui_info: ArgumentGUIInfo::IntegerField,
+            hints: BTreeMap::new(),
         }
     }
     pub fn float_argument(name: &str, option: bool) -> Self {
@@ -341,6 +509,7 @@ impl ArgumentInfo {
             multiple: false,
             injected: false,
             gui_info: ArgumentGUIInfo::FloatField,
+            hints: BTreeMap::new(),
         }
     }
     pub fn boolean_argument(name: &str) -> Self {
@@ -352,6 +521,31 @@ impl ArgumentInfo {
             multiple: false,
             injected: false,
             gui_info: ArgumentGUIInfo::Checkbox,
+            hints: BTreeMap::new(),
+        }
+    }
+    pub fn duration_argument(name: &str) -> Self {
+        ArgumentInfo {
+            name: name.to_string(),
+            label: name.replace("_", " ").to_string(),
+            default: CommandParameterValue::None,
+            argument_type: ArgumentType::Duration,
+            multiple: false,
+            injected: false,
+            gui_info: ArgumentGUIInfo::TextField(20),
+            hints: BTreeMap::new(),
+        }
+    }
+    pub fn datetime_argument(name: &str) -> Self {
+        ArgumentInfo {
+            name: name.to_string(),
+            label: name.replace("_", " ").to_string(),
+            default: CommandParameterValue::None,
+            argument_type: ArgumentType::DateTime,
+            multiple: false,
+            injected: false,
+            gui_info: ArgumentGUIInfo::TextField(30),
+            hints: BTreeMap::new(),
         }
     }
     pub fn with_default_none(mut self) -> Self {
@@ -366,6 +560,20 @@ impl ArgumentInfo {
         self.default = CommandParameterValue::from_value(value.into());
         self
     }
+    /// Use the result of a query as the default value.
+    /// The query is parsed right away, so that an invalid query is reported when the command is registered
+    /// rather than when a plan using the default is built.
+    pub fn with_default_link(mut self, query: &str) -> Result<Self, Error> {
+        let query = crate::parse::parse_query(query).map_err(|e| {
+            Error::new(
+                e.error_type,
+                format!("Invalid default query of argument '{}': {}", self.name, e.message),
+            )
+            .with_position(&e.position)
+        })?;
+        self.default = CommandParameterValue::from_query(query);
+        Ok(self)
+    }
     pub fn true_by_default(mut self) -> Self {
         self=self.with_type(ArgumentType::Boolean);
         self.default = CommandParameterValue::from_value(Value::Bool(true));
@@ -377,6 +585,11 @@ impl ArgumentInfo {
         self
     }
 
+    /// Add a hint for the user interface (see [ArgumentInfo::hints])
+    pub fn with_hint(mut self, name: &str, value: &str) -> Self {
+        self.hints.insert(name.to_owned(), value.to_owned());
+        self
+    }
     pub fn with_label(mut self, label: &str) -> Self {
         self.label = label.to_string();
         self
@@ -518,6 +731,18 @@ pub struct CommandMetadata {
     pub cache: bool,
     pub volatile: bool,
     pub definition: CommandDefinition,
+    /// Maximum number of concurrent executions of the command, None if unlimited.
+    /// Useful for resource-heavy commands, see [CommandMetadataRegistry::command_semaphore].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrency: Option<usize>,
+    /// Log a warning when the command produces an empty result (e.g. an empty table) from a non-empty input.
+    /// This is advisory, meant for commands like filters or joins that can silently return nothing.
+    #[serde(default)]
+    pub warn_if_empty: bool,
+    /// The command is CPU-bound or otherwise blocking, it is executed on the blocking thread pool
+    /// (see [tokio::task::spawn_blocking]) so that it does not stall the asynchronous runtime.
+    #[serde(default)]
+    pub blocking: bool,
 }
 
 impl CommandMetadata {
@@ -533,6 +758,9 @@ impl CommandMetadata {
             cache: true,
             volatile: false,
             definition: CommandDefinition::Registered,
+            max_concurrency: None,
+            warn_if_empty: false,
+            blocking: false,
         }
     }
     pub fn from_key(key: CommandKey) -> Self {
@@ -547,6 +775,9 @@ impl CommandMetadata {
             cache: true,
             volatile: false,
             definition: CommandDefinition::Registered,
+            max_concurrency: None,
+            warn_if_empty: false,
+            blocking: false,
         }
     }
     pub fn key(&self) -> CommandKey {
@@ -570,12 +801,59 @@ impl CommandMetadata {
                 "Command name 'ns' is reserved".to_string(),
             ));
         }
-        for a in self.arguments.iter() {
+        for (i, a) in self.arguments.iter().enumerate() {
             issues.append(&mut a.check(&self.realm, &self.namespace, &self.name));
+            if self.arguments[..i].iter().any(|b| b.name == a.name) {
+                issues.push(CommandRegistryIssue::error(
+                    &self.realm,
+                    &self.namespace,
+                    &self.name,
+                    format!("Duplicate argument name '{}'", a.name),
+                ));
+            }
+        }
+        if self.max_concurrency == Some(0) {
+            issues.push(CommandRegistryIssue::error(
+                &self.realm,
+                &self.namespace,
+                &self.name,
+                "Maximal concurrency must be positive".to_string(),
+            ));
         }
+        issues.append(&mut self.argument_order_issues());
         issues
     }
 
+    /// Multiple argument consumes all the remaining parameters,
+    /// so a positional argument following it would never receive a value.
+    fn argument_order_issues(&self) -> Vec<CommandRegistryIssue> {
+        let mut issues = Vec::new();
+        if let Some(i) = self.arguments.iter().position(|a| a.multiple && !a.injected) {
+            for a in self.arguments[i + 1..].iter().filter(|a| !a.injected) {
+                issues.push(CommandRegistryIssue::error(
+                    &self.realm,
+                    &self.namespace,
+                    &self.name,
+                    format!(
+                        "Argument '{}' follows the multiple argument '{}' and cannot receive a value",
+                        a.name, self.arguments[i].name
+                    ),
+                ));
+            }
+        }
+        issues
+    }
+
+    /// Check that all the positional arguments can receive a value,
+    /// i.e. that a multiple argument (e.g. a vector or a tuple) is the last one.
+    /// Called when a command is registered by [crate::ng_register_command].
+    pub fn validate_arguments(&self) -> Result<(), Error> {
+        match self.argument_order_issues().first() {
+            Some(issue) => Err(Error::general_error(issue.to_string())),
+            None => Ok(()),
+        }
+    }
+
     pub fn with_state_argument(&mut self, state_argument: ArgumentInfo) -> &mut Self {
         self.state_argument = state_argument;
         self
@@ -585,6 +863,20 @@ impl CommandMetadata {
         self
     }
 
+    /// Add a hint (see [ArgumentInfo::hints]) to the argument with the name
+    pub fn with_argument_hint(&mut self, argument: &str, name: &str, value: &str) -> Result<&mut Self, Error> {
+        match self.arguments.iter_mut().find(|a| a.name == argument) {
+            Some(a) => {
+                a.hints.insert(name.to_owned(), value.to_owned());
+                Ok(self)
+            }
+            None => Err(Error::general_error(format!(
+                "Hint '{}' of an unknown argument '{}' in command '{}'",
+                name, argument, self.name
+            ))),
+        }
+    }
+
     pub fn with_doc(&mut self, doc: &str) -> &mut Self {
         self.doc = doc.to_string();
         self
@@ -605,6 +897,31 @@ impl CommandMetadata {
         self.module = module.to_string();
         self
     }
+    /// Limit the number of concurrent executions of the command; the limit must be positive
+    pub fn with_max_concurrency(&mut self, max_concurrency: usize) -> Result<&mut Self, Error> {
+        if max_concurrency == 0 {
+            return Err(Error::general_error(format!(
+                "Maximal concurrency of command '{}' must be positive",
+                self.name
+            )));
+        }
+        self.max_concurrency = Some(max_concurrency);
+        Ok(self)
+    }
+    pub fn with_warn_if_empty(&mut self) -> &mut Self {
+        self.warn_if_empty = true;
+        self
+    }
+    pub fn with_blocking(&mut self) -> &mut Self {
+        self.blocking = true;
+        self
+    }
+    /// The result of the command changes even when the inputs don't (e.g. current time),
+    /// so results depending on it are never cached.
+    pub fn with_volatile(&mut self) -> &mut Self {
+        self.volatile = true;
+        self
+    }
 }
 
 // TODO: Refactor CommandMetadataRegistry to use realm/ns hierarchy and CommandKey
@@ -613,15 +930,37 @@ impl CommandMetadata {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CommandMetadataRegistry {
     pub commands: Vec<CommandMetadata>,
+    /// Names of known commands which are not available, because they require
+    /// a cargo feature that is not enabled, mapped to the name of the feature.
+    #[serde(default)]
+    pub feature_gated: BTreeMap<String, String>,
+    /// Semaphores limiting the concurrent executions of the commands with max_concurrency
+    #[serde(skip)]
+    semaphores: Arc<Mutex<HashMap<CommandKey, Arc<Semaphore>>>>,
 }
 
 impl CommandMetadataRegistry {
     pub fn new() -> Self {
         CommandMetadataRegistry {
             commands: Vec::new(),
+            feature_gated: BTreeMap::new(),
+            semaphores: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
+    /// Declare a command that is not available because the required feature is not enabled.
+    /// Referencing the command in a query then reports the missing feature
+    /// instead of a generic "not registered" error.
+    pub fn add_feature_gated(&mut self, name: &str, feature: &str) -> &mut Self {
+        self.feature_gated.insert(name.to_owned(), feature.to_owned());
+        self
+    }
+
+    /// Name of the feature required by a command declared by [Self::add_feature_gated]
+    pub fn required_feature(&self, name: &str) -> Option<&str> {
+        self.feature_gated.get(name).map(|f| f.as_str())
+    }
+
     pub fn add_command(&mut self, command: &CommandMetadata) -> &mut Self {
         self.commands.push(command.to_owned());
         self
@@ -659,6 +998,57 @@ impl CommandMetadataRegistry {
         None
     }
 
+    /// Check all the registered commands and collect the issues (errors and warnings)
+    pub fn check(&self) -> Vec<CommandRegistryIssue> {
+        let mut issues = Vec::new();
+        for (i, command) in self.commands.iter().enumerate() {
+            issues.append(&mut command.check());
+            if self.commands[..i].iter().any(|c| c.key() == command.key()) {
+                issues.push(CommandRegistryIssue::error(
+                    &command.realm,
+                    &command.namespace,
+                    &command.name,
+                    "Command is registered more than once".to_string(),
+                ));
+            }
+        }
+        issues
+    }
+
+    /// Validate the registry, typically called after all the commands are registered.
+    /// Returns an error describing all the errors found; if there are no errors,
+    /// the warnings are returned.
+    pub fn validate(&self) -> Result<Vec<CommandRegistryIssue>, Error> {
+        let (errors, warnings): (Vec<_>, Vec<_>) =
+            self.check().into_iter().partition(|issue| issue.is_error);
+        if errors.is_empty() {
+            Ok(warnings)
+        } else {
+            Err(Error::general_error(format!(
+                "Command registry is not valid:\n{}",
+                errors
+                    .iter()
+                    .map(|issue| issue.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )))
+        }
+    }
+
+    /// Semaphore limiting the concurrent executions of a command to its max_concurrency.
+    /// Returns None if the command is not limited (or not registered).
+    /// The semaphore is created on the first use and shared by the clones of the registry.
+    pub fn command_semaphore(&self, key: &CommandKey) -> Option<Arc<Semaphore>> {
+        let max_concurrency = self.get(key.clone())?.max_concurrency.filter(|n| *n > 0)?;
+        let mut semaphores = self.semaphores.lock().unwrap();
+        Some(
+            semaphores
+                .entry(key.clone())
+                .or_insert_with(|| Arc::new(Semaphore::new(max_concurrency)))
+                .clone(),
+        )
+    }
+
     pub fn find_command(
         &self,
         realm: &str,
@@ -687,3 +1077,184 @@ impl CommandMetadataRegistry {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_argument() {
+        let argument = ArgumentInfo::typed_argument("n", "i64");
+        assert!(matches!(argument.argument_type, ArgumentType::Integer));
+        assert!(matches!(argument.gui_info, ArgumentGUIInfo::IntegerField));
+        let argument = ArgumentInfo::typed_argument("flag", "bool");
+        assert!(matches!(argument.argument_type, ArgumentType::Boolean));
+        assert!(matches!(argument.gui_info, ArgumentGUIInfo::Checkbox));
+        assert!(matches!(
+            ArgumentInfo::typed_argument("x", "f64").argument_type,
+            ArgumentType::Float
+        ));
+        assert!(matches!(
+            ArgumentInfo::typed_argument("name", "String").argument_type,
+            ArgumentType::String
+        ));
+        let argument = ArgumentInfo::typed_argument("min", "Option<f64>");
+        assert!(matches!(argument.argument_type, ArgumentType::FloatOption));
+        assert!(matches!(argument.default, CommandParameterValue::Value(Value::Null)));
+        assert!(matches!(
+            ArgumentInfo::typed_argument("v", "Value").argument_type,
+            ArgumentType::Any
+        ));
+    }
+
+    #[test]
+    fn test_validate_valid_command() {
+        let mut registry = CommandMetadataRegistry::new();
+        let mut command = CommandMetadata::new("valid");
+        command
+            .with_argument(ArgumentInfo::string_argument("a"))
+            .with_argument(ArgumentInfo::argument("b").set_multiple())
+            .with_argument(ArgumentInfo::argument("context").set_injected());
+        registry.add_command(&command);
+        assert!(registry.check().is_empty());
+        assert!(registry.validate().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_validate_duplicate_argument() {
+        let mut registry = CommandMetadataRegistry::new();
+        let mut command = CommandMetadata::new("duplicate");
+        command
+            .with_argument(ArgumentInfo::string_argument("a"))
+            .with_argument(ArgumentInfo::integer_argument("a", false));
+        registry.add_command(&command);
+        let issues = registry.check();
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].is_error);
+        assert_eq!(issues[0].name, "duplicate");
+        assert_eq!(issues[0].namespace, "root");
+        let err = registry.validate().unwrap_err();
+        assert!(err.to_string().contains("Duplicate argument name 'a'"));
+    }
+
+    #[test]
+    fn test_max_concurrency_must_be_positive() {
+        let key = CommandKey::new_name("heavy");
+        let mut command = CommandMetadata::from_key(key.clone());
+        assert!(command.with_max_concurrency(0).is_err());
+        assert_eq!(command.max_concurrency, None);
+        command.with_max_concurrency(2).unwrap();
+        let mut registry = CommandMetadataRegistry::new();
+        registry.add_command(&command);
+        assert!(registry.command_semaphore(&key).is_some());
+
+        // Zero can still arrive via deserialization; it is reported and never limits
+        command.max_concurrency = Some(0);
+        assert!(command.check().iter().any(|issue| issue.is_error));
+        let mut registry = CommandMetadataRegistry::new();
+        registry.add_command(&command);
+        assert!(registry.command_semaphore(&key).is_none());
+    }
+
+    #[test]
+    fn test_validate_ordering_and_enum() {
+        let mut registry = CommandMetadataRegistry::new();
+        let mut command = CommandMetadata::new("bad");
+        command
+            .with_argument(ArgumentInfo::argument("values").set_multiple())
+            .with_argument(ArgumentInfo::string_argument("after"))
+            .with_argument(
+                ArgumentInfo::argument("choice").with_type(ArgumentType::Enum(EnumArgument::new("empty"))),
+            );
+        registry.add_command(&command);
+        registry.add_command(&CommandMetadata::new("bad"));
+        let issues = registry.check();
+        assert_eq!(issues.len(), 4);
+        assert!(issues.iter().all(|issue| issue.is_error));
+        assert!(registry.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_duration_and_datetime() {
+        use std::time::Duration;
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("1.5d"), Some(Duration::from_secs(129600)));
+        assert_eq!(parse_duration("250ms"), Some(Duration::from_millis(250)));
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        for invalid in ["", "s", "10x", "1h30", "h1", "-5s"] {
+            assert_eq!(parse_duration(invalid), None, "{}", invalid);
+        }
+        assert_eq!(
+            parse_datetime("2024-01-31T13:00:00+01:00").unwrap().to_rfc3339(),
+            "2024-01-31T12:00:00+00:00"
+        );
+        assert!(parse_datetime("2024-01-31").is_none());
+
+        assert!(matches!(ArgumentType::from_type_name("Duration"), ArgumentType::Duration));
+        assert!(matches!(
+            ArgumentType::from_type_name("chrono::DateTime < chrono::Utc >"),
+            ArgumentType::DateTime
+        ));
+        assert!(matches!(ArgumentType::from_type_name("u8"), ArgumentType::Any));
+    }
+
+    #[test]
+    fn test_default_link() -> Result<(), Error> {
+        let a = ArgumentInfo::argument("source").with_default_link("data/input.csv/-/head")?;
+        match &a.default {
+            CommandParameterValue::Query(q) => assert_eq!(
+                q.encode(),
+                crate::parse::parse_query("data/input.csv/-/head")?.encode()
+            ),
+            _ => panic!("Query default expected"),
+        }
+        let err = ArgumentInfo::argument("source")
+            .with_default_link("data/hello world")
+            .unwrap_err();
+        assert!(err.message.contains("argument 'source'"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_argument_hints_round_trip() {
+        let mut registry = CommandMetadataRegistry::new();
+        let mut command = CommandMetadata::from_key(CommandKey::new_name("move_by"));
+        command
+            .with_argument(ArgumentInfo::float_argument("distance", false).with_hint("unit", "meters"))
+            .with_argument(ArgumentInfo::string_argument("label"));
+        registry.add_command(&command);
+
+        let json = serde_json::to_value(registry.get("move_by").unwrap()).unwrap();
+        assert_eq!(json["arguments"][0]["hints"]["unit"], "meters");
+        assert!(json["arguments"][1].get("hints").is_none());
+
+        let command: CommandMetadata = serde_json::from_value(json).unwrap();
+        assert_eq!(command.arguments[0].hints.get("unit").map(String::as_str), Some("meters"));
+        assert!(command.arguments[1].hints.is_empty());
+    }
+
+    #[test]
+    fn test_typed_vector_and_tuple_argument() {
+        let a = ArgumentInfo::typed_argument("xs", "Vec < f64 >");
+        assert!(a.multiple);
+        assert!(matches!(a.argument_type, ArgumentType::Float));
+        let a = ArgumentInfo::typed_argument("point", "(f64, f64)");
+        assert!(a.multiple);
+        assert!(matches!(a.argument_type, ArgumentType::Float));
+        let a = ArgumentInfo::typed_argument("pair", "(String, i64)");
+        assert!(a.multiple);
+        assert!(matches!(a.argument_type, ArgumentType::Any));
+        let a = ArgumentInfo::typed_argument("n", "i64");
+        assert!(!a.multiple);
+        assert!(matches!(a.argument_type, ArgumentType::Integer));
+        let a = ArgumentInfo::typed_argument("x", "f64");
+        assert!(!a.multiple);
+        assert!(matches!(a.argument_type, ArgumentType::Float));
+        let a = ArgumentInfo::typed_argument("value", "Value");
+        assert!(matches!(a.argument_type, ArgumentType::Any));
+        let a = ArgumentInfo::typed_argument("window", "Duration");
+        assert!(!a.multiple);
+        assert!(matches!(a.argument_type, ArgumentType::Duration));
+    }
+}
@@ -91,6 +91,17 @@ impl CommandArguments {
         T::from_context(name, context)
     }
 
+    /// Skip the injected context parameter if it is the next parameter.
+    /// Context is passed to the command directly by the wrapper,
+    /// but the plan contains it as an injected parameter.
+    pub fn skip_context(&mut self) {
+        if let Some(ParameterValue::Injected(name)) = self.parameters.0.get(self.argument_number) {
+            if name == "context" {
+                self.argument_number += 1;
+            }
+        }
+    }
+
     /// Returns true if all parameters have been used
     /// This is checked during the command execution
     pub fn all_parameters_used(&self) -> bool {
@@ -245,6 +256,17 @@ impl<V: ValueInterface> NGCommandArguments<V> {
         T::from_context(name, context)
     }
 
+    /// Skip the injected context parameter if it is the next parameter.
+    /// Context is passed to the command directly by the wrapper,
+    /// but the plan contains it as an injected parameter.
+    pub fn skip_context(&mut self) {
+        if let Some(ParameterValue::Injected(name)) = self.parameters.0.get(self.argument_number) {
+            if name == "context" {
+                self.argument_number += 1;
+            }
+        }
+    }
+
     /// Returns true if all parameters have been used
     /// This is checked during the command execution
     pub fn all_parameters_used(&self) -> bool {
@@ -312,6 +334,37 @@ macro_rules! impl_from_parameter_value {
             }
         }
     };
+    ($t:ty, $jsonvalue_to_opt:expr) => {
+        impl<E: Environment> FromParameterValue<$t, E> for $t {
+            fn from_parameter_value(
+                param: &ParameterValue,
+                context: &impl ContextInterface<E>,
+            ) -> Result<$t, Error> {
+                let from_json = |p: &serde_json::Value| {
+                    $jsonvalue_to_opt(p).ok_or(Error::conversion_error_with_message(
+                        p,
+                        stringify!($t),
+                        concat!(stringify!($t), " parameter value expected"),
+                    ))
+                };
+                if let Some(ref p) = param.value() {
+                    from_json(p).map_err(|e| e.with_position(&param.position()))
+                } else if let Some(link) = param.link().as_ref() {
+                    let state = context.evaluate_dependency(link)?;
+                    let value = state.read().unwrap().try_into_json_value();
+                    value
+                        .and_then(|p| from_json(&p))
+                        .map_err(|e| e.with_query(link))
+                } else {
+                    Err(Error::conversion_error_with_message(
+                        param,
+                        stringify!($t),
+                        "Value or link parameter value expected",
+                    ))
+                }
+            }
+        }
+    };
 }
 
 /// Macro to simplify the implementation of the FromParameterValue trait
@@ -397,6 +450,87 @@ impl_ng_from_parameter_value!(
 );
 impl_from_parameter_value!(bool, |p: &serde_json::Value| p.as_bool(), try_into_bool);
 impl_ng_from_parameter_value!(bool, |p: &serde_json::Value| p.as_bool());
+
+/// Duration parameter is either a number of seconds or a text like `1h30m`
+fn duration_from_json(p: &serde_json::Value) -> Option<std::time::Duration> {
+    match p {
+        serde_json::Value::Number(n) => n
+            .as_f64()
+            .and_then(|x| std::time::Duration::try_from_secs_f64(x).ok()),
+        serde_json::Value::String(s) => command_metadata::parse_duration(s),
+        _ => None,
+    }
+}
+
+fn datetime_from_json(p: &serde_json::Value) -> Option<chrono::DateTime<chrono::Utc>> {
+    p.as_str().and_then(command_metadata::parse_datetime)
+}
+
+/// Elements of a vector or tuple parameter:
+/// the parameters of a multiple parameter or the items of an array value
+fn parameter_elements(param: &ParameterValue) -> Vec<ParameterValue> {
+    match param {
+        ParameterValue::MultipleParameters(p) => p.clone(),
+        ParameterValue::DefaultValue(name, serde_json::Value::Array(a))
+        | ParameterValue::OverrideValue(name, serde_json::Value::Array(a)) => a
+            .iter()
+            .map(|x| ParameterValue::DefaultValue(name.clone(), x.clone()))
+            .collect(),
+        ParameterValue::ParameterValue(name, serde_json::Value::Array(a), pos) => a
+            .iter()
+            .map(|x| ParameterValue::ParameterValue(name.clone(), x.clone(), pos.clone()))
+            .collect(),
+        _ => vec![param.clone()],
+    }
+}
+
+/// Macro to implement the NGFromParameterValue trait for a vector of a type implementing it
+macro_rules! impl_ng_from_parameter_value_vec {
+    ($t:ty) => {
+        impl NGFromParameterValue<Vec<$t>> for Vec<$t> {
+            fn from_parameter_value(param: &ParameterValue) -> Result<Vec<$t>, Error> {
+                parameter_elements(param)
+                    .iter()
+                    .map(|p| <$t as NGFromParameterValue<$t>>::from_parameter_value(p))
+                    .collect()
+            }
+        }
+    };
+}
+
+/// Macro to implement the NGFromParameterValue trait for a tuple of types implementing it;
+/// the number of elements must match the tuple length
+macro_rules! impl_ng_from_parameter_value_tuple {
+    ($n:expr; $($t:ident),+) => {
+        impl<$($t: NGFromParameterValue<$t>),+> NGFromParameterValue<($($t,)+)> for ($($t,)+) {
+            fn from_parameter_value(param: &ParameterValue) -> Result<($($t,)+), Error> {
+                let elements = parameter_elements(param);
+                if elements.len() != $n {
+                    return Err(Error::conversion_error_with_message(
+                        param,
+                        concat!("tuple of ", stringify!($n)),
+                        &format!("{} values expected, {} found", $n, elements.len()),
+                    )
+                    .with_position(&elements.first().map(|p| p.position()).unwrap_or_default()));
+                }
+                let mut elements = elements.iter();
+                Ok(($($t::from_parameter_value(elements.next().unwrap())?,)+))
+            }
+        }
+    };
+}
+
+impl_ng_from_parameter_value_vec!(String);
+impl_ng_from_parameter_value_vec!(i64);
+impl_ng_from_parameter_value_vec!(f64);
+impl_ng_from_parameter_value_vec!(bool);
+impl_ng_from_parameter_value_tuple!(2; A, B);
+impl_ng_from_parameter_value_tuple!(3; A, B, C);
+
+impl_from_parameter_value!(std::time::Duration, duration_from_json);
+impl_ng_from_parameter_value!(std::time::Duration, duration_from_json);
+impl_from_parameter_value!(chrono::DateTime<chrono::Utc>, datetime_from_json);
+impl_ng_from_parameter_value!(chrono::DateTime<chrono::Utc>, datetime_from_json);
 /*
 impl<E: Environment> FromParameterValue<Vec<String>, E> for Vec<String> {
     fn from_parameter_value(
@@ -807,6 +941,32 @@ impl<P: Send+Sync, V:ValueInterface, C:ActionContext<P,V>> NGCommandExecutor<P,
     }
 }
 
+/// Convert the state returned by a command (registered with `-> state`, see [ng_register_command])
+/// to the command result: the filename (and thus the extension), media type and attributes
+/// of the state metadata are transferred to the metadata of the evaluation and the data are returned.
+/// A state with an error status is turned into an error.
+pub fn state_result<P, V: ValueInterface>(
+    state: State<V>,
+    context: &impl ActionContext<P, V>,
+) -> Result<V, Error> {
+    if let Some(metadata) = state.metadata.metadata_record() {
+        if let Some(error) = metadata.as_error() {
+            return Err(error);
+        }
+        if let Some(filename) = metadata.filename {
+            context.set_filename(filename);
+        }
+        if !metadata.media_type.is_empty() {
+            context.set_media_type(metadata.media_type);
+        }
+        for (name, value) in metadata.attributes {
+            context.set_attribute(&name, value);
+        }
+    }
+    let data = state.data.read().unwrap().clone();
+    Ok(data)
+}
+
 /*
 #[macro_export]
 macro_rules! command_wrapper_typed {
@@ -847,6 +1007,9 @@ macro_rules! command_wrapper_parameter_name {
     ($cxpar:ident, $statepar:ident, multiple $argname:ident) => {
         $argname
     };
+    ($cxpar:ident, $statepar:ident, choice $argname:ident) => {
+        $argname
+    };
     ($cxpar:ident, $statepar:ident, $argname:ident) => {
         $argname
     };
@@ -855,6 +1018,7 @@ macro_rules! command_wrapper_parameter_name {
 #[macro_export]
 macro_rules! command_wrapper_parameter_assignment {
     ($cxpar:ident, $statepar:ident, $arguments:ident, $state:ident, $context:ident, context) => {
+        $arguments.skip_context();
         let $crate::command_wrapper_parameter_name!($cxpar, $statepar, context) = $context;
     };
     ($cxpar:ident, $statepar:ident, $arguments:ident, $state:ident, $context:ident, state) => {
@@ -884,6 +1048,7 @@ macro_rules! command_wrapper_parameter_assignment {
 #[macro_export]
 macro_rules! ng_command_wrapper_parameter_assignment {
     ($cxpar:ident, $statepar:ident, $arguments:ident, $state:ident, $context:ident, context) => {
+        $arguments.skip_context();
         let $crate::command_wrapper_parameter_name!($cxpar, $statepar, context) = $context;
     };
     ($cxpar:ident, $statepar:ident, $arguments:ident, $state:ident, $context:ident, state) => {
@@ -898,6 +1063,10 @@ macro_rules! ng_command_wrapper_parameter_assignment {
         let $crate::command_wrapper_parameter_name!($cxpar, $statepar, $argname): $argtype =
             $arguments.get()?;
     };
+    ($cxpar:ident, $statepar:ident, $arguments:ident, $state:ident, $context:ident, choice $argname:ident:$argtype:ty) => {
+        let $crate::command_wrapper_parameter_name!($cxpar, $statepar, $argname): $argtype =
+            $arguments.get()?;
+    };
     ($cxpar:ident, $statepar:ident, $arguments:ident, $state:ident, $context:ident, multiple $argname:ident:$argtype:ty) => {
         let $crate::command_wrapper_parameter_name!($cxpar, $statepar, $argname): std::vec::Vec<$argtype> =
             $arguments.get()?;
@@ -938,6 +1107,29 @@ macro_rules! command_wrapper {
 
 #[macro_export]
 macro_rules! ng_command_wrapper {
+    ($name:ident
+        ($($argname:ident $($argname2:ident)? $(:$argtype:ty)?),*) -> state) => {
+            |state, arguments, context|{
+                let result_context = $crate::context::ActionContext::clone_context(&context);
+                let cx_wrapper_parameter = 0;
+                $(
+                    $crate::ng_command_wrapper_parameter_assignment!(cx_wrapper_parameter, state, arguments, state, context, $argname $($argname2)? $(:$argtype)?);
+                )*
+                if arguments.all_parameters_used(){
+                    $crate::commands::state_result(
+                        $name($($crate::command_wrapper_parameter_name!(cx_wrapper_parameter, state, $argname $($argname2)?)),*)?,
+                        &result_context,
+                    )
+                }
+                else{
+                        Err($crate::error::Error::new(
+                            $crate::error::ErrorType::TooManyParameters,
+                            format!("Too many parameters: {}; {} excess parameters found", arguments.len(), arguments.excess_parameters()),
+                        )
+                        .with_position(&arguments.parameter_position()))
+                }
+            }
+        };
     ($name:ident
         ($($argname:ident $($argname2:ident)? $(:$argtype:ty)?),*)) => {
             //stringify!(
@@ -1003,20 +1195,44 @@ macro_rules! register_command {
         $cm.with_argument($crate::command_metadata::ArgumentInfo::argument(stringify!($argname)).set_injected());
      };
      (@arg $cm:ident $argname:ident:$argtype:ty) =>{
-       $cm.with_argument($crate::command_metadata::ArgumentInfo::argument(stringify!($argname)));
+       $cm.with_argument($crate::command_metadata::ArgumentInfo::typed_argument(stringify!($argname), stringify!($argtype)));
     };
 
 }
 
+/// Register a command function with its arguments.
+/// The argument types are derived from the Rust types of the arguments (see [crate::command_metadata::ArgumentInfo::typed_argument]).
+/// A default value of an argument follows its type,
+/// e.g. `ng_register_command!(cr, transpose(state, header: String = ""))`.
+/// A command returning a [State] instead of a value is registered with `-> state`,
+/// e.g. `ng_register_command!(cr, plot(state, width: i64) -> state)`;
+/// the metadata of the returned state are used for the result (see [state_result]).
+/// An argument of an enum declared with [ng_enum_argument] is marked with `choice`,
+/// e.g. `ng_register_command!(cr, sort(state, choice order: Order))`, so that it is registered as an enum argument.
+/// Hints for the user interface (see [crate::command_metadata::ArgumentInfo::hints]) precede the argument,
+/// e.g. `ng_register_command!(cr, move_by(state, #[hint(unit = "meters")] distance: f64))`.
 #[macro_export]
 macro_rules! ng_register_command {
-    ($cr:ident, $name:ident ($( $argname:ident $($argname2:ident)? $(:$argtype:ty)?),*)) => {
+    ($cr:ident, $name:ident ($( $(#[hint($($hname:ident = $hvalue:literal),*)])* $argname:ident $($argname2:ident)? $(:$argtype:ty)? $(= $default:expr)?),*)) => {
         {
         let reg_command_metadata = $cr.register_command(stringify!($name), $crate::ng_command_wrapper!($name($($argname $($argname2)? $(:$argtype)?),*)))?
         .with_name(stringify!($name));
         $(
-            $crate::ng_register_command!(@arg reg_command_metadata $argname $($argname2)? $(:$argtype)?);
+            $crate::ng_register_command!(@arg reg_command_metadata $argname $($argname2)? $(:$argtype)? $(= $default)?);
+            $crate::ng_register_command!(@hints reg_command_metadata [$argname $($argname2)?] $([$($hname = $hvalue),*])*);
+        )*
+        reg_command_metadata.validate_arguments()?;
+    }
+    };
+    ($cr:ident, $name:ident ($( $(#[hint($($hname:ident = $hvalue:literal),*)])* $argname:ident $($argname2:ident)? $(:$argtype:ty)? $(= $default:expr)?),*) -> state) => {
+        {
+        let reg_command_metadata = $cr.register_command(stringify!($name), $crate::ng_command_wrapper!($name($($argname $($argname2)? $(:$argtype)?),*) -> state))?
+        .with_name(stringify!($name));
+        $(
+            $crate::ng_register_command!(@arg reg_command_metadata $argname $($argname2)? $(:$argtype)? $(= $default)?);
+            $crate::ng_register_command!(@hints reg_command_metadata [$argname $($argname2)?] $([$($hname = $hvalue),*])*);
         )*
+        reg_command_metadata.validate_arguments()?;
     }
     };
     (@arg $cm:ident state) =>{
@@ -1044,15 +1260,132 @@ macro_rules! ng_register_command {
         println!("multiple Any arguments: {}", stringify!($argname));
         $cm.with_argument($crate::command_metadata::ArgumentInfo::argument(stringify!($argname)).set_multiple());
     };
+    (@arg $cm:ident choice $argname:ident:$argtype:ty) =>{
+        $cm.with_argument($crate::command_metadata::ArgumentInfo::argument(stringify!($argname))
+            .with_type(<$argtype>::argument_type()));
+    };
     (@arg $cm:ident injected $argname:ident:$argtype:ty) =>{
         $cm.with_argument($crate::command_metadata::ArgumentInfo::argument(stringify!($argname)).set_injected());
      };
-     (@arg $cm:ident $argname:ident:$argtype:ty) =>{
-       $cm.with_argument($crate::command_metadata::ArgumentInfo::argument(stringify!($argname)));
+     (@arg $cm:ident $argname:ident:$argtype:ty $(= $default:expr)?) =>{
+       $cm.with_argument($crate::command_metadata::ArgumentInfo::typed_argument(stringify!($argname), stringify!($argtype))$(.with_default($default))?);
+    };
+    (@hints $cm:ident [$prefix:ident $argname:ident] $($hints:tt)*) =>{
+        $crate::ng_register_command!(@hints $cm [$argname] $($hints)*);
+    };
+    (@hints $cm:ident [$argname:ident] $([$($hname:ident = $hvalue:literal),*])*) =>{
+        $($(
+            $cm.with_argument_hint(stringify!($argname), stringify!($hname), $hvalue)?;
+        )*)*
     };
 
 }
 
+/// Generate a function registering a list of commands with [ng_register_command].
+/// Each command is declared with the same syntax as in [ng_register_command];
+/// registration stops at the first command failing to register and the error is returned.
+///
+/// ```ignore
+/// ng_register_all!(
+///     /// Register the commands of this module
+///     pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+///         transpose(state),
+///         set_meta(state, multiple attributes:Value, context),
+///     }
+/// );
+/// ```
+#[macro_export]
+macro_rules! ng_register_all {
+    ($(#[$meta:meta])* $vis:vis fn $fname:ident $(<$gen:ident : $bound:path>)? ($cr:ident : $crtype:ty) {
+        $($name:ident ($($args:tt)*) $(-> $ret:ident)?),* $(,)?
+    }) => {
+        $(#[$meta])*
+        $vis fn $fname $(<$gen: $bound>)? ($cr: $crtype) -> ::std::result::Result<(), $crate::error::Error> {
+            $(
+                $crate::ng_register_command!($cr, $name($($args)*) $(-> $ret)?);
+            )*
+            Ok(())
+        }
+    };
+}
+
+/// Declare a fieldless enum usable as a command argument.
+/// Each variant is given the alias used in queries; the enum gets [crate::command_metadata::EnumArgument]
+/// describing the alternatives (`enum_argument` and `argument_type`) and the conversions from a parameter value.
+/// An unknown alias is an error. `Debug`, `Clone`, `Copy`, `PartialEq` and `Eq` are derived.
+///
+/// ```ignore
+/// ng_enum_argument!(
+///     /// Sort order
+///     pub enum Order {
+///         Ascending = "asc",
+///         Descending = "desc",
+///     }
+/// );
+/// ng_register_command!(cr, sort(state, choice order: Order));
+/// ```
+#[macro_export]
+macro_rules! ng_enum_argument {
+    ($(#[$meta:meta])* $vis:vis enum $name:ident {
+        $($(#[$vmeta:meta])* $variant:ident = $alias:literal),+ $(,)?
+    }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        $vis enum $name {
+            $($(#[$vmeta])* $variant),+
+        }
+
+        impl $name {
+            /// Aliases of the variants as used in queries
+            pub const ALIASES: &'static [&'static str] = &[$($alias),+];
+
+            pub fn alias(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $alias),+
+                }
+            }
+            pub fn from_alias(alias: &str) -> Option<Self> {
+                match alias {
+                    $($alias => Some($name::$variant),)+
+                    _ => None,
+                }
+            }
+            pub fn enum_argument() -> $crate::command_metadata::EnumArgument {
+                $crate::command_metadata::EnumArgument::new(stringify!($name))
+                    $(.with_alternative($alias))+
+            }
+            pub fn argument_type() -> $crate::command_metadata::ArgumentType {
+                $crate::command_metadata::ArgumentType::Enum(Self::enum_argument())
+            }
+            fn unknown_alias(alias: &str) -> $crate::error::Error {
+                $crate::error::Error::conversion_error_with_message(
+                    alias,
+                    stringify!($name),
+                    &format!("Unknown {} '{}', expected one of: {}", stringify!($name), alias, Self::ALIASES.join(", ")),
+                )
+            }
+        }
+
+        impl $crate::commands::NGFromParameterValue<$name> for $name {
+            fn from_parameter_value(
+                param: &$crate::plan::ParameterValue,
+            ) -> ::std::result::Result<$name, $crate::error::Error> {
+                let alias = <String as $crate::commands::NGFromParameterValue<String>>::from_parameter_value(param)?;
+                $name::from_alias(&alias)
+                    .ok_or_else(|| $name::unknown_alias(&alias).with_position(&param.position()))
+            }
+        }
+
+        impl ::std::convert::TryFrom<$crate::value::Value> for $name {
+            type Error = $crate::error::Error;
+            fn try_from(value: $crate::value::Value) -> ::std::result::Result<Self, Self::Error> {
+                let alias = String::try_from(value)?;
+                $name::from_alias(&alias).ok_or_else(|| $name::unknown_alias(&alias))
+            }
+        }
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{context::StatEnvRef, metadata::MetadataRecord, query::Key};
@@ -1104,6 +1437,12 @@ mod tests {
         fn set_filename(&self, filename: String) {
             
         }
+
+        fn set_media_type(&self, media_type: String) {
+        }
+
+        fn set_attribute(&self, name: &str, value: serde_json::Value) {
+        }
     
         fn debug(&self, message: &str) {
         }
@@ -1288,4 +1627,118 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_ng_wrapper_skips_injected_context() -> Result<(), Error> {
+        fn greet(context: TrivialContext, name: String) -> Result<Value, Error> {
+            Ok(Value::from_string(format!("Hello {}", name)))
+        }
+        let mut cr = NGCommandRegistry::<NoInjection, Value, TrivialContext>::new();
+        ng_register_command!(cr, greet(context, name: String));
+
+        // The plan contains the context as an injected parameter
+        let mut rp = ResolvedParameterValues::new();
+        rp.0.push(ParameterValue::Injected("context".into()));
+        rp.0.push(ParameterValue::ParameterValue(
+            "name".into(),
+            "World".into(),
+            Position::unknown(),
+        ));
+        let mut ca = NGCommandArguments::new(rp);
+        let s = cr.execute(&CommandKey::new("", "", "greet"), &State::new(), &mut ca, TrivialContext)?;
+        assert_eq!(s.try_into_string()?, "Hello World");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ng_register_command_with_default() -> Result<(), Error> {
+        fn greet(state: &State<Value>, name: String, count: i64) -> Result<Value, Error> {
+            Ok(Value::from_string(format!("Hello {} {}", name, count)))
+        }
+        let mut cr = NGCommandRegistry::<NoInjection, Value, TrivialContext>::new();
+        ng_register_command!(cr, greet(state, name: String = "World", count: i64));
+        let metadata = cr.command_metadata_registry.get("greet").unwrap();
+        match &metadata.arguments[0].default {
+            command_metadata::CommandParameterValue::Value(value) => assert_eq!(value, "World"),
+            other => panic!("Unexpected default {:?}", other),
+        }
+        assert!(matches!(
+            metadata.arguments[1].default,
+            command_metadata::CommandParameterValue::None
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ng_register_command_with_hints() -> Result<(), Error> {
+        fn move_by(state: &State<Value>, distance: f64, xs: Vec<Value>) -> Result<Value, Error> {
+            Ok(Value::from_string(format!("{} {}", distance, xs.len())))
+        }
+        let mut cr = NGCommandRegistry::<NoInjection, Value, TrivialContext>::new();
+        ng_register_command!(
+            cr,
+            move_by(
+                state,
+                #[hint(unit = "meters", widget = "slider")] distance: f64 = 1.0,
+                #[hint(unit = "points")] multiple xs: Value
+            )
+        );
+        let json = serde_json::to_value(cr.command_metadata_registry.get("move_by").unwrap()).unwrap();
+        assert_eq!(json["arguments"][0]["hints"]["unit"], "meters");
+        let metadata: command_metadata::CommandMetadata = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            metadata.arguments[0].hints,
+            [("unit", "meters"), ("widget", "slider")]
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        );
+        assert_eq!(metadata.arguments[1].hints.get("unit").map(String::as_str), Some("points"));
+        Ok(())
+    }
+
+    mod small_library {
+        use super::*;
+
+        fn hello() -> Result<Value, Error> {
+            Ok(Value::from_string("Hello".into()))
+        }
+        fn greet(name: String) -> Result<Value, Error> {
+            Ok(Value::from_string(format!("Hello {}", name)))
+        }
+        fn debug(context: TrivialContext) -> Result<Value, Error> {
+            context.debug("debug called");
+            Ok(Value::none())
+        }
+
+        ng_register_all!(
+            pub fn register_all(cr: &mut NGCommandRegistry<NoInjection, Value, TrivialContext>) {
+                hello(),
+                greet(name: String),
+                debug(context),
+            }
+        );
+    }
+
+    #[test]
+    fn test_ng_register_all() -> Result<(), Error> {
+        let mut cr = NGCommandRegistry::<NoInjection, Value, TrivialContext>::new();
+        small_library::register_all(&mut cr)?;
+        for name in ["hello", "greet", "debug"] {
+            assert!(
+                cr.command_metadata_registry.get(name).is_some(),
+                "{} not registered",
+                name
+            );
+        }
+        let greet = cr.command_metadata_registry.get("greet").unwrap();
+        assert_eq!(greet.arguments.len(), 1);
+        assert_eq!(greet.arguments[0].name, "name");
+
+        let state = State::new();
+        let mut ca = NGCommandArguments::new(ResolvedParameterValues::new());
+        let s = cr.execute(&CommandKey::new("", "", "hello"), &state, &mut ca, TrivialContext)?;
+        assert_eq!(s.try_into_string()?, "Hello");
+        Ok(())
+    }
+
 }
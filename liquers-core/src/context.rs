@@ -1,5 +1,5 @@
 use std::{
-    cell::RefCell, marker::PhantomData, rc::Rc, sync::{Arc, Mutex}
+    cell::RefCell, collections::BTreeMap, marker::PhantomData, rc::Rc, sync::{Arc, Mutex}, time::Duration
 };
 
 use crate::{
@@ -7,11 +7,11 @@ use crate::{
     command_metadata::CommandMetadataRegistry,
     commands::{CommandExecutor, CommandRegistry, NGCommandExecutor, NGCommandRegistry},
     error::Error,
-    metadata::{Metadata, MetadataRecord},
+    metadata::{LogEntry, Metadata, MetadataRecord, Status},
     query::{Key, Query, TryToQuery},
     state::State,
     store::{NoStore, Store},
-    value::ValueInterface,
+    value::{ValueDeserializerRegistry, ValueInterface},
 };
 
 pub trait Environment: Sized + Sync + Send {
@@ -56,7 +56,20 @@ pub trait NGEnvironment: Sized + Sync + Send + 'static {
     fn get_cache(&self) -> Arc<Mutex<Box<dyn Cache<Self::Value>>>>;
     #[cfg(feature = "async_store")]
     fn get_async_store(&self) -> Arc<Box<dyn crate::store::AsyncStore>>;
-    
+    /// Configuration values available to the queries (see the `config_get` command).
+    /// This is a sandboxed map held by the environment, not the process environment.
+    fn get_config(&self) -> Arc<BTreeMap<String, Self::Value>> {
+        Arc::new(BTreeMap::new())
+    }
+    /// Deserializers of custom value types, consulted when loading values from a store
+    fn get_value_deserializer_registry(&self) -> Option<&ValueDeserializerRegistry<Self::Value>> {
+        None
+    }
+    /// Interval of writing the metadata (log, progress) of a running evaluation into a progress sidecar
+    /// in the store (see [crate::interpreter::progress_key]); None (default) disables the writing.
+    fn get_metadata_flush_interval(&self) -> Option<Duration> {
+        None
+    }
 
     fn get_bytes(&self, key: &Key) -> Result<Vec<u8>, Error> {
         self.get_store().get_bytes(key)
@@ -64,6 +77,40 @@ pub trait NGEnvironment: Sized + Sync + Send + 'static {
     fn get_metadata(&self, key: &Key) -> Result<Metadata, Error> {
         self.get_store().get_metadata(key)
     }
+    /// Deserialize a value loaded from a store, using a deserializer from the
+    /// [value deserializer registry](NGEnvironment::get_value_deserializer_registry) if there is one
+    /// for the type identifier, otherwise the built-in deserialization of the value type.
+    fn deserialize_value(
+        &self,
+        b: &[u8],
+        type_identifier: &str,
+        format: &str,
+    ) -> Result<Self::Value, Error> {
+        match self.get_value_deserializer_registry() {
+            Some(registry) => registry.deserialize_from_bytes(b, type_identifier, format),
+            None => {
+                use crate::value::DefaultValueSerializer;
+                Self::Value::deserialize_from_bytes(b, type_identifier, format)
+            }
+        }
+    }
+    /// Deserialize only the given columns of a table loaded from a store, see [deserialize_value](NGEnvironment::deserialize_value)
+    /// and [DefaultValueSerializer::deserialize_projected](crate::value::DefaultValueSerializer::deserialize_projected).
+    fn deserialize_value_projected(
+        &self,
+        b: &[u8],
+        type_identifier: &str,
+        format: &str,
+        columns: &[String],
+    ) -> Result<Self::Value, Error> {
+        match self.get_value_deserializer_registry() {
+            Some(registry) => registry.deserialize_projected(b, type_identifier, format, columns),
+            None => {
+                use crate::value::DefaultValueSerializer;
+                Self::Value::deserialize_projected(b, type_identifier, format, columns)
+            }
+        }
+    }
 }
 
 pub trait EnvRef<E: Environment>: Sized {
@@ -102,22 +149,102 @@ impl<E:NGEnvironment> Clone for NGEnvRef<E> {
 pub struct NGContext<E:NGEnvironment>{
     envref: NGEnvRef<E>,
     store: Arc<Box<dyn Store>>,
+    config: Arc<BTreeMap<String, E::Value>>,
     metadata: Arc<Mutex<MetadataRecord>>,
+    trace_id: Option<String>,
+    outputs: Arc<Mutex<Vec<(String, E::Value)>>>,
+    cwd_key: Arc<Mutex<Option<Key>>>,
+    namespaces: Vec<String>,
 }
 
 
 impl <E:NGEnvironment> NGContext<E> {
     pub async fn new(env: NGEnvRef<E>) -> Self {
-        let store = {
+        let (store, config) = {
             let env = env.0.read().await;
-            env.get_store()
+            (env.get_store(), env.get_config())
         };
         NGContext {
             envref: env,
             store: store,
+            config,
             metadata: Arc::new(Mutex::new(MetadataRecord::new())),
+            trace_id: None,
+            outputs: Arc::new(Mutex::new(Vec::new())),
+            cwd_key: Arc::new(Mutex::new(None)),
+            namespaces: Vec::new(),
         }
     }
+
+    /// Replace the metadata of the context, e.g. to continue with the metadata of an input state
+    pub fn with_metadata(self, metadata: MetadataRecord) -> Self {
+        *self.metadata.lock().unwrap() = metadata;
+        self
+    }
+
+    /// Set the trace (request) id attached to all the log entries of the evaluation
+    /// and passed to the evaluations of the dependencies
+    pub fn with_trace_id(mut self, trace_id: Option<String>) -> Self {
+        self.trace_id = trace_id;
+        self
+    }
+
+    pub fn get_trace_id(&self) -> Option<String> {
+        self.trace_id.clone()
+    }
+
+    /// Set the namespaces in which the commands of the evaluated query are looked up
+    pub fn with_namespaces(mut self, namespaces: Vec<String>) -> Self {
+        self.namespaces = namespaces;
+        self
+    }
+
+    pub fn get_namespaces(&self) -> Vec<String> {
+        self.namespaces.clone()
+    }
+
+    /// Set the current working directory key, i.e. the parent of the last resource loaded by the evaluation
+    pub fn set_cwd_key(&self, key: Option<Key>) {
+        *self.cwd_key.lock().unwrap() = key;
+    }
+
+    pub fn get_cwd_key(&self) -> Option<Key> {
+        self.cwd_key.lock().unwrap().clone()
+    }
+
+    /// Value of a configuration key of the environment (see [NGEnvironment::get_config])
+    pub fn get_config_value(&self, name: &str) -> Option<E::Value> {
+        self.config.get(name).cloned()
+    }
+
+    /// Emit an additional named output of the evaluation besides the resulting value,
+    /// e.g. a chart accompanying a table. When the result is stored under a key,
+    /// the outputs are stored next to it (see [crate::interpreter::output_key]).
+    /// The name is a single key element (e.g. `chart.svg`); emitting the same name again replaces the output.
+    pub fn emit_output(&self, name: &str, value: E::Value) -> Result<(), Error> {
+        if name.is_empty() || name.contains('/') {
+            return Err(Error::general_error(format!(
+                "Invalid output name '{}': a non-empty name without '/' expected",
+                name
+            )));
+        }
+        let mut outputs = self.outputs.lock().unwrap();
+        outputs.retain(|(n, _)| n != name);
+        outputs.push((name.to_owned(), value));
+        Ok(())
+    }
+
+    /// Named outputs emitted so far, see [NGContext::emit_output]
+    pub fn get_outputs(&self) -> Vec<(String, E::Value)> {
+        self.outputs.lock().unwrap().clone()
+    }
+
+    fn log(&self, mut entry: LogEntry) {
+        if let Some(trace_id) = &self.trace_id {
+            entry.with_trace_id(trace_id.clone());
+        }
+        self.metadata.lock().unwrap().add_log_entry(entry);
+    }
 }
 
 impl<E:NGEnvironment> ActionContext<NGEnvRef<E>, E::Value> for NGContext<E>
@@ -140,23 +267,35 @@ impl<E:NGEnvironment> ActionContext<NGEnvRef<E>, E::Value> for NGContext<E>
     fn set_filename(&self, filename: String) {
         self.metadata.lock().unwrap().with_filename(filename);
     }
+    fn set_media_type(&self, media_type: String) {
+        self.metadata.lock().unwrap().with_media_type(media_type);
+    }
+    fn set_attribute(&self, name: &str, value: serde_json::Value) {
+        self.metadata.lock().unwrap().with_attribute(name, value);
+    }
     fn debug(&self, message: &str) {
-        self.metadata.lock().unwrap().debug(message);
+        self.log(LogEntry::debug(message.to_owned()));
     }
     fn info(&self, message: &str) {
-        self.metadata.lock().unwrap().info(message);
+        self.log(LogEntry::info(message.to_owned()));
     }
     fn warning(&self, message: &str) {
-        self.metadata.lock().unwrap().warning(message);
+        self.log(LogEntry::warning(message.to_owned()));
     }
     fn error(&self, message: &str) {
-        self.metadata.lock().unwrap().error(message);
+        self.log(LogEntry::error(message.to_owned()));
+        self.metadata.lock().unwrap().with_status(Status::Error);
     }
     fn clone_context(&self) -> Self {
         NGContext {
             envref: self.clone_payload(),
             store: self.store.clone(),
+            config: self.config.clone(),
             metadata: self.metadata.clone(),
+            trace_id: self.trace_id.clone(),
+            outputs: self.outputs.clone(),
+            cwd_key: self.cwd_key.clone(),
+            namespaces: self.namespaces.clone(),
         }
     }    
 }
@@ -229,6 +368,7 @@ pub trait ContextInterface<E: Environment>{
     fn get_store(&self) -> Arc<Box<dyn Store>>;
     fn get_metadata(&self) -> MetadataRecord;
     fn set_filename(&self, filename: String);
+    fn set_attribute(&self, name: &str, value: serde_json::Value);
     fn debug(&self, message: &str);
     fn info(&self, message: &str);
     fn warning(&self, message: &str);
@@ -243,6 +383,8 @@ pub trait ActionContext<P, V: ValueInterface> {
     fn get_store(&self) -> Arc<Box<dyn Store>>;
     fn get_metadata(&self) -> MetadataRecord;
     fn set_filename(&self, filename: String);
+    fn set_media_type(&self, media_type: String);
+    fn set_attribute(&self, name: &str, value: serde_json::Value);
     fn debug(&self, message: &str);
     fn info(&self, message: &str);
     fn warning(&self, message: &str);
@@ -273,6 +415,9 @@ impl <E: Environment> ContextInterface<E> for Context<<E as Environment>::Enviro
     fn set_filename(&self, filename: String) {
         self.metadata.lock().unwrap().with_filename(filename);
     }
+    fn set_attribute(&self, name: &str, value: serde_json::Value) {
+        self.metadata.lock().unwrap().with_attribute(name, value);
+    }
     fn debug(&self, message: &str) {
         self.metadata.lock().unwrap().debug(message);
     }
@@ -382,8 +527,11 @@ pub struct SimpleNGEnvironment<V: ValueInterface> {
     store: Arc<Box<dyn Store>>,
     #[cfg(feature = "async_store")]
     async_store: Arc<Box<dyn crate::store::AsyncStore>>,
-    //cache: Arc<tokio::sync::RwLock<Box<dyn Cache<V>>>>,
+    cache: Arc<Mutex<Box<dyn Cache<V>>>>,
     command_registry: NGCommandRegistry<NGEnvRef<Self>, V, NGContext<Self>>,
+    value_deserializers: ValueDeserializerRegistry<V>,
+    config: Arc<BTreeMap<String, V>>,
+    metadata_flush_interval: Option<Duration>,
 }
 
 impl<V:ValueInterface> SimpleNGEnvironment<V> {
@@ -391,9 +539,12 @@ impl<V:ValueInterface> SimpleNGEnvironment<V> {
         SimpleNGEnvironment {
             store: Arc::new(Box::new(NoStore)),
             command_registry: NGCommandRegistry::new(),
-//            cache: Arc::new(tokio::sync::RwLock::new(Box::new(NoCache::<V>::new()))),
+            cache: Arc::new(Mutex::new(Box::new(NoCache::<V>::new()))),
             #[cfg(feature = "async_store")]
             async_store: Arc::new(Box::new(crate::store::NoAsyncStore)),
+            value_deserializers: ValueDeserializerRegistry::new(),
+            config: Arc::new(BTreeMap::new()),
+            metadata_flush_interval: None,
         }
     }
     pub fn with_store(&mut self, store: Box<dyn Store>) -> &mut Self {
@@ -406,7 +557,28 @@ impl<V:ValueInterface> SimpleNGEnvironment<V> {
         self
     }
     pub fn with_cache(&mut self, cache: Box<dyn Cache<V>>) -> &mut Self {
-        panic!("SimpleNGEnvironment does not support cache for now");
+        self.cache = Arc::new(Mutex::new(cache));
+        self
+    }
+    /// Registry of deserializers of custom value types, e.g. to register a new type identifier
+    pub fn get_mut_value_deserializer_registry(&mut self) -> &mut ValueDeserializerRegistry<V> {
+        &mut self.value_deserializers
+    }
+    /// Set a configuration value available to the queries (see [NGEnvironment::get_config])
+    pub fn with_config_value(&mut self, name: &str, value: V) -> &mut Self {
+        Arc::make_mut(&mut self.config).insert(name.to_owned(), value);
+        self
+    }
+    /// Periodically write the metadata of running evaluations to the store (see [NGEnvironment::get_metadata_flush_interval])
+    /// The interval must be positive.
+    pub fn with_metadata_flush_interval(&mut self, interval: Duration) -> Result<&mut Self, Error> {
+        if interval.is_zero() {
+            return Err(Error::general_error(
+                "Metadata flush interval must be positive".to_owned(),
+            ));
+        }
+        self.metadata_flush_interval = Some(interval);
+        Ok(self)
     }
     pub fn to_ref(self) -> NGEnvRef<Self> {
         NGEnvRef::new(self)
@@ -437,15 +609,25 @@ impl <V:ValueInterface> NGEnvironment for SimpleNGEnvironment<V> {
 
     fn get_cache(&self) -> Arc<Mutex<Box<dyn Cache<Self::Value>>>>
     {
-        panic!("SimpleNGEnvironment does not support cache for now");
-//        let cache = NoCache::<V>::new();
-//        Arc::new(Mutex::new(Box::new(cache)))
+        self.cache.clone()
     }
 
     #[cfg(feature = "async_store")]
     fn get_async_store(&self) -> Arc<Box<dyn crate::store::AsyncStore>> {
         self.async_store.clone()
     }
+
+    fn get_value_deserializer_registry(&self) -> Option<&ValueDeserializerRegistry<V>> {
+        Some(&self.value_deserializers)
+    }
+
+    fn get_config(&self) -> Arc<BTreeMap<String, V>> {
+        self.config.clone()
+    }
+
+    fn get_metadata_flush_interval(&self) -> Option<Duration> {
+        self.metadata_flush_interval
+    }
 }
 
 mod tests {
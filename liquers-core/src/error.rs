@@ -28,6 +28,13 @@ pub enum ErrorType {
     KeyWriteError,
     UnexpectedError,
     ExecutionError,
+    QueryTooLong,
+    QueryTooComplex,
+    StoreNotConfigured,
+    PermissionDenied,
+    NotADirectory,
+    RangeNotSatisfiable,
+    ReadOnlyStore,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -40,6 +47,10 @@ pub struct Error {
     pub key: Option<String>,
 }
 
+/// Maximal length (in bytes) of a query reported by the errors of queries exceeding the limits,
+/// longer queries are truncated
+pub const MAX_ERROR_QUERY_LENGTH: usize = 256;
+
 impl Error {
     pub fn new(error_type: ErrorType, message: String) -> Self {
         Error {
@@ -129,6 +140,19 @@ impl Error {
             key: None,
         }
     }
+    /// Action refers to a known command, which is not available because a feature is not enabled.
+    pub fn action_requires_feature(action: &ActionRequest, feature: &str) -> Self {
+        Error {
+            error_type: ErrorType::ActionNotRegistered,
+            message: format!(
+                "Command '{}' requires the '{}' feature which is not enabled",
+                action.name, feature
+            ),
+            position: action.position.clone(),
+            query: None,
+            key: None,
+        }
+    }
     pub fn missing_argument(i: usize, name: &str, position: &Position) -> Self {
         Error {
             error_type: ErrorType::ArgumentMissing,
@@ -191,6 +215,43 @@ impl Error {
             key: None,
         }
     }
+    /// Query shortened to at most [MAX_ERROR_QUERY_LENGTH] bytes to be reported in an error
+    fn truncated_query(query: &str) -> String {
+        if query.len() <= MAX_ERROR_QUERY_LENGTH {
+            return query.to_owned();
+        }
+        let mut end = MAX_ERROR_QUERY_LENGTH;
+        while !query.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}...", &query[..end])
+    }
+    /// Query string exceeds the configured maximum length.
+    pub fn query_too_long(query: &str, length: usize, max_length: usize) -> Self {
+        Error {
+            error_type: ErrorType::QueryTooLong,
+            message: format!(
+                "Query length {} exceeds the maximum allowed length {}",
+                length, max_length
+            ),
+            position: Position::unknown(),
+            query: Some(Self::truncated_query(query)),
+            key: None,
+        }
+    }
+    /// Query exceeds one of the configured complexity limits (segments, actions, links).
+    pub fn query_too_complex(query: &str, what: &str, count: usize, max_count: usize) -> Self {
+        Error {
+            error_type: ErrorType::QueryTooComplex,
+            message: format!(
+                "Query has {} {}, maximum allowed is {}",
+                count, what, max_count
+            ),
+            position: Position::unknown(),
+            query: Some(Self::truncated_query(query)),
+            key: None,
+        }
+    }
     pub fn general_error(message: String) -> Self {
         Error {
             error_type: ErrorType::General,
@@ -245,6 +306,49 @@ impl Error {
             key: Some(key.encode()),
         }
     }
+    pub fn key_permission_denied(key: &Key, store_name: &str, message: &(impl Display + ?Sized)) -> Self {
+        Error {
+            error_type: ErrorType::PermissionDenied,
+            message: format!("Key '{}' permission denied by store {}: {}", key, store_name, message),
+            position: Position::unknown(),
+            query: None,
+            key: Some(key.encode()),
+        }
+    }
+    pub fn key_not_a_directory(key: &Key, store_name: &str) -> Self {
+        Error {
+            error_type: ErrorType::NotADirectory,
+            message: format!("Key '{}' is not a directory in store {}", key, store_name),
+            position: Position::unknown(),
+            query: None,
+            key: Some(key.encode()),
+        }
+    }
+    /// Store read error classified by the kind of the I/O error:
+    /// missing file, permission denied and not a directory have their own error types,
+    /// any other I/O error is a [ErrorType::KeyReadError].
+    pub fn key_read_io_error(key: &Key, store_name: &str, error: &std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::NotFound => Error::key_not_found(key),
+            _ => Error::key_io_error(key, store_name, error)
+                .unwrap_or_else(|| Error::key_read_error(key, store_name, error)),
+        }
+    }
+    /// Store write error classified by the kind of the I/O error (see [Error::key_read_io_error]).
+    /// A missing file (e.g. parent directory) is reported as a [ErrorType::KeyWriteError].
+    pub fn key_write_io_error(key: &Key, store_name: &str, error: &std::io::Error) -> Self {
+        Error::key_io_error(key, store_name, error)
+            .unwrap_or_else(|| Error::key_write_error(key, store_name, error))
+    }
+    fn key_io_error(key: &Key, store_name: &str, error: &std::io::Error) -> Option<Self> {
+        match error.kind() {
+            std::io::ErrorKind::PermissionDenied => {
+                Some(Error::key_permission_denied(key, store_name, error))
+            }
+            std::io::ErrorKind::NotADirectory => Some(Error::key_not_a_directory(key, store_name)),
+            _ => None,
+        }
+    }
     pub fn key_read_error(key: &Key, store_name:&str, message: &(impl Display + ?Sized)) -> Self {
         Error {
             error_type: ErrorType::KeyReadError,
@@ -263,6 +367,41 @@ impl Error {
             key: Some(key.encode()),
         }
     }
+    /// Requested byte range (start and exclusive end) lies outside of the data of the given length.
+    pub fn range_not_satisfiable(key: &Key, start: u64, end: Option<u64>, length: u64) -> Self {
+        let range = end.map_or(format!("{}-", start), |end| format!("{}-{}", start, end));
+        Error {
+            error_type: ErrorType::RangeNotSatisfiable,
+            message: format!(
+                "Range {} of key '{}' not satisfiable, data length is {}",
+                range, key, length
+            ),
+            position: Position::unknown(),
+            query: None,
+            key: Some(key.encode()),
+        }
+    }
+    /// Modification of a key in a store that only allows reading.
+    /// The key is reported as the query of the error too, so that it is visible to the clients.
+    pub fn read_only_store(key: &Key, store_name: &str) -> Self {
+        Error {
+            error_type: ErrorType::ReadOnlyStore,
+            message: format!("Key '{}' can't be modified, store {} is read-only", key, store_name),
+            position: Position::unknown(),
+            query: Some(key.encode()),
+            key: Some(key.encode()),
+        }
+    }
+    /// Store operation attempted in an environment where no store has been configured.
+    pub fn store_not_configured(key: &Key) -> Self {
+        Error {
+            error_type: ErrorType::StoreNotConfigured,
+            message: format!("No store configured in environment (key '{}')", key),
+            position: Position::unknown(),
+            query: None,
+            key: Some(key.encode()),
+        }
+    }
     pub fn execution_error(message: String) -> Self {
         Error {
             error_type: ErrorType::ExecutionError,
@@ -7,10 +7,11 @@ use crate::context::{
     NGEnvironment,
 };
 use crate::error::Error;
+use crate::metadata::{MetadataRecord, Status};
 use crate::plan::{Plan, PlanBuilder, Step};
-use crate::query::TryToQuery;
+use crate::query::{ActionRequest, Key, Query, TryToQuery};
 use crate::state::State;
-use crate::value::ValueInterface;
+use crate::value::{DefaultValueSerializer, ValueInterface};
 use futures::future::{BoxFuture, FutureExt};
 
 pub struct PlanInterpreter<ER: EnvRef<E>, E: Environment> {
@@ -104,9 +105,7 @@ impl<ER: EnvRef<E>, E: Environment<EnvironmentReference = ER>> PlanInterpreter<E
         match step {
             crate::plan::Step::GetResource(key) => {
                 let store = self.environment.get_store();
-                let (data, metadata) = store
-                    .get(&key)
-                    .map_err(|e| Error::general_error(format!("Store error: {}", e)))?; // TODO: use store error type - convert to Error
+                let (data, metadata) = store.get(&key)?;
                 let value = <<E as Environment>::Value as ValueInterface>::from_bytes(data);
                 return Ok(State::new().with_data(value).with_metadata(metadata));
             }
@@ -252,10 +251,7 @@ impl<ER: EnvRef<E>, E: Environment<EnvironmentReference = ER>> AsyncPlanInterpre
         match step {
             crate::plan::Step::GetResource(key) => {
                 let store = self.environment.get_async_store();
-                let (data, metadata) = store
-                    .get(&key)
-                    .await
-                    .map_err(|e| Error::general_error(format!("Store error: {}", e)))?; // TODO: use store error type - convert to Error
+                let (data, metadata) = store.get(&key).await?;
                 let value = <<E as Environment>::Value as ValueInterface>::from_bytes(data);
                 return Ok(State::new().with_data(value).with_metadata(metadata));
             }
@@ -310,6 +306,8 @@ pub struct NGPlanInterpreter<E: NGEnvironment> {
     plan: Option<Plan>,
     environment: NGEnvRef<E>,
     step_number: usize,
+    trace_id: Option<String>,
+    outputs: Vec<(String, E::Value)>,
     //state: Option<State<E::Value>>,
 }
 
@@ -320,16 +318,28 @@ impl<E: NGEnvironment> NGPlanInterpreter<E> {
             plan: None,
             environment,
             step_number: 0,
+            trace_id: None,
+            outputs: Vec::new(),
             //state: None,
         }
     }
     pub fn with_plan(&mut self, plan: Plan) -> &mut Self {
-        println!("with plan {:?}", plan);
         self.plan = Some(plan);
         self.step_number = 0;
         self
     }
 
+    /// Set the trace (request) id of the evaluation, see [NGContext::with_trace_id]
+    pub fn with_trace_id(&mut self, trace_id: Option<String>) -> &mut Self {
+        self.trace_id = trace_id;
+        self
+    }
+
+    /// Named outputs emitted by the commands of the last evaluation, see [NGContext::emit_output]
+    pub fn outputs(&self) -> &[(String, E::Value)] {
+        &self.outputs
+    }
+
     pub async fn set_query<Q: TryToQuery>(&mut self, query: Q) -> Result<(), Error> {
         let query = query.try_to_query()?;
         let plan = {
@@ -357,6 +367,12 @@ impl<E: NGEnvironment> NGPlanInterpreter<E> {
             if self.plan.is_none() {
                 Err(Error::general_error("No plan".to_string()))
             } else {
+                let namespaces = self
+                    .plan
+                    .as_ref()
+                    .and_then(|plan| crate::plan::query_namespaces(&plan.query).ok())
+                    .unwrap_or_default();
+                let context = context.with_namespaces(namespaces);
                 let mut state = input_state;
                 for i in 0..self.len() {
                     let step = self.get_step(i)?.clone();
@@ -366,6 +382,7 @@ impl<E: NGEnvironment> NGPlanInterpreter<E> {
                         async move { Self::do_step(envref, step, state, ctx).await }.await?;
                     state = output_state;
                 }
+                self.outputs = context.get_outputs();
                 Ok(state)
             }
         }
@@ -373,9 +390,42 @@ impl<E: NGEnvironment> NGPlanInterpreter<E> {
         .await
     }
 
+    /// Apply the plan to an input state.
+    /// The commands see the complete metadata of the input state. Unlike [NGPlanInterpreter::apply]
+    /// with a fresh context, the attributes of the input are carried into the result metadata
+    /// and the query of the input (with its dependencies) is recorded in the dependencies.
+    /// The result metadata has the query of the applied plan.
+    pub async fn apply_state(
+        &mut self,
+        input_state: State<<E as NGEnvironment>::Value>,
+    ) -> Result<State<<E as NGEnvironment>::Value>, Error> {
+        let mut metadata = MetadataRecord::new();
+        if let Some(plan) = self.plan.as_ref() {
+            metadata.with_query(plan.query.clone());
+        }
+        if let Some(input) = input_state.metadata.metadata_record() {
+            metadata.attributes = input.attributes;
+            if !input.query.is_empty() {
+                metadata.dependencies.push(input.query.encode());
+            }
+            for dependency in input.dependencies {
+                if !metadata.dependencies.contains(&dependency) {
+                    metadata.dependencies.push(dependency);
+                }
+            }
+        }
+        let context = NGContext::new(self.environment.clone())
+            .await
+            .with_trace_id(self.trace_id.clone())
+            .with_metadata(metadata);
+        self.apply(context, input_state).await
+    }
+
     pub async fn run(&mut self) -> Result<State<<E as NGEnvironment>::Value>, Error> {
         self.apply(
-            NGContext::new(self.environment.clone()).await,
+            NGContext::new(self.environment.clone())
+                .await
+                .with_trace_id(self.trace_id.clone()),
             Self::initial_state(),
         )
         .await
@@ -414,8 +464,38 @@ impl<E: NGEnvironment> NGPlanInterpreter<E> {
             match step {
                 crate::plan::Step::GetResource(key) => {
                     let store = envref.get_async_store().await;
+                    // A missing resource is made by its recipe if there is one (see Recipe::evaluate)
+                    if !store.contains(&key).await? {
+                        let recipe = crate::recipes::DefaultRecipeProvider::new(envref.clone())
+                            .get_recipe(&key)
+                            .await?;
+                        if let Some(recipe) = recipe {
+                            context.set_cwd_key(Some(key.parent()));
+                            return recipe.evaluate(envref, State::new()).await;
+                        }
+                    }
                     let (data, metadata) = store.get(&key).await?;
-                    let value = <<E as NGEnvironment>::Value as ValueInterface>::from_bytes(data);
+                    // A deserializer registered for the type identifier takes precedence over raw bytes
+                    let deserializer = match metadata.type_identifier() {
+                        Ok(type_identifier) => envref
+                            .0
+                            .read()
+                            .await
+                            .get_value_deserializer_registry()
+                            .and_then(|registry| registry.get(&type_identifier)),
+                        Err(_) => None,
+                    };
+                    let value = match deserializer {
+                        Some(deserializer) => {
+                            let format = metadata
+                                .extension()
+                                .or_else(|| key.extension())
+                                .unwrap_or("b".to_owned());
+                            deserializer(&data, &format)?
+                        }
+                        None => <<E as NGEnvironment>::Value as ValueInterface>::from_bytes(data),
+                    };
+                    context.set_cwd_key(Some(key.parent()));
                     return Ok(State::new().with_data(value).with_metadata(metadata));
                 }
                 crate::plan::Step::GetResourceMetadata(_) => todo!(),
@@ -425,9 +505,13 @@ impl<E: NGEnvironment> NGPlanInterpreter<E> {
                     //                todo!()  //TODO: ! evaluate
 
                     let query = q.clone();
+                    let trace_id = context.get_trace_id();
                     return async move {
-                        let context = NGContext::new(envref.clone()).await;
+                        let context = NGContext::new(envref.clone())
+                            .await
+                            .with_trace_id(trace_id.clone());
                         let mut interpreter = Self::new(envref);
+                        interpreter.with_trace_id(trace_id);
                         interpreter.set_query(query).await?;
                         interpreter.apply(context, Self::initial_state()).await
                     }
@@ -444,13 +528,55 @@ impl<E: NGEnvironment> NGPlanInterpreter<E> {
                     let mut arguments =
                         NGCommandArguments::<<E as NGEnvironment>::Value>::new(parameters.clone());
                     arguments.action_position = position.clone();
-                    let result = {
+                    let command_key = CommandKey::new(realm, ns, action_name);
+                    // Commands with max_concurrency wait for a permit, which is held during the execution
+                    let (semaphore, warn_if_empty, blocking) = {
+                        let env = envref.0.read().await;
+                        let registry = env.get_command_metadata_registry();
+                        let command = registry.get(command_key.clone());
+                        (
+                            registry.command_semaphore(&command_key),
+                            command.is_some_and(|command| command.warn_if_empty),
+                            command.is_some_and(|command| command.blocking),
+                        )
+                    };
+                    let permit = match semaphore {
+                        Some(semaphore) => Some(semaphore.acquire_owned().await.map_err(|e| {
+                            Error::general_error(format!(
+                                "Can't acquire execution permit for command '{}': {}",
+                                action_name, e
+                            ))
+                        })?),
+                        None => None,
+                    };
+                    let result = if blocking {
+                        // Blocking commands run on the blocking thread pool, so that they don't stall the runtime
+                        let envref = envref.clone();
+                        let input_state = input_state.clone();
+                        let context = context.clone_context();
+                        let key = command_key.clone();
+                        // The permit moves to the blocking thread, so it is held until the command finishes
+                        // even if the evaluation is not awaited anymore (e.g. after a recipe timeout)
+                        tokio::task::spawn_blocking(move || {
+                            let _permit = permit;
+                            let env = envref.0.blocking_read();
+                            env.get_command_executor()
+                                .execute(&key, &input_state, &mut arguments, context)
+                        })
+                        .await
+                        .map_err(|e| {
+                            Error::general_error(format!(
+                                "Execution of the blocking command '{}' failed: {}",
+                                action_name, e
+                            ))
+                        })??
+                    } else {
                         #[cfg(not(feature = "tokio_exec"))]
                         {
                             let env = envref.0.read().await;
                             let ce = env.get_command_executor();
                             ce.execute(
-                                &CommandKey::new(realm, ns, action_name),
+                                &command_key,
                                 &input_state,
                                 &mut arguments,
                                 context.clone_context(),
@@ -479,6 +605,15 @@ impl<E: NGEnvironment> NGPlanInterpreter<E> {
                             */
                         }
                     };
+                    if warn_if_empty
+                        && result.collection_len() == Some(0)
+                        && input_state.read().unwrap().collection_len().is_some_and(|n| n > 0)
+                    {
+                        context.warning(&format!(
+                            "Command '{}' produced an empty result from a non-empty input",
+                            action_name
+                        ));
+                    }
 
                     let state = State::<<E as NGEnvironment>::Value>::new()
                         .with_data(result)
@@ -505,6 +640,447 @@ impl<E: NGEnvironment> NGPlanInterpreter<E> {
     }
 }
 
+/// Outcome of warming the cache, see [warm_blocking]
+#[derive(Debug, Default)]
+pub struct WarmReport {
+    /// Queries that were successfully warmed
+    pub warmed: Vec<Query>,
+    /// Queries that failed, with their errors
+    pub failed: Vec<(Query, Error)>,
+}
+
+/// Evaluate the queries and store the results in the cache of the environment,
+/// so that the first requests for them are served from the cache.
+/// Only the requests going through [evaluate_cached] use the warmed results;
+/// [NGPlanInterpreter::evaluate] does not consult the cache and always evaluates the query.
+/// Failures do not stop warming of the remaining queries, they are collected in the report.
+#[cfg(feature = "async_store")]
+pub async fn warm_blocking<E: NGEnvironment>(envref: NGEnvRef<E>, queries: &[Query]) -> WarmReport {
+    let mut report = WarmReport::default();
+    for query in queries {
+        match warm_query(envref.clone(), query).await {
+            Ok(_) => report.warmed.push(query.clone()),
+            Err(e) => report.failed.push((query.clone(), e)),
+        }
+        // Warming runs with a low priority, give way to other tasks between the queries
+        tokio::task::yield_now().await;
+    }
+    report
+}
+
+/// Warm the cache in a background task, see [warm_blocking].
+/// Returns immediately, the handle can be awaited to get the report.
+#[cfg(feature = "async_store")]
+pub fn warm<E: NGEnvironment>(
+    envref: NGEnvRef<E>,
+    queries: Vec<Query>,
+) -> tokio::task::JoinHandle<WarmReport> {
+    tokio::spawn(async move { warm_blocking(envref, &queries).await })
+}
+
+/// Evaluate a query and store the result in the cache of the environment.
+/// Results of volatile plans (see [Plan::is_volatile]) are not cached.
+#[cfg(feature = "async_store")]
+async fn warm_query<E: NGEnvironment>(
+    envref: NGEnvRef<E>,
+    query: &Query,
+) -> Result<State<E::Value>, Error> {
+    let mut pi = NGPlanInterpreter::new(envref.clone());
+    pi.set_query(query).await?;
+    let state = pi.run().await?;
+    let mut metadata = state.metadata.metadata_record().unwrap_or_default();
+    metadata.with_query(query.clone()).with_status(Status::Ready);
+    let state = state.with_metadata(metadata.into());
+    if pi.plan.as_ref().is_some_and(|plan| plan.is_volatile()) {
+        return Ok(state);
+    }
+    let cache = envref.0.read().await.get_cache();
+    let mut cache = cache.lock().unwrap();
+    cache.set(state.clone())?;
+    Ok(state)
+}
+
+/// Evaluate a query using the cache of the environment.
+/// A ready cached result is returned without evaluation (or loaded from the store, if the cached result
+/// has been stored under a key), otherwise (e.g. if the result has been invalidated by [invalidate_prefix])
+/// the query is evaluated and the result is cached, unless the query executes a volatile command.
+#[cfg(feature = "async_store")]
+pub async fn evaluate_cached<E: NGEnvironment>(
+    envref: NGEnvRef<E>,
+    query: &Query,
+) -> Result<State<E::Value>, Error> {
+    let stored_key = {
+        let cache = envref.0.read().await.get_cache();
+        let cache = cache.lock().unwrap();
+        match cache
+            .get_metadata(query)
+            .and_then(|metadata| metadata.metadata_record())
+        {
+            Some(record) if record.status == Status::Ready => {
+                if let Ok(state) = cache.get(query) {
+                    return Ok(state);
+                }
+                record.key
+            }
+            _ => None,
+        }
+    };
+    // The cached binary may have been evicted or only the index may have been imported
+    // (see [crate::cache::import_cache]); the result is then loaded from the store if possible,
+    // otherwise the query is evaluated again
+    if let Some(key) = stored_key {
+        if let Ok(state) = load_stored(envref.clone(), &key).await {
+            let cache = envref.0.read().await.get_cache();
+            let mut cache = cache.lock().unwrap();
+            cache.set(state.clone())?;
+            return Ok(state);
+        }
+    }
+    warm_query(envref, query).await
+}
+
+/// Evaluate a query ignoring the cached result (and the result stored under its key),
+/// e.g. to force recomputation after a change the cache doesn't know about.
+/// The fresh result still replaces the result in the cache, so that subsequent [evaluate_cached] calls use it.
+#[cfg(feature = "async_store")]
+pub async fn evaluate_fresh<E: NGEnvironment>(
+    envref: NGEnvRef<E>,
+    query: &Query,
+) -> Result<State<E::Value>, Error> {
+    warm_query(envref, query).await
+}
+
+/// Apply an action to the result of a base query (e.g. a follow-up action on a displayed result)
+/// by evaluating the combined query (see [Query::with_action]). The base query may be a pure resource
+/// or a transformation; it is not modified and its result stays available under the base query.
+#[cfg(feature = "async_store")]
+pub async fn apply_action<E: NGEnvironment>(
+    envref: NGEnvRef<E>,
+    base: &Query,
+    action: ActionRequest,
+) -> Result<State<E::Value>, Error> {
+    let query = base.with_action(action);
+    let mut pi = NGPlanInterpreter::new(envref);
+    pi.evaluate(&query).await
+}
+
+/// Invalidate the cached results affected by a change of the resources under a key prefix,
+/// e.g. when the resources were updated externally.
+/// A cached result is affected if its key or any of the resource dependencies of its query
+/// (see [Plan::resource_dependencies], links in the action parameters included) falls under the prefix;
+/// if the dependencies can't be determined, the result is invalidated as well.
+/// Invalidation is transitive: the results depending on the key of an invalidated result
+/// are invalidated too. Invalidation drops the cached data and sets the status
+/// to [Status::Expired]. Results still being evaluated (see [Status::is_in_flight]) are left alone.
+/// Returns the invalidated queries.
+#[cfg(feature = "async_store")]
+pub async fn invalidate_prefix<E: NGEnvironment>(
+    envref: NGEnvRef<E>,
+    prefix: &Key,
+) -> Result<Vec<Query>, Error> {
+    let env = envref.0.read().await;
+    let cache = env.get_cache();
+    let records = {
+        let cache = cache.lock().unwrap();
+        cache
+            .keys()
+            .into_iter()
+            .map(|query| {
+                let record = cache
+                    .get_metadata(&query)
+                    .and_then(|metadata| metadata.metadata_record());
+                (query, record)
+            })
+            .collect::<Vec<_>>()
+    };
+    // Dependency graph of the cached results, built without holding the cache lock:
+    // the key the result is stored under and the resource dependencies (None if they can't be determined)
+    let nodes = records
+        .iter()
+        .map(|(query, record)| {
+            let dependencies = PlanBuilder::new(query.clone(), env.get_command_metadata_registry())
+                .build()
+                .ok()
+                .map(|plan| plan.resource_dependencies());
+            (record.as_ref().and_then(|r| r.key.clone()), dependencies)
+        })
+        .collect::<Vec<_>>();
+
+    // Results affected by the changed keys, starting with the prefix and continuing with the keys of the affected results
+    let mut affected = std::collections::HashSet::new();
+    let mut changed = vec![prefix.clone()];
+    while let Some(changed_key) = changed.pop() {
+        for (i, (key, dependencies)) in nodes.iter().enumerate() {
+            if affected.contains(&i) || records[i].1.as_ref().is_some_and(|r| r.status.is_in_flight()) {
+                continue;
+            }
+            let is_affected = key.as_ref().is_some_and(|key| key.has_key_prefix(&changed_key))
+                || dependencies.as_ref().map_or(true, |dependencies| {
+                    dependencies.iter().any(|key| key.has_key_prefix(&changed_key))
+                });
+            if is_affected {
+                affected.insert(i);
+                changed.extend(key.clone());
+            }
+        }
+    }
+
+    let mut cache = cache.lock().unwrap();
+    let mut invalidated = Vec::new();
+    for (i, (query, _)) in records.into_iter().enumerate() {
+        if !affected.contains(&i) {
+            continue;
+        }
+        // The result may have changed since the snapshot
+        let record = cache
+            .get_metadata(&query)
+            .and_then(|metadata| metadata.metadata_record());
+        if record.as_ref().is_some_and(|r| r.status.is_in_flight()) {
+            continue;
+        }
+        cache.remove(&query)?;
+        if let Some(mut record) = record {
+            record.with_status(Status::Expired);
+            cache.set_metadata(&record.into())?;
+        }
+        invalidated.push(query);
+    }
+    Ok(invalidated)
+}
+
+/// Check if the result stored under the key is stale with respect to the resources the query depends on.
+/// The stored result is stale if it does not exist, if its updated timestamp is not known,
+/// if any of the dependencies has been updated later (or has an unknown updated timestamp)
+/// or if the query is volatile (see [Plan::is_volatile]).
+#[cfg(feature = "async_store")]
+pub async fn is_stale<E: NGEnvironment>(
+    envref: NGEnvRef<E>,
+    query: &Query,
+    key: &Key,
+) -> Result<bool, Error> {
+    let plan = {
+        let env = envref.0.read().await;
+        PlanBuilder::new(query.clone(), env.get_command_metadata_registry()).build()?
+    };
+    if plan.is_volatile() {
+        return Ok(true);
+    }
+    let store = envref.get_async_store().await;
+    if !store.contains(key).await? {
+        return Ok(true);
+    }
+    let updated = match store.get_metadata(key).await?.updated() {
+        Some(updated) => updated,
+        None => return Ok(true),
+    };
+    for dependency in plan.resource_dependencies() {
+        match store.get_metadata(&dependency).await?.updated() {
+            Some(dependency_updated) if dependency_updated <= updated => (),
+            _ => return Ok(true),
+        }
+    }
+    Ok(false)
+}
+
+/// Key of a named output (see [NGContext::emit_output]) of a result stored under `key`.
+/// Outputs are stored in a folder named as the key without the extension,
+/// e.g. the output `chart.svg` of `reports/summary.json` is stored under `reports/summary/chart.svg`.
+pub fn output_key(key: &Key, name: &str) -> Key {
+    match key.filename() {
+        Some(filename) => key.parent().join(filename.base_name()).join(name),
+        None => Key::new().join(name),
+    }
+}
+
+/// Key of the progress sidecar of a result stored under `key`, e.g. `reports/summary.progress.json`
+/// for `reports/summary.json`. See [NGEnvironment::get_metadata_flush_interval].
+pub fn progress_key(key: &Key) -> Key {
+    match key.filename() {
+        Some(filename) => key
+            .parent()
+            .join(format!("{}.progress.json", filename.base_name())),
+        None => Key::new().join("progress.json"),
+    }
+}
+
+/// Write the current metadata of an evaluation (as JSON) into the progress sidecar of the key
+#[cfg(feature = "async_store")]
+async fn write_progress<E: NGEnvironment>(
+    store: &dyn crate::store::AsyncStore,
+    context: &NGContext<E>,
+    query: &Query,
+    key: &Key,
+) -> Result<(), Error> {
+    let mut metadata = context.get_metadata();
+    if metadata.status != Status::Error {
+        metadata.with_status(Status::Evaluation);
+    }
+    metadata
+        .with_query(query.clone())
+        .with_key(key.clone())
+        .with_updated_now();
+    let data = serde_json::to_vec_pretty(&metadata).map_err(|e| {
+        Error::general_error(format!("Can't serialize the progress of {}: {}", key, e))
+    })?;
+    let progress = progress_key(key);
+    let mut progress_metadata = MetadataRecord::new();
+    progress_metadata
+        .with_key(progress.clone())
+        .with_media_type("application/json".to_owned())
+        .with_status(Status::SideEffect)
+        .with_updated_now();
+    store.set(&progress, &data, &progress_metadata.into()).await
+}
+
+/// Load and deserialize a result stored under the key.
+/// The format is determined by the stored metadata or the key extension.
+#[cfg(feature = "async_store")]
+async fn load_stored<E: NGEnvironment>(
+    envref: NGEnvRef<E>,
+    key: &Key,
+) -> Result<State<E::Value>, Error> {
+    load_stored_projected(envref, key, None).await
+}
+
+/// Load only the given columns of a table stored under the key (schema projection).
+/// Formats supporting projection read only the requested columns, other formats are loaded fully and then projected
+/// (see [DefaultValueSerializer::deserialize_projected](crate::value::DefaultValueSerializer::deserialize_projected)).
+/// A requested column missing in the table is an error.
+#[cfg(feature = "async_store")]
+pub async fn load_projected<E: NGEnvironment>(
+    envref: NGEnvRef<E>,
+    key: &Key,
+    columns: &[String],
+) -> Result<State<E::Value>, Error> {
+    load_stored_projected(envref, key, Some(columns)).await
+}
+
+#[cfg(feature = "async_store")]
+async fn load_stored_projected<E: NGEnvironment>(
+    envref: NGEnvRef<E>,
+    key: &Key,
+    columns: Option<&[String]>,
+) -> Result<State<E::Value>, Error> {
+    let store = envref.get_async_store().await;
+    let (data, metadata) = store.get(key).await?;
+    let format = metadata
+        .extension()
+        .or_else(|| key.extension())
+        .unwrap_or("b".to_owned());
+    let type_identifier = metadata.type_identifier()?;
+    let env = envref.0.read().await;
+    let value = match columns {
+        Some(columns) => env.deserialize_value_projected(&data, &type_identifier, &format, columns)?,
+        None => env.deserialize_value(&data, &type_identifier, &format)?,
+    };
+    Ok(State::new().with_data(value).with_metadata(metadata))
+}
+
+/// Make-style evaluation of a query, which result is stored under a key.
+/// If the stored result is up to date (see [is_stale]), it is returned without evaluation,
+/// otherwise the query is evaluated and the result is stored under the key.
+/// The format is determined by the key extension (or the default extension of the value).
+/// Named outputs emitted by the evaluation are stored under the keys given by [output_key].
+/// If the environment has a [metadata flush interval](NGEnvironment::get_metadata_flush_interval),
+/// the metadata of the running evaluation are periodically written to the [progress sidecar](progress_key),
+/// which is removed when the result is stored (and kept with the error if the evaluation fails).
+/// The trace id (e.g. the request id) is attached to the log entries of the evaluation,
+/// including those written to the progress sidecar.
+#[cfg(feature = "async_store")]
+pub async fn evaluate_if_stale<E: NGEnvironment>(
+    envref: NGEnvRef<E>,
+    query: &Query,
+    key: &Key,
+    trace_id: Option<String>,
+) -> Result<State<E::Value>, Error> {
+    let store = envref.get_async_store().await;
+    if !is_stale(envref.clone(), query, key).await? {
+        return load_stored(envref, key).await;
+    }
+
+    let flush_interval = envref.0.read().await.get_metadata_flush_interval();
+    if flush_interval.is_some_and(|interval| interval.is_zero()) {
+        return Err(Error::general_error(
+            "Metadata flush interval must be positive".to_owned(),
+        ));
+    }
+    let mut pi = NGPlanInterpreter::new(envref.clone());
+    pi.with_trace_id(trace_id.clone());
+    pi.set_query(query).await?;
+    let context = NGContext::new(envref.clone()).await.with_trace_id(trace_id);
+    let flush = flush_interval.map(|interval| {
+        let (store, context) = (store.clone(), context.clone_context());
+        let (query, key) = (query.clone(), key.clone());
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                // Progress is best-effort, a failed write must not fail the evaluation
+                let _ = write_progress(&**store, &context, &query, &key).await;
+            }
+        })
+    });
+    if flush.is_some() {
+        // Let the flush task start before the evaluation, which may block this worker thread
+        tokio::task::yield_now().await;
+    }
+    let result = pi
+        .apply(context.clone_context(), NGPlanInterpreter::<E>::initial_state())
+        .await;
+    if let Some(flush) = flush {
+        flush.abort();
+        let _ = flush.await;
+        match &result {
+            Ok(_) => {
+                let _ = store.remove(&progress_key(key)).await;
+            }
+            Err(e) => {
+                context.error(&e.to_string());
+                let _ = write_progress(&**store, &context, query, key).await;
+            }
+        }
+    }
+    let state = result?;
+    let format = key
+        .extension()
+        .unwrap_or_else(|| state.read().unwrap().default_extension().to_string());
+    let data = state.as_bytes(&format)?;
+    let mut metadata = state.metadata.metadata_record().unwrap_or_default();
+    metadata
+        .with_query(query.clone())
+        .with_key(key.clone())
+        .with_type_identifier(state.read().unwrap().identifier().to_string())
+        .with_status(Status::Ready)
+        .with_updated_now();
+    let metadata: crate::metadata::Metadata = metadata.into();
+    store.set(key, &data, &metadata).await?;
+    if !pi.plan.as_ref().is_some_and(|plan| plan.is_volatile()) {
+        // The stored result is cached as well, so that it is part of the cache index (see [crate::cache::export_cache]).
+        // Caching is best-effort, the environment may not support it; volatile results are not cached.
+        let cache = envref.0.read().await.get_cache();
+        let _ = cache
+            .lock()
+            .unwrap()
+            .set(state.clone().with_metadata(metadata.clone()));
+    }
+    for (name, value) in pi.outputs() {
+        let output = output_key(key, name);
+        let format = output
+            .extension()
+            .unwrap_or_else(|| value.default_extension().to_string());
+        let mut output_metadata = MetadataRecord::new();
+        output_metadata
+            .with_query(query.clone())
+            .with_key(output.clone())
+            .with_type_identifier(value.identifier().to_string())
+            .with_status(Status::Ready)
+            .with_updated_now();
+        store
+            .set(&output, &value.as_bytes(&format)?, &output_metadata.into())
+            .await?;
+    }
+    Ok(state.with_metadata(metadata))
+}
+
 #[cfg(test)]
 mod tests {
     use std::cell::RefCell;
@@ -524,7 +1100,7 @@ mod tests {
     use crate::context::SimpleEnvironment;
     use crate::context::StatEnvRef;
     use crate::metadata::Metadata;
-    use crate::parse::parse_key;
+    use crate::parse::{parse_key, parse_query};
     use crate::query::Key;
     use crate::value::{Value, ValueInterface};
     pub struct TestExecutor;
@@ -1010,6 +1586,966 @@ mod tests {
         );
         Ok(())
     }
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_apply_state_keeps_metadata() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::metadata::MetadataRecord;
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        {
+            let cr = env.get_mut_command_executor();
+            fn title(state: &State<Value>) -> Result<Value, Error> {
+                let title = state
+                    .metadata
+                    .get_attribute("title")
+                    .ok_or(Error::general_error("No title".to_string()))?;
+                Ok(Value::from_string(format!(
+                    "{}: {}",
+                    title.as_str().unwrap(),
+                    state.try_into_string()?
+                )))
+            }
+            ng_register_command!(cr, title(state));
+        }
+        let mut metadata = MetadataRecord::new();
+        metadata
+            .with_query(parse_query("input.txt")?)
+            .with_key(crate::parse::parse_key("input.txt")?)
+            .with_attribute("title", serde_json::json!("Report"))
+            .info("input loaded");
+        let input = State::new()
+            .with_data(Value::from("content"))
+            .with_metadata(metadata.into());
+
+        let mut pi = NGPlanInterpreter::new(env.to_ref());
+        pi.set_query("title").await?;
+        let state = pi.apply_state(input).await?;
+        assert_eq!(state.try_into_string()?, "Report: content");
+        assert_eq!(
+            state.metadata.get_attribute("title"),
+            Some(serde_json::json!("Report"))
+        );
+        assert_eq!(state.metadata.query()?.encode(), "title");
+        let record = state.metadata.metadata_record().unwrap();
+        assert!(record.key.is_none());
+        assert!(!record.log.iter().any(|e| e.message == "input loaded"));
+        assert_eq!(record.dependencies, vec!["input.txt"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_enum_argument() -> Result<(), Error> {
+        use crate::command_metadata::ArgumentType;
+        use crate::context::SimpleNGEnvironment;
+        use crate::error::ErrorType;
+
+        crate::ng_enum_argument!(
+            /// Sort order
+            enum Order {
+                Ascending = "asc",
+                Descending = "desc",
+            }
+        );
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        {
+            let cr = env.get_mut_command_executor();
+            fn sort(_state: &State<Value>, order: Order) -> Result<Value, Error> {
+                Ok(Value::from_string(format!("{:?}", order)))
+            }
+            ng_register_command!(cr, sort(state, choice order: Order));
+        }
+        let envref = env.to_ref();
+        {
+            let env = envref.0.read().await;
+            let command = env.get_command_metadata_registry().get("sort").unwrap();
+            match &command.arguments[0].argument_type {
+                ArgumentType::Enum(e) => {
+                    let aliases = e.values.iter().map(|v| v.alias.as_str()).collect::<Vec<_>>();
+                    assert_eq!(aliases, vec!["asc", "desc"]);
+                    assert!(!e.others_allowed);
+                }
+                _ => panic!("Enum argument expected"),
+            }
+        }
+        let state = NGPlanInterpreter::new(envref.clone()).evaluate("sort-desc").await?;
+        assert_eq!(state.try_into_string()?, "Descending");
+        assert_eq!(Order::from_alias("asc"), Some(Order::Ascending));
+        assert_eq!(Order::Descending.alias(), "desc");
+
+        let err = NGPlanInterpreter::new(envref)
+            .evaluate("sort-sideways")
+            .await
+            .unwrap_err();
+        assert_eq!(err.error_type, ErrorType::ConversionError);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_command_returning_state() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::error::ErrorType;
+        use crate::metadata::MetadataRecord;
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        {
+            let cr = env.get_mut_command_executor();
+            fn plot(_state: &State<Value>) -> Result<State<Value>, Error> {
+                let mut metadata = MetadataRecord::new();
+                metadata.set_extension("png");
+                metadata.with_attribute("title", serde_json::json!("Plot"));
+                Ok(State::new()
+                    .with_data(Value::Bytes(vec![0x89, b'P', b'N', b'G']))
+                    .with_metadata(metadata.into()))
+            }
+            fn broken(_state: &State<Value>) -> Result<State<Value>, Error> {
+                let mut metadata = MetadataRecord::new();
+                metadata
+                    .with_key(parse_key("plots/plot.png").unwrap())
+                    .with_status(Status::Error)
+                    .with_message("Rendering failed".to_owned());
+                Ok(State::new().with_metadata(metadata.into()))
+            }
+            ng_register_command!(cr, plot(state) -> state);
+            ng_register_command!(cr, broken(state) -> state);
+        }
+        let envref = env.to_ref();
+        let state = NGPlanInterpreter::new(envref.clone()).evaluate("plot").await?;
+        assert_eq!(state.extension(), "png");
+        let record = state.metadata.metadata_record().unwrap();
+        assert_eq!(record.media_type, "image/png");
+        assert_eq!(record.get_attribute("title"), Some(&serde_json::json!("Plot")));
+        assert!(matches!(&*state.read().unwrap(), Value::Bytes(b) if b.len() == 4));
+
+        let err = NGPlanInterpreter::new(envref)
+            .evaluate("broken")
+            .await
+            .unwrap_err();
+        assert!(err.message.contains("Rendering failed"));
+        assert_eq!(err.error_type, ErrorType::ExecutionError);
+        assert_eq!(err.key, Some("plots/plot.png".to_owned()));
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_trace_id_in_dependency_logs() -> Result<(), Error> {
+        use crate::context::{ActionContext, NGContext, SimpleNGEnvironment};
+        use crate::plan::{Plan, Step};
+
+        type Env = SimpleNGEnvironment<Value>;
+        let mut env = Env::new();
+        {
+            let cr = env.get_mut_command_executor();
+            fn note(_state: &State<Value>, context: NGContext<Env>) -> Result<Value, Error> {
+                context.info("dependency evaluated");
+                Ok(Value::from("note"))
+            }
+            ng_register_command!(cr, note(state, context));
+        }
+        let mut plan = Plan::new();
+        plan.steps.push(Step::Evaluate(parse_query("note")?));
+
+        let mut pi = NGPlanInterpreter::new(env.to_ref());
+        pi.with_plan(plan).with_trace_id(Some("request-42".to_owned()));
+        let state = pi.run().await?;
+        assert_eq!(state.try_into_string()?, "note");
+        let record = state.metadata.metadata_record().unwrap();
+        let entry = record
+            .log
+            .iter()
+            .find(|e| e.message == "dependency evaluated")
+            .unwrap();
+        assert_eq!(entry.trace_id.as_deref(), Some("request-42"));
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_warm() -> Result<(), Error> {
+        use crate::cache::{MemoryBinCache, SerializingCache};
+        use crate::context::SimpleNGEnvironment;
+        use crate::metadata::Status;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static ANSWERS: AtomicUsize = AtomicUsize::new(0);
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        env.with_cache(Box::new(SerializingCache::new(MemoryBinCache::new())));
+        {
+            let cr = env.get_mut_command_executor();
+            fn greet(_state: &State<Value>, who: String) -> Result<Value, Error> {
+                Ok(Value::from_string(format!("Hello {}!", who)))
+            }
+            fn answer(_state: &State<Value>) -> Result<Value, Error> {
+                ANSWERS.fetch_add(1, Ordering::SeqCst);
+                Ok(Value::I64(42))
+            }
+            ng_register_command!(cr, greet(state, who: String));
+            ng_register_command!(cr, answer(state));
+        }
+        let envref = env.to_ref();
+        let queries = vec![
+            parse_query("greet-world")?,
+            parse_query("unknown")?,
+            parse_query("greet-alice")?,
+        ];
+        let report = warm(envref.clone(), queries).await.unwrap();
+        assert_eq!(report.warmed.len(), 2);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, parse_query("unknown")?);
+
+        {
+            let cache = envref.0.read().await.get_cache();
+            let cache = cache.lock().unwrap();
+            for query in report.warmed.iter() {
+                assert!(cache.contains(query));
+                let metadata = cache.get_metadata(query).unwrap();
+                assert_eq!(metadata.metadata_record().unwrap().status, Status::Ready);
+            }
+            assert!(!cache.contains(&parse_query("unknown")?));
+        }
+
+        // Only evaluate_cached is served from the warmed cache
+        let query = parse_query("answer")?;
+        let report = warm_blocking(envref.clone(), std::slice::from_ref(&query)).await;
+        assert_eq!(report.warmed.len(), 1);
+        assert_eq!(ANSWERS.load(Ordering::SeqCst), 1);
+        let state = evaluate_cached(envref.clone(), &query).await?;
+        assert_eq!(state.read().unwrap().try_into_i64()?, 42);
+        assert_eq!(ANSWERS.load(Ordering::SeqCst), 1);
+        let state = NGPlanInterpreter::new(envref.clone()).evaluate(&query).await?;
+        assert_eq!(state.read().unwrap().try_into_i64()?, 42);
+        assert_eq!(ANSWERS.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_duration_and_datetime_arguments() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::error::ErrorType;
+        use crate::query::encode_token;
+        use chrono::{DateTime, Utc};
+        use std::time::Duration;
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        {
+            let cr = env.get_mut_command_executor();
+            fn window(
+                _state: &State<Value>,
+                length: Duration,
+                since: DateTime<Utc>,
+            ) -> Result<Value, Error> {
+                Ok(Value::from_string(format!(
+                    "{}s since {}",
+                    length.as_secs(),
+                    since.to_rfc3339()
+                )))
+            }
+            ng_register_command!(cr, window(state, length: Duration, since: DateTime<Utc>));
+        }
+        let envref = env.to_ref();
+        let query = format!("window-1h30m-{}", encode_token("2024-01-31T13:00:00+01:00"));
+        let state = NGPlanInterpreter::new(envref.clone()).evaluate(query.as_str()).await?;
+        assert_eq!(
+            state.try_into_string()?,
+            "5400s since 2024-01-31T12:00:00+00:00"
+        );
+
+        let err = NGPlanInterpreter::new(envref)
+            .evaluate("window-1h30x-now")
+            .await
+            .unwrap_err();
+        assert_eq!(err.error_type, ErrorType::ConversionError);
+        assert!(!err.position.is_unknown());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_vector_and_tuple_arguments() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::error::ErrorType;
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        {
+            let cr = env.get_mut_command_executor();
+            fn total(_state: &State<Value>, xs: Vec<f64>) -> Result<Value, Error> {
+                Ok(Value::from(xs.iter().sum::<f64>()))
+            }
+            fn shift(_state: &State<Value>, label: String, by: (f64, f64)) -> Result<Value, Error> {
+                Ok(Value::from_string(format!("{} {} {}", label, by.0, by.1)))
+            }
+            ng_register_command!(cr, total(state, xs: Vec<f64>));
+            ng_register_command!(cr, shift(state, label: String, by: (f64, f64)));
+
+            // A tuple takes all the remaining parameters, so it must be the last argument
+            fn shift_first(_state: &State<Value>, by: (f64, f64), label: String) -> Result<Value, Error> {
+                Ok(Value::from_string(format!("{} {} {}", label, by.0, by.1)))
+            }
+            let mut register = || -> Result<(), Error> {
+                ng_register_command!(cr, shift_first(state, by: (f64, f64), label: String));
+                Ok(())
+            };
+            let err = register().unwrap_err();
+            assert!(err.message.contains("follows the multiple argument 'by'"));
+        }
+        let envref = env.to_ref();
+        let state = NGPlanInterpreter::new(envref.clone()).evaluate("total-1.5-2-0.5").await?;
+        assert_eq!(state.read().unwrap().try_into_f64()?, 4.0);
+        let state = NGPlanInterpreter::new(envref.clone()).evaluate("total").await?;
+        assert_eq!(state.read().unwrap().try_into_f64()?, 0.0);
+        let state = NGPlanInterpreter::new(envref.clone()).evaluate("shift-p-1.5-2").await?;
+        assert_eq!(state.try_into_string()?, "p 1.5 2");
+
+        let err = NGPlanInterpreter::new(envref.clone())
+            .evaluate("total-1.5-x")
+            .await
+            .unwrap_err();
+        assert_eq!(err.error_type, ErrorType::ConversionError);
+        let err = NGPlanInterpreter::new(envref)
+            .evaluate("shift-p-1.5-2-3")
+            .await
+            .unwrap_err();
+        assert_eq!(err.error_type, ErrorType::ConversionError);
+        assert!(err.message.contains("2 values expected, 3 found"));
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_evaluate_fresh() -> Result<(), Error> {
+        use crate::cache::{MemoryBinCache, SerializingCache};
+        use crate::context::SimpleNGEnvironment;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        env.with_cache(Box::new(SerializingCache::new(MemoryBinCache::new())));
+        {
+            let cr = env.get_mut_command_executor();
+            fn spy(_state: &State<Value>) -> Result<Value, Error> {
+                let calls = CALLS.fetch_add(1, Ordering::SeqCst) + 1;
+                Ok(Value::I64(calls as i64))
+            }
+            ng_register_command!(cr, spy(state));
+        }
+        let envref = env.to_ref();
+        let query = parse_query("spy")?;
+        let value = |state: State<Value>| state.read().unwrap().try_into_i64().unwrap();
+
+        assert_eq!(value(evaluate_cached(envref.clone(), &query).await?), 1);
+        assert_eq!(value(evaluate_cached(envref.clone(), &query).await?), 1);
+        assert_eq!(value(evaluate_fresh(envref.clone(), &query).await?), 2);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+        // The fresh result replaced the cached one
+        assert_eq!(value(evaluate_cached(envref.clone(), &query).await?), 2);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_invalidate_prefix() -> Result<(), Error> {
+        use crate::cache::{MemoryBinCache, SerializingCache};
+        use crate::context::SimpleNGEnvironment;
+        use crate::metadata::{MetadataRecord, Status};
+        use crate::store::{AsyncStoreWrapper, MemoryStore};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static EVALUATIONS: AtomicUsize = AtomicUsize::new(0);
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        env.with_cache(Box::new(SerializingCache::new(MemoryBinCache::new())));
+        env.with_async_store(Box::new(AsyncStoreWrapper(MemoryStore::new(&Key::new()))));
+        {
+            let cr = env.get_mut_command_executor();
+            fn length(state: &State<Value>) -> Result<Value, Error> {
+                EVALUATIONS.fetch_add(1, Ordering::SeqCst);
+                match &*state.read().unwrap() {
+                    Value::Bytes(b) => Ok(Value::I64(b.len() as i64)),
+                    v => Ok(Value::I64(v.try_into_string()?.len() as i64)),
+                }
+            }
+            ng_register_command!(cr, length(state));
+        }
+        let envref = env.to_ref();
+        let store = envref.get_async_store().await;
+        store.set(&parse_key("data/a.txt")?, b"abc", &MetadataRecord::new().into()).await?;
+        store.set(&parse_key("other/b.txt")?, b"hello", &MetadataRecord::new().into()).await?;
+        let derived = parse_query("data/a.txt/-/length")?;
+        let unrelated = parse_query("other/b.txt/-/length")?;
+
+        assert_eq!(*evaluate_cached(envref.clone(), &derived).await?.read().unwrap(), Value::I64(3));
+        evaluate_cached(envref.clone(), &unrelated).await?;
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 2);
+        // Served from the cache; the cached value is deserialized, so the integer width is not preserved
+        assert_eq!(evaluate_cached(envref.clone(), &derived).await?.read().unwrap().try_into_i64()?, 3);
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 2);
+
+        // The source changes externally and its prefix is invalidated
+        store.set(&parse_key("data/a.txt")?, b"abcdef", &MetadataRecord::new().into()).await?;
+        let invalidated = invalidate_prefix(envref.clone(), &parse_key("data")?).await?;
+        assert_eq!(invalidated, vec![derived.clone()]);
+        {
+            let cache = envref.0.read().await.get_cache();
+            let cache = cache.lock().unwrap();
+            let record = cache.get_metadata(&derived).unwrap().metadata_record().unwrap();
+            assert_eq!(record.status, Status::Expired);
+            assert!(cache.get_binary(&derived).is_none());
+            assert!(cache.get_binary(&unrelated).is_some());
+        }
+
+        // The invalidated result is re-evaluated, the unrelated one is still cached
+        assert_eq!(*evaluate_cached(envref.clone(), &derived).await?.read().unwrap(), Value::I64(6));
+        evaluate_cached(envref.clone(), &unrelated).await?;
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_volatile_not_cached() -> Result<(), Error> {
+        use crate::cache::{MemoryBinCache, SerializingCache};
+        use crate::context::SimpleNGEnvironment;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        env.with_cache(Box::new(SerializingCache::new(MemoryBinCache::new())));
+        {
+            let cr = env.get_mut_command_executor();
+            fn now(_state: &State<Value>) -> Result<Value, Error> {
+                Ok(Value::I64(CALLS.fetch_add(1, Ordering::SeqCst) as i64))
+            }
+            ng_register_command!(cr, now(state));
+            cr.command_metadata_registry.get_mut("now").unwrap().with_volatile();
+        }
+        let envref = env.to_ref();
+        let query = parse_query("now")?;
+        evaluate_cached(envref.clone(), &query).await?;
+        evaluate_cached(envref.clone(), &query).await?;
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+        let cache = envref.0.read().await.get_cache();
+        assert!(!cache.lock().unwrap().contains(&query));
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_invalidate_transitive() -> Result<(), Error> {
+        use crate::cache::{MemoryBinCache, SerializingCache};
+        use crate::context::SimpleNGEnvironment;
+        use crate::metadata::{MetadataRecord, Status};
+        use crate::store::{AsyncStoreWrapper, MemoryStore};
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        env.with_cache(Box::new(SerializingCache::new(MemoryBinCache::new())));
+        env.with_async_store(Box::new(AsyncStoreWrapper(MemoryStore::new(&Key::new()))));
+        {
+            let cr = env.get_mut_command_executor();
+            fn length(state: &State<Value>) -> Result<Value, Error> {
+                Ok(Value::I64(state.read().unwrap().try_into_string()?.len() as i64))
+            }
+            fn suffix(state: &State<Value>, s: String) -> Result<Value, Error> {
+                Ok(Value::from(format!("{}{}", state.read().unwrap().try_into_string()?, s)))
+            }
+            ng_register_command!(cr, length(state));
+            ng_register_command!(cr, suffix(state, s: String));
+        }
+        let envref = env.to_ref();
+
+        // derived/len.txt is computed from data/a.txt, report depends on derived/len.txt,
+        // linked depends on data/a.txt only through a link parameter
+        let derived = parse_query("data/a.txt/-/length")?;
+        let report = parse_query("derived/len.txt/-/suffix-x")?;
+        let linked = parse_query("other/b.txt/-/suffix-~X~data/a.txt/-/length~E")?;
+        let unrelated = parse_query("other/b.txt/-/length")?;
+        let store = envref.get_async_store().await;
+        let derived_key = parse_key("derived/len.txt")?;
+        // Evaluate the derived result and store it under its key
+        let store_derived = || async {
+            let length = evaluate_cached(envref.clone(), &derived).await?.read().unwrap().try_into_i64()?;
+            store.set(&derived_key, length.to_string().as_bytes(), &MetadataRecord::new().into()).await?;
+            let cache = envref.0.read().await.get_cache();
+            let mut cache = cache.lock().unwrap();
+            let mut record = cache.get_metadata(&derived).unwrap().metadata_record().unwrap();
+            record.with_key(derived_key.clone());
+            cache.set_metadata(&record.into())
+        };
+        store.set(&parse_key("data/a.txt")?, b"abc", &MetadataRecord::new().into()).await?;
+        store_derived().await?;
+        assert_eq!(evaluate_cached(envref.clone(), &report).await?.try_into_string()?, "3x");
+        {
+            let cache = envref.0.read().await.get_cache();
+            let mut cache = cache.lock().unwrap();
+            for query in [&linked, &unrelated] {
+                let mut record = MetadataRecord::new();
+                record.with_query(query.clone()).with_status(Status::Ready);
+                cache.set_binary(b"1", &record.into())?;
+            }
+        }
+
+        // The source is overwritten and its prefix is invalidated
+        store.set(&parse_key("data/a.txt")?, b"abcdef", &MetadataRecord::new().into()).await?;
+        let mut invalidated = invalidate_prefix(envref.clone(), &parse_key("data")?).await?;
+        invalidated.sort_by_key(|query| query.encode());
+        let mut expected = vec![derived.clone(), report.clone(), linked.clone()];
+        expected.sort_by_key(|query| query.encode());
+        assert_eq!(invalidated, expected);
+        {
+            let cache = envref.0.read().await.get_cache();
+            let cache = cache.lock().unwrap();
+            for query in expected.iter() {
+                assert!(cache.get_binary(query).is_none());
+                let record = cache.get_metadata(query).unwrap().metadata_record().unwrap();
+                assert_eq!(record.status, Status::Expired);
+            }
+            assert!(cache.get_binary(&unrelated).is_some());
+        }
+
+        // The derived result is recomputed and stored again, the report depending on it is re-evaluated
+        store_derived().await?;
+        assert_eq!(evaluate_cached(envref.clone(), &report).await?.try_into_string()?, "6x");
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_load_projected() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::metadata::MetadataRecord;
+        use crate::store::{AsyncStoreWrapper, MemoryStore};
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        env.with_async_store(Box::new(AsyncStoreWrapper(MemoryStore::new(&Key::new()))));
+        let envref = env.to_ref();
+        let store = envref.get_async_store().await;
+        let key = parse_key("data/table.json")?;
+        let data = br#"[{"a":1,"b":"x","c":true},{"a":2,"b":"y","c":false}]"#;
+        store.set(&key, data, &MetadataRecord::new().into()).await?;
+
+        let columns = vec!["a".to_owned(), "c".to_owned()];
+        let state = load_projected(envref.clone(), &key, &columns).await?;
+        let expected: Value = serde_json::from_str(r#"[{"a":1,"c":true},{"a":2,"c":false}]"#).unwrap();
+        assert_eq!(*state.read().unwrap(), expected);
+
+        let missing = vec!["z".to_owned()];
+        assert!(load_projected(envref.clone(), &key, &missing).await.is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_cache_snapshot_fast_track() -> Result<(), Error> {
+        use crate::cache::{export_cache, import_cache, MemoryBinCache, SerializingCache};
+        use crate::context::SimpleNGEnvironment;
+        use crate::metadata::MetadataRecord;
+        use crate::store::{AsyncStoreWrapper, MemoryStore};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static EVALUATIONS: AtomicUsize = AtomicUsize::new(0);
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        env.with_cache(Box::new(SerializingCache::new(MemoryBinCache::new())));
+        env.with_async_store(Box::new(AsyncStoreWrapper(MemoryStore::new(&Key::new()))));
+        {
+            let cr = env.get_mut_command_executor();
+            fn length(state: &State<Value>) -> Result<Value, Error> {
+                EVALUATIONS.fetch_add(1, Ordering::SeqCst);
+                match &*state.read().unwrap() {
+                    Value::Bytes(b) => Ok(Value::I64(b.len() as i64)),
+                    v => Ok(Value::I64(v.try_into_string()?.len() as i64)),
+                }
+            }
+            ng_register_command!(cr, length(state));
+        }
+        let envref = env.to_ref();
+        let store = envref.get_async_store().await;
+        store.set(&parse_key("data/a.txt")?, b"abc", &MetadataRecord::new().into()).await?;
+        let query = parse_query("data/a.txt/-/length")?;
+        let transient = parse_query("data/a.txt/-/length/length")?;
+        evaluate_if_stale(envref.clone(), &query, &parse_key("results/length.json")?, None).await?;
+        evaluate_cached(envref.clone(), &transient).await?;
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 3);
+
+        // Only the result stored under a key is part of the snapshot
+        let snapshot = {
+            let cache = envref.0.read().await.get_cache();
+            let cache = cache.lock().unwrap();
+            export_cache(&**cache)
+        };
+        assert_eq!(snapshot.entries.len(), 1);
+        assert_eq!(snapshot.entries[0].query, query);
+
+        // A fresh cache with the imported index loads the result from the store
+        envref
+            .0
+            .write()
+            .await
+            .with_cache(Box::new(SerializingCache::new(MemoryBinCache::new())));
+        {
+            let cache = envref.0.read().await.get_cache();
+            let mut cache = cache.lock().unwrap();
+            assert_eq!(import_cache(&mut **cache, &snapshot)?, 1);
+        }
+        let state = evaluate_cached(envref.clone(), &query).await?;
+        assert_eq!(state.read().unwrap().try_into_json_value()?, serde_json::json!(3));
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 3);
+        {
+            let cache = envref.0.read().await.get_cache();
+            assert!(cache.lock().unwrap().get_binary(&query).is_some());
+        }
+        evaluate_cached(envref.clone(), &transient).await?;
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 5);
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_evaluate_if_stale() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::metadata::MetadataRecord;
+        use crate::store::{AsyncStoreWrapper, MemoryStore};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static EVALUATIONS: AtomicUsize = AtomicUsize::new(0);
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        env.with_async_store(Box::new(AsyncStoreWrapper(MemoryStore::new(&Key::new()))));
+        {
+            let cr = env.get_mut_command_executor();
+            fn upper(state: &State<Value>) -> Result<Value, Error> {
+                EVALUATIONS.fetch_add(1, Ordering::SeqCst);
+                let text = match &*state.read().unwrap() {
+                    Value::Bytes(b) => String::from_utf8_lossy(b).to_string(),
+                    v => v.try_into_string()?,
+                };
+                Ok(Value::from_string(text.to_uppercase()))
+            }
+            ng_register_command!(cr, upper(state));
+        }
+        let envref = env.to_ref();
+        let store = envref.get_async_store().await;
+        let input = parse_key("input.txt")?;
+        let output = parse_key("output.json")?;
+        let query = parse_query("input.txt/-/upper")?;
+        // The store records the time of the write
+        let metadata = MetadataRecord::new();
+        store.set(&input, b"hello", &metadata.clone().into()).await?;
+
+        assert!(is_stale(envref.clone(), &query, &output).await?);
+        let state = evaluate_if_stale(envref.clone(), &query, &output, None).await?;
+        assert_eq!(state.try_into_string()?, "HELLO");
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 1);
+
+        // Fresh output is fast-tracked from the store
+        assert!(!is_stale(envref.clone(), &query, &output).await?);
+        let state = evaluate_if_stale(envref.clone(), &query, &output, None).await?;
+        assert_eq!(state.try_into_string()?, "HELLO");
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 1);
+
+        // Updated dependency makes the output stale
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        store.set(&input, b"world", &metadata.into()).await?;
+        assert!(is_stale(envref.clone(), &query, &output).await?);
+        let state = evaluate_if_stale(envref.clone(), &query, &output, None).await?;
+        assert_eq!(state.try_into_string()?, "WORLD");
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_emit_output() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::store::{AsyncStoreWrapper, MemoryStore};
+
+        type Env = SimpleNGEnvironment<Value>;
+        let mut env = Env::new();
+        env.with_async_store(Box::new(AsyncStoreWrapper(MemoryStore::new(&Key::new()))));
+        {
+            let cr = env.get_mut_command_executor();
+            fn report(_state: &State<Value>, context: NGContext<Env>) -> Result<Value, Error> {
+                context.emit_output("rows.json", Value::I64(3))?;
+                assert!(context.emit_output("a/b", Value::None).is_err());
+                Ok(Value::from("report"))
+            }
+            ng_register_command!(cr, report(state, context));
+        }
+        let envref = env.to_ref();
+        let key = parse_key("reports/main.json")?;
+        let state = evaluate_if_stale(envref.clone(), &parse_query("report")?, &key, None).await?;
+        assert_eq!(state.try_into_string()?, "report");
+
+        let output = output_key(&key, "rows.json");
+        assert_eq!(output.encode(), "reports/main/rows.json");
+        let store = envref.get_async_store().await;
+        let (data, metadata) = store.get(&output).await?;
+        assert_eq!(Value::deserialize_from_bytes(&data, "generic", "json")?.try_into_i64()?, 3);
+        assert_eq!(metadata.metadata_record().unwrap().status, Status::Ready);
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_apply_action() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::query::{ActionParameter, ActionRequest};
+        use crate::store::{AsyncStoreWrapper, MemoryStore};
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        env.with_async_store(Box::new(AsyncStoreWrapper(MemoryStore::new(&Key::new()))));
+        {
+            let cr = env.get_mut_command_executor();
+            fn upper(state: &State<Value>) -> Result<Value, Error> {
+                let text = match &*state.read().unwrap() {
+                    Value::Bytes(b) => String::from_utf8_lossy(b).to_string(),
+                    v => v.try_into_string()?,
+                };
+                Ok(Value::from(text.to_uppercase()))
+            }
+            fn suffix(state: &State<Value>, s: String) -> Result<Value, Error> {
+                Ok(Value::from(format!("{}{}", state.try_into_string()?, s)))
+            }
+            ng_register_command!(cr, upper(state));
+            ng_register_command!(cr, suffix(state, s: String));
+        }
+        let envref = env.to_ref();
+        let store = envref.get_async_store().await;
+        store.set(&parse_key("data/a.txt")?, b"abc", &MetadataRecord::new().into()).await?;
+
+        // Action applied to a pure resource
+        let base = parse_query("-R/data/a.txt")?;
+        let state = apply_action(envref.clone(), &base, ActionRequest::new("upper".to_owned())).await?;
+        assert_eq!(state.try_into_string()?, "ABC");
+        assert_eq!(base.encode(), "-R/data/a.txt");
+
+        // Action applied to a query
+        let base = parse_query("data/a.txt/-/upper")?;
+        let action = ActionRequest::new("suffix".to_owned())
+            .with_parameters(vec![ActionParameter::new_string("x".to_owned())]);
+        let state = apply_action(envref.clone(), &base, action).await?;
+        assert_eq!(state.try_into_string()?, "ABCx");
+        let state = NGPlanInterpreter::new(envref.clone()).evaluate(&base).await?;
+        assert_eq!(state.try_into_string()?, "ABC");
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+    async fn test_metadata_flush() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::store::{AsyncStoreWrapper, MemoryStore};
+        use std::time::Duration;
+
+        type Env = SimpleNGEnvironment<Value>;
+        let mut env = Env::new();
+        env.with_async_store(Box::new(AsyncStoreWrapper(MemoryStore::new(&Key::new()))));
+        assert!(env.with_metadata_flush_interval(Duration::ZERO).is_err());
+        env.with_metadata_flush_interval(Duration::from_millis(20))?;
+        {
+            let cr = env.get_mut_command_executor();
+            fn slow(_state: &State<Value>, context: NGContext<Env>) -> Result<Value, Error> {
+                context.info("slow started");
+                std::thread::sleep(Duration::from_millis(300));
+                Ok(Value::from("slow"))
+            }
+            ng_register_command!(cr, slow(state, context));
+            // Slow synchronous command must not block the runtime running the flush
+            cr.command_metadata_registry.get_mut("slow").unwrap().with_blocking();
+        }
+        let envref = env.to_ref();
+        let key = parse_key("reports/slow.txt")?;
+        let progress = progress_key(&key);
+        assert_eq!(progress.encode(), "reports/slow.progress.json");
+
+        let evaluation = {
+            let (envref, key) = (envref.clone(), key.clone());
+            let trace_id = Some("job-1".to_owned());
+            tokio::spawn(async move { evaluate_if_stale(envref, &parse_query("slow")?, &key, trace_id).await })
+        };
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let store = envref.get_async_store().await;
+        let (data, _) = store.get(&progress).await?;
+        let record: MetadataRecord = serde_json::from_slice(&data).unwrap();
+        assert_eq!(record.status, Status::Evaluation);
+        let entry = record.log.iter().find(|entry| entry.message == "slow started").unwrap();
+        assert_eq!(entry.trace_id.as_deref(), Some("job-1"));
+        assert!(!store.contains(&key).await?);
+
+        let state = evaluation.await.unwrap()?;
+        assert_eq!(state.try_into_string()?, "slow");
+        assert!(!store.contains(&progress).await?);
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_max_concurrency() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static RUNNING: AtomicUsize = AtomicUsize::new(0);
+        static MAX_RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        {
+            let cr = env.get_mut_command_executor();
+            fn heavy(_state: &State<Value>) -> Result<Value, Error> {
+                let running = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+                MAX_RUNNING.fetch_max(running, Ordering::SeqCst);
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                RUNNING.fetch_sub(1, Ordering::SeqCst);
+                Ok(Value::from("done"))
+            }
+            ng_register_command!(cr, heavy(state));
+            cr.command_metadata_registry
+                .get_mut("heavy")
+                .unwrap()
+                .with_max_concurrency(1)?;
+        }
+        let envref = env.to_ref();
+        let tasks = (0..2)
+            .map(|_| {
+                let envref = envref.clone();
+                tokio::spawn(async move { NGPlanInterpreter::new(envref).evaluate("heavy").await })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            let state = task.await.unwrap()?;
+            assert_eq!(state.try_into_string()?, "done");
+        }
+        assert_eq!(MAX_RUNNING.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_blocking_command() -> Result<(), Error> {
+        use crate::context::{ActionContext, NGContext, SimpleNGEnvironment};
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        type Env = SimpleNGEnvironment<Value>;
+        let mut env = Env::new();
+        {
+            let cr = env.get_mut_command_executor();
+            fn slow(_state: &State<Value>, context: NGContext<Env>) -> Result<Value, Error> {
+                std::thread::sleep(std::time::Duration::from_millis(300));
+                context.info("slept");
+                Ok(Value::from("awake"))
+            }
+            ng_register_command!(cr, slow(state, context));
+            cr.command_metadata_registry
+                .get_mut("slow")
+                .unwrap()
+                .with_blocking();
+        }
+        let envref = env.to_ref();
+        // The test runtime has a single thread, a command blocking it would stop the ticker
+        let done = Arc::new(AtomicBool::new(false));
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = {
+            let (done, ticks) = (done.clone(), ticks.clone());
+            tokio::spawn(async move {
+                while !done.load(Ordering::SeqCst) {
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        };
+        let state = NGPlanInterpreter::new(envref).evaluate("slow").await?;
+        done.store(true, Ordering::SeqCst);
+        ticker.await.unwrap();
+        assert_eq!(state.try_into_string()?, "awake");
+        assert!(ticks.load(Ordering::SeqCst) >= 5);
+        let record = state.metadata.metadata_record().unwrap();
+        assert!(record.log.iter().any(|entry| entry.message == "slept"));
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_value_deserializer_registry() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::metadata::MetadataRecord;
+        use crate::store::{AsyncStoreWrapper, MemoryStore};
+
+        // Custom value type: temperature stored as text like "21.5C"
+        fn serialize_temperature(value: &Value) -> Result<Vec<u8>, Error> {
+            Ok(format!("{}C", value.try_into_f64()?).into_bytes())
+        }
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        env.with_async_store(Box::new(AsyncStoreWrapper(MemoryStore::new(&Key::new()))));
+        env.get_mut_value_deserializer_registry()
+            .register("temperature", |b, format| {
+                assert_eq!(format, "tmp");
+                let text = String::from_utf8_lossy(b);
+                let celsius = text.trim_end_matches('C').parse::<f64>().map_err(|e| {
+                    Error::conversion_error_with_message("bytes", "temperature", &e.to_string())
+                })?;
+                Ok(Value::F64(celsius))
+            });
+        let envref = env.to_ref();
+        let store = envref.get_async_store().await;
+        let key = parse_key("room.tmp")?;
+        let mut metadata = MetadataRecord::new();
+        metadata.with_type_identifier("temperature".to_owned());
+        store
+            .set(&key, &serialize_temperature(&Value::F64(21.5))?, &metadata.into())
+            .await?;
+
+        let mut pi = NGPlanInterpreter::new(envref.clone());
+        let state = pi.evaluate("-R/room.tmp").await?;
+        assert_eq!(*state.read().unwrap(), Value::F64(21.5));
+
+        // Unregistered type identifiers fall back to the built-in deserialization
+        assert_eq!(
+            envref.0.read().await.deserialize_value(b"[1,2]", "generic", "json")?,
+            Value::Array(vec![Value::I32(1), Value::I32(2)])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resource_without_store() {
+        use crate::error::ErrorType;
+
+        let env: SimpleEnvironment<Value> = SimpleEnvironment::new();
+        let mut pi = PlanInterpreter::new(env.to_ref());
+        let err = pi.evaluate("-R/hello.txt").unwrap_err();
+        assert_eq!(err.error_type, ErrorType::StoreNotConfigured);
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_ng_resource_without_store() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::error::ErrorType;
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        {
+            let cr = env.get_mut_command_executor();
+            fn hello(_state: &State<Value>) -> Result<Value, Error> {
+                Ok(Value::from("hello"))
+            }
+            ng_register_command!(cr, hello(state));
+        }
+        let envref = env.to_ref();
+        let mut pi = NGPlanInterpreter::new(envref.clone());
+        let err = pi.evaluate("-R/hello.txt").await.unwrap_err();
+        assert_eq!(err.error_type, ErrorType::StoreNotConfigured);
+
+        // Saving the result requires a store as well
+        let err = evaluate_if_stale(envref, &parse_query("hello")?, &parse_key("hello.txt")?, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.error_type, ErrorType::StoreNotConfigured);
+        Ok(())
+    }
+
     #[cfg(feature = "async_store")]
     #[tokio::test]
     async fn test_async_resource_interpreter() -> Result<(), Error> {
@@ -17,4 +17,5 @@ pub mod state;
 pub mod store;
 pub mod value;
 pub mod media_type;
-pub mod recipes;
\ No newline at end of file
+pub mod recipes;
+pub mod library;
\ No newline at end of file
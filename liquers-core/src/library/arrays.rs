@@ -0,0 +1,315 @@
+//! Commands operating on arrays.
+//!
+//! Commands combining several arrays take them as an array of arrays in the state,
+//! e.g. a result of a query producing `[[1, 2, 3], ["a", "b", "c"]]`.
+
+use std::collections::HashSet;
+
+use crate::context::NGEnvironment;
+use crate::error::Error;
+use crate::state::State;
+use crate::value::{Value, ValueInterface};
+
+use super::LibraryCommandRegistry;
+
+fn array_items(value: &Value, what: &str) -> Result<Vec<Value>, Error> {
+    match value {
+        Value::Array(a) => Ok(a.clone()),
+        Value::None => Ok(vec![]),
+        _ => Err(Error::conversion_error(value.type_name(), what)),
+    }
+}
+
+fn field_names(names: Vec<Value>) -> Result<Vec<String>, Error> {
+    names.iter().map(|n| n.try_into_string()).collect()
+}
+
+/// Zip an array of arrays into an array of tuples: the i-th tuple contains the i-th items
+/// of all the arrays. Tuples are arrays, or objects if field `names` are given
+/// (one name per zipped array).
+/// Arrays of different lengths are truncated to the shortest one,
+/// unless `strict` is set, in which case a length mismatch is an error.
+pub fn zip(state: &State<Value>, strict: bool, names: Vec<Value>) -> Result<Value, Error> {
+    let names = field_names(names)?;
+    let arrays = array_items(&state.read().unwrap(), "array of arrays")?
+        .iter()
+        .map(|a| array_items(a, "array"))
+        .collect::<Result<Vec<_>, _>>()?;
+    if !names.is_empty() && names.len() != arrays.len() {
+        return Err(Error::general_error(format!(
+            "Zip of {} arrays needs {} field names, {} given",
+            arrays.len(),
+            arrays.len(),
+            names.len()
+        )));
+    }
+    let length = arrays.iter().map(|a| a.len()).min().unwrap_or(0);
+    if strict {
+        if let Some((i, a)) = arrays.iter().enumerate().find(|(_, a)| a.len() != length) {
+            return Err(Error::general_error(format!(
+                "Zipped arrays differ in length: array {} has {} items, expected {}",
+                i,
+                a.len(),
+                length
+            )));
+        }
+    }
+    let tuples = (0..length)
+        .map(|i| {
+            let items = arrays.iter().map(|a| a[i].clone());
+            if names.is_empty() {
+                Value::Array(items.collect())
+            } else {
+                Value::Object(names.iter().cloned().zip(items).collect())
+            }
+        })
+        .collect();
+    Ok(Value::Array(tuples))
+}
+
+/// Unzip an array of tuples into an array of arrays, the inverse of [zip].
+/// Tuples are arrays of the same length, or objects if field `names` are given;
+/// there is one resulting array per tuple item (or per name). Fields missing in an object are None.
+pub fn unzip(state: &State<Value>, names: Vec<Value>) -> Result<Value, Error> {
+    let names = field_names(names)?;
+    let tuples = array_items(&state.read().unwrap(), "array of tuples")?;
+    let width = if names.is_empty() {
+        match tuples.first() {
+            Some(Value::Array(a)) => a.len(),
+            _ => 0,
+        }
+    } else {
+        names.len()
+    };
+    let mut arrays = vec![Vec::with_capacity(tuples.len()); width];
+    for (i, tuple) in tuples.into_iter().enumerate() {
+        let items = match (tuple, names.is_empty()) {
+            (Value::Array(a), true) if a.len() == width => a,
+            (Value::Object(mut o), false) => names
+                .iter()
+                .map(|n| o.remove(n).unwrap_or(Value::None))
+                .collect(),
+            (tuple, true) => {
+                return Err(Error::conversion_error_with_message(
+                    tuple.type_name(),
+                    "tuple",
+                    &format!("Item {} is not an array of {} items", i, width),
+                ))
+            }
+            (tuple, false) => {
+                return Err(Error::conversion_error_with_message(
+                    tuple.type_name(),
+                    "object",
+                    &format!("Item {} is not an object", i),
+                ))
+            }
+        };
+        for (array, item) in arrays.iter_mut().zip(items) {
+            array.push(item);
+        }
+    }
+    Ok(Value::Array(arrays.into_iter().map(Value::Array).collect()))
+}
+
+/// Key identifying an item in the set operations.
+/// Values are not hashable in general (e.g. floats, objects), so items are compared by their JSON representation;
+/// e.g. objects with the same fields and values are equal, while `1` and `1.0` are different items.
+fn set_key(item: &Value) -> Result<String, Error> {
+    Ok(item.try_into_json_value()?.to_string())
+}
+
+/// Arrays of the state (an array of arrays) with the keys of their items
+fn keyed_arrays(state: &State<Value>) -> Result<Vec<Vec<(String, Value)>>, Error> {
+    array_items(&state.read().unwrap(), "array of arrays")?
+        .iter()
+        .map(|a| {
+            array_items(a, "array")?
+                .into_iter()
+                .map(|item| Ok((set_key(&item)?, item)))
+                .collect()
+        })
+        .collect()
+}
+
+/// Items of the first array contained (or not contained, if `contained` is false) in all the other arrays,
+/// without duplicates and in the order of the first occurrence
+fn filter_first(state: &State<Value>, contained: bool) -> Result<Value, Error> {
+    let mut arrays = keyed_arrays(state)?.into_iter();
+    let first = arrays.next().unwrap_or_default();
+    let others = arrays
+        .map(|a| a.into_iter().map(|(k, _)| k).collect::<HashSet<_>>())
+        .collect::<Vec<_>>();
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+    for (key, item) in first {
+        let keep = if contained {
+            others.iter().all(|o| o.contains(&key))
+        } else {
+            !others.iter().any(|o| o.contains(&key))
+        };
+        if keep && seen.insert(key) {
+            result.push(item);
+        }
+    }
+    Ok(Value::Array(result))
+}
+
+/// Union of an array of arrays: items of all the arrays without duplicates,
+/// in the order of the first occurrence. Items are compared by their JSON representation.
+pub fn union(state: &State<Value>) -> Result<Value, Error> {
+    let mut seen = HashSet::new();
+    let mut result = Vec::new();
+    for (key, item) in keyed_arrays(state)?.into_iter().flatten() {
+        if seen.insert(key) {
+            result.push(item);
+        }
+    }
+    Ok(Value::Array(result))
+}
+
+/// Intersection of an array of arrays: items of the first array contained in all the other arrays,
+/// without duplicates and in the order of the first array. Items are compared by their JSON representation.
+pub fn intersection(state: &State<Value>) -> Result<Value, Error> {
+    filter_first(state, true)
+}
+
+/// Difference of an array of arrays: items of the first array not contained in any of the other arrays,
+/// without duplicates and in the order of the first array. Items are compared by their JSON representation.
+pub fn difference(state: &State<Value>) -> Result<Value, Error> {
+    filter_first(state, false)
+}
+
+/// Reverse the order of the items of an array (e.g. the rows of a table) or of the lines of a text.
+/// A trailing newline of the text is kept at the end.
+pub fn reverse(state: &State<Value>) -> Result<Value, Error> {
+    match &*state.read().unwrap() {
+        Value::Array(a) => Ok(Value::Array(a.iter().rev().cloned().collect())),
+        Value::Text(t) => {
+            let (body, newline) = match t.strip_suffix('\n') {
+                Some(body) => (body, "\n"),
+                None => (t.as_str(), ""),
+            };
+            let mut lines = body.split('\n').collect::<Vec<_>>();
+            lines.reverse();
+            Ok(Value::from(format!("{}{}", lines.join("\n"), newline)))
+        }
+        Value::None => Ok(Value::None),
+        value => Err(Error::conversion_error(value.type_name(), "array or text")),
+    }
+}
+
+ng_register_all!(
+    /// Register the commands working with arrays
+    pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+        zip(state, strict: bool = false, multiple names: Value),
+        unzip(state, multiple names: Value),
+        union(state),
+        intersection(state),
+        difference(state),
+        reverse(state),
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(json: &str) -> State<Value> {
+        let value: Value = serde_json::from_str(json).unwrap();
+        State::new().with_data(value)
+    }
+
+    fn value(json: &str) -> Value {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_zip_equal_length() -> Result<(), Error> {
+        let s = state(r#"[[1,2,3],["a","b","c"]]"#);
+        let pairs = zip(&s, true, vec![])?;
+        assert_eq!(pairs, value(r#"[[1,"a"],[2,"b"],[3,"c"]]"#));
+        let objects = zip(&s, true, vec![Value::from("n"), Value::from("s")])?;
+        assert_eq!(objects, value(r#"[{"n":1,"s":"a"},{"n":2,"s":"b"},{"n":3,"s":"c"}]"#));
+
+        assert_eq!(unzip(&State::new().with_data(pairs), vec![])?, value(r#"[[1,2,3],["a","b","c"]]"#));
+        assert_eq!(
+            unzip(&State::new().with_data(objects), vec![Value::from("s"), Value::from("n")])?,
+            value(r#"[["a","b","c"],[1,2,3]]"#)
+        );
+        assert!(zip(&s, false, vec![Value::from("n")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_truncate() -> Result<(), Error> {
+        let s = state(r#"[[1,2,3],["a","b"]]"#);
+        assert_eq!(zip(&s, false, vec![])?, value(r#"[[1,"a"],[2,"b"]]"#));
+        assert_eq!(zip(&state("[]"), false, vec![])?, value("[]"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_strict_length_mismatch() {
+        let s = state(r#"[[1,2,3],["a","b"]]"#);
+        let err = zip(&s, true, vec![]).unwrap_err();
+        assert!(err.message.contains("array 0 has 3 items, expected 2"));
+        assert!(unzip(&state(r#"[[1,"a"],[2]]"#), vec![]).is_err());
+    }
+
+    #[test]
+    fn test_union() -> Result<(), Error> {
+        let s = state("[[3,1,3,2],[2,4,1,5]]");
+        assert_eq!(union(&s)?, value("[3,1,2,4,5]"));
+        assert_eq!(union(&state("[]"))?, value("[]"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_intersection() -> Result<(), Error> {
+        let s = state("[[3,1,3,2,5],[2,5,1],[5,1,7]]");
+        assert_eq!(intersection(&s)?, value("[1,5]"));
+        assert_eq!(intersection(&state("[[1,1,2]]"))?, value("[1,2]"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_difference() -> Result<(), Error> {
+        let s = state("[[3,1,3,2,5],[2],[5,7]]");
+        assert_eq!(difference(&s)?, value("[3,1]"));
+        assert!(difference(&state("[1,2]")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_operations_objects() -> Result<(), Error> {
+        let s = state(r#"[[{"a":1,"b":"x"},{"a":2}],[{"b":"x","a":1},{"a":3}]]"#);
+        assert_eq!(union(&s)?, value(r#"[{"a":1,"b":"x"},{"a":2},{"a":3}]"#));
+        assert_eq!(intersection(&s)?, value(r#"[{"a":1,"b":"x"}]"#));
+        assert_eq!(difference(&s)?, value(r#"[{"a":2}]"#));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse_array() -> Result<(), Error> {
+        assert_eq!(reverse(&state(r#"[1,"a",null,[2]]"#))?, value(r#"[[2],null,"a",1]"#));
+        assert_eq!(reverse(&state("[]"))?, value("[]"));
+        assert!(reverse(&state("1")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse_text() -> Result<(), Error> {
+        let s = State::new().with_data(Value::from("first\nsecond\nthird\n"));
+        assert_eq!(reverse(&s)?.try_into_string()?, "third\nsecond\nfirst\n");
+        let s = State::new().with_data(Value::from("a\nb"));
+        assert_eq!(reverse(&s)?.try_into_string()?, "b\na");
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse_table() -> Result<(), Error> {
+        let s = state(r#"[{"n":1,"s":"a"},{"n":2,"s":"b"},{"n":3}]"#);
+        assert_eq!(reverse(&s)?, value(r#"[{"n":3},{"n":2,"s":"b"},{"n":1,"s":"a"}]"#));
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+//! Command rendering a table as a simple SVG chart (feature `chart`).
+//!
+//! The SVG is generated directly, without a plotting library; the charts are meant
+//! as quick visual previews of the data rather than publication graphics.
+
+use std::fmt::Write;
+
+use crate::context::{ActionContext, NGContext, NGEnvironment};
+use crate::error::Error;
+use crate::state::State;
+use crate::value::{Value, ValueInterface};
+
+use super::table::{cell_to_string, Table};
+use super::LibraryCommandRegistry;
+
+pub const CHART_WIDTH: f64 = 640.0;
+pub const CHART_HEIGHT: f64 = 400.0;
+const MARGIN: f64 = 40.0;
+
+/// Kind of the chart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartKind {
+    /// Points connected by a polyline in the order of the rows, numeric x
+    Line,
+    /// One bar per row, x values are used as category labels
+    Bar,
+    /// One circle per row, numeric x
+    Scatter,
+}
+
+impl ChartKind {
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "line" => Ok(ChartKind::Line),
+            "bar" => Ok(ChartKind::Bar),
+            "scatter" => Ok(ChartKind::Scatter),
+            _ => Err(Error::general_error(format!(
+                "Unknown chart type '{}'; expected 'line', 'bar' or 'scatter'",
+                name
+            ))),
+        }
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Linear mapping of the range min..max to the pixel range from..to
+fn scale(min: f64, max: f64, from: f64, to: f64) -> impl Fn(f64) -> f64 {
+    let (min, max) = if min == max { (min - 1.0, max + 1.0) } else { (min, max) };
+    move |v| from + (v - min) / (max - min) * (to - from)
+}
+
+fn range(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+        (min.min(v), max.max(v))
+    })
+}
+
+/// Numeric cell, None for a missing value
+fn number(value: &Value, column: &str, row: usize) -> Result<Option<f64>, Error> {
+    if value.is_none() {
+        return Ok(None);
+    }
+    value.try_into_f64().map(Some).map_err(|_| {
+        Error::conversion_error_with_message(
+            value.type_name(),
+            "number",
+            &format!("Column '{}', row {} must be numeric", column, row),
+        )
+    })
+}
+
+/// Render the `x` and `y` columns of a table as an SVG chart of the given kind
+/// (`line`, `bar` or `scatter`, see [ChartKind]). Rows with a missing value are skipped.
+pub fn render_chart(table: &Table, kind: ChartKind, x: &str, y: &str) -> Result<String, Error> {
+    table.check_column(x)?;
+    table.check_column(y)?;
+    let (left, right) = (MARGIN, CHART_WIDTH - MARGIN / 2.0);
+    let (top, bottom) = (MARGIN / 2.0, CHART_HEIGHT - MARGIN);
+
+    let mut svg = String::new();
+    write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = CHART_WIDTH,
+        h = CHART_HEIGHT
+    )
+    .unwrap();
+    write!(
+        svg,
+        r#"<line x1="{left}" y1="{bottom}" x2="{right}" y2="{bottom}" stroke="black"/><line x1="{left}" y1="{top}" x2="{left}" y2="{bottom}" stroke="black"/>"#
+    )
+    .unwrap();
+    write!(
+        svg,
+        r#"<text x="{}" y="{}" text-anchor="middle">{}</text><text x="{}" y="{}" text-anchor="middle" transform="rotate(-90 {} {})">{}</text>"#,
+        (left + right) / 2.0,
+        CHART_HEIGHT - 5.0,
+        escape_xml(x),
+        15.0,
+        (top + bottom) / 2.0,
+        15.0,
+        (top + bottom) / 2.0,
+        escape_xml(y)
+    )
+    .unwrap();
+
+    match kind {
+        ChartKind::Line | ChartKind::Scatter => {
+            let mut points = Vec::new();
+            for row in 0..table.len() {
+                if let (Some(px), Some(py)) = (
+                    number(table.get(row, x), x, row)?,
+                    number(table.get(row, y), y, row)?,
+                ) {
+                    points.push((px, py));
+                }
+            }
+            let (xmin, xmax) = range(points.iter().map(|p| p.0));
+            let (ymin, ymax) = range(points.iter().map(|p| p.1));
+            let sx = scale(xmin, xmax, left, right);
+            let sy = scale(ymin, ymax, bottom, top);
+            if kind == ChartKind::Line {
+                let coordinates = points
+                    .iter()
+                    .map(|&(px, py)| format!("{:.1},{:.1}", sx(px), sy(py)))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                write!(
+                    svg,
+                    r#"<polyline points="{}" fill="none" stroke="steelblue"/>"#,
+                    coordinates
+                )
+                .unwrap();
+            } else {
+                for &(px, py) in points.iter() {
+                    write!(
+                        svg,
+                        r#"<circle cx="{:.1}" cy="{:.1}" r="3" fill="steelblue"/>"#,
+                        sx(px),
+                        sy(py)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        ChartKind::Bar => {
+            let mut bars = Vec::new();
+            for row in 0..table.len() {
+                if let Some(py) = number(table.get(row, y), y, row)? {
+                    bars.push((cell_to_string(table.get(row, x))?, py));
+                }
+            }
+            let (ymin, ymax) = range(bars.iter().map(|b| b.1).chain([0.0]));
+            let sy = scale(ymin, ymax, bottom, top);
+            let slot = (right - left) / bars.len().max(1) as f64;
+            for (i, (label, py)) in bars.iter().enumerate() {
+                let (y0, y1) = (sy(0.0), sy(*py));
+                write!(
+                    svg,
+                    r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="steelblue"><title>{}</title></rect>"#,
+                    left + slot * (i as f64 + 0.1),
+                    y0.min(y1),
+                    slot * 0.8,
+                    (y0 - y1).abs(),
+                    escape_xml(label)
+                )
+                .unwrap();
+            }
+        }
+    }
+    svg.push_str("</svg>");
+    Ok(svg)
+}
+
+/// Render a table as an SVG chart; `kind` is `line`, `bar` or `scatter`,
+/// `x` and `y` are the names of the columns to plot.
+/// The result is SVG text with the `image/svg+xml` media type.
+pub fn chart<E: NGEnvironment<Value = Value>>(
+    state: &State<Value>,
+    kind: String,
+    x: String,
+    y: String,
+    context: NGContext<E>,
+) -> Result<Value, Error> {
+    let table = Table::from_value(&state.read().unwrap())?;
+    let svg = render_chart(&table, ChartKind::from_name(&kind)?, &x, &y)?;
+    context.set_media_type("image/svg+xml".to_owned());
+    Ok(Value::Text(svg))
+}
+
+ng_register_all!(
+    /// Register the chart command
+    pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+        chart(state, kind: String, x: String, y: String, context),
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> Table {
+        let value: Value = serde_json::from_str(
+            r#"[{"month":"Jan","t":1.5,"n":3},{"month":"Feb","t":2,"n":null},{"month":"Mar","t":4,"n":5}]"#,
+        )
+        .unwrap();
+        Table::from_value(&value).unwrap()
+    }
+
+    #[test]
+    fn test_chart_elements() -> Result<(), Error> {
+        let t = table();
+        let svg = render_chart(&t, ChartKind::Bar, "month", "t")?;
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>"));
+        assert_eq!(svg.matches("<rect").count(), 3);
+        assert!(svg.contains("<title>Feb</title>"));
+
+        let svg = render_chart(&t, ChartKind::Scatter, "t", "n")?;
+        assert_eq!(svg.matches("<circle").count(), 2);
+
+        let svg = render_chart(&t, ChartKind::Line, "t", "n")?;
+        assert_eq!(svg.matches("<polyline").count(), 1);
+        assert_eq!(svg.matches("<line ").count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_chart_errors() {
+        let t = table();
+        let err = render_chart(&t, ChartKind::Bar, "month", "missing").unwrap_err();
+        assert!(err.message.contains("Column 'missing' not found"));
+        assert!(render_chart(&t, ChartKind::Line, "month", "t").is_err());
+        assert!(ChartKind::from_name("pie").is_err());
+    }
+}
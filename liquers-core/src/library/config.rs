@@ -0,0 +1,74 @@
+//! Commands reading the configuration of the environment
+
+use crate::context::{NGContext, NGEnvironment};
+use crate::error::Error;
+use crate::state::State;
+use crate::value::Value;
+
+use super::LibraryCommandRegistry;
+
+/// Value of the configuration key `name` of the environment (e.g. a base path or a feature flag).
+/// Only the configuration map held by the environment is accessible
+/// (see [NGEnvironment::get_config]), never the variables of the process environment.
+/// If the key is missing, the `default` text is returned if given (not empty), otherwise it is an error.
+pub fn config_get<E: NGEnvironment<Value = Value>>(
+    _state: &State<Value>,
+    name: String,
+    default: String,
+    context: NGContext<E>,
+) -> Result<Value, Error> {
+    match context.get_config_value(&name) {
+        Some(value) => Ok(value),
+        None if !default.is_empty() => Ok(Value::from(default)),
+        None => Err(Error::general_error(format!(
+            "Configuration key '{}' is not defined",
+            name
+        ))),
+    }
+}
+
+ng_register_all!(
+    /// Register the command reading the configuration
+    pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+        config_get(state, name: String, default: String = "", context),
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::SimpleNGEnvironment;
+    use crate::interpreter::NGPlanInterpreter;
+
+    fn env() -> Result<SimpleNGEnvironment<Value>, Error> {
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        crate::library::register_commands(env.get_mut_command_executor())?;
+        env.with_config_value("base_path", Value::from("data/reports"))
+            .with_config_value("beta", Value::Bool(true));
+        Ok(env)
+    }
+
+    #[tokio::test]
+    async fn test_config_get() -> Result<(), Error> {
+        let mut pi = NGPlanInterpreter::new(env()?.to_ref());
+        let state = pi.evaluate("config_get-base_path").await?;
+        assert_eq!(state.try_into_string()?, "data/reports");
+        let state = pi.evaluate("config_get-beta-false").await?;
+        assert_eq!(*state.read().unwrap(), Value::Bool(true));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_config_get_missing() -> Result<(), Error> {
+        let mut pi = NGPlanInterpreter::new(env()?.to_ref());
+        let state = pi.evaluate("config_get-output_path-out").await?;
+        assert_eq!(state.try_into_string()?, "out");
+
+        let err = pi.evaluate("config_get-output_path").await.unwrap_err();
+        assert!(err.message.contains("Configuration key 'output_path' is not defined"));
+        // The process environment is not accessible
+        let err = pi.evaluate("config_get-PATH").await.unwrap_err();
+        assert!(err.message.contains("'PATH' is not defined"));
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+//! Diagnostic commands describing the state of the evaluation
+
+use std::collections::BTreeMap;
+
+use crate::context::{ActionContext, NGContext, NGEnvironment};
+use crate::error::Error;
+use crate::state::State;
+use crate::value::{Value, ValueInterface};
+
+use super::LibraryCommandRegistry;
+
+fn optional_text(text: Option<String>) -> Value {
+    text.map(Value::from).unwrap_or(Value::None)
+}
+
+/// Describe the current context as an object, e.g. to find out why commands resolve the way they do:
+/// the current working directory key (parent of the last loaded resource), the filename,
+/// the namespaces in which the commands are looked up, the trace id and whether the input state has data.
+/// The input data (payload) is only reported as present or not, its contents are never included.
+pub fn context_info<E: NGEnvironment<Value = Value>>(
+    state: &State<Value>,
+    context: NGContext<E>,
+) -> Result<Value, Error> {
+    let namespaces = context
+        .get_namespaces()
+        .into_iter()
+        .map(Value::from)
+        .collect::<Vec<_>>();
+    let mut info = BTreeMap::new();
+    info.insert(
+        "cwd_key".to_owned(),
+        optional_text(context.get_cwd_key().map(|key| key.encode())),
+    );
+    info.insert(
+        "filename".to_owned(),
+        optional_text(context.get_metadata().filename),
+    );
+    info.insert("namespaces".to_owned(), Value::Array(namespaces));
+    info.insert("trace_id".to_owned(), optional_text(context.get_trace_id()));
+    info.insert(
+        "has_payload".to_owned(),
+        Value::Bool(!state.read().unwrap().is_none()),
+    );
+    Ok(Value::Object(info))
+}
+
+ng_register_all!(
+    /// Register the diagnostic commands
+    pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+        context_info(state, context),
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::SimpleNGEnvironment;
+    use crate::interpreter::NGPlanInterpreter;
+    use crate::metadata::MetadataRecord;
+    use crate::parse::{parse_key, parse_query};
+    use crate::plan::{PlanBuilder, Step};
+    use crate::query::{Key, ResourceName};
+    use crate::store::{AsyncStoreWrapper, MemoryStore, Store};
+
+    #[tokio::test]
+    async fn test_context_info() -> Result<(), Error> {
+        let store = MemoryStore::new(&Key::new());
+        store.set(&parse_key("data/secret.txt")?, b"password", &MetadataRecord::new().into())?;
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        env.with_async_store(Box::new(AsyncStoreWrapper(store)));
+        crate::library::register_commands(env.get_mut_command_executor())?;
+
+        // The filename is set by a filename step preceding the command
+        let query = parse_query("data/secret.txt/-/context_info")?;
+        let mut plan = PlanBuilder::new(query, env.get_command_metadata_registry()).build()?;
+        plan.steps.insert(1, Step::Filename(ResourceName::new("report.json".to_owned())));
+        let mut pi = NGPlanInterpreter::new(env.to_ref());
+        pi.with_plan(plan).with_trace_id(Some("request-1".to_owned()));
+        let state = pi.run().await?;
+
+        let info = state.read().unwrap().try_into_json_value()?;
+        assert_eq!(info["cwd_key"], serde_json::json!("data"));
+        assert_eq!(info["filename"], serde_json::json!("report.json"));
+        assert_eq!(info["trace_id"], serde_json::json!("request-1"));
+        assert_eq!(info["has_payload"], serde_json::json!(true));
+        assert!(info["namespaces"].as_array().unwrap().contains(&serde_json::json!("root")));
+        assert!(!serde_json::to_string(&info).unwrap().contains("password"));
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+//! Command describing the type and shape of a value, useful for debugging pipelines.
+
+use std::collections::BTreeMap;
+
+use crate::context::NGEnvironment;
+use crate::error::Error;
+use crate::state::State;
+use crate::value::{Value, ValueInterface};
+
+use super::table::Table;
+use super::LibraryCommandRegistry;
+
+/// Common type name of the values, ignoring the missing ones:
+/// `none` if all are missing (or there are none), `mixed` if the types differ
+fn common_type<'a>(values: impl Iterator<Item = &'a Value>) -> String {
+    let mut result: Option<String> = None;
+    for value in values.filter(|v| !v.is_none()) {
+        let name = value.type_name().to_string();
+        match &result {
+            None => result = Some(name),
+            Some(r) if *r != name => return "mixed".to_owned(),
+            _ => (),
+        }
+    }
+    result.unwrap_or_else(|| "none".to_owned())
+}
+
+/// Structured description of a value: the `type_identifier` and `type` of the value,
+/// its `kind` (`table`, `array`, `object`, `text`, `bytes` or `scalar`) and its shape:
+/// - table (non-empty array of records): `rows` and `columns` (`name` and `type` of each column),
+/// - array: `length` and `element_type`,
+/// - object: `keys`,
+/// - text and bytes: `length` (in characters or bytes).
+///
+/// Common types ignore missing values; differing types are reported as `mixed`.
+pub fn describe(value: &Value) -> Result<Value, Error> {
+    let mut description = BTreeMap::new();
+    description.insert("type_identifier".to_owned(), Value::from(value.identifier().to_string()));
+    description.insert("type".to_owned(), Value::from(value.type_name().to_string()));
+    let kind = match value {
+        Value::Array(a) if !a.is_empty() && a.iter().all(|v| matches!(v, Value::Object(_))) => {
+            let table = Table::from_value(value)?;
+            let columns = table
+                .columns
+                .iter()
+                .map(|c| {
+                    let cells = table.column_values(c);
+                    Value::Object(BTreeMap::from([
+                        ("name".to_owned(), Value::from(c.as_str())),
+                        ("type".to_owned(), Value::from(common_type(cells.iter()))),
+                    ]))
+                })
+                .collect();
+            description.insert("rows".to_owned(), Value::I64(table.len() as i64));
+            description.insert("columns".to_owned(), Value::Array(columns));
+            "table"
+        }
+        Value::Array(a) => {
+            description.insert("length".to_owned(), Value::I64(a.len() as i64));
+            description.insert("element_type".to_owned(), Value::from(common_type(a.iter())));
+            "array"
+        }
+        Value::Object(o) => {
+            description.insert(
+                "keys".to_owned(),
+                Value::Array(o.keys().map(|k| Value::from(k.as_str())).collect()),
+            );
+            "object"
+        }
+        Value::Text(t) => {
+            description.insert("length".to_owned(), Value::I64(t.chars().count() as i64));
+            "text"
+        }
+        Value::Bytes(b) => {
+            description.insert("length".to_owned(), Value::I64(b.len() as i64));
+            "bytes"
+        }
+        _ => "scalar",
+    };
+    description.insert("kind".to_owned(), Value::from(kind));
+    Ok(Value::Object(description))
+}
+
+/// Describe the type and shape of the state value, see [describe]
+pub fn describe_value(state: &State<Value>) -> Result<Value, Error> {
+    describe(&state.read().unwrap())
+}
+
+ng_register_all!(
+    /// Register the command describing values
+    pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+        describe_value(state),
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn describe_json(json: &str) -> serde_json::Value {
+        let value: Value = serde_json::from_str(json).unwrap();
+        describe_value(&State::new().with_data(value))
+            .unwrap()
+            .try_into_json_value()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_describe_table() {
+        let d = describe_json(r#"[{"name":"Ann","age":31},{"name":"Bob","age":null,"score":1.5},{"name":"Cid","age":"n/a"}]"#);
+        assert_eq!(d["kind"], json!("table"));
+        assert_eq!(d["rows"], json!(3));
+        assert_eq!(
+            d["columns"],
+            json!([
+                {"name": "age", "type": "mixed"},
+                {"name": "name", "type": "text"},
+                {"name": "score", "type": "f64"}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_describe_array() {
+        let d = describe_json("[1, 2, null, 3]");
+        assert_eq!(d["kind"], json!("array"));
+        assert_eq!(d["type"], json!("array"));
+        assert_eq!(d["length"], json!(4));
+        assert_eq!(d["element_type"], json!("i32"));
+        assert_eq!(describe_json("[]")["element_type"], json!("none"));
+    }
+
+    #[test]
+    fn test_describe_object() {
+        let d = describe_json(r#"{"b": 1, "a": {"x": true}}"#);
+        assert_eq!(d["kind"], json!("object"));
+        assert_eq!(d["type_identifier"], json!("dictionary"));
+        assert_eq!(d["keys"], json!(["a", "b"]));
+        assert_eq!(describe_json("\"hello\"")["length"], json!(5));
+        assert_eq!(describe_json("true")["kind"], json!("scalar"));
+    }
+}
@@ -0,0 +1,328 @@
+//! Commands encoding binary values as text (base64, hex, URL percent-encoding) and decoding them back.
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE};
+use base64::Engine;
+
+use crate::context::NGEnvironment;
+use crate::error::Error;
+use crate::state::State;
+use crate::value::{Value, ValueInterface};
+
+use super::table::cell_to_string;
+use super::LibraryCommandRegistry;
+
+/// Bytes to be encoded - binary value or UTF-8 bytes of a text
+fn input_bytes(value: &Value, command: &str) -> Result<Vec<u8>, Error> {
+    match value {
+        Value::Bytes(b) => Ok(b.clone()),
+        Value::Text(t) => Ok(t.as_bytes().to_vec()),
+        _ => Err(Error::conversion_error_with_message(
+            value.type_name(),
+            "bytes",
+            &format!("{} expects bytes or text", command),
+        )),
+    }
+}
+
+/// Text to be decoded, surrounding whitespace is ignored
+fn input_text(value: &Value, command: &str) -> Result<String, Error> {
+    match value {
+        Value::Text(t) => Ok(t.trim().to_owned()),
+        Value::Bytes(b) => std::str::from_utf8(b)
+            .map(|t| t.trim().to_owned())
+            .map_err(|e| {
+                Error::conversion_error_with_message(
+                    "bytes",
+                    "text",
+                    &format!("{} ({})", e, command),
+                )
+            }),
+        _ => Err(Error::conversion_error_with_message(
+            value.type_name(),
+            "text",
+            &format!("{} expects text or bytes", command),
+        )),
+    }
+}
+
+/// Encode bytes (or text) as base64 text; `url_safe` selects the URL and filename safe alphabet
+pub fn encode_base64(state: &State<Value>, url_safe: bool) -> Result<Value, Error> {
+    let b = input_bytes(&state.read().unwrap(), "encode_base64")?;
+    let engine = if url_safe { URL_SAFE } else { STANDARD };
+    Ok(Value::Text(engine.encode(b)))
+}
+
+/// Decode base64 text into bytes; `url_safe` selects the URL and filename safe alphabet
+pub fn decode_base64(state: &State<Value>, url_safe: bool) -> Result<Value, Error> {
+    let text = input_text(&state.read().unwrap(), "decode_base64")?;
+    let engine = if url_safe { URL_SAFE } else { STANDARD };
+    engine
+        .decode(text.as_bytes())
+        .map(Value::Bytes)
+        .map_err(|e| {
+            Error::conversion_error_with_message("text", "bytes", &format!("Invalid base64: {}", e))
+        })
+}
+
+/// Encode bytes (or text) as lowercase hexadecimal text
+pub fn encode_hex(state: &State<Value>) -> Result<Value, Error> {
+    let b = input_bytes(&state.read().unwrap(), "encode_hex")?;
+    Ok(Value::Text(
+        b.iter().map(|x| format!("{:02x}", x)).collect(),
+    ))
+}
+
+/// Decode hexadecimal text (upper or lower case) into bytes
+pub fn decode_hex(state: &State<Value>) -> Result<Value, Error> {
+    let text = input_text(&state.read().unwrap(), "decode_hex")?;
+    if text.len() % 2 != 0 {
+        return Err(Error::conversion_error_with_message(
+            "text",
+            "bytes",
+            &format!("Invalid hex: odd number of digits ({})", text.len()),
+        ));
+    }
+    let digits = text.as_bytes();
+    let digit = |i: usize| -> Result<u8, Error> {
+        (digits[i] as char)
+            .to_digit(16)
+            .map(|d| d as u8)
+            .ok_or_else(|| {
+                Error::conversion_error_with_message(
+                    "text",
+                    "bytes",
+                    &format!(
+                        "Invalid hex digit '{}' at offset {}",
+                        text.get(i..).and_then(|t| t.chars().next()).unwrap_or('?'),
+                        i
+                    ),
+                )
+            })
+    };
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| Ok(digit(i)? * 16 + digit(i + 1)?))
+        .collect::<Result<Vec<u8>, Error>>()
+        .map(Value::Bytes)
+}
+
+/// Percent-encode bytes; only the unreserved characters (`A-Z a-z 0-9 - _ . ~`) are kept as they are
+fn percent_encode(b: &[u8]) -> String {
+    let mut result = String::with_capacity(b.len());
+    for x in b {
+        match x {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                result.push(*x as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", x)),
+        }
+    }
+    result
+}
+
+/// Encode bytes (or text) for use in a URL component (percent-encoding, UTF-8 for text)
+pub fn url_encode(state: &State<Value>) -> Result<Value, Error> {
+    let b = input_bytes(&state.read().unwrap(), "url_encode")?;
+    Ok(Value::Text(percent_encode(&b)))
+}
+
+/// Decode a percent-encoded URL component; `+` is decoded as a space (as in query strings).
+/// The result is text if it is valid UTF-8, bytes otherwise.
+pub fn url_decode(state: &State<Value>) -> Result<Value, Error> {
+    let text = input_text(&state.read().unwrap(), "url_decode")?;
+    let digits = text.as_bytes();
+    let mut b = Vec::with_capacity(digits.len());
+    let mut i = 0;
+    while i < digits.len() {
+        match digits[i] {
+            b'%' => {
+                let code = text
+                    .get(i + 1..i + 3)
+                    .filter(|h| h.bytes().all(|c| c.is_ascii_hexdigit()))
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                    .ok_or_else(|| {
+                        Error::conversion_error_with_message(
+                            "text",
+                            "bytes",
+                            &format!(
+                                "Invalid percent-encoding '{}' at offset {}",
+                                text.get(i..(i + 3).min(text.len())).unwrap_or("%"),
+                                i
+                            ),
+                        )
+                    })?;
+                b.push(code);
+                i += 3;
+            }
+            b'+' => {
+                b.push(b' ');
+                i += 1;
+            }
+            x => {
+                b.push(x);
+                i += 1;
+            }
+        }
+    }
+    match String::from_utf8(b) {
+        Ok(t) => Ok(Value::Text(t)),
+        Err(e) => Ok(Value::Bytes(e.into_bytes())),
+    }
+}
+
+/// Build a query string (`key=value&...`) from an object, keys and values are percent-encoded.
+/// Arrays are represented by repeating the key for each element, missing values become empty.
+pub fn build_query_string(state: &State<Value>) -> Result<Value, Error> {
+    let value = state.read().unwrap();
+    let object = match &*value {
+        Value::Object(o) => o,
+        v => {
+            return Err(Error::conversion_error_with_message(
+                v.type_name(),
+                "object",
+                "build_query_string expects an object",
+            ))
+        }
+    };
+    let mut parameters = Vec::new();
+    for (key, value) in object.iter() {
+        let values = match value {
+            Value::Array(a) => a.iter().collect::<Vec<_>>(),
+            v => vec![v],
+        };
+        for v in values {
+            let v = if v.is_none() {
+                String::new()
+            } else {
+                cell_to_string(v)?
+            };
+            parameters.push(format!(
+                "{}={}",
+                percent_encode(key.as_bytes()),
+                percent_encode(v.as_bytes())
+            ));
+        }
+    }
+    Ok(Value::Text(parameters.join("&")))
+}
+
+ng_register_all!(
+    /// Register the encoding and decoding commands
+    pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+        encode_base64(state, url_safe: bool = false),
+        decode_base64(state, url_safe: bool = false),
+        encode_hex(state),
+        decode_hex(state),
+        url_encode(state),
+        url_decode(state),
+        build_query_string(state),
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::SimpleNGEnvironment;
+    use crate::interpreter::NGPlanInterpreter;
+
+    fn bytes(b: &[u8]) -> State<Value> {
+        State::new().with_data(Value::Bytes(b.to_vec()))
+    }
+
+    fn text(t: &str) -> State<Value> {
+        State::new().with_data(Value::from(t))
+    }
+
+    #[test]
+    fn test_base64_roundtrip() -> Result<(), Error> {
+        let data = vec![0u8, 1, 2, 250, 251, 252, 253, 254, 255];
+        let encoded = encode_base64(&bytes(&data), false)?;
+        assert_eq!(encoded, Value::from("AAEC+vv8/f7/"));
+        let decoded = decode_base64(&State::new().with_data(encoded), false)?;
+        assert_eq!(decoded, Value::Bytes(data.clone()));
+
+        let encoded = encode_base64(&bytes(&data), true)?;
+        assert_eq!(encoded, Value::from("AAEC-vv8_f7_"));
+        let decoded = decode_base64(&State::new().with_data(encoded), true)?;
+        assert_eq!(decoded, Value::Bytes(data));
+        Ok(())
+    }
+
+    #[test]
+    fn test_base64_invalid() {
+        assert!(decode_base64(&text("AAEC-vv8_f7_"), false).is_err());
+        let err = decode_base64(&text("AB!D"), false).unwrap_err();
+        assert!(err.to_string().contains("Invalid base64"));
+        assert!(encode_base64(&State::new().with_data(Value::I32(1)), false).is_err());
+    }
+
+    #[test]
+    fn test_hex_roundtrip() -> Result<(), Error> {
+        let encoded = encode_hex(&bytes(&[0, 15, 16, 255]))?;
+        assert_eq!(encoded, Value::from("000f10ff"));
+        assert_eq!(
+            decode_hex(&State::new().with_data(encoded))?,
+            Value::Bytes(vec![0, 15, 16, 255])
+        );
+        assert_eq!(decode_hex(&text("0A0b"))?, Value::Bytes(vec![10, 11]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_hex_invalid() {
+        let err = decode_hex(&text("abc")).unwrap_err();
+        assert!(err.to_string().contains("odd number of digits"));
+        let err = decode_hex(&text("0g")).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Invalid hex digit 'g' at offset 1"));
+    }
+
+    #[test]
+    fn test_url_roundtrip() -> Result<(), Error> {
+        let encoded = url_encode(&text("a b&c=d/é~"))?;
+        assert_eq!(encoded, Value::from("a%20b%26c%3Dd%2F%C3%A9~"));
+        let decoded = url_decode(&State::new().with_data(encoded))?;
+        assert_eq!(decoded, Value::from("a b&c=d/é~"));
+        assert_eq!(url_decode(&text("x+y%2By"))?, Value::from("x y+y"));
+        assert_eq!(
+            url_decode(&State::new().with_data(url_encode(&bytes(&[0, 255]))?))?,
+            Value::Bytes(vec![0, 255])
+        );
+        let err = url_decode(&text("100%")).unwrap_err();
+        assert!(err.to_string().contains("Invalid percent-encoding '%' at offset 3"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_query_string() -> Result<(), Error> {
+        let value: Value = serde_json::from_str(
+            r#"{"q": "rust lang", "page": 2, "tag": ["a&b", "c"], "empty": null}"#,
+        )
+        .unwrap();
+        let query = build_query_string(&State::new().with_data(value))?;
+        assert_eq!(
+            query,
+            Value::from("empty=&page=2&q=rust%20lang&tag=a%26b&tag=c")
+        );
+        assert!(build_query_string(&text("q=1")).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_encoding_commands() -> Result<(), Error> {
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        crate::library::register_commands(env.get_mut_command_executor())?;
+        let mut pi = NGPlanInterpreter::new(env.to_ref());
+        pi.set_query("encode_hex").await?;
+        let state = pi.apply_state(text("??>")).await?;
+        assert_eq!(state.try_into_string()?, "3f3f3e");
+        pi.set_query("encode_base64-t").await?;
+        let state = pi.apply_state(text("??>")).await?;
+        assert_eq!(state.try_into_string()?, "Pz8-");
+        pi.set_query("encode_base64").await?;
+        let state = pi.apply_state(text("??>")).await?;
+        assert_eq!(state.try_into_string()?, "Pz8+");
+        Ok(())
+    }
+}
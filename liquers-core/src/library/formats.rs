@@ -0,0 +1,398 @@
+//! Commands parsing text values (JSON, YAML, CSV) into structured values
+//! and formatting values as JSON or YAML text.
+
+use std::collections::BTreeMap;
+
+use crate::context::NGEnvironment;
+use crate::error::{Error, ErrorType};
+use crate::state::State;
+use crate::value::{Value, ValueInterface};
+
+use super::table::Table;
+use super::LibraryCommandRegistry;
+
+/// Text to be parsed - text value or UTF-8 encoded bytes
+fn input_text(value: &Value, command: &str) -> Result<String, Error> {
+    match value {
+        Value::Text(t) => Ok(t.clone()),
+        Value::Bytes(b) => String::from_utf8(b.clone()).map_err(|e| {
+            Error::conversion_error_with_message("bytes", "text", &format!("{} ({})", e, command))
+        }),
+        _ => Err(Error::conversion_error_with_message(
+            value.type_name(),
+            "text",
+            &format!("{} expects text or bytes", command),
+        )),
+    }
+}
+
+fn parse_error(format: &str, line: usize, column: usize, message: &str) -> Error {
+    Error::new(
+        ErrorType::SerializationError,
+        format!(
+            "{} parse error at line {}, column {}: {}",
+            format, line, column, message
+        ),
+    )
+}
+
+/// Parse JSON text, errors report the line and column
+fn parse_json<T: serde::de::DeserializeOwned>(text: &str) -> Result<T, Error> {
+    serde_json::from_str(text)
+        .map_err(|e| parse_error("JSON", e.line(), e.column(), &e.to_string()))
+}
+
+/// Parse YAML text, errors report the line and column if known
+fn parse_yaml<T: serde::de::DeserializeOwned>(text: &str) -> Result<T, Error> {
+    serde_yaml::from_str(text).map_err(|e| match e.location() {
+        Some(location) => parse_error("YAML", location.line(), location.column(), &e.to_string()),
+        None => Error::new(
+            ErrorType::SerializationError,
+            format!("YAML parse error: {}", e),
+        ),
+    })
+}
+
+/// Parse JSON text into a value
+pub fn from_json(state: &State<Value>) -> Result<Value, Error> {
+    let text = input_text(&state.read().unwrap(), "from_json")?;
+    parse_json(&text)
+}
+
+/// Parse YAML text into a value
+pub fn from_yaml(state: &State<Value>) -> Result<Value, Error> {
+    let text = input_text(&state.read().unwrap(), "from_yaml")?;
+    parse_yaml(&text)
+}
+
+/// Split CSV text into records of fields.
+/// Fields may be quoted with double quotes, a double quote inside a quoted field is escaped by doubling it.
+/// Quoted fields may contain separators and line breaks. Empty lines are skipped.
+/// Each record is returned together with the line number where it starts.
+pub fn parse_csv_records(text: &str, separator: char) -> Result<Vec<(usize, Vec<String>)>, Error> {
+    let mut records = Vec::new();
+    let mut record: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut field_was_quoted = false;
+    let (mut line, mut column) = (1, 0);
+    let mut record_line = 1;
+    let (mut quote_line, mut quote_column) = (1, 0);
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        column += 1;
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    column += 1;
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                '\n' => {
+                    field.push(c);
+                    line += 1;
+                    column = 0;
+                }
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() && !field_was_quoted => {
+                quoted = true;
+                field_was_quoted = true;
+                quote_line = line;
+                quote_column = column;
+            }
+            '"' => {
+                return Err(parse_error(
+                    "CSV",
+                    line,
+                    column,
+                    "unexpected quote in an unquoted field",
+                ))
+            }
+            c if c == separator => {
+                record.push(std::mem::take(&mut field));
+                field_was_quoted = false;
+            }
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' => {
+                if !(record.is_empty() && field.is_empty() && !field_was_quoted) {
+                    record.push(std::mem::take(&mut field));
+                    records.push((record_line, std::mem::take(&mut record)));
+                }
+                field_was_quoted = false;
+                record_line = line + 1;
+                line += 1;
+                column = 0;
+            }
+            _ if field_was_quoted => {
+                return Err(parse_error(
+                    "CSV",
+                    line,
+                    column,
+                    "unexpected character after a closing quote",
+                ))
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(parse_error(
+            "CSV",
+            quote_line,
+            quote_column,
+            "quoted field is not terminated",
+        ));
+    }
+    if !(record.is_empty() && field.is_empty() && !field_was_quoted) {
+        record.push(field);
+        records.push((record_line, record));
+    }
+    Ok(records)
+}
+
+/// Value of a CSV cell: empty cell is None, numbers are converted, anything else stays text
+fn csv_cell(text: String) -> Value {
+    if text.is_empty() {
+        return Value::None;
+    }
+    if let Ok(n) = text.parse::<i32>() {
+        return Value::I32(n);
+    }
+    if let Ok(n) = text.parse::<i64>() {
+        return Value::I64(n);
+    }
+    if text.chars().any(|c| c.is_ascii_digit()) {
+        if let Ok(x) = text.parse::<f64>() {
+            return Value::F64(x);
+        }
+    }
+    Value::Text(text)
+}
+
+/// Parse CSV text with a header line into a table (array of records).
+/// The `separator` is a single character, "tab" can be used for tab separated values.
+pub fn from_csv(state: &State<Value>, separator: String) -> Result<Value, Error> {
+    let text = input_text(&state.read().unwrap(), "from_csv")?;
+    let separator = match separator.as_str() {
+        "tab" | "\\t" => '\t',
+        s if s.chars().count() == 1 => s.chars().next().unwrap(),
+        _ => {
+            return Err(Error::general_error(format!(
+                "CSV separator must be a single character, '{}' given",
+                separator
+            )))
+        }
+    };
+    let mut records = parse_csv_records(&text, separator)?.into_iter();
+    let columns = records.next().map(|(_, r)| r).unwrap_or_default();
+    for (i, column) in columns.iter().enumerate() {
+        if columns[..i].contains(column) {
+            return Err(parse_error(
+                "CSV",
+                1,
+                1,
+                &format!("duplicate column '{}' in the header", column),
+            ));
+        }
+    }
+    let mut table = Table::new(columns.clone());
+    for (line, record) in records {
+        if record.len() != columns.len() {
+            return Err(parse_error(
+                "CSV",
+                line,
+                1,
+                &format!("expected {} fields, found {}", columns.len(), record.len()),
+            ));
+        }
+        let row = columns
+            .iter()
+            .cloned()
+            .zip(record.into_iter().map(csv_cell))
+            .collect::<BTreeMap<_, _>>();
+        table.rows.push(row);
+    }
+    Ok(table.into_value())
+}
+
+/// Value to be formatted as JSON: text (or bytes) is parsed as JSON, other values are used directly
+fn json_input(value: &Value, command: &str) -> Result<serde_json::Value, Error> {
+    match value {
+        Value::Text(_) | Value::Bytes(_) => parse_json(&input_text(value, command)?),
+        _ => value.try_into_json_value(),
+    }
+}
+
+fn serialization_error(format: &str, e: impl std::fmt::Display) -> Error {
+    Error::new(
+        ErrorType::SerializationError,
+        format!("Can't format the value as {}: {}", format, e),
+    )
+}
+
+/// Format a value (or JSON text) as indented JSON text; `indent` is the number of spaces per level
+pub fn json_pretty(state: &State<Value>, indent: i64) -> Result<Value, Error> {
+    if !(0..=16).contains(&indent) {
+        return Err(Error::general_error(format!(
+            "Invalid JSON indentation {}, expected 0 to 16 spaces",
+            indent
+        )));
+    }
+    let value = json_input(&state.read().unwrap(), "json_pretty")?;
+    let indent = " ".repeat(indent as usize);
+    let mut b = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    let mut serializer = serde_json::Serializer::with_formatter(&mut b, formatter);
+    serde::Serialize::serialize(&value, &mut serializer)
+        .map_err(|e| serialization_error("JSON", e))?;
+    String::from_utf8(b)
+        .map(Value::Text)
+        .map_err(|e| serialization_error("JSON", e))
+}
+
+/// Format a value (or JSON text) as compact JSON text without whitespace
+pub fn json_minify(state: &State<Value>) -> Result<Value, Error> {
+    let value = json_input(&state.read().unwrap(), "json_minify")?;
+    serde_json::to_string(&value)
+        .map(Value::Text)
+        .map_err(|e| serialization_error("JSON", e))
+}
+
+/// Format a value (or YAML text) as YAML text, indented by two spaces per level
+pub fn yaml_format(state: &State<Value>) -> Result<Value, Error> {
+    let value = state.read().unwrap();
+    let yaml = match &*value {
+        Value::Text(_) | Value::Bytes(_) => {
+            let parsed: serde_yaml::Value = parse_yaml(&input_text(&value, "yaml_format")?)?;
+            serde_yaml::to_string(&parsed)
+        }
+        _ => serde_yaml::to_string(&value.try_into_json_value()?),
+    };
+    yaml.map(Value::Text)
+        .map_err(|e| serialization_error("YAML", e))
+}
+
+ng_register_all!(
+    /// Register the data format commands
+    pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+        from_json(state),
+        from_yaml(state),
+        from_csv(state, separator: String = ","),
+        json_pretty(state, indent: i64 = 2),
+        json_minify(state),
+        yaml_format(state),
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(t: &str) -> State<Value> {
+        State::new().with_data(Value::from(t))
+    }
+
+    #[test]
+    fn test_from_json() -> Result<(), Error> {
+        let value = from_json(
+            &State::new().with_data(Value::Bytes(br#"{"a": [1, 2.5, "x"], "b": null}"#.to_vec())),
+        )?;
+        let expected: Value = serde_json::from_str(r#"{"a": [1, 2.5, "x"], "b": null}"#).unwrap();
+        assert_eq!(value, expected);
+
+        let err = from_json(&text("{\n  \"a\": [1, 2,\n}")).unwrap_err();
+        assert!(err.to_string().contains("line 3, column 1"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_yaml() -> Result<(), Error> {
+        let value = from_yaml(&text("title: Report\nitems:\n  - 1\n  - two\n"))?;
+        let expected: Value =
+            serde_json::from_str(r#"{"title": "Report", "items": [1, "two"]}"#).unwrap();
+        assert_eq!(value, expected);
+
+        let err = from_yaml(&text("a: 1\nb: [1, 2\nc: 3\n")).unwrap_err();
+        assert!(err.to_string().contains("YAML parse error at line"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_csv() -> Result<(), Error> {
+        let value = from_csv(
+            &text("name,x,note\r\na,1,\"quoted, with \"\"quotes\"\"\"\nb,2.5,\n\n"),
+            ",".to_owned(),
+        )?;
+        let table = Table::from_value(&value)?;
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(0, "name"), &Value::from("a"));
+        assert_eq!(table.get(0, "x"), &Value::I32(1));
+        assert_eq!(
+            table.get(0, "note"),
+            &Value::from("quoted, with \"quotes\"")
+        );
+        assert_eq!(table.get(1, "x"), &Value::F64(2.5));
+        assert_eq!(table.get(1, "note"), &Value::None);
+
+        let value = from_csv(&text("a\tb\n1\t2\n"), "tab".to_owned())?;
+        assert_eq!(Table::from_value(&value)?.get(0, "b"), &Value::I32(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_csv_malformed() {
+        let err = from_csv(&text("a,b\n1,\"2\n"), ",".to_owned()).unwrap_err();
+        assert!(err.to_string().contains("line 2, column 3"));
+        let err = from_csv(&text("a,b\n1,2\n3\n"), ",".to_owned()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("line 3, column 1: expected 2 fields, found 1"));
+        let err = from_csv(&text("a,b\n1,x\"y\n"), ",".to_owned()).unwrap_err();
+        assert!(err.to_string().contains("line 2, column 4"));
+    }
+
+    #[test]
+    fn test_json_pretty() -> Result<(), Error> {
+        let value: Value = serde_json::from_str(r#"{"a": [1, "x"], "b": null}"#).unwrap();
+        let pretty = json_pretty(&State::new().with_data(value), 2)?;
+        assert_eq!(
+            pretty.try_into_string()?,
+            "{\n  \"a\": [\n    1,\n    \"x\"\n  ],\n  \"b\": null\n}"
+        );
+        let pretty = json_pretty(&text(r#"{"a":{"b":1}}"#), 4)?;
+        assert_eq!(
+            pretty.try_into_string()?,
+            "{\n    \"a\": {\n        \"b\": 1\n    }\n}"
+        );
+        assert!(json_pretty(&text("{}"), -1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_minify_and_yaml() -> Result<(), Error> {
+        let minified = json_minify(&text("{\n  \"a\": [1, 2.5],\n  \"b\": \"x y\"\n}\n"))?;
+        assert_eq!(minified.try_into_string()?, r#"{"a":[1,2.5],"b":"x y"}"#);
+
+        let value: Value = serde_json::from_str(r#"{"a": [1, 2], "b": "x"}"#).unwrap();
+        let yaml = yaml_format(&State::new().with_data(value))?;
+        assert_eq!(yaml.try_into_string()?, "a:\n- 1\n- 2\nb: x\n");
+        let yaml = yaml_format(&text("{b: 1, a: [x]}"))?;
+        assert_eq!(yaml.try_into_string()?, "b: 1\na:\n- x\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_malformed() {
+        let err = json_pretty(&text("{\n  \"a\": 1,\n  \"b\"\n}"), 2).unwrap_err();
+        assert!(err.to_string().contains("JSON parse error at line 4, column 1"));
+        let err = json_minify(&text("[1, 2")).unwrap_err();
+        assert!(err.to_string().contains("line 1"));
+        let err = yaml_format(&text("a: [1, 2\nb: 3\n")).unwrap_err();
+        assert!(err.to_string().contains("YAML parse error at line"));
+    }
+}
@@ -0,0 +1,250 @@
+//! Commands operating on geospatial points and bounding boxes (feature `geo`).
+//!
+//! Like tables (see [super::table]), geo values have no dedicated [Value] variant,
+//! they are represented by their GeoJSON objects:
+//! * a point is a GeoJSON `Point` geometry, `{"type": "Point", "coordinates": [lon, lat]}`,
+//! * a bounding box is a GeoJSON `Polygon` geometry with the `bbox` member
+//!   `[min_lon, min_lat, max_lon, max_lat]`.
+//!
+//! Bounding boxes do not wrap around the antimeridian.
+
+use std::collections::BTreeMap;
+
+use crate::context::NGEnvironment;
+use crate::error::Error;
+use crate::state::State;
+use crate::value::{Value, ValueInterface};
+
+use super::LibraryCommandRegistry;
+
+/// Geographic point in degrees (WGS 84)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Bounding box in degrees (WGS 84)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+fn geometry(geometry_type: &str, coordinates: Value) -> BTreeMap<String, Value> {
+    let mut o = BTreeMap::new();
+    o.insert("type".to_owned(), Value::Text(geometry_type.to_owned()));
+    o.insert("coordinates".to_owned(), coordinates);
+    o
+}
+
+fn numbers(value: &Value, what: &str, count: usize) -> Result<Vec<f64>, Error> {
+    match value {
+        Value::Array(a) if a.len() >= count => a.iter().map(|x| x.try_into_f64()).collect(),
+        _ => Err(Error::conversion_error_with_message(
+            value.type_name(),
+            what,
+            &format!("Array of at least {} numbers expected", count),
+        )),
+    }
+}
+
+impl Point {
+    pub fn new(lat: f64, lon: f64) -> Result<Self, Error> {
+        if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+            return Err(Error::general_error(format!(
+                "Invalid coordinates: latitude {}, longitude {}",
+                lat, lon
+            )));
+        }
+        Ok(Point { lat, lon })
+    }
+
+    /// Read a point from a GeoJSON `Point` geometry or from a record with `lat` and `lon` fields
+    pub fn from_value(value: &Value) -> Result<Self, Error> {
+        match value {
+            Value::Object(o) => match (o.get("type"), o.get("coordinates"), o.get("lat"), o.get("lon")) {
+                (Some(Value::Text(t)), Some(coordinates), _, _) if t == "Point" => {
+                    let c = numbers(coordinates, "point", 2)?;
+                    Point::new(c[1], c[0])
+                }
+                (_, _, Some(lat), Some(lon)) => Point::new(lat.try_into_f64()?, lon.try_into_f64()?),
+                _ => Err(Error::conversion_error_with_message(
+                    "object",
+                    "point",
+                    "GeoJSON Point or a record with lat and lon expected",
+                )),
+            },
+            _ => Err(Error::conversion_error(value.type_name(), "point")),
+        }
+    }
+
+    /// GeoJSON `Point` geometry
+    pub fn to_value(&self) -> Value {
+        Value::Object(geometry(
+            "Point",
+            Value::Array(vec![Value::F64(self.lon), Value::F64(self.lat)]),
+        ))
+    }
+}
+
+impl BoundingBox {
+    /// Smallest bounding box containing all the points; None if there are no points
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a Point>) -> Option<Self> {
+        points.into_iter().fold(None, |bbox, p| {
+            Some(match bbox {
+                None => BoundingBox {
+                    min_lon: p.lon,
+                    min_lat: p.lat,
+                    max_lon: p.lon,
+                    max_lat: p.lat,
+                },
+                Some(b) => BoundingBox {
+                    min_lon: b.min_lon.min(p.lon),
+                    min_lat: b.min_lat.min(p.lat),
+                    max_lon: b.max_lon.max(p.lon),
+                    max_lat: b.max_lat.max(p.lat),
+                },
+            })
+        })
+    }
+
+    /// True if the point is inside the bounding box or on its boundary
+    pub fn contains(&self, point: &Point) -> bool {
+        (self.min_lat..=self.max_lat).contains(&point.lat)
+            && (self.min_lon..=self.max_lon).contains(&point.lon)
+    }
+
+    /// Read a bounding box from a GeoJSON object with a `bbox` member
+    /// or from an array `[min_lon, min_lat, max_lon, max_lat]`
+    pub fn from_value(value: &Value) -> Result<Self, Error> {
+        let b = match value {
+            Value::Object(o) => match o.get("bbox") {
+                Some(bbox) => numbers(bbox, "bounding box", 4)?,
+                None => {
+                    return Err(Error::conversion_error_with_message(
+                        "object",
+                        "bounding box",
+                        "GeoJSON object with bbox expected",
+                    ))
+                }
+            },
+            Value::Array(_) => numbers(value, "bounding box", 4)?,
+            _ => return Err(Error::conversion_error(value.type_name(), "bounding box")),
+        };
+        Ok(BoundingBox {
+            min_lon: b[0],
+            min_lat: b[1],
+            max_lon: b[2],
+            max_lat: b[3],
+        })
+    }
+
+    /// GeoJSON `Polygon` geometry (the box outline) with the `bbox` member
+    pub fn to_value(&self) -> Value {
+        let corner = |lon: f64, lat: f64| Value::Array(vec![Value::F64(lon), Value::F64(lat)]);
+        let ring = vec![
+            corner(self.min_lon, self.min_lat),
+            corner(self.max_lon, self.min_lat),
+            corner(self.max_lon, self.max_lat),
+            corner(self.min_lon, self.max_lat),
+            corner(self.min_lon, self.min_lat),
+        ];
+        let mut o = geometry("Polygon", Value::Array(vec![Value::Array(ring)]));
+        o.insert(
+            "bbox".to_owned(),
+            Value::Array(vec![
+                Value::F64(self.min_lon),
+                Value::F64(self.min_lat),
+                Value::F64(self.max_lon),
+                Value::F64(self.max_lat),
+            ]),
+        );
+        Value::Object(o)
+    }
+}
+
+/// Create a point from latitude and longitude
+pub fn point(_state: &State<Value>, lat: f64, lon: f64) -> Result<Value, Error> {
+    Ok(Point::new(lat, lon)?.to_value())
+}
+
+/// Bounding box of a collection of points.
+/// The collection is an array of points (GeoJSON points or records with `lat` and `lon`),
+/// e.g. a table with `lat` and `lon` columns.
+pub fn bbox(state: &State<Value>) -> Result<Value, Error> {
+    let points = match &*state.read().unwrap() {
+        Value::Array(a) => a.iter().map(Point::from_value).collect::<Result<Vec<_>, _>>()?,
+        value => vec![Point::from_value(value)?],
+    };
+    BoundingBox::from_points(&points)
+        .map(|b| b.to_value())
+        .ok_or_else(|| Error::general_error("Bounding box of an empty collection".to_owned()))
+}
+
+/// Check whether a bounding box contains a point given by latitude and longitude
+pub fn contains(state: &State<Value>, lat: f64, lon: f64) -> Result<Value, Error> {
+    let bbox = BoundingBox::from_value(&state.read().unwrap())?;
+    Ok(Value::Bool(bbox.contains(&Point::new(lat, lon)?)))
+}
+
+ng_register_all!(
+    /// Register the geographic commands
+    pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+        point(state, lat: f64, lon: f64),
+        bbox(state),
+        contains(state, lat: f64, lon: f64),
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(json: &str) -> State<Value> {
+        let value: Value = serde_json::from_str(json).unwrap();
+        State::new().with_data(value)
+    }
+
+    #[test]
+    fn test_bbox() -> Result<(), Error> {
+        let s = state(
+            r#"[{"lat":50.1,"lon":14.4},{"type":"Point","coordinates":[16.6,49.2]},{"lat":48.1,"lon":17.1}]"#,
+        );
+        let b = BoundingBox::from_value(&bbox(&s)?)?;
+        assert_eq!(
+            b,
+            BoundingBox {
+                min_lon: 14.4,
+                min_lat: 48.1,
+                max_lon: 17.1,
+                max_lat: 50.1
+            }
+        );
+        assert!(bbox(&state("[]")).is_err());
+        assert!(bbox(&state(r#"[{"lat":91,"lon":0}]"#)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_point_in_bbox() -> Result<(), Error> {
+        let b = BoundingBox {
+            min_lon: 14.0,
+            min_lat: 48.0,
+            max_lon: 17.0,
+            max_lat: 51.0,
+        };
+        let s = State::new().with_data(b.to_value());
+        assert_eq!(contains(&s, 50.0, 15.0)?, Value::Bool(true));
+        assert_eq!(contains(&s, 48.0, 17.0)?, Value::Bool(true));
+        assert_eq!(contains(&s, 52.0, 15.0)?, Value::Bool(false));
+        assert_eq!(contains(&s, 50.0, 13.9)?, Value::Bool(false));
+
+        let p = Point::from_value(&point(&State::new(), 50.0, 15.0)?)?;
+        assert_eq!(p, Point { lat: 50.0, lon: 15.0 });
+        assert!(b.contains(&p));
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+//! Commands working with the metadata of the state
+
+use crate::context::{ActionContext, NGContext, NGEnvironment};
+use crate::error::Error;
+use crate::metadata::Metadata;
+use crate::parse::parse_key;
+use crate::state::State;
+use crate::value::{Value, ValueInterface};
+
+use super::LibraryCommandRegistry;
+
+/// Set user-defined attributes in the metadata, the value is passed through unchanged.
+/// Attributes are specified either as name/value pairs (`set_meta-title-Report-owner-alice`)
+/// or as object values, which are merged into the attributes.
+pub fn set_meta<E: NGEnvironment<Value = Value>>(
+    state: &State<Value>,
+    attributes: Vec<Value>,
+    context: NGContext<E>,
+) -> Result<Value, Error> {
+    let mut name: Option<String> = None;
+    for attribute in attributes {
+        if let Some(n) = name.take() {
+            context.set_attribute(&n, attribute.try_into_json_value()?);
+            continue;
+        }
+        match attribute {
+            Value::Object(o) => {
+                for (k, v) in o {
+                    context.set_attribute(&k, v.try_into_json_value()?);
+                }
+            }
+            _ => name = Some(attribute.try_into_string()?),
+        }
+    }
+    if let Some(n) = name {
+        return Err(Error::general_error(format!(
+            "Missing value for attribute '{}' in set_meta",
+            n
+        )));
+    }
+    Ok(state.read().unwrap().clone())
+}
+
+/// Metadata of a resource in the store as an object value (e.g. to branch on its status or attributes).
+/// Only the metadata are read, the data of the resource are not fetched; the state is ignored.
+/// Slashes in the key are encoded as `~I`, e.g. `meta_of-data~Ireport.csv`.
+pub fn meta_of<E: NGEnvironment<Value = Value>>(
+    _state: &State<Value>,
+    key: String,
+    context: NGContext<E>,
+) -> Result<Value, Error> {
+    let key = parse_key(&key)?;
+    let json = match context.get_store().get_metadata(&key)? {
+        Metadata::MetadataRecord(m) => serde_json::to_value(m).map_err(|e| {
+            Error::general_error(format!("Can't convert the metadata of {}: {}", key, e))
+        })?,
+        Metadata::LegacyMetadata(json) => json,
+    };
+    serde_json::from_value(json)
+        .map_err(|e| Error::general_error(format!("Can't convert the metadata of {}: {}", key, e)))
+}
+
+ng_register_all!(
+    /// Register the metadata commands
+    pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+        set_meta(state, multiple attributes:Value, context),
+        meta_of(state, key: String, context),
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::SimpleNGEnvironment;
+    use crate::interpreter::NGPlanInterpreter;
+
+    #[tokio::test]
+    async fn test_set_meta() -> Result<(), Error> {
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        crate::library::register_commands(env.get_mut_command_executor())?;
+        let mut pi = NGPlanInterpreter::new(env.to_ref());
+        let state = pi.evaluate("set_meta-title-Report-owner-alice").await?;
+        if let Metadata::MetadataRecord(m) = &*state.metadata {
+            assert_eq!(m.attributes.len(), 2);
+            assert_eq!(m.attributes["title"], serde_json::json!("Report"));
+            assert_eq!(m.attributes["owner"], serde_json::json!("alice"));
+        } else {
+            panic!("MetadataRecord expected");
+        }
+        let json = state.metadata.to_json().unwrap();
+        assert!(json.contains("\"owner\":\"alice\""));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_set_meta_missing_value() -> Result<(), Error> {
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        crate::library::register_commands(env.get_mut_command_executor())?;
+        let mut pi = NGPlanInterpreter::new(env.to_ref());
+        assert!(pi.evaluate("set_meta-title").await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_meta_of() -> Result<(), Error> {
+        use crate::metadata::{MetadataRecord, Status};
+        use crate::query::Key;
+        use crate::store::{MemoryStore, Store};
+
+        let store = MemoryStore::new(&Key::new());
+        let mut metadata = MetadataRecord::new();
+        metadata
+            .with_status(Status::Ready)
+            .with_attribute("rows", serde_json::json!(42));
+        store.set(&parse_key("data/a.csv")?, b"a,b\n1,2\n", &metadata.into())?;
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        env.with_store(Box::new(store));
+        crate::library::register_commands(env.get_mut_command_executor())?;
+        let mut pi = NGPlanInterpreter::new(env.to_ref());
+        let state = pi.evaluate("meta_of-data~Ia.csv").await?;
+        let record = state.read().unwrap().try_into_json_value()?;
+        assert_eq!(record["status"], serde_json::json!("Ready"));
+        assert_eq!(record["attributes"]["rows"], serde_json::json!(42));
+        assert!(pi.evaluate("meta_of-data~Imissing.csv").await.is_err());
+        Ok(())
+    }
+}
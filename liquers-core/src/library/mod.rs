@@ -0,0 +1,108 @@
+//! Library of general purpose commands operating on the core [Value](crate::value::Value).
+//! Commands are registered into a [NGCommandRegistry] by [register_commands].
+
+use crate::commands::NGCommandRegistry;
+use crate::context::{NGContext, NGEnvRef, NGEnvironment};
+use crate::error::Error;
+use crate::value::Value;
+
+pub mod arrays;
+#[cfg(feature = "chart")]
+pub mod chart;
+pub mod config;
+pub mod debug;
+pub mod describe;
+pub mod encoding;
+pub mod formats;
+#[cfg(feature = "geo")]
+pub mod geo;
+pub mod meta;
+pub mod objects;
+pub mod predicate;
+pub mod table;
+pub mod template;
+
+/// Command registry type used by the library commands
+pub type LibraryCommandRegistry<E> = NGCommandRegistry<NGEnvRef<E>, Value, NGContext<E>>;
+
+/// Commands of the optional library modules, by the feature enabling them.
+/// Commands of disabled features are declared in the registry (see
+/// [CommandMetadataRegistry::add_feature_gated](crate::command_metadata::CommandMetadataRegistry::add_feature_gated)),
+/// so that using them reports the missing feature.
+pub const FEATURE_GATED_COMMANDS: &[(&str, &[&str])] = &[
+    ("chart", &["chart"]),
+    ("geo", &["point", "bbox", "contains"]),
+];
+
+fn is_feature_enabled(feature: &str) -> bool {
+    match feature {
+        "chart" => cfg!(feature = "chart"),
+        "geo" => cfg!(feature = "geo"),
+        _ => false,
+    }
+}
+
+/// Register all the library commands
+pub fn register_commands<E: NGEnvironment<Value = Value>>(
+    cr: &mut LibraryCommandRegistry<E>,
+) -> Result<(), Error> {
+    for (feature, commands) in FEATURE_GATED_COMMANDS {
+        if !is_feature_enabled(feature) {
+            for command in commands.iter() {
+                cr.command_metadata_registry.add_feature_gated(command, feature);
+            }
+        }
+    }
+    arrays::register_commands(cr)?;
+    #[cfg(feature = "chart")]
+    chart::register_commands(cr)?;
+    config::register_commands(cr)?;
+    debug::register_commands(cr)?;
+    describe::register_commands(cr)?;
+    encoding::register_commands(cr)?;
+    formats::register_commands(cr)?;
+    #[cfg(feature = "geo")]
+    geo::register_commands(cr)?;
+    meta::register_commands(cr)?;
+    objects::register_commands(cr)?;
+    predicate::register_commands(cr)?;
+    table::register_commands(cr)?;
+    template::register_commands(cr)?;
+    for command in WARN_IF_EMPTY_COMMANDS {
+        cr.command_metadata_registry
+            .get_mut(*command)
+            .ok_or_else(|| {
+                Error::general_error(format!("Command '{}' is not registered", command))
+            })?
+            .with_warn_if_empty();
+    }
+    Ok(())
+}
+
+/// Commands (e.g. filters and aggregations) logging a warning when they produce an empty result
+/// from a non-empty input, see [CommandMetadata::warn_if_empty](crate::command_metadata::CommandMetadata::warn_if_empty).
+pub const WARN_IF_EMPTY_COMMANDS: &[&str] = &["intersection", "difference", "pivot", "group_agg"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(feature = "geo"))]
+    #[tokio::test]
+    async fn test_feature_gated_command() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::interpreter::NGPlanInterpreter;
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        register_commands(env.get_mut_command_executor())?;
+        let mut pi = NGPlanInterpreter::new(env.to_ref());
+        let err = pi.evaluate("point-50-14").await.unwrap_err();
+        assert_eq!(
+            err.message,
+            "Command 'point' requires the 'geo' feature which is not enabled"
+        );
+        let err = pi.evaluate("no_such_command").await.unwrap_err();
+        assert!(err.message.contains("not registered"));
+        Ok(())
+    }
+}
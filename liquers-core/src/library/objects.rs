@@ -0,0 +1,218 @@
+//! Commands operating on object values (maps from names to values), e.g. configurations.
+
+use std::collections::BTreeMap;
+
+use crate::context::NGEnvironment;
+use crate::error::Error;
+use crate::state::State;
+use crate::value::{Value, ValueInterface};
+
+use super::LibraryCommandRegistry;
+
+/// How to resolve a conflict when both merged objects contain a different non-object value
+/// under the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeConflict {
+    /// The value of the later object is used (`last`)
+    LastWins,
+    /// The value of the earlier object is kept (`first`)
+    FirstWins,
+    /// Conflict is an error (`error`)
+    Error,
+}
+
+impl MergeConflict {
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "last" => Ok(MergeConflict::LastWins),
+            "first" => Ok(MergeConflict::FirstWins),
+            "error" => Ok(MergeConflict::Error),
+            _ => Err(Error::general_error(format!(
+                "Unknown merge conflict strategy '{}'; expected 'last', 'first' or 'error'",
+                name
+            ))),
+        }
+    }
+}
+
+/// Deep-merge `value` into `target`.
+/// Objects are merged recursively. Arrays are concatenated if `concat_arrays` is set,
+/// otherwise they are treated as any other value.
+/// Different values under the same name are resolved by the `conflict` strategy,
+/// `path` is the location of the target used in error messages.
+pub fn merge_into(
+    target: &mut Value,
+    value: Value,
+    conflict: MergeConflict,
+    concat_arrays: bool,
+    path: &str,
+) -> Result<(), Error> {
+    match (target, value) {
+        (Value::Object(t), Value::Object(o)) => {
+            for (name, v) in o {
+                let item_path = format!("{}/{}", path, name);
+                match t.get_mut(&name) {
+                    Some(existing) => merge_into(existing, v, conflict, concat_arrays, &item_path)?,
+                    None => {
+                        t.insert(name, v);
+                    }
+                }
+            }
+        }
+        (Value::Array(t), Value::Array(a)) if concat_arrays => t.extend(a),
+        (t, v) if *t == v => {}
+        (t, v) => match conflict {
+            MergeConflict::LastWins => *t = v,
+            MergeConflict::FirstWins => {}
+            MergeConflict::Error => {
+                return Err(Error::general_error(format!(
+                    "Merge conflict at '{}': {} vs {}",
+                    if path.is_empty() { "/" } else { path },
+                    t.try_into_json_value()?,
+                    v.try_into_json_value()?
+                )))
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Deep-merge an array of objects into a single object, in the order of the array.
+/// `conflict` is the conflict strategy (`last`, `first` or `error`, see [MergeConflict]),
+/// `concat_arrays` selects whether arrays are concatenated or merged like other values.
+pub fn merge(state: &State<Value>, conflict: String, concat_arrays: bool) -> Result<Value, Error> {
+    let conflict = MergeConflict::from_name(&conflict)?;
+    let objects = match &*state.read().unwrap() {
+        Value::Array(a) => a.clone(),
+        Value::None => vec![],
+        value => return Err(Error::conversion_error(value.type_name(), "array of objects")),
+    };
+    let mut result = Value::Object(BTreeMap::new());
+    for (i, object) in objects.into_iter().enumerate() {
+        if !matches!(object, Value::Object(_)) {
+            return Err(Error::conversion_error_with_message(
+                object.type_name(),
+                "object",
+                &format!("Item {} of the merged array is not an object", i),
+            ));
+        }
+        merge_into(&mut result, object, conflict, concat_arrays, "")?;
+    }
+    Ok(result)
+}
+
+/// Navigate into a value by a dot-separated path of object field names and array indices,
+/// e.g. `items.0.name`. An empty path refers to the value itself.
+/// Returns None if the path does not exist.
+pub fn value_at_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    path.split('.').try_fold(value, |v, element| match v {
+        Value::Object(o) => o.get(element),
+        Value::Array(a) => element.parse::<usize>().ok().and_then(|i| a.get(i)),
+        _ => None,
+    })
+}
+
+/// Get the value at a dot-separated path (see [value_at_path]), a missing path is an error
+pub fn get_path(state: &State<Value>, path: String) -> Result<Value, Error> {
+    let value = state.read().unwrap();
+    value_at_path(&value, &path)
+        .cloned()
+        .ok_or_else(|| Error::general_error(format!("Path '{}' not found in the value", path)))
+}
+
+/// Get the part of the value referenced by a JSON pointer (RFC 6901), e.g. `/items/0/name`
+pub fn json_get(state: &State<Value>, pointer: String) -> Result<Value, Error> {
+    state.read().unwrap().get_pointer(&pointer)
+}
+
+ng_register_all!(
+    /// Register the commands working with objects
+    pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+        merge(state, conflict: String = "last", concat_arrays: bool = false),
+        get_path(state, path: String),
+        json_get(state, pointer: String),
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(json: &str) -> State<Value> {
+        let value: Value = serde_json::from_str(json).unwrap();
+        State::new().with_data(value)
+    }
+
+    fn json(value: Value) -> serde_json::Value {
+        value.try_into_json_value().unwrap()
+    }
+
+    #[test]
+    fn test_deep_merge() -> Result<(), Error> {
+        let s = state(
+            r#"[
+                {"name":"app","db":{"host":"localhost","port":5432},"tags":["a"]},
+                {"db":{"port":6543,"user":"admin"},"tags":["b"],"debug":true}
+            ]"#,
+        );
+        assert_eq!(
+            json(merge(&s, "last".to_owned(), false)?),
+            serde_json::json!({
+                "name":"app",
+                "db":{"host":"localhost","port":6543,"user":"admin"},
+                "tags":["b"],
+                "debug":true
+            })
+        );
+        assert_eq!(
+            json(merge(&s, "first".to_owned(), true)?),
+            serde_json::json!({
+                "name":"app",
+                "db":{"host":"localhost","port":5432,"user":"admin"},
+                "tags":["a","b"],
+                "debug":true
+            })
+        );
+        assert!(merge(&state(r#"[{"a":1},2]"#), "last".to_owned(), false).is_err());
+        assert!(merge(&s, "unknown".to_owned(), false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_error_on_conflict() -> Result<(), Error> {
+        let s = state(r#"[{"a":{"b":1,"c":2}},{"a":{"b":1,"d":3}}]"#);
+        assert_eq!(
+            json(merge(&s, "error".to_owned(), false)?),
+            serde_json::json!({"a":{"b":1,"c":2,"d":3}})
+        );
+        let s = state(r#"[{"a":{"b":1}},{"a":{"b":2}}]"#);
+        let err = merge(&s, "error".to_owned(), false).unwrap_err();
+        assert!(err.message.contains("/a/b"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_path() -> Result<(), Error> {
+        let s = state(r#"{"a": {"items": [{"name": "x"}, {"name": "y"}]}, "b": 1}"#);
+        assert_eq!(get_path(&s, "a.items.1.name".to_owned())?, Value::from("y"));
+        assert_eq!(get_path(&s, "b".to_owned())?, Value::I32(1));
+        assert_eq!(get_path(&s, "".to_owned())?, *s.read().unwrap());
+        assert!(get_path(&s, "a.items.2".to_owned()).is_err());
+        assert!(get_path(&s, "b.c".to_owned()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_get() -> Result<(), Error> {
+        let s = state(r#"{"a": {"items": [{"name": "x"}, {"name": "y"}]}, "b/c": 1}"#);
+        assert_eq!(json(json_get(&s, "/a/items/1/name".to_owned())?), serde_json::json!("y"));
+        assert_eq!(json(json_get(&s, "/b~1c".to_owned())?), serde_json::json!(1));
+        let err = json_get(&s, "/a/items/2".to_owned()).unwrap_err();
+        assert_eq!(err.error_type, crate::error::ErrorType::KeyNotFound);
+        assert!(err.message.contains("/a/items/2"));
+        Ok(())
+    }
+}
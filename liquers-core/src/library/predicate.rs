@@ -0,0 +1,563 @@
+//! Command filtering table rows by a predicate expression.
+//!
+//! The predicate is a small expression language, not code: it can only compare columns
+//! with literal values and combine the comparisons. The grammar is
+//!
+//! ```text
+//! predicate  := or
+//! or         := and ("or" and)*
+//! and        := unary ("and" unary)*
+//! unary      := "not" unary | "(" or ")" | comparison
+//! comparison := column operator value
+//! operator   := "==" | "=" | "!=" | "<" | "<=" | ">" | ">=" | "eq" | "ne" | "lt" | "le" | "gt" | "ge"
+//! value      := number | 'text' | "text" | true | false | null | word
+//! ```
+//!
+//! Columns and words consist of alphanumeric characters, `_`, `.` and `-`;
+//! a column name with other characters can be quoted. Tokens are separated by whitespace,
+//! which in a query can be written as the `~.` entity, e.g. `where-age~.gt~.30~.and~.city~.eq~.Paris`.
+//! The word operators avoid characters that are not allowed in query parameters.
+
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+use crate::command_metadata::ArgumentInfo;
+use crate::context::NGEnvironment;
+use crate::error::{Error, ErrorType};
+use crate::state::State;
+use crate::value::{Value, ValueInterface};
+
+use super::table::Table;
+use super::LibraryCommandRegistry;
+
+/// Comparison operator of a predicate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Operator {
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "==" | "=" | "eq" => Some(Operator::Eq),
+            "!=" | "ne" => Some(Operator::Ne),
+            "<" | "lt" => Some(Operator::Lt),
+            "<=" | "le" => Some(Operator::Le),
+            ">" | "gt" => Some(Operator::Gt),
+            ">=" | "ge" => Some(Operator::Ge),
+            _ => None,
+        }
+    }
+}
+
+/// Parsed predicate expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Compare {
+        column: String,
+        operator: Operator,
+        value: Value,
+    },
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    /// Bare word: column name, keyword, operator word, number or unquoted text
+    Word(String),
+    /// Quoted text
+    Quoted(String),
+    /// Symbolic operator
+    Symbol(String),
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    /// Character position of the token start (0-based)
+    position: usize,
+}
+
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenKind::Word(w) | TokenKind::Symbol(w) => write!(f, "'{}'", w),
+            TokenKind::Quoted(q) => write!(f, "quoted text '{}'", q),
+            TokenKind::Open => write!(f, "'('"),
+            TokenKind::Close => write!(f, "')'"),
+        }
+    }
+}
+
+fn parse_error(predicate: &str, message: &str, position: usize) -> Error {
+    Error::new(
+        ErrorType::ParseError,
+        format!(
+            "Can't parse predicate '{}': {} at position {}",
+            predicate,
+            message,
+            position + 1
+        ),
+    )
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || c == '-'
+}
+
+fn tokenize(predicate: &str) -> Result<Vec<Token>, Error> {
+    let chars = predicate.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let kind = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                TokenKind::Open
+            }
+            ')' => {
+                i += 1;
+                TokenKind::Close
+            }
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&q| q == c)
+                    .ok_or_else(|| parse_error(predicate, "unterminated quoted text", start))?;
+                let text = chars[i + 1..i + 1 + end].iter().collect();
+                i += end + 2;
+                TokenKind::Quoted(text)
+            }
+            '=' | '!' | '<' | '>' => {
+                i += 1;
+                if i < chars.len() && chars[i] == '=' {
+                    i += 1;
+                }
+                let symbol = chars[start..i].iter().collect::<String>();
+                if symbol == "!" {
+                    return Err(parse_error(predicate, "expected '!='", start));
+                }
+                TokenKind::Symbol(symbol)
+            }
+            c if is_word_char(c) => {
+                while i < chars.len() && is_word_char(chars[i]) {
+                    i += 1;
+                }
+                TokenKind::Word(chars[start..i].iter().collect())
+            }
+            c => {
+                return Err(parse_error(
+                    predicate,
+                    &format!("unexpected character '{}'", c),
+                    start,
+                ))
+            }
+        };
+        tokens.push(Token {
+            kind,
+            position: start,
+        });
+    }
+    Ok(tokens)
+}
+
+/// Maximal nesting of `not` and parentheses; the parser is recursive, so deeper nesting is a parse error
+const MAX_NESTING: usize = 64;
+
+struct Parser<'a> {
+    predicate: &'a str,
+    tokens: Vec<Token>,
+    index: usize,
+    /// Current nesting of `not` and parentheses
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index)
+    }
+
+    fn advance(&mut self) {
+        self.index += 1;
+    }
+
+    /// Position for an error at the current token (end of the predicate if there are no more tokens)
+    fn position(&self) -> usize {
+        self.peek()
+            .map(|t| t.position)
+            .unwrap_or_else(|| self.predicate.chars().count())
+    }
+
+    fn error(&self, expected: &str) -> Error {
+        let found = self
+            .peek()
+            .map(|t| t.kind.to_string())
+            .unwrap_or_else(|| "end of predicate".to_owned());
+        parse_error(
+            self.predicate,
+            &format!("expected {}, found {}", expected, found),
+            self.position(),
+        )
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Word(w), .. }) if w == keyword)
+    }
+
+    fn parse_or(&mut self) -> Result<Predicate, Error> {
+        let mut left = self.parse_and()?;
+        while self.is_keyword("or") {
+            self.advance();
+            let right = self.parse_and()?;
+            left = Predicate::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Predicate, Error> {
+        let mut left = self.parse_unary()?;
+        while self.is_keyword("and") {
+            self.advance();
+            let right = self.parse_unary()?;
+            left = Predicate::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    /// Parse a nested expression, failing if it is nested too deep (see [MAX_NESTING])
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        if self.depth >= MAX_NESTING {
+            return Err(parse_error(
+                self.predicate,
+                &format!("nesting deeper than {} levels", MAX_NESTING),
+                self.position(),
+            ));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_unary(&mut self) -> Result<Predicate, Error> {
+        if self.is_keyword("not") {
+            self.advance();
+            return Ok(Predicate::Not(Box::new(self.nested(Self::parse_unary)?)));
+        }
+        if let Some(Token {
+            kind: TokenKind::Open,
+            ..
+        }) = self.peek()
+        {
+            self.advance();
+            let inner = self.nested(Self::parse_or)?;
+            return match self.peek() {
+                Some(Token {
+                    kind: TokenKind::Close,
+                    ..
+                }) => {
+                    self.advance();
+                    Ok(inner)
+                }
+                _ => Err(self.error("')'")),
+            };
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Predicate, Error> {
+        let column = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Word(w)) if !matches!(w.as_str(), "and" | "or" | "not") => w.clone(),
+            Some(TokenKind::Quoted(q)) => q.clone(),
+            _ => return Err(self.error("a column name")),
+        };
+        self.advance();
+        let operator = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Word(w)) | Some(TokenKind::Symbol(w)) => Operator::from_token(w),
+            _ => None,
+        }
+        .ok_or_else(|| self.error("a comparison operator"))?;
+        self.advance();
+        let value = match self.peek().map(|t| &t.kind) {
+            Some(TokenKind::Quoted(q)) => Value::Text(q.clone()),
+            Some(TokenKind::Word(w)) if !matches!(w.as_str(), "and" | "or" | "not") => {
+                literal(w)
+            }
+            _ => return Err(self.error("a value")),
+        };
+        self.advance();
+        Ok(Predicate::Compare {
+            column,
+            operator,
+            value,
+        })
+    }
+}
+
+/// Value of an unquoted literal: keyword, number or text
+fn literal(word: &str) -> Value {
+    match word {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "null" => Value::None,
+        _ => {
+            if let Ok(i) = word.parse::<i64>() {
+                Value::I64(i)
+            } else if let Ok(f) = word.parse::<f64>() {
+                Value::F64(f)
+            } else {
+                Value::Text(word.to_owned())
+            }
+        }
+    }
+}
+
+fn is_number(value: &Value) -> bool {
+    matches!(value, Value::I32(_) | Value::I64(_) | Value::F64(_))
+}
+
+/// Order of a cell and a literal, None if they are of incomparable types
+fn compare(cell: &Value, value: &Value) -> Option<Ordering> {
+    match (cell, value) {
+        (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (a, b) if is_number(a) && is_number(b) => {
+            a.try_into_f64().ok()?.partial_cmp(&b.try_into_f64().ok()?)
+        }
+        _ => None,
+    }
+}
+
+impl Predicate {
+    /// Parse a predicate expression; the error reports the position of the problem
+    pub fn parse(predicate: &str) -> Result<Self, Error> {
+        let mut parser = Parser {
+            predicate,
+            tokens: tokenize(predicate)?,
+            index: 0,
+            depth: 0,
+        };
+        let result = parser.parse_or()?;
+        if parser.peek().is_some() {
+            return Err(parser.error("'and', 'or' or end of predicate"));
+        }
+        Ok(result)
+    }
+
+    /// Columns referenced by the predicate
+    pub fn columns(&self) -> Vec<&str> {
+        match self {
+            Predicate::Compare { column, .. } => vec![column.as_str()],
+            Predicate::And(a, b) | Predicate::Or(a, b) => {
+                let mut columns = a.columns();
+                columns.extend(b.columns());
+                columns
+            }
+            Predicate::Not(a) => a.columns(),
+        }
+    }
+
+    /// Evaluate the predicate for a row of a table.
+    /// Equality holds only between values of the same kind (numbers of any type are one kind);
+    /// `null` equals a missing cell. Ordering comparisons with a missing cell are false,
+    /// ordering a cell and a value of an incomparable type is an error.
+    pub fn evaluate(&self, table: &Table, row: usize) -> Result<bool, Error> {
+        match self {
+            Predicate::Compare {
+                column,
+                operator,
+                value,
+            } => {
+                let cell = table.get(row, column);
+                let equal = match (cell, value) {
+                    (Value::None, Value::None) => true,
+                    _ => compare(cell, value) == Some(Ordering::Equal),
+                };
+                match operator {
+                    Operator::Eq => Ok(equal),
+                    Operator::Ne => Ok(!equal),
+                    _ if cell.is_none() || value.is_none() => Ok(false),
+                    _ => {
+                        let ordering = compare(cell, value).ok_or_else(|| {
+                            Error::conversion_error_with_message(
+                                cell.type_name(),
+                                value.type_name(),
+                                &format!(
+                                    "Column '{}', row {}: value can't be compared with {}",
+                                    column,
+                                    row,
+                                    value
+                                        .try_into_json_value()
+                                        .map(|v| v.to_string())
+                                        .unwrap_or_default()
+                                ),
+                            )
+                        })?;
+                        Ok(match operator {
+                            Operator::Lt => ordering == Ordering::Less,
+                            Operator::Le => ordering != Ordering::Greater,
+                            Operator::Gt => ordering == Ordering::Greater,
+                            _ => ordering != Ordering::Less,
+                        })
+                    }
+                }
+            }
+            Predicate::And(a, b) => Ok(a.evaluate(table, row)? && b.evaluate(table, row)?),
+            Predicate::Or(a, b) => Ok(a.evaluate(table, row)? || b.evaluate(table, row)?),
+            Predicate::Not(a) => Ok(!a.evaluate(table, row)?),
+        }
+    }
+}
+
+/// Rows of a table matching a `predicate` expression (see the [module documentation](self)
+/// for the grammar), in the original order. All the columns used in the predicate must exist.
+pub fn filter_where(state: &State<Value>, predicate: String) -> Result<Value, Error> {
+    let parsed = Predicate::parse(&predicate)?;
+    let mut table = Table::from_value(&state.read().unwrap())?;
+    for column in parsed.columns() {
+        table.check_column(column)?;
+    }
+    let mut selected = Vec::new();
+    for row in 0..table.len() {
+        selected.push(parsed.evaluate(&table, row)?);
+    }
+    let rows = std::mem::take(&mut table.rows);
+    table.rows = rows
+        .into_iter()
+        .zip(selected)
+        .filter_map(|(row, keep)| keep.then_some(row))
+        .collect();
+    Ok(table.into_value())
+}
+
+pub fn register_commands<E: NGEnvironment<Value = Value>>(
+    cr: &mut LibraryCommandRegistry<E>,
+) -> Result<(), Error> {
+    // `where` is a Rust keyword, so the command is registered under a name
+    // different from the function implementing it.
+    cr.register_command("where", ng_command_wrapper!(filter_where(state, predicate: String)))?
+        .with_name("where")
+        .with_state_argument(ArgumentInfo::argument("state"))
+        .with_argument(ArgumentInfo::string_argument("predicate"))
+        .with_warn_if_empty();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> State<Value> {
+        let value: Value = serde_json::from_str(
+            r#"[{"name":"Ann","age":31,"city":"Paris"},{"name":"Bob","age":25,"city":"Rome"},{"name":"Cid","age":40,"city":"Rome"},{"name":"Dan","age":null,"city":"Oslo"}]"#,
+        )
+        .unwrap();
+        State::new().with_data(value)
+    }
+
+    fn names(value: Value) -> Vec<String> {
+        let table = Table::from_value(&value).unwrap();
+        (0..table.len())
+            .map(|i| table.get(i, "name").try_into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_where_comparison() -> Result<(), Error> {
+        assert_eq!(names(filter_where(&state(), "age > 30".to_owned())?), vec!["Ann", "Cid"]);
+        assert_eq!(names(filter_where(&state(), "age le 31".to_owned())?), vec!["Ann", "Bob"]);
+        assert_eq!(names(filter_where(&state(), "city == 'Rome'".to_owned())?), vec!["Bob", "Cid"]);
+        assert_eq!(names(filter_where(&state(), "age eq null".to_owned())?), vec!["Dan"]);
+        assert!(filter_where(&state(), "missing > 1".to_owned()).is_err());
+        assert!(filter_where(&state(), "name > 1".to_owned()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_where_compound() -> Result<(), Error> {
+        assert_eq!(
+            names(filter_where(&state(), "city eq Rome and age gt 30 or name eq Ann".to_owned())?),
+            vec!["Ann", "Cid"]
+        );
+        assert_eq!(
+            names(filter_where(&state(), "city = Rome and (age > 30 or name = Bob)".to_owned())?),
+            vec!["Bob", "Cid"]
+        );
+        assert_eq!(
+            names(filter_where(&state(), "not (city != Oslo)".to_owned())?),
+            vec!["Dan"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_where_parse_error() {
+        let err = Predicate::parse("age > 30 and").unwrap_err();
+        assert_eq!(err.error_type, ErrorType::ParseError);
+        assert!(err.message.contains("expected a column name, found end of predicate at position 13"));
+        let err = Predicate::parse("age >> 30").unwrap_err();
+        assert!(err.message.contains("expected a value, found '>' at position 6"));
+        let err = Predicate::parse("(age > 30").unwrap_err();
+        assert!(err.message.contains("expected ')'"));
+        let err = Predicate::parse("name = 'Ann").unwrap_err();
+        assert!(err.message.contains("unterminated quoted text at position 8"));
+
+        let nested = |depth: usize| format!("{}age > 30{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Predicate::parse(&nested(MAX_NESTING)).is_ok());
+        let err = Predicate::parse(&nested(100_000)).unwrap_err();
+        assert_eq!(err.error_type, ErrorType::ParseError);
+        assert!(err.message.contains("nesting deeper than 64 levels"));
+        let err = Predicate::parse(&"not ".repeat(100_000)).unwrap_err();
+        assert!(err.message.contains("nesting deeper than 64 levels"));
+    }
+
+    #[tokio::test]
+    async fn test_where_empty_result_warning() -> Result<(), Error> {
+        use crate::context::SimpleNGEnvironment;
+        use crate::interpreter::NGPlanInterpreter;
+        use crate::metadata::LogEntryKind;
+
+        let mut env = SimpleNGEnvironment::<Value>::new();
+        crate::library::register_commands(env.get_mut_command_executor())?;
+        let mut pi = NGPlanInterpreter::new(env.to_ref());
+        let warnings = |state: &State<Value>| {
+            state
+                .metadata
+                .metadata_record()
+                .unwrap()
+                .log
+                .into_iter()
+                .filter(|entry| matches!(entry.kind, LogEntryKind::Warning))
+                .map(|entry| entry.message)
+                .collect::<Vec<_>>()
+        };
+
+        pi.set_query("where-age~.gt~.100").await?;
+        let result = pi.apply_state(state()).await?;
+        assert_eq!(result.read().unwrap().collection_len(), Some(0));
+        assert_eq!(
+            warnings(&result),
+            vec!["Command 'where' produced an empty result from a non-empty input"]
+        );
+
+        pi.set_query("where-age~.gt~.30").await?;
+        let result = pi.apply_state(state()).await?;
+        assert!(warnings(&result).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,1856 @@
+//! Commands operating on tables.
+//!
+//! The core [Value] has no dedicated table type, a table is represented as an array of records
+//! (objects mapping column names to cell values), i.e. the usual JSON representation
+//! of a data frame. The [Table] helper converts between this representation
+//! and a row/column view used by the commands.
+//! Since records do not keep the order of their keys, a table with a meaningful column order
+//! can be represented as an object with the `columns` (array of column names)
+//! and `records` (array of records), see [Table::into_ordered_value].
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::error::Error;
+use crate::state::State;
+use crate::value::{Value, ValueInterface};
+
+use super::LibraryCommandRegistry;
+use crate::context::NGEnvironment;
+
+/// Key of the column names in the ordered table representation
+pub const COLUMNS_KEY: &str = "columns";
+/// Key of the records in the ordered table representation
+pub const RECORDS_KEY: &str = "records";
+
+/// Row/column view of a record table.
+/// The columns are kept in the order of their first appearance in the records.
+/// Cells missing in a record are treated as [Value::None].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Table {
+    pub columns: Vec<String>,
+    pub rows: Vec<BTreeMap<String, Value>>,
+}
+
+impl Table {
+    pub fn new(columns: Vec<String>) -> Self {
+        Table {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Create a table from an array of records or from the ordered representation (see [Table::into_ordered_value])
+    pub fn from_value(value: &Value) -> Result<Self, Error> {
+        match value {
+            Value::Array(a) => {
+                let mut table = Table::default();
+                for (i, record) in a.iter().enumerate() {
+                    match record {
+                        Value::Object(o) => table.push_row(o.clone()),
+                        _ => {
+                            return Err(Error::conversion_error_with_message(
+                                record.type_name(),
+                                "table record",
+                                &format!("Row {} of the table is not an object", i),
+                            ))
+                        }
+                    }
+                }
+                Ok(table)
+            }
+            Value::Object(o)
+                if o.len() == 2
+                    && matches!(o.get(COLUMNS_KEY), Some(Value::Array(_)))
+                    && o.contains_key(RECORDS_KEY) =>
+            {
+                let mut columns = Vec::new();
+                if let Some(Value::Array(names)) = o.get(COLUMNS_KEY) {
+                    for name in names {
+                        let name = cell_to_string(name)?;
+                        if !columns.contains(&name) {
+                            columns.push(name);
+                        }
+                    }
+                }
+                let mut table = Table::from_value(&o[RECORDS_KEY])?;
+                for column in table.columns {
+                    if !columns.contains(&column) {
+                        columns.push(column);
+                    }
+                }
+                table.columns = columns;
+                Ok(table)
+            }
+            Value::None => Ok(Table::default()),
+            _ => Err(Error::conversion_error(value.type_name(), "table")),
+        }
+    }
+
+    /// Convert the table into an array of records
+    pub fn into_value(self) -> Value {
+        Value::Array(self.rows.into_iter().map(Value::Object).collect())
+    }
+
+    /// Convert the table into an object with the `columns` and `records`,
+    /// which (unlike [Table::into_value]) keeps the order of the columns
+    pub fn into_ordered_value(self) -> Value {
+        let mut value = BTreeMap::new();
+        value.insert(
+            COLUMNS_KEY.to_owned(),
+            Value::Array(self.columns.iter().cloned().map(Value::Text).collect()),
+        );
+        value.insert(RECORDS_KEY.to_owned(), self.into_value());
+        Value::Object(value)
+    }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn has_column(&self, column: &str) -> bool {
+        self.columns.iter().any(|c| c == column)
+    }
+
+    /// Return an error if the column does not exist
+    pub fn check_column(&self, column: &str) -> Result<(), Error> {
+        if self.has_column(column) {
+            Ok(())
+        } else {
+            Err(Error::general_error(format!(
+                "Column '{}' not found in the table; available columns: {}",
+                column,
+                self.columns.join(", ")
+            )))
+        }
+    }
+
+    /// Append a row; columns not yet present in the table are added
+    pub fn push_row(&mut self, row: BTreeMap<String, Value>) {
+        for column in row.keys() {
+            if !self.has_column(column) {
+                self.columns.push(column.clone());
+            }
+        }
+        self.rows.push(row);
+    }
+
+    /// Get a cell, missing cell is returned as [Value::None]
+    pub fn get(&self, row: usize, column: &str) -> &Value {
+        static NONE: Value = Value::None;
+        self.rows
+            .get(row)
+            .and_then(|r| r.get(column))
+            .unwrap_or(&NONE)
+    }
+
+    /// All the values in a column
+    pub fn column_values(&self, column: &str) -> Vec<Value> {
+        (0..self.len())
+            .map(|i| self.get(i, column).clone())
+            .collect()
+    }
+}
+
+/// Text representation of a cell, used e.g. when a cell becomes a column name.
+/// Text is used directly, other values are encoded as JSON.
+pub fn cell_to_string(value: &Value) -> Result<String, Error> {
+    match value {
+        Value::Text(t) => Ok(t.clone()),
+        _ => Ok(value.try_into_json_value()?.to_string()),
+    }
+}
+
+/// Make a homogeneous column out of cells of possibly different types.
+/// Integers are widened to [Value::I64] and mixed integers and floats become [Value::F64].
+/// Any other mixture is converted to text via [cell_to_string].
+/// Missing values ([Value::None]) do not influence the resulting type.
+pub fn widen_column(cells: Vec<Value>) -> Result<Vec<Value>, Error> {
+    #[derive(PartialEq, Clone, Copy)]
+    enum Kind {
+        Bool,
+        I32,
+        I64,
+        F64,
+        Text,
+        Other,
+    }
+    let mut kinds = cells.iter().filter_map(|cell| match cell {
+        Value::None => None,
+        Value::Bool(_) => Some(Kind::Bool),
+        Value::I32(_) => Some(Kind::I32),
+        Value::I64(_) => Some(Kind::I64),
+        Value::F64(_) => Some(Kind::F64),
+        Value::Text(_) => Some(Kind::Text),
+        _ => Some(Kind::Other),
+    });
+    let first = match kinds.next() {
+        Some(k) => k,
+        None => return Ok(cells),
+    };
+    let kind = kinds.try_fold(first, |a, b| match (a, b) {
+        (a, b) if a == b => Some(a),
+        (Kind::I32 | Kind::I64, Kind::I32 | Kind::I64) => Some(Kind::I64),
+        (Kind::I32 | Kind::I64 | Kind::F64, Kind::I32 | Kind::I64 | Kind::F64) => Some(Kind::F64),
+        _ => None,
+    });
+    match kind {
+        Some(Kind::I64) => cells
+            .into_iter()
+            .map(|cell| match cell {
+                Value::I32(n) => Ok(Value::I64(n as i64)),
+                _ => Ok(cell),
+            })
+            .collect(),
+        Some(Kind::F64) => cells
+            .into_iter()
+            .map(|cell| match cell {
+                Value::None => Ok(cell),
+                _ => Ok(Value::F64(cell.try_into_f64()?)),
+            })
+            .collect(),
+        Some(_) => Ok(cells),
+        None => cells
+            .into_iter()
+            .map(|cell| match cell {
+                Value::None => Ok(cell),
+                _ => Ok(Value::Text(cell_to_string(&cell)?)),
+            })
+            .collect(),
+    }
+}
+
+/// Name of the column holding the original column names in a transposed table
+pub const TRANSPOSE_COLUMN: &str = "column";
+
+/// Transpose a table, swapping rows and columns.
+/// The values of the `header` column become the new column names;
+/// if `header` is empty, positional column names (zero-padded row numbers) are generated.
+/// Each of the remaining columns becomes a row, with the original column name
+/// stored in the [TRANSPOSE_COLUMN] column.
+/// Cells of a new column are widened to a common type (see [widen_column]).
+/// The result keeps the column order ([TRANSPOSE_COLUMN] first, then the new columns),
+/// see [Table::into_ordered_value].
+pub fn transpose(state: &State<Value>, header: String) -> Result<Value, Error> {
+    let table = Table::from_value(&state.read().unwrap())?;
+    let names = if header.is_empty() {
+        let width = table.len().saturating_sub(1).to_string().len();
+        (0..table.len())
+            .map(|i| format!("{:0width$}", i, width = width))
+            .collect::<Vec<_>>()
+    } else {
+        table.check_column(&header)?;
+        let mut names: Vec<String> = Vec::new();
+        for cell in table.column_values(&header) {
+            let name = cell_to_string(&cell)?;
+            if name == TRANSPOSE_COLUMN || names.contains(&name) {
+                return Err(Error::general_error(format!(
+                    "Duplicate column name '{}' in transposed table (header column '{}')",
+                    name, header
+                )));
+            }
+            names.push(name);
+        }
+        names
+    };
+
+    let columns = table
+        .columns
+        .iter()
+        .filter(|c| **c != header)
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut rows = columns
+        .iter()
+        .map(|c| {
+            let mut row = BTreeMap::new();
+            row.insert(TRANSPOSE_COLUMN.to_owned(), Value::Text(c.clone()));
+            row
+        })
+        .collect::<Vec<_>>();
+    for (i, name) in names.iter().enumerate() {
+        let cells = columns.iter().map(|c| table.get(i, c).clone()).collect();
+        for (row, cell) in rows.iter_mut().zip(widen_column(cells)?) {
+            row.insert(name.clone(), cell);
+        }
+    }
+
+    let mut result_columns = vec![TRANSPOSE_COLUMN.to_owned()];
+    result_columns.extend(names);
+    Ok(Table {
+        columns: result_columns,
+        rows,
+    }
+    .into_ordered_value())
+}
+
+/// Maximal number of parts produced by [partition]
+pub const MAX_PARTITIONS: usize = 10_000;
+
+/// Split a table into sub-tables, returned as an array of tables (arrays of records),
+/// e.g. to be processed independently downstream. At most [MAX_PARTITIONS] parts are produced.
+/// Supported strategies:
+/// * `count` - `parameter` is the number of parts N; the rows are split into N contiguous
+///   row ranges of nearly equal size (sizes differ by at most one row, larger parts first).
+///   Exactly N parts are produced, some of them may be empty if the table has less than N rows.
+/// * `column` - `parameter` is a column name; there is one part per distinct value of the column,
+///   in the order of the first appearance. Rows with a missing cell form a part of their own.
+///
+/// Row order is preserved within each part.
+pub fn partition(state: &State<Value>, strategy: String, parameter: String) -> Result<Value, Error> {
+    let table = Table::from_value(&state.read().unwrap())?;
+    let parts = match strategy.as_str() {
+        "count" => {
+            let count = parameter.parse::<usize>().map_err(|e| {
+                Error::general_error(format!(
+                    "Invalid number of partitions '{}': {}",
+                    parameter, e
+                ))
+            })?;
+            if count == 0 {
+                return Err(Error::general_error(
+                    "Number of partitions must be positive".to_owned(),
+                ));
+            }
+            if count > MAX_PARTITIONS {
+                return Err(Error::general_error(format!(
+                    "Number of partitions {} exceeds the maximum of {}",
+                    count, MAX_PARTITIONS
+                )));
+            }
+            let (size, larger) = (table.len() / count, table.len() % count);
+            let mut rows = table.rows.into_iter();
+            (0..count)
+                .map(|i| {
+                    let mut part = Table::new(table.columns.clone());
+                    let n = if i < larger { size + 1 } else { size };
+                    part.rows.extend(rows.by_ref().take(n));
+                    part
+                })
+                .collect::<Vec<_>>()
+        }
+        "column" => {
+            table.check_column(&parameter)?;
+            let mut parts: Vec<(Value, Table)> = Vec::new();
+            for (i, row) in table.rows.iter().enumerate() {
+                let key = table.get(i, &parameter);
+                match parts.iter_mut().find(|(k, _)| k == key) {
+                    Some((_, part)) => part.rows.push(row.clone()),
+                    None => {
+                        if parts.len() == MAX_PARTITIONS {
+                            return Err(Error::general_error(format!(
+                                "Column '{}' has more than {} distinct values to partition by",
+                                parameter, MAX_PARTITIONS
+                            )));
+                        }
+                        let mut part = Table::new(table.columns.clone());
+                        part.rows.push(row.clone());
+                        parts.push((key.clone(), part));
+                    }
+                }
+            }
+            parts.into_iter().map(|(_, part)| part).collect()
+        }
+        _ => {
+            return Err(Error::general_error(format!(
+                "Unknown partitioning strategy '{}'; expected 'count' or 'column'",
+                strategy
+            )))
+        }
+    };
+    Ok(Value::Array(
+        parts.into_iter().map(|part| part.into_value()).collect(),
+    ))
+}
+
+/// Aggregation function combining several values into one, used by [pivot] and [group_agg].
+/// Missing values are skipped by all the aggregations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    /// Number of non-missing values
+    Count,
+    /// Sum of the numeric values (integers stay integers)
+    Sum,
+    /// Arithmetic mean of the numeric values
+    Mean,
+    /// Smallest value (numbers are compared numerically, texts alphabetically)
+    Min,
+    /// Largest value (numbers are compared numerically, texts alphabetically)
+    Max,
+    /// First value in the row order
+    First,
+}
+
+/// Order of two cells for [Aggregation::Min] and [Aggregation::Max]
+fn compare_cells(a: &Value, b: &Value) -> Result<std::cmp::Ordering, Error> {
+    match (a, b) {
+        (Value::Text(a), Value::Text(b)) => Ok(a.cmp(b)),
+        _ => {
+            let (x, y) = (a.try_into_f64()?, b.try_into_f64()?);
+            x.partial_cmp(&y).ok_or_else(|| {
+                Error::general_error(format!("Values {} and {} can't be compared", x, y))
+            })
+        }
+    }
+}
+
+impl Aggregation {
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "count" => Ok(Aggregation::Count),
+            "sum" => Ok(Aggregation::Sum),
+            "mean" => Ok(Aggregation::Mean),
+            "min" => Ok(Aggregation::Min),
+            "max" => Ok(Aggregation::Max),
+            "first" => Ok(Aggregation::First),
+            _ => Err(Error::general_error(format!(
+                "Unknown aggregation '{}'; expected 'count', 'sum', 'mean', 'min', 'max' or 'first'",
+                name
+            ))),
+        }
+    }
+
+    /// Aggregate the values collected for a single cell;
+    /// the result is None if there are no values (except for count)
+    pub fn aggregate(&self, values: Vec<Value>) -> Result<Value, Error> {
+        let mut values = values.into_iter().filter(|v| !v.is_none());
+        match self {
+            Aggregation::First => Ok(values.next().unwrap_or(Value::None)),
+            Aggregation::Count => Ok(Value::I64(values.count() as i64)),
+            Aggregation::Sum => {
+                // Integers are summed exactly; on overflow the sum continues as a float
+                values.try_fold(Value::I64(0), |total, value| {
+                    let sum = match (&total, &value) {
+                        (Value::I64(a), Value::I32(b)) => a.checked_add(*b as i64),
+                        (Value::I64(a), Value::I64(b)) => a.checked_add(*b),
+                        _ => None,
+                    };
+                    match sum {
+                        Some(sum) => Ok(Value::I64(sum)),
+                        None => Ok(Value::F64(total.try_into_f64()? + value.try_into_f64()?)),
+                    }
+                })
+            }
+            Aggregation::Mean => {
+                let (mut total, mut count) = (0.0, 0);
+                for value in values {
+                    total += value.try_into_f64()?;
+                    count += 1;
+                }
+                Ok(if count == 0 {
+                    Value::None
+                } else {
+                    Value::F64(total / count as f64)
+                })
+            }
+            Aggregation::Min | Aggregation::Max => {
+                let wanted = if *self == Aggregation::Min {
+                    std::cmp::Ordering::Less
+                } else {
+                    std::cmp::Ordering::Greater
+                };
+                let mut best: Option<Value> = None;
+                for value in values {
+                    best = match best {
+                        Some(b) if compare_cells(&value, &b)? != wanted => Some(b),
+                        _ => Some(value),
+                    };
+                }
+                Ok(best.unwrap_or(Value::None))
+            }
+        }
+    }
+}
+
+/// Reshape a table from the long to the wide format.
+/// The rows are grouped by the `index` columns, there is one row per distinct combination of
+/// their values (in the order of the first appearance). Distinct values of the `column` column
+/// become new columns, with cells taken from the `values` column.
+/// Values falling into the same cell are combined by the `aggregate` function
+/// (e.g. `sum`, `first` or `count`, see [Aggregation]); cells without values are missing.
+pub fn pivot(
+    state: &State<Value>,
+    column: String,
+    values: String,
+    aggregate: String,
+    index: Vec<Value>,
+) -> Result<Value, Error> {
+    let aggregation = Aggregation::from_name(&aggregate)?;
+    let table = Table::from_value(&state.read().unwrap())?;
+    let index = index
+        .iter()
+        .map(|i| i.try_into_string())
+        .collect::<Result<Vec<_>, _>>()?;
+    for c in index.iter().chain([&column, &values]) {
+        table.check_column(c)?;
+    }
+
+    let mut names: Vec<String> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut groups: Vec<(Vec<Value>, BTreeMap<String, Vec<Value>>)> = Vec::new();
+    for row in 0..table.len() {
+        let name = cell_to_string(table.get(row, &column))?;
+        if index.contains(&name) {
+            return Err(Error::general_error(format!(
+                "Pivot value '{}' clashes with an index column",
+                name
+            )));
+        }
+        if !names.contains(&name) {
+            names.push(name.clone());
+        }
+        let key = index
+            .iter()
+            .map(|c| table.get(row, c).clone())
+            .collect::<Vec<_>>();
+        let encoded = serde_json::to_string(&key).map_err(|e| {
+            Error::general_error(format!("Can't group rows of the table: {}", e))
+        })?;
+        let position = *positions.entry(encoded).or_insert_with(|| {
+            groups.push((key, BTreeMap::new()));
+            groups.len() - 1
+        });
+        groups[position]
+            .1
+            .entry(name)
+            .or_default()
+            .push(table.get(row, &values).clone());
+    }
+
+    let mut result = Table::new(index.iter().chain(names.iter()).cloned().collect());
+    for (key, cells) in groups {
+        let mut row = index.iter().cloned().zip(key).collect::<BTreeMap<_, _>>();
+        for (name, cell_values) in cells {
+            row.insert(name, aggregation.aggregate(cell_values)?);
+        }
+        result.rows.push(row);
+    }
+    Ok(result.into_value())
+}
+
+/// Group the rows of a table and aggregate each group into a single row.
+/// `by` are the grouping columns separated by `+` (e.g. `city+year`); if empty,
+/// the whole table is a single group. Groups are in the order of their first appearance.
+/// Each aggregation is specified as `column.function` (e.g. `sales.sum`), where the function
+/// is one of `count`, `sum`, `mean`, `min`, `max` or `first` (see [Aggregation]).
+/// The result has the grouping columns and one `column_function` column per aggregation.
+pub fn group_agg(state: &State<Value>, by: String, aggregations: Vec<Value>) -> Result<Value, Error> {
+    let table = Table::from_value(&state.read().unwrap())?;
+    let by = by
+        .split('+')
+        .filter(|c| !c.is_empty())
+        .map(|c| c.to_owned())
+        .collect::<Vec<_>>();
+    let mut specs = Vec::new();
+    for spec in aggregations.iter() {
+        let spec = spec.try_into_string()?;
+        let (column, function) = spec.rsplit_once('.').ok_or_else(|| {
+            Error::general_error(format!(
+                "Aggregation '{}' must have the form column.function",
+                spec
+            ))
+        })?;
+        specs.push((column.to_owned(), Aggregation::from_name(function)?, format!("{}_{}", column, function)));
+    }
+    for column in by.iter().chain(specs.iter().map(|(c, _, _)| c)) {
+        table.check_column(column)?;
+    }
+
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut groups: Vec<(Vec<Value>, Vec<Vec<Value>>)> = Vec::new();
+    for row in 0..table.len() {
+        let key = by
+            .iter()
+            .map(|c| table.get(row, c).clone())
+            .collect::<Vec<_>>();
+        let encoded = serde_json::to_string(&key).map_err(|e| {
+            Error::general_error(format!("Can't group rows of the table: {}", e))
+        })?;
+        let position = *positions.entry(encoded).or_insert_with(|| {
+            groups.push((key, vec![Vec::new(); specs.len()]));
+            groups.len() - 1
+        });
+        for (i, (column, _, _)) in specs.iter().enumerate() {
+            groups[position].1[i].push(table.get(row, column).clone());
+        }
+    }
+    if by.is_empty() && groups.is_empty() {
+        groups.push((vec![], vec![Vec::new(); specs.len()]));
+    }
+
+    let mut result = Table::new(
+        by.iter()
+            .cloned()
+            .chain(specs.iter().map(|(_, _, name)| name.clone()))
+            .collect(),
+    );
+    for (key, values) in groups {
+        let mut row = by.iter().cloned().zip(key).collect::<BTreeMap<_, _>>();
+        for ((_, aggregation, name), cell_values) in specs.iter().zip(values) {
+            row.insert(name.clone(), aggregation.aggregate(cell_values)?);
+        }
+        result.rows.push(row);
+    }
+    Ok(result.into_value())
+}
+
+/// Rolling (moving window) aggregation of table columns.
+/// For each row, the `aggregation` (`count`, `sum`, `mean`, `min`, `max` or `first`, see [Aggregation])
+/// is applied to the values in the window of `window` rows ending with the row.
+/// `columns` are the aggregated columns separated by `+` (e.g. `price+volume`); the results are
+/// added as new `column_function` columns (e.g. `price_mean`), the rows stay in the original order.
+/// If `partition` is not empty, windows do not cross groups of rows with the same value
+/// in the `partition` column.
+/// A window needs at least `min_periods` non-missing values, otherwise the result is missing;
+/// `min_periods` 0 (default) means the window size, so the partial windows at the start
+/// of the table (or of a group) have a missing result.
+pub fn rolling(
+    state: &State<Value>,
+    window: i64,
+    aggregation: String,
+    columns: String,
+    min_periods: i64,
+    partition: String,
+) -> Result<Value, Error> {
+    if window < 1 {
+        return Err(Error::general_error(format!(
+            "Rolling window size must be positive, got {}",
+            window
+        )));
+    }
+    if min_periods < 0 || min_periods > window {
+        return Err(Error::general_error(format!(
+            "Rolling min_periods must be between 0 and the window size {}, got {}",
+            window, min_periods
+        )));
+    }
+    let min_periods = if min_periods == 0 { window } else { min_periods } as usize;
+    let window = window as usize;
+    let function = Aggregation::from_name(&aggregation)?;
+    let mut table = Table::from_value(&state.read().unwrap())?;
+    let columns = columns
+        .split('+')
+        .filter(|c| !c.is_empty())
+        .map(|c| c.to_owned())
+        .collect::<Vec<_>>();
+    if columns.is_empty() {
+        return Err(Error::general_error(
+            "Rolling aggregation needs at least one column".to_owned(),
+        ));
+    }
+    for column in columns.iter().chain((!partition.is_empty()).then_some(&partition)) {
+        table.check_column(column)?;
+    }
+
+    // Row indices of the partitions, in the order of the rows
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut partitions: Vec<Vec<usize>> = Vec::new();
+    for row in 0..table.len() {
+        let key = if partition.is_empty() {
+            String::new()
+        } else {
+            serde_json::to_string(table.get(row, &partition)).map_err(|e| {
+                Error::general_error(format!("Can't partition rows of the table: {}", e))
+            })?
+        };
+        let position = *positions.entry(key).or_insert_with(|| {
+            partitions.push(Vec::new());
+            partitions.len() - 1
+        });
+        partitions[position].push(row);
+    }
+
+    let mut results = vec![BTreeMap::new(); table.len()];
+    for rows in partitions.iter() {
+        for (i, &row) in rows.iter().enumerate() {
+            let start = (i + 1).saturating_sub(window);
+            for column in columns.iter() {
+                let values = rows[start..=i]
+                    .iter()
+                    .map(|&r| table.get(r, column).clone())
+                    .collect::<Vec<_>>();
+                let present = values.iter().filter(|v| !v.is_none()).count();
+                let result = if present < min_periods {
+                    Value::None
+                } else {
+                    function.aggregate(values)?
+                };
+                results[row].insert(format!("{}_{}", column, aggregation), result);
+            }
+        }
+    }
+    for (row, result) in table.rows.iter_mut().zip(results) {
+        row.extend(result);
+    }
+    Ok(table.into_value())
+}
+
+/// Target type of the [astype] conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellType {
+    /// 64-bit integer (`int`)
+    Int,
+    /// 64-bit float (`float`)
+    Float,
+    /// Text (`text`)
+    Text,
+    /// Boolean (`bool`)
+    Bool,
+}
+
+impl CellType {
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "int" => Ok(CellType::Int),
+            "float" => Ok(CellType::Float),
+            "text" | "str" => Ok(CellType::Text),
+            "bool" => Ok(CellType::Bool),
+            _ => Err(Error::general_error(format!(
+                "Unknown column type '{}'; expected 'int', 'float', 'text' or 'bool'",
+                name
+            ))),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            CellType::Int => "int",
+            CellType::Float => "float",
+            CellType::Text => "text",
+            CellType::Bool => "bool",
+        }
+    }
+
+    /// Convert a non-missing cell, None if the value can't be converted
+    fn convert(&self, value: &Value) -> Option<Value> {
+        match (self, value) {
+            (CellType::Int, Value::I32(_) | Value::I64(_)) => value.try_into_i64().ok().map(Value::I64),
+            (CellType::Int, Value::F64(x)) if x.fract() == 0.0 && x.abs() < 9.0e18 => {
+                Some(Value::I64(*x as i64))
+            }
+            (CellType::Int, Value::Text(t)) => t.trim().parse::<i64>().ok().map(Value::I64),
+            (CellType::Float, Value::I32(_) | Value::I64(_) | Value::F64(_)) => {
+                value.try_into_f64().ok().map(Value::F64)
+            }
+            (CellType::Float, Value::Text(t)) => t.trim().parse::<f64>().ok().map(Value::F64),
+            (CellType::Text, Value::Text(_)) => Some(value.clone()),
+            (CellType::Text, Value::Bool(_) | Value::I32(_) | Value::I64(_) | Value::F64(_)) => {
+                cell_to_string(value).ok().map(Value::Text)
+            }
+            (CellType::Bool, Value::Bool(_)) => Some(value.clone()),
+            (CellType::Bool, Value::I32(0 | 1) | Value::I64(0 | 1)) => {
+                value.try_into_bool().ok().map(Value::Bool)
+            }
+            (CellType::Bool, Value::Text(t)) => match t.trim().to_lowercase().as_str() {
+                "true" | "1" => Some(Value::Bool(true)),
+                "false" | "0" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Convert the cells of a single column to the `target` type (`int`, `float`, `text` or `bool`,
+/// see [CellType]); other columns are left unchanged.
+/// If `nullable` is set, missing cells (and empty texts when converting to a non-text type)
+/// become missing values, otherwise they are an error.
+/// A cell that can't be converted is an error reporting the row.
+pub fn astype(state: &State<Value>, column: String, target: String, nullable: bool) -> Result<Value, Error> {
+    let cell_type = CellType::from_name(&target)?;
+    let mut table = Table::from_value(&state.read().unwrap())?;
+    table.check_column(&column)?;
+    for (i, row) in table.rows.iter_mut().enumerate() {
+        let value = row.remove(&column).unwrap_or(Value::None);
+        let missing = match &value {
+            Value::None => true,
+            Value::Text(t) => t.trim().is_empty() && cell_type != CellType::Text,
+            _ => false,
+        };
+        let converted = if missing {
+            if !nullable {
+                return Err(Error::conversion_error_with_message(
+                    value.type_name(),
+                    cell_type.name(),
+                    &format!("Column '{}', row {}: missing value in a non-nullable column", column, i),
+                ));
+            }
+            Value::None
+        } else {
+            cell_type.convert(&value).ok_or_else(|| {
+                Error::conversion_error_with_message(
+                    value.type_name(),
+                    cell_type.name(),
+                    &format!(
+                        "Column '{}', row {}: value {} can't be converted to {}",
+                        column,
+                        i,
+                        value
+                            .try_into_json_value()
+                            .map(|v| v.to_string())
+                            .unwrap_or_default(),
+                        cell_type.name()
+                    ),
+                )
+            })?
+        };
+        row.insert(column.clone(), converted);
+    }
+    Ok(table.into_value())
+}
+
+/// Seed used by [sample] when no seed is given
+pub const DEFAULT_SAMPLE_SEED: i64 = 0;
+
+/// SplitMix64 pseudo-random generator; good enough for sampling and reproducible
+/// across platforms and versions, which matters for cached results.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Random number in range 0..n (n > 0)
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Random sample of `n` rows of a table, keeping the original row order.
+/// The sample is fully determined by the `seed` (default [DEFAULT_SAMPLE_SEED]):
+/// the same query always yields the same rows, so the result can be cached under the query.
+/// A truly random sample would make the cached value depend on when it was evaluated.
+/// Use a different seed to get a different sample.
+/// If `n` is not smaller than the number of rows, all the rows are returned.
+pub fn sample(state: &State<Value>, n: i64, seed: i64) -> Result<Value, Error> {
+    if n < 0 {
+        return Err(Error::general_error(format!(
+            "Sample size must not be negative, got {}",
+            n
+        )));
+    }
+    let mut table = Table::from_value(&state.read().unwrap())?;
+    let n = n as usize;
+    if n < table.len() {
+        // Partial Fisher-Yates shuffle of the row indices
+        let mut rng = SplitMix64(seed as u64);
+        let mut indices = (0..table.len()).collect::<Vec<_>>();
+        for i in 0..n {
+            let j = i + rng.below(indices.len() - i);
+            indices.swap(i, j);
+        }
+        let mut selected = indices[..n].to_vec();
+        selected.sort_unstable();
+        let mut rows = std::mem::take(&mut table.rows)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        table.rows = selected
+            .into_iter()
+            .filter_map(|i| rows[i].take())
+            .collect();
+    }
+    Ok(table.into_value())
+}
+
+/// Remove duplicate rows from a table, keeping the first occurrence and the original row order.
+/// If `columns` are given, only these columns are compared, otherwise whole rows are compared.
+/// A missing cell is equal to a null cell.
+pub fn distinct(state: &State<Value>, columns: Vec<Value>) -> Result<Value, Error> {
+    let mut table = Table::from_value(&state.read().unwrap())?;
+    let columns = if columns.is_empty() {
+        table.columns.clone()
+    } else {
+        columns
+            .iter()
+            .map(|c| c.try_into_string())
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    for c in columns.iter() {
+        table.check_column(c)?;
+    }
+    let mut seen = HashSet::new();
+    let mut rows = std::mem::take(&mut table.rows);
+    for row in rows.drain(..) {
+        let key = columns
+            .iter()
+            .map(|c| row.get(c).unwrap_or(&Value::None))
+            .collect::<Vec<_>>();
+        let key = serde_json::to_string(&key).map_err(|e| {
+            Error::general_error(format!("Can't compare rows of the table: {}", e))
+        })?;
+        if seen.insert(key) {
+            table.rows.push(row);
+        }
+    }
+    Ok(table.into_value())
+}
+
+/// Convert a table (array of records) into an object of columns, mapping each column name
+/// to the array of its values. Cells missing in a record are filled with null.
+pub fn to_columns(state: &State<Value>) -> Result<Value, Error> {
+    let table = Table::from_value(&state.read().unwrap())?;
+    Ok(Value::Object(
+        table
+            .columns
+            .iter()
+            .map(|c| (c.clone(), Value::Array(table.column_values(c))))
+            .collect(),
+    ))
+}
+
+/// Convert an object of columns (column names mapped to arrays of values) into a table (array of records).
+/// Columns of different lengths are padded with null to the length of the longest column.
+pub fn to_records(state: &State<Value>) -> Result<Value, Error> {
+    let columns = match &*state.read().unwrap() {
+        Value::Object(o) => o
+            .iter()
+            .map(|(name, column)| match column {
+                Value::Array(a) => Ok((name.clone(), a.clone())),
+                Value::None => Ok((name.clone(), vec![])),
+                _ => Err(Error::conversion_error_with_message(
+                    column.type_name(),
+                    "array",
+                    &format!("Column '{}' is not an array", name),
+                )),
+            })
+            .collect::<Result<Vec<_>, Error>>()?,
+        Value::None => vec![],
+        value => return Err(Error::conversion_error(value.type_name(), "object of columns")),
+    };
+    let len = columns.iter().map(|(_, c)| c.len()).max().unwrap_or(0);
+    let mut table = Table::new(columns.iter().map(|(name, _)| name.clone()).collect());
+    for i in 0..len {
+        table.push_row(
+            columns
+                .iter()
+                .map(|(name, c)| (name.clone(), c.get(i).cloned().unwrap_or(Value::None)))
+                .collect(),
+        );
+    }
+    Ok(table.into_value())
+}
+
+/// Clamp a numeric value to the range given by the optional bounds.
+/// Integers stay integers: the bounds are rounded inwards (the minimum up, the maximum down).
+/// Missing values ([Value::None]) are left unchanged.
+fn clamp_number(value: &Value, min: Option<f64>, max: Option<f64>) -> Option<Value> {
+    fn clamp_int(x: i64, min: Option<f64>, max: Option<f64>) -> i64 {
+        let x = min.map_or(x, |m| x.max(m.ceil() as i64));
+        max.map_or(x, |m| x.min(m.floor() as i64))
+    }
+    match value {
+        Value::None => Some(Value::None),
+        Value::I32(x) => Some(Value::I32(clamp_int(*x as i64, min, max) as i32)),
+        Value::I64(x) => Some(Value::I64(clamp_int(*x, min, max))),
+        Value::F64(x) => {
+            let x = min.map_or(*x, |m| x.max(m));
+            Some(Value::F64(max.map_or(x, |m| x.min(m))))
+        }
+        _ => None,
+    }
+}
+
+/// Clamp numeric values to the range [`min`, `max`], either bound is optional.
+/// The state is either a numeric scalar or a table, in which case the values of the `column` are clamped.
+pub fn clamp(state: &State<Value>, min: Option<f64>, max: Option<f64>, column: String) -> Result<Value, Error> {
+    if let (Some(min), Some(max)) = (min, max) {
+        if min > max {
+            return Err(Error::general_error(format!(
+                "Invalid clamp range: minimum {} is greater than maximum {}",
+                min, max
+            )));
+        }
+    }
+    let value = state.read().unwrap();
+    if !matches!(&*value, Value::Array(_)) {
+        return clamp_number(&value, min, max)
+            .ok_or_else(|| Error::conversion_error(value.type_name(), "number"));
+    }
+    if column.is_empty() {
+        return Err(Error::general_error(
+            "Clamping a table requires the column argument".to_owned(),
+        ));
+    }
+    let mut table = Table::from_value(&value)?;
+    table.check_column(&column)?;
+    for (i, row) in table.rows.iter_mut().enumerate() {
+        if let Some(cell) = row.get_mut(&column) {
+            *cell = clamp_number(cell, min, max).ok_or_else(|| {
+                Error::conversion_error_with_message(
+                    cell.type_name(),
+                    "number",
+                    &format!("Column '{}', row {}: only numeric values can be clamped", column, i),
+                )
+            })?;
+        }
+    }
+    Ok(table.into_value())
+}
+
+/// Name of the column labeling the rows of the matrices computed by [corr] and [cov]
+pub const MATRIX_LABEL_COLUMN: &str = "column";
+
+/// Numeric columns of a table as vectors of optional numbers (missing cells are None).
+/// A column is numeric if all its present cells are integers or floats.
+/// Non-numeric columns are dropped if `non_numeric` is `drop`, or reported as an error if it is `error`.
+fn numeric_columns(table: &Table, non_numeric: &str) -> Result<Vec<(String, Vec<Option<f64>>)>, Error> {
+    if non_numeric != "drop" && non_numeric != "error" {
+        return Err(Error::general_error(format!(
+            "Unknown non-numeric column handling '{}'; expected 'drop' or 'error'",
+            non_numeric
+        )));
+    }
+    let mut columns = Vec::new();
+    for column in table.columns.iter() {
+        let cells = (0..table.len())
+            .map(|i| match table.get(i, column) {
+                Value::None => Ok(None),
+                Value::I32(x) => Ok(Some(*x as f64)),
+                Value::I64(x) => Ok(Some(*x as f64)),
+                Value::F64(x) => Ok(Some(*x)),
+                value => Err(value.type_name()),
+            })
+            .collect::<Result<Vec<_>, _>>();
+        match cells {
+            Ok(cells) => columns.push((column.clone(), cells)),
+            Err(type_name) if non_numeric == "error" => {
+                return Err(Error::conversion_error_with_message(
+                    type_name,
+                    "number",
+                    &format!("Column '{}' is not numeric", column),
+                ))
+            }
+            Err(_) => {}
+        }
+    }
+    Ok(columns)
+}
+
+/// Sample covariance and the standard deviations of two columns over the rows where both are present,
+/// None if there are less than two such rows
+fn covariance(x: &[Option<f64>], y: &[Option<f64>]) -> Option<(f64, f64, f64)> {
+    let pairs = x
+        .iter()
+        .zip(y.iter())
+        .filter_map(|(a, b)| Some(((*a)?, (*b)?)))
+        .collect::<Vec<_>>();
+    if pairs.len() < 2 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|(a, _)| a).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|(_, b)| b).sum::<f64>() / n;
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (a, b) in pairs.iter() {
+        cov += (a - mean_x) * (b - mean_y);
+        var_x += (a - mean_x) * (a - mean_x);
+        var_y += (b - mean_y) * (b - mean_y);
+    }
+    Some((cov / (n - 1.0), (var_x / (n - 1.0)).sqrt(), (var_y / (n - 1.0)).sqrt()))
+}
+
+/// Square matrix of a pairwise statistic of the numeric columns, as a table with one row per column,
+/// labeled in the [MATRIX_LABEL_COLUMN]
+fn column_matrix(
+    state: &State<Value>,
+    non_numeric: &str,
+    statistic: impl Fn(&[Option<f64>], &[Option<f64>]) -> Option<f64>,
+) -> Result<Value, Error> {
+    let table = Table::from_value(&state.read().unwrap())?;
+    let columns = numeric_columns(&table, non_numeric)?;
+    let mut matrix = Table::new(
+        std::iter::once(MATRIX_LABEL_COLUMN.to_owned())
+            .chain(columns.iter().map(|(name, _)| name.clone()))
+            .collect(),
+    );
+    for (name, x) in columns.iter() {
+        let mut row = BTreeMap::new();
+        row.insert(MATRIX_LABEL_COLUMN.to_owned(), Value::from(name.clone()));
+        for (other, y) in columns.iter() {
+            let cell = statistic(x, y).map_or(Value::None, Value::F64);
+            row.insert(other.clone(), cell);
+        }
+        matrix.push_row(row);
+    }
+    Ok(matrix.into_value())
+}
+
+/// Pearson correlation matrix of the numeric columns of a table (see [numeric_columns] for `non_numeric`).
+/// The result is a square table with one row per column, labeled in the [MATRIX_LABEL_COLUMN].
+/// Each pair of columns is correlated over the rows where both values are present.
+/// The correlation is missing (null) if it is undefined, i.e. for a column with zero variance
+/// or with less than two values.
+pub fn corr(state: &State<Value>, non_numeric: String) -> Result<Value, Error> {
+    column_matrix(state, &non_numeric, |x, y| {
+        let (cov, sx, sy) = covariance(x, y)?;
+        (sx > 0.0 && sy > 0.0).then(|| (cov / (sx * sy)).clamp(-1.0, 1.0))
+    })
+}
+
+/// Sample covariance matrix of the numeric columns of a table, in the same layout as [corr].
+/// The covariance is missing (null) for pairs of columns with less than two common values.
+pub fn cov(state: &State<Value>, non_numeric: String) -> Result<Value, Error> {
+    column_matrix(state, &non_numeric, |x, y| covariance(x, y).map(|(cov, _, _)| cov))
+}
+
+/// Explode an array column: each row is repeated for every element of the array in the `column`,
+/// with the other columns duplicated. Rows with an empty array are dropped,
+/// unless `keep_empty` is set, in which case they are kept with a missing value.
+/// Missing cells and non-array values are kept as they are.
+pub fn explode(state: &State<Value>, column: String, keep_empty: bool) -> Result<Value, Error> {
+    let mut table = Table::from_value(&state.read().unwrap())?;
+    table.check_column(&column)?;
+    let rows = std::mem::take(&mut table.rows);
+    for mut row in rows {
+        match row.remove(&column) {
+            Some(Value::Array(items)) if items.is_empty() => {
+                if keep_empty {
+                    row.insert(column.clone(), Value::None);
+                    table.rows.push(row);
+                }
+            }
+            Some(Value::Array(items)) => {
+                for item in items {
+                    let mut exploded = row.clone();
+                    exploded.insert(column.clone(), item);
+                    table.rows.push(exploded);
+                }
+            }
+            Some(value) => {
+                row.insert(column.clone(), value);
+                table.rows.push(row);
+            }
+            None => table.rows.push(row),
+        }
+    }
+    Ok(table.into_value())
+}
+
+/// Replace the missing (null) cells of the `columns` (all columns if none are given) of a table.
+/// The `fill` is either a strategy or a constant:
+/// - `ffill` - the last present value above in the column (forward fill),
+/// - `bfill` - the next present value below in the column (backward fill),
+/// - `mean` - the mean of the present values of a numeric column,
+/// - a constant, parsed as JSON if possible (e.g. `0`, `1.5`, `true`), otherwise used as text.
+///
+/// Cells that can't be filled (e.g. leading cells with forward fill) stay missing.
+pub fn fillna(state: &State<Value>, fill: String, columns: Vec<Value>) -> Result<Value, Error> {
+    let mut table = Table::from_value(&state.read().unwrap())?;
+    let columns = if columns.is_empty() {
+        table.columns.clone()
+    } else {
+        columns
+            .iter()
+            .map(|c| c.try_into_string())
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    for c in columns.iter() {
+        table.check_column(c)?;
+    }
+    for column in columns.iter() {
+        let cells = table.column_values(column);
+        let filled: Vec<Value> = match fill.as_str() {
+            "ffill" => {
+                let mut last = Value::None;
+                cells
+                    .into_iter()
+                    .map(|cell| {
+                        if !cell.is_none() {
+                            last = cell;
+                        }
+                        last.clone()
+                    })
+                    .collect()
+            }
+            "bfill" => {
+                let mut next = Value::None;
+                let mut filled = cells
+                    .into_iter()
+                    .rev()
+                    .map(|cell| {
+                        if !cell.is_none() {
+                            next = cell;
+                        }
+                        next.clone()
+                    })
+                    .collect::<Vec<_>>();
+                filled.reverse();
+                filled
+            }
+            "mean" => {
+                let mean = Aggregation::Mean
+                    .aggregate(cells.clone())
+                    .map_err(|e| {
+                        Error::general_error(format!(
+                            "Column '{}' can't be filled by the mean: {}",
+                            column, e
+                        ))
+                    })?;
+                cells
+                    .into_iter()
+                    .map(|cell| if cell.is_none() { mean.clone() } else { cell })
+                    .collect()
+            }
+            constant => {
+                let constant = serde_json::from_str::<Value>(constant)
+                    .unwrap_or_else(|_| Value::from(constant.to_owned()));
+                cells
+                    .into_iter()
+                    .map(|cell| if cell.is_none() { constant.clone() } else { cell })
+                    .collect()
+            }
+        };
+        for (row, cell) in table.rows.iter_mut().zip(filled) {
+            if !cell.is_none() {
+                row.insert(column.clone(), cell);
+            }
+        }
+    }
+    Ok(table.into_value())
+}
+
+/// Rename columns of a table; `pairs` are the old and the new names, e.g. `rename-a-x-b-y`.
+/// The result is an array of records (objects with the keys sorted by name), so a renamed column
+/// takes the place given by its new name. It is an error if an old column does not exist or if a new name
+/// collides with another column (columns can be swapped, since all the renames are applied at once).
+pub fn rename(state: &State<Value>, pairs: Vec<Value>) -> Result<Value, Error> {
+    if pairs.len() % 2 != 0 {
+        return Err(Error::general_error(format!(
+            "Rename expects pairs of old and new column names, {} names given",
+            pairs.len()
+        )));
+    }
+    let names = pairs
+        .iter()
+        .map(|name| name.try_into_string())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mapping = names
+        .chunks(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect::<HashMap<_, _>>();
+    if mapping.len() != names.len() / 2 {
+        return Err(Error::general_error(
+            "A column can be renamed only once".to_owned(),
+        ));
+    }
+    let mut table = Table::from_value(&state.read().unwrap())?;
+    for old in mapping.keys() {
+        table.check_column(old)?;
+    }
+    let columns = table
+        .columns
+        .iter()
+        .map(|c| mapping.get(c).cloned().unwrap_or_else(|| c.clone()))
+        .collect::<Vec<_>>();
+    let mut unique = HashSet::new();
+    if let Some(duplicate) = columns.iter().find(|c| !unique.insert(*c)) {
+        return Err(Error::general_error(format!(
+            "Renamed column '{}' collides with another column",
+            duplicate
+        )));
+    }
+    table.columns = columns;
+    for row in table.rows.iter_mut() {
+        let cells = std::mem::take(row);
+        *row = cells
+            .into_iter()
+            .map(|(c, v)| (mapping.get(&c).cloned().unwrap_or(c), v))
+            .collect();
+    }
+    Ok(table.into_value())
+}
+
+ng_register_all!(
+    /// Register the table commands
+    pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+        transpose(state, header: String = ""),
+        partition(state, strategy: String, parameter: String),
+        pivot(state, column: String, values: String, aggregate: String, multiple index: Value),
+        sample(state, n: i64, seed: i64 = DEFAULT_SAMPLE_SEED),
+        distinct(state, multiple columns: Value),
+        group_agg(state, by: String, multiple aggregations: Value),
+        rolling(state, window: i64, aggregation: String, columns: String, min_periods: i64 = 0, partition: String = ""),
+        astype(state, column: String, target: String, nullable: bool = true),
+        to_columns(state),
+        to_records(state),
+        clamp(state, min: Option<f64>, max: Option<f64>, column: String = ""),
+        explode(state, column: String, keep_empty: bool = false),
+        fillna(state, fill: String, multiple columns: Value),
+        rename(state, multiple pairs: Value),
+        corr(state, non_numeric: String = "drop"),
+        cov(state, non_numeric: String = "drop"),
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(json: &str) -> State<Value> {
+        let value: Value = serde_json::from_str(json).unwrap();
+        State::new().with_data(value)
+    }
+
+    #[test]
+    fn test_transpose_numeric() -> Result<(), Error> {
+        let state = table(
+            r#"[{"name":"a","x":1,"y":2},{"name":"b","x":3,"y":4},{"name":"c","x":5,"y":6}]"#,
+        );
+        let t = Table::from_value(&transpose(&state, "name".to_owned())?)?;
+        assert_eq!(t.columns, vec!["column", "a", "b", "c"]);
+        assert_eq!(t.len(), 2);
+        assert_eq!(t.get(0, "column"), &Value::Text("x".to_owned()));
+        assert_eq!(t.get(0, "b"), &Value::I32(3));
+        assert_eq!(t.get(1, "c"), &Value::I32(6));
+
+        let t = Table::from_value(&transpose(&state, "".to_owned())?)?;
+        assert_eq!(t.columns, vec!["column", "0", "1", "2"]);
+        assert_eq!(t.len(), 3);
+        assert_eq!(t.get(0, "column"), &Value::Text("name".to_owned()));
+        assert_eq!(t.get(2, "1"), &Value::Text("4".to_owned()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_transpose_widening() -> Result<(), Error> {
+        let state = table(
+            r#"[{"id":"r1","a":1,"b":2.5,"c":null},{"id":"r2","a":true,"b":"text","c":[1,2]}]"#,
+        );
+        let t = Table::from_value(&transpose(&state, "id".to_owned())?)?;
+        assert_eq!(t.columns, vec!["column", "r1", "r2"]);
+        assert_eq!(t.column_values("r1"), vec![
+            Value::F64(1.0),
+            Value::F64(2.5),
+            Value::None
+        ]);
+        assert_eq!(t.column_values("r2"), vec![
+            Value::Text("true".to_owned()),
+            Value::Text("text".to_owned()),
+            Value::Text("[1,2]".to_owned())
+        ]);
+        assert!(transpose(&state, "missing".to_owned()).is_err());
+        Ok(())
+    }
+
+    fn parts(value: Value) -> Vec<Table> {
+        match value {
+            Value::Array(a) => a.iter().map(|t| Table::from_value(t).unwrap()).collect(),
+            _ => panic!("Array of tables expected"),
+        }
+    }
+
+    #[test]
+    fn test_partition_count() -> Result<(), Error> {
+        let state = table(r#"[{"x":1},{"x":2},{"x":3},{"x":4},{"x":5}]"#);
+        let p = parts(partition(&state, "count".to_owned(), "3".to_owned())?);
+        assert_eq!(p.len(), 3);
+        assert_eq!(p[0].column_values("x"), vec![Value::I32(1), Value::I32(2)]);
+        assert_eq!(p[1].column_values("x"), vec![Value::I32(3), Value::I32(4)]);
+        assert_eq!(p[2].column_values("x"), vec![Value::I32(5)]);
+
+        let p = parts(partition(&state, "count".to_owned(), "7".to_owned())?);
+        assert_eq!(p.len(), 7);
+        assert!(p[5].is_empty() && p[6].is_empty());
+
+        assert!(partition(&state, "count".to_owned(), "0".to_owned()).is_err());
+        assert!(partition(&state, "count".to_owned(), "x".to_owned()).is_err());
+        let err = partition(&state, "count".to_owned(), (MAX_PARTITIONS + 1).to_string()).unwrap_err();
+        assert!(err.message.contains("exceeds the maximum"));
+        assert!(partition(&state, "random".to_owned(), "2".to_owned()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition_by_column() -> Result<(), Error> {
+        let state = table(
+            r#"[{"kind":"b","x":1},{"kind":"a","x":2},{"kind":"b","x":3},{"x":4},{"kind":"a","x":5}]"#,
+        );
+        let p = parts(partition(&state, "column".to_owned(), "kind".to_owned())?);
+        assert_eq!(p.len(), 3);
+        assert_eq!(p[0].column_values("x"), vec![Value::I32(1), Value::I32(3)]);
+        assert_eq!(p[1].column_values("x"), vec![Value::I32(2), Value::I32(5)]);
+        assert_eq!(p[2].column_values("x"), vec![Value::I32(4)]);
+        assert_eq!(p[2].column_values("kind"), vec![Value::None]);
+        assert!(partition(&state, "column".to_owned(), "missing".to_owned()).is_err());
+
+        let rows = (0..=MAX_PARTITIONS)
+            .map(|i| format!(r#"{{"x":{}}}"#, i))
+            .collect::<Vec<_>>()
+            .join(",");
+        let state = table(&format!("[{}]", rows));
+        let err = partition(&state, "column".to_owned(), "x".to_owned()).unwrap_err();
+        assert!(err.message.contains("more than"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample() -> Result<(), Error> {
+        let rows = (0..100)
+            .map(|i| format!(r#"{{"x":{}}}"#, i))
+            .collect::<Vec<_>>()
+            .join(",");
+        let state = table(&format!("[{}]", rows));
+        let a = Table::from_value(&sample(&state, 10, 42)?)?;
+        let b = Table::from_value(&sample(&state, 10, 42)?)?;
+        assert_eq!(a.len(), 10);
+        assert_eq!(a, b);
+        let x = a
+            .column_values("x")
+            .iter()
+            .map(|v| v.try_into_i64().unwrap())
+            .collect::<Vec<_>>();
+        assert!(x.windows(2).all(|w| w[0] < w[1]));
+        let c = Table::from_value(&sample(&state, 10, 43)?)?;
+        assert_ne!(a, c);
+        assert!(sample(&state, -1, 42).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_all_rows() -> Result<(), Error> {
+        let state = table(r#"[{"x":1},{"x":2},{"x":3}]"#);
+        let t = Table::from_value(&sample(&state, 5, DEFAULT_SAMPLE_SEED)?)?;
+        assert_eq!(
+            t.column_values("x"),
+            vec![Value::I32(1), Value::I32(2), Value::I32(3)]
+        );
+        assert_eq!(Table::from_value(&sample(&state, 0, 1)?)?.len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pivot() -> Result<(), Error> {
+        let state = table(
+            r#"[
+                {"city":"Prague","year":2023,"sales":10},
+                {"city":"Prague","year":2024,"sales":12},
+                {"city":"Brno","year":2023,"sales":7}
+            ]"#,
+        );
+        let index = vec![Value::Text("city".to_owned())];
+        let t = Table::from_value(&pivot(
+            &state,
+            "year".to_owned(),
+            "sales".to_owned(),
+            "first".to_owned(),
+            index.clone(),
+        )?)?;
+        assert_eq!(t.len(), 2);
+        assert_eq!(t.column_values("city"), vec![Value::from("Prague"), Value::from("Brno")]);
+        assert_eq!(t.column_values("2023"), vec![Value::I32(10), Value::I32(7)]);
+        assert_eq!(t.column_values("2024"), vec![Value::I32(12), Value::None]);
+        assert!(pivot(
+            &state,
+            "year".to_owned(),
+            "missing".to_owned(),
+            "first".to_owned(),
+            index
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pivot_aggregation() -> Result<(), Error> {
+        let state = table(
+            r#"[
+                {"city":"Prague","shop":"A","product":"tea","sales":10},
+                {"city":"Prague","shop":"A","product":"tea","sales":5},
+                {"city":"Prague","shop":"A","product":"coffee","sales":2.5},
+                {"city":"Prague","shop":"A","product":"coffee","sales":1},
+                {"city":"Brno","shop":"B","product":"tea","sales":3}
+            ]"#,
+        );
+        let index = vec![Value::from("city"), Value::from("shop")];
+        let run = |aggregate: &str| {
+            Table::from_value(
+                &pivot(
+                    &state,
+                    "product".to_owned(),
+                    "sales".to_owned(),
+                    aggregate.to_owned(),
+                    index.clone(),
+                )
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        let t = run("sum");
+        assert_eq!(t.column_values("tea"), vec![Value::I64(15), Value::I64(3)]);
+        assert_eq!(t.column_values("coffee"), vec![Value::F64(3.5), Value::None]);
+        let t = run("count");
+        assert_eq!(t.column_values("tea"), vec![Value::I64(2), Value::I64(1)]);
+        let t = run("first");
+        assert_eq!(t.column_values("tea"), vec![Value::I32(10), Value::I32(3)]);
+        assert_eq!(t.column_values("shop"), vec![Value::from("A"), Value::from("B")]);
+
+        let sum = Aggregation::Sum.aggregate(vec![Value::I64(i64::MAX), Value::I32(1), Value::I64(1)])?;
+        assert_eq!(sum, Value::F64(i64::MAX as f64 + 2.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_distinct_rows() -> Result<(), Error> {
+        let state = table(
+            r#"[{"a":1,"b":"x"},{"a":2,"b":"y"},{"a":1,"b":"x"},{"a":1,"b":null},{"a":1}]"#,
+        );
+        let t = Table::from_value(&distinct(&state, vec![])?)?;
+        assert_eq!(t.column_values("a"), vec![Value::I32(1), Value::I32(2), Value::I32(1)]);
+        assert_eq!(
+            t.column_values("b"),
+            vec![Value::from("x"), Value::from("y"), Value::None]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_distinct_subset() -> Result<(), Error> {
+        let state = table(
+            r#"[{"a":1,"b":"x","n":1},{"a":2,"b":"x","n":2},{"a":1,"b":"x","n":3},{"a":1,"b":"y","n":4}]"#,
+        );
+        let t = Table::from_value(&distinct(&state, vec![Value::from("a"), Value::from("b")])?)?;
+        assert_eq!(t.column_values("n"), vec![Value::I32(1), Value::I32(2), Value::I32(4)]);
+        let t = Table::from_value(&distinct(&state, vec![Value::from("b")])?)?;
+        assert_eq!(t.column_values("n"), vec![Value::I32(1), Value::I32(4)]);
+        assert!(distinct(&state, vec![Value::from("missing")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_agg() -> Result<(), Error> {
+        let state = table(
+            r#"[
+                {"city":"Prague","sales":10,"price":2.0},
+                {"city":"Brno","sales":3,"price":1.0},
+                {"city":"Prague","sales":5,"price":4.0},
+                {"city":"Brno","sales":null,"price":3.0}
+            ]"#,
+        );
+        let t = Table::from_value(&group_agg(
+            &state,
+            "city".to_owned(),
+            vec![Value::from("sales.sum"), Value::from("price.mean")],
+        )?)?;
+        // Records are objects, so the columns come back sorted
+        assert_eq!(t.columns, vec!["city", "price_mean", "sales_sum"]);
+        assert_eq!(t.column_values("city"), vec![Value::from("Prague"), Value::from("Brno")]);
+        assert_eq!(t.column_values("sales_sum"), vec![Value::I64(15), Value::I64(3)]);
+        assert_eq!(t.column_values("price_mean"), vec![Value::F64(3.0), Value::F64(2.0)]);
+
+        let t = Table::from_value(&group_agg(
+            &state,
+            "".to_owned(),
+            vec![Value::from("sales.count"), Value::from("price.max"), Value::from("city.min")],
+        )?)?;
+        assert_eq!(t.len(), 1);
+        assert_eq!(t.get(0, "sales_count"), &Value::I64(3));
+        assert_eq!(t.get(0, "price_max"), &Value::F64(4.0));
+        assert_eq!(t.get(0, "city_min"), &Value::from("Brno"));
+
+        let err = group_agg(&state, "city".to_owned(), vec![Value::from("missing.sum")]).unwrap_err();
+        assert!(err.message.contains("Column 'missing' not found"));
+        assert!(group_agg(&state, "town".to_owned(), vec![]).is_err());
+        assert!(group_agg(&state, "city".to_owned(), vec![Value::from("sales.median")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rolling_mean() -> Result<(), Error> {
+        let state = table(
+            r#"[
+                {"city":"Prague","t":1},
+                {"city":"Brno","t":10},
+                {"city":"Prague","t":3},
+                {"city":"Prague","t":null},
+                {"city":"Brno","t":20},
+                {"city":"Prague","t":8}
+            ]"#,
+        );
+        // Full windows only
+        let t = Table::from_value(&rolling(&state, 2, "mean".to_owned(), "t".to_owned(), 0, "".to_owned())?)?;
+        assert_eq!(
+            t.column_values("t_mean"),
+            vec![Value::None, Value::F64(5.5), Value::F64(6.5), Value::None, Value::None, Value::F64(14.0)]
+        );
+        // Partial windows at the start
+        let t = Table::from_value(&rolling(&state, 3, "mean".to_owned(), "t".to_owned(), 1, "".to_owned())?)?;
+        assert_eq!(
+            t.column_values("t_mean"),
+            vec![Value::F64(1.0), Value::F64(5.5), Value::F64(14.0 / 3.0), Value::F64(6.5), Value::F64(11.5), Value::F64(14.0)]
+        );
+        // Partitioned windows
+        let t = Table::from_value(&rolling(&state, 2, "mean".to_owned(), "t".to_owned(), 1, "city".to_owned())?)?;
+        assert_eq!(
+            t.column_values("t_mean"),
+            vec![Value::F64(1.0), Value::F64(10.0), Value::F64(2.0), Value::F64(3.0), Value::F64(15.0), Value::F64(8.0)]
+        );
+        assert_eq!(t.column_values("t"), Table::from_value(&state.read().unwrap())?.column_values("t"));
+
+        assert!(rolling(&state, 0, "mean".to_owned(), "t".to_owned(), 0, "".to_owned()).is_err());
+        assert!(rolling(&state, 2, "mean".to_owned(), "t".to_owned(), 3, "".to_owned()).is_err());
+        assert!(rolling(&state, 2, "mean".to_owned(), "missing".to_owned(), 0, "".to_owned()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_astype() -> Result<(), Error> {
+        let state = table(r#"[{"n":"1","x":"a"},{"n":" 42 ","x":"b"},{"n":"","x":"c"},{"x":"d"}]"#);
+        let t = Table::from_value(&astype(&state, "n".to_owned(), "int".to_owned(), true)?)?;
+        assert_eq!(
+            t.column_values("n"),
+            vec![Value::I64(1), Value::I64(42), Value::None, Value::None]
+        );
+        assert_eq!(t.column_values("x"), vec![Value::from("a"), Value::from("b"), Value::from("c"), Value::from("d")]);
+        let t = Table::from_value(&astype(&table(r#"[{"f":1},{"f":2.5}]"#), "f".to_owned(), "text".to_owned(), false)?)?;
+        assert_eq!(t.column_values("f"), vec![Value::from("1"), Value::from("2.5")]);
+
+        let err = astype(&state, "n".to_owned(), "int".to_owned(), false).unwrap_err();
+        assert!(err.message.contains("row 2: missing value"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_astype_failing_row() {
+        let state = table(r#"[{"n":"1"},{"n":"2"},{"n":"three"}]"#);
+        let err = astype(&state, "n".to_owned(), "int".to_owned(), true).unwrap_err();
+        assert!(err.message.contains("Column 'n', row 2: value \"three\" can't be converted to int"));
+        assert!(astype(&state, "m".to_owned(), "int".to_owned(), true).is_err());
+        assert!(astype(&state, "n".to_owned(), "date".to_owned(), true).is_err());
+    }
+
+    #[test]
+    fn test_to_columns_roundtrip() -> Result<(), Error> {
+        let state = table(r#"[{"a":1,"b":"x"},{"a":2,"b":"y"}]"#);
+        let columns = to_columns(&state)?;
+        let expected: Value = serde_json::from_str(r#"{"a":[1,2],"b":["x","y"]}"#).unwrap();
+        assert_eq!(columns, expected);
+        let records = to_records(&State::new().with_data(columns))?;
+        assert_eq!(records, *state.read().unwrap());
+        assert_eq!(to_columns(&table("[]"))?, Value::Object(BTreeMap::new()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_records_ragged() -> Result<(), Error> {
+        let records = to_records(&table(r#"{"a":[1,2,3],"b":["x"]}"#))?;
+        let expected: Value = serde_json::from_str(
+            r#"[{"a":1,"b":"x"},{"a":2,"b":null},{"a":3,"b":null}]"#,
+        )
+        .unwrap();
+        assert_eq!(records, expected);
+
+        let columns = to_columns(&table(r#"[{"a":1},{"b":"y"}]"#))?;
+        let expected: Value = serde_json::from_str(r#"{"a":[1,null],"b":[null,"y"]}"#).unwrap();
+        assert_eq!(columns, expected);
+
+        assert!(to_records(&table(r#"{"a":1}"#)).is_err());
+        assert!(to_columns(&table("[1,2]")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_clamp_scalar() -> Result<(), Error> {
+        let s = State::new().with_data(Value::F64(7.5));
+        assert_eq!(clamp(&s, Some(0.0), Some(5.0), "".to_owned())?, Value::F64(5.0));
+        let s = State::new().with_data(Value::I64(-3));
+        assert_eq!(clamp(&s, Some(0.5), Some(5.0), "".to_owned())?, Value::I64(1));
+        assert!(clamp(&s, Some(5.0), Some(0.0), "".to_owned()).is_err());
+        let s = State::new().with_data(Value::from("x"));
+        assert!(clamp(&s, Some(0.0), None, "".to_owned()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_clamp_column() -> Result<(), Error> {
+        let state = table(r#"[{"a": -1, "b": 100}, {"a": 50, "b": 2}, {"a": 7}, {"a": null}]"#);
+        let result = clamp(&state, Some(0.0), Some(10.0), "a".to_owned())?;
+        assert_eq!(
+            result.try_into_json_value()?,
+            serde_json::json!([{"a": 0, "b": 100}, {"a": 10, "b": 2}, {"a": 7}, {"a": null}])
+        );
+        assert!(clamp(&state, Some(0.0), Some(10.0), "".to_owned()).is_err());
+        assert!(clamp(&state, Some(0.0), Some(10.0), "c".to_owned()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_clamp_one_sided() -> Result<(), Error> {
+        let state = table(r#"[{"x": -2.5}, {"x": 1e6}]"#);
+        let result = clamp(&state, None, Some(1000.0), "x".to_owned())?;
+        assert_eq!(
+            result.try_into_json_value()?,
+            serde_json::json!([{"x": -2.5}, {"x": 1000.0}])
+        );
+        let result = clamp(&state, Some(0.0), None, "x".to_owned())?;
+        assert_eq!(
+            result.try_into_json_value()?,
+            serde_json::json!([{"x": 0.0}, {"x": 1e6}])
+        );
+        Ok(())
+    }
+
+    fn matrix_cell(matrix: &Value, row: &str, column: &str) -> Value {
+        let table = Table::from_value(matrix).unwrap();
+        let i = (0..table.len())
+            .find(|i| table.get(*i, MATRIX_LABEL_COLUMN) == &Value::from(row))
+            .unwrap();
+        table.get(i, column).clone()
+    }
+
+    #[test]
+    fn test_corr() -> Result<(), Error> {
+        let state = table(
+            r#"[{"x":1,"y":2,"z":5,"c":3,"name":"a"},{"x":2,"y":4,"z":3,"c":3,"name":"b"},{"x":3,"y":6,"z":4,"c":3,"name":"c"},{"x":4,"y":8,"z":1,"c":3,"name":"d"}]"#,
+        );
+        let matrix = corr(&state, "drop".to_owned())?;
+        let table_matrix = Table::from_value(&matrix)?;
+        assert_eq!(table_matrix.columns, vec!["c", "column", "x", "y", "z"]);
+        assert_eq!(table_matrix.len(), 4);
+        let close = |value: Value, expected: f64| {
+            let actual = value.try_into_f64().unwrap();
+            assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+        };
+        close(matrix_cell(&matrix, "x", "y"), 1.0);
+        close(matrix_cell(&matrix, "x", "x"), 1.0);
+        // z = [5, 3, 4, 1]: cov(x, z) = -11/6, var(x) = 5/3, var(z) = 35/12
+        let expected = -(11.0f64 / 6.0) / ((5.0f64 / 3.0) * (35.0 / 12.0)).sqrt();
+        close(matrix_cell(&matrix, "x", "z"), expected);
+        close(matrix_cell(&matrix, "z", "x"), expected);
+        // Zero variance
+        assert_eq!(matrix_cell(&matrix, "c", "x"), Value::None);
+
+        let covariance = cov(&state, "drop".to_owned())?;
+        close(matrix_cell(&covariance, "x", "y"), 10.0 / 3.0);
+        close(matrix_cell(&covariance, "c", "c"), 0.0);
+
+        assert!(corr(&state, "error".to_owned()).is_err());
+        assert!(corr(&state, "ignore".to_owned()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_explode() -> Result<(), Error> {
+        let state = table(r#"[{"id":1,"tags":["a","b"]},{"id":2,"tags":["c"]}]"#);
+        assert_eq!(
+            explode(&state, "tags".to_owned(), false)?.try_into_json_value()?,
+            serde_json::json!([{"id":1,"tags":"a"},{"id":1,"tags":"b"},{"id":2,"tags":"c"}])
+        );
+        assert!(explode(&state, "missing".to_owned(), false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_explode_empty_array() -> Result<(), Error> {
+        let state = table(r#"[{"id":1,"tags":[]},{"id":2,"tags":["c"]}]"#);
+        assert_eq!(
+            explode(&state, "tags".to_owned(), false)?.try_into_json_value()?,
+            serde_json::json!([{"id":2,"tags":"c"}])
+        );
+        assert_eq!(
+            explode(&state, "tags".to_owned(), true)?.try_into_json_value()?,
+            serde_json::json!([{"id":1,"tags":null},{"id":2,"tags":"c"}])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explode_null_cell() -> Result<(), Error> {
+        let state = table(r#"[{"id":1,"tags":null},{"id":2},{"id":3,"tags":["x","y"]}]"#);
+        assert_eq!(
+            explode(&state, "tags".to_owned(), false)?.try_into_json_value()?,
+            serde_json::json!([{"id":1,"tags":null},{"id":2},{"id":3,"tags":"x"},{"id":3,"tags":"y"}])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fillna_constant() -> Result<(), Error> {
+        let state = table(r#"[{"a":1,"b":"x"},{"a":null,"b":null},{"b":"y"}]"#);
+        assert_eq!(
+            fillna(&state, "0".to_owned(), vec![Value::from("a")])?.try_into_json_value()?,
+            serde_json::json!([{"a":1,"b":"x"},{"a":0,"b":null},{"a":0,"b":"y"}])
+        );
+        assert_eq!(
+            fillna(&state, "n/a".to_owned(), vec![])?.try_into_json_value()?,
+            serde_json::json!([{"a":1,"b":"x"},{"a":"n/a","b":"n/a"},{"a":"n/a","b":"y"}])
+        );
+        assert!(fillna(&state, "0".to_owned(), vec![Value::from("c")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fillna_forward_backward() -> Result<(), Error> {
+        let state = table(r#"[{"a":null},{"a":1},{"a":null},{"a":3},{"a":null}]"#);
+        assert_eq!(
+            fillna(&state, "ffill".to_owned(), vec![])?.try_into_json_value()?,
+            serde_json::json!([{"a":null},{"a":1},{"a":1},{"a":3},{"a":3}])
+        );
+        assert_eq!(
+            fillna(&state, "bfill".to_owned(), vec![])?.try_into_json_value()?,
+            serde_json::json!([{"a":1},{"a":1},{"a":3},{"a":3},{"a":null}])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fillna_mean() -> Result<(), Error> {
+        let state = table(r#"[{"a":1,"b":"x"},{"a":null,"b":null},{"a":4,"b":"y"}]"#);
+        assert_eq!(
+            fillna(&state, "mean".to_owned(), vec![Value::from("a")])?.try_into_json_value()?,
+            serde_json::json!([{"a":1,"b":"x"},{"a":2.5,"b":null},{"a":4,"b":"y"}])
+        );
+        assert!(fillna(&state, "mean".to_owned(), vec![Value::from("b")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename() -> Result<(), Error> {
+        let state = table(r#"[{"a":1,"b":2,"c":3},{"a":4,"c":6}]"#);
+        let renamed = rename(&state, vec![Value::from("a"), Value::from("x")])?;
+        assert_eq!(
+            renamed.try_into_json_value()?,
+            serde_json::json!([{"x":1,"b":2,"c":3},{"x":4,"c":6}])
+        );
+        assert_eq!(Table::from_value(&renamed)?.columns, vec!["b", "c", "x"]);
+        // Swap
+        let swapped = rename(
+            &state,
+            vec![Value::from("a"), Value::from("b"), Value::from("b"), Value::from("a")],
+        )?;
+        assert_eq!(
+            swapped.try_into_json_value()?,
+            serde_json::json!([{"b":1,"a":2,"c":3},{"b":4,"c":6}])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rename_missing_column() {
+        let state = table(r#"[{"a":1}]"#);
+        let err = rename(&state, vec![Value::from("z"), Value::from("x")]).unwrap_err();
+        assert!(err.message.contains("Column 'z' not found"));
+        assert!(rename(&state, vec![Value::from("a")]).is_err());
+    }
+
+    #[test]
+    fn test_rename_collision() {
+        let state = table(r#"[{"a":1,"b":2}]"#);
+        let err = rename(&state, vec![Value::from("a"), Value::from("b")]).unwrap_err();
+        assert!(err.message.contains("'b' collides"));
+        assert!(rename(
+            &state,
+            vec![Value::from("a"), Value::from("x"), Value::from("a"), Value::from("y")]
+        )
+        .is_err());
+    }
+}
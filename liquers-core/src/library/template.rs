@@ -0,0 +1,131 @@
+//! Text templates with `{{path}}` placeholders filled from a context value.
+
+use crate::context::NGEnvironment;
+use crate::error::Error;
+use crate::state::State;
+use crate::value::{Value, ValueInterface};
+
+use super::objects::value_at_path;
+use super::table::cell_to_string;
+use super::LibraryCommandRegistry;
+
+/// Text substituted for a placeholder; text is used directly, None renders empty
+/// and other values are encoded as JSON
+fn placeholder_text(value: &Value) -> Result<String, Error> {
+    match value {
+        Value::None => Ok(String::new()),
+        _ => cell_to_string(value),
+    }
+}
+
+/// Render a template, replacing `{{path}}` placeholders by the values at the path in the context
+/// (see [value_at_path]). Whitespace around the path is ignored.
+/// Literal braces and backslashes are escaped by a backslash (`\{`, `\}`, `\\`).
+/// If strict, an unresolved placeholder is an error, otherwise it renders empty.
+pub fn render_template(template: &str, context: &Value, strict: bool) -> Result<String, Error> {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    let mut offset = 0;
+    while let Some(i) = rest.find(['\\', '{']) {
+        result.push_str(&rest[..i]);
+        let tail = &rest[i..];
+        if tail.starts_with('\\') {
+            match tail[1..].chars().next() {
+                Some(c) if c == '{' || c == '}' || c == '\\' => {
+                    result.push(c);
+                    rest = &tail[2..];
+                    offset += i + 2;
+                }
+                _ => {
+                    result.push('\\');
+                    rest = &tail[1..];
+                    offset += i + 1;
+                }
+            }
+        } else if let Some(inner) = tail.strip_prefix("{{") {
+            let end = inner.find("}}").ok_or_else(|| {
+                Error::general_error(format!("Unclosed placeholder at offset {}", offset + i))
+            })?;
+            let path = inner[..end].trim();
+            match value_at_path(context, path) {
+                Some(value) => result.push_str(&placeholder_text(value)?),
+                None if strict => {
+                    return Err(Error::general_error(format!(
+                        "Unresolved placeholder '{}' at offset {}",
+                        path,
+                        offset + i
+                    )))
+                }
+                None => {}
+            }
+            rest = &inner[end + 2..];
+            offset += i + 2 + end + 2;
+        } else {
+            result.push('{');
+            rest = &tail[1..];
+            offset += i + 1;
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Render a template; the state is an array with the template text and the context value
+/// (typically an object), see [render_template]
+pub fn template(state: &State<Value>, strict: bool) -> Result<Value, Error> {
+    let value = state.read().unwrap();
+    match &*value {
+        Value::Array(items) if items.len() == 2 => {
+            let text = items[0].try_into_string().map_err(|_| {
+                Error::general_error(
+                    "The first element of the template input must be the template text".to_owned(),
+                )
+            })?;
+            Ok(Value::from(render_template(&text, &items[1], strict)?))
+        }
+        _ => Err(Error::general_error(
+            "The template command expects an array with the template text and the context value"
+                .to_owned(),
+        )),
+    }
+}
+
+ng_register_all!(
+    /// Register the template command
+    pub fn register_commands<E: NGEnvironment<Value = Value>>(cr: &mut LibraryCommandRegistry<E>) {
+        template(state, strict: bool = true),
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(json: &str) -> State<Value> {
+        let value: Value = serde_json::from_str(json).unwrap();
+        State::new().with_data(value)
+    }
+
+    #[test]
+    fn test_template_nested() -> Result<(), Error> {
+        let s = state(
+            r#"["Hello {{ user.name }}, first tag: {{user.tags.0}}, n={{n}}, \\{{literal\\}} \\\\", {"user": {"name": "Alice", "tags": ["a", "b"]}, "n": 3}]"#,
+        );
+        assert_eq!(
+            template(&s, true)?.try_into_string()?,
+            r"Hello Alice, first tag: a, n=3, {{literal}} \"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_unresolved() -> Result<(), Error> {
+        let s = state(r#"["a{{missing}}b{{x}}", {"x": null}]"#);
+        let err = template(&s, true).unwrap_err();
+        assert!(err.message.contains("'missing' at offset 1"));
+        assert_eq!(template(&s, false)?.try_into_string()?, "ab");
+        assert!(template(&state(r#"["{{x", {}]"#), false).is_err());
+        assert!(template(&state(r#""{{x}}""#), false).is_err());
+        Ok(())
+    }
+}
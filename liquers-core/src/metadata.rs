@@ -1,9 +1,11 @@
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
+use std::collections::BTreeMap;
+
 use serde_json::{self, Value};
 
-use crate::error::Error;
+use crate::error::{Error, ErrorType};
 use crate::parse;
 use crate::query::{Key, Position, Query};
 
@@ -22,13 +24,38 @@ pub enum Status {
     SideEffect,
 }
 
+impl Status {
+    /// Rank used to keep the worst status when merging metadata (see [MetadataRecord::merge]):
+    /// an error is worse than an expired result, which is worse than a result being produced
+    fn severity(&self) -> u8 {
+        match self {
+            Status::Error => 3,
+            Status::Expired => 2,
+            s if s.is_in_flight() => 1,
+            _ => 0,
+        }
+    }
+
+    /// The value is being produced (submitted or under evaluation)
+    pub fn is_in_flight(&self) -> bool {
+        matches!(
+            self,
+            Status::Submitted
+                | Status::EvaluatingParent
+                | Status::Evaluation
+                | Status::EvaluatingDependencies
+        )
+    }
+}
+
 impl Default for Status {
     fn default() -> Self {
         Self::None
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Kind (level) of a log entry; the levels are ordered by severity from Debug to Error
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogEntryKind {
     #[serde(rename = "debug")]
     Debug,
@@ -53,6 +80,9 @@ pub struct LogEntry {
     pub position: Position,
     #[serde(default)]
     pub traceback: Option<String>,
+    /// Trace (request) id of the evaluation that produced the entry, used to correlate logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
 }
 
 impl LogEntry {
@@ -87,6 +117,10 @@ impl LogEntry {
         self.traceback = Some(traceback);
         self
     }
+    pub fn with_trace_id(&mut self, trace_id: String) -> &mut Self {
+        self.trace_id = Some(trace_id);
+        self
+    }
     pub fn with_message_html(&mut self, message_html: String) -> &mut Self {
         self.message_html = Some(message_html);
         self
@@ -99,6 +133,10 @@ impl LogEntry {
         self.timestamp = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
         self
     }
+    /// Time of the entry, None if not known or not valid
+    pub fn time(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        chrono::DateTime::parse_from_rfc3339(&self.timestamp).ok()
+    }
 }
 
 impl Default for LogEntry {
@@ -111,6 +149,7 @@ impl Default for LogEntry {
             query: None,
             position: Position::default(),
             traceback: None,
+            trace_id: None,
         }
     }
 }
@@ -128,6 +167,14 @@ pub struct MetadataRecord {
     pub is_error: bool,
     pub media_type: String,
     pub filename: Option<String>,
+    #[serde(default)]
+    pub attributes: BTreeMap<String, serde_json::Value>,
+    /// Time (RFC 3339) when the data were last updated, empty if not known
+    #[serde(default)]
+    pub updated: String,
+    /// Encoded queries of the results the data were derived from, see [MetadataRecord::merge]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
 }
 
 mod query_format {
@@ -296,10 +343,134 @@ impl MetadataRecord {
         ).to_owned();
         self
     }
+    /// Set a user-defined attribute (tag, label...)
+    pub fn with_attribute(&mut self, name: &str, value: serde_json::Value) -> &mut Self {
+        self.attributes.insert(name.to_owned(), value);
+        self
+    }
+    /// Get a user-defined attribute
+    pub fn get_attribute(&self, name: &str) -> Option<&serde_json::Value> {
+        self.attributes.get(name)
+    }
+    /// Set a user-defined attribute
+    pub fn set_attribute(&mut self, name: &str, value: serde_json::Value) {
+        self.attributes.insert(name.to_owned(), value);
+    }
+    /// Remove a user-defined attribute, returns the removed value if present
+    pub fn remove_attribute(&mut self, name: &str) -> Option<serde_json::Value> {
+        self.attributes.remove(name)
+    }
+    /// All user-defined attributes
+    pub fn attributes(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.attributes
+    }
+    /// Set the updated timestamp to the current time
+    pub fn with_updated_now(&mut self) -> &mut Self {
+        self.updated = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        self
+    }
+    /// Time when the data were last updated, None if not known or not valid
+    pub fn updated(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        chrono::DateTime::parse_from_rfc3339(&self.updated).ok()
+    }
     pub fn clean_log(&mut self) -> &mut Self {
         self.log = vec![];
         self
     }
+    /// Log entries of the given kind
+    pub fn logs_by_level(&self, kind: LogEntryKind) -> Vec<&LogEntry> {
+        self.log.iter().filter(|entry| entry.kind == kind).collect()
+    }
+    /// Log entries of the given kind or more severe, e.g. warnings and errors for [LogEntryKind::Warning]
+    pub fn logs_at_least(&self, kind: LogEntryKind) -> Vec<&LogEntry> {
+        self.log.iter().filter(|entry| entry.kind >= kind).collect()
+    }
+    /// Error log entries
+    pub fn errors(&self) -> Vec<&LogEntry> {
+        self.logs_by_level(LogEntryKind::Error)
+    }
+    /// Error of a failed result, None if the result did not fail.
+    /// The message, the query and the key are taken from the metadata,
+    /// the position (and the query if the metadata have none) from the last error log entry.
+    pub fn as_error(&self) -> Option<Error> {
+        if !self.is_error {
+            return None;
+        }
+        let entry = self.errors().last().copied();
+        let message = if self.message.is_empty() {
+            entry.map_or_else(|| "Execution failed".to_owned(), |e| e.message.clone())
+        } else {
+            self.message.clone()
+        };
+        let mut error = Error::new(ErrorType::ExecutionError, message);
+        if let Some(entry) = entry {
+            error = error.with_position(&entry.position);
+        }
+        let query = Some(&self.query)
+            .filter(|query| !query.is_empty())
+            .or_else(|| entry.and_then(|e| e.query.as_ref()));
+        if let Some(query) = query {
+            error = error.with_query(query);
+        }
+        error.key = self.key.as_ref().map(|key| key.encode());
+        Some(error)
+    }
+    /// Fold the metadata of a sub-result (e.g. of a link dependency) into this metadata.
+    /// The log entries are merged chronologically (entries without a valid timestamp keep their place)
+    /// and the entries of the other metadata are marked with its query if they have none.
+    /// The query and the dependencies of the other metadata are added to the dependencies
+    /// and the worse status is kept; an error message is taken over if there is none.
+    pub fn merge(&mut self, other: &MetadataRecord) -> &mut Self {
+        let source = (!other.query.is_empty()).then(|| other.query.clone());
+        let mut own = std::mem::take(&mut self.log).into_iter().peekable();
+        let mut merged = other
+            .log
+            .iter()
+            .cloned()
+            .map(|mut entry| {
+                if entry.query.is_none() {
+                    entry.query = source.clone();
+                }
+                entry
+            })
+            .peekable();
+        loop {
+            let take_merged = match (own.peek(), merged.peek()) {
+                (Some(a), Some(b)) => matches!((a.time(), b.time()), (Some(ta), Some(tb)) if tb < ta),
+                (Some(_), None) => false,
+                (None, Some(_)) => true,
+                (None, None) => break,
+            };
+            let entry = if take_merged { merged.next() } else { own.next() };
+            self.log.extend(entry);
+        }
+        for dependency in source
+            .map(|query| query.encode())
+            .into_iter()
+            .chain(other.dependencies.iter().cloned())
+        {
+            if !self.dependencies.contains(&dependency) {
+                self.dependencies.push(dependency);
+            }
+        }
+        if other.status.severity() > self.status.severity() {
+            self.status = other.status;
+        }
+        if other.is_error {
+            self.is_error = true;
+            if self.message.is_empty() {
+                self.message = other.message.clone();
+            }
+        }
+        self
+    }
+    /// Log entries logged at or after the time; entries without a valid timestamp are skipped
+    pub fn logs_since<Tz: chrono::TimeZone>(&self, since: &chrono::DateTime<Tz>) -> Vec<&LogEntry> {
+        self.log
+            .iter()
+            .filter(|entry| entry.time().is_some_and(|time| time >= *since))
+            .collect()
+    }
     pub fn info(&mut self, message: &str) -> &mut Self {
         self.add_log_entry(LogEntry::info(message.to_owned()));
         self
@@ -549,6 +720,110 @@ impl Metadata {
         None
     }
 
+    /// Log entries of the given kind, see [MetadataRecord::logs_by_level]; empty for legacy metadata
+    pub fn logs_by_level(&self, kind: LogEntryKind) -> Vec<&LogEntry> {
+        match self {
+            Metadata::MetadataRecord(m) => m.logs_by_level(kind),
+            Metadata::LegacyMetadata(_) => Vec::new(),
+        }
+    }
+
+    /// Log entries of the given kind or more severe, see [MetadataRecord::logs_at_least]; empty for legacy metadata
+    pub fn logs_at_least(&self, kind: LogEntryKind) -> Vec<&LogEntry> {
+        match self {
+            Metadata::MetadataRecord(m) => m.logs_at_least(kind),
+            Metadata::LegacyMetadata(_) => Vec::new(),
+        }
+    }
+
+    /// Error log entries; empty for legacy metadata
+    pub fn errors(&self) -> Vec<&LogEntry> {
+        self.logs_by_level(LogEntryKind::Error)
+    }
+
+    /// Log entries logged at or after the time, see [MetadataRecord::logs_since]; empty for legacy metadata
+    pub fn logs_since<Tz: chrono::TimeZone>(&self, since: &chrono::DateTime<Tz>) -> Vec<&LogEntry> {
+        match self {
+            Metadata::MetadataRecord(m) => m.logs_since(since),
+            Metadata::LegacyMetadata(_) => Vec::new(),
+        }
+    }
+
+    /// Get a user-defined attribute.
+    /// In legacy metadata, attributes are looked up in the "attributes" object if present.
+    pub fn get_attribute(&self, name: &str) -> Option<serde_json::Value> {
+        match self {
+            Metadata::LegacyMetadata(serde_json::Value::Object(o)) => {
+                if let Some(Value::Object(attributes)) = o.get("attributes") {
+                    attributes.get(name).cloned()
+                } else {
+                    None
+                }
+            }
+            Metadata::MetadataRecord(m) => m.get_attribute(name).cloned(),
+            _ => None,
+        }
+    }
+
+    pub fn set_attribute(&mut self, name: &str, value: serde_json::Value) -> Result<&mut Self, Error> {
+        match self {
+            Metadata::LegacyMetadata(_) => {
+                let error =
+                    Error::general_error("Cannot set attribute on legacy metadata".to_string());
+                if let Ok(query) = self.query() {
+                    Err(error.with_query(&query))
+                } else {
+                    Err(error)
+                }
+            }
+            Metadata::MetadataRecord(m) => {
+                m.set_attribute(name, value);
+                Ok(self)
+            }
+        }
+    }
+
+    /// Get the metadata as a [MetadataRecord].
+    /// Legacy metadata is converted if it can be deserialized as a [MetadataRecord], otherwise None is returned.
+    pub fn metadata_record(&self) -> Option<MetadataRecord> {
+        match self {
+            Metadata::MetadataRecord(m) => Some(m.clone()),
+            Metadata::LegacyMetadata(v) => serde_json::from_value(v.clone()).ok(),
+        }
+    }
+
+    /// Time when the data were last updated, None if not known.
+    /// In legacy metadata, the "updated" field is used if present.
+    pub fn updated(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        match self {
+            Metadata::LegacyMetadata(serde_json::Value::Object(o)) => o
+                .get("updated")
+                .and_then(|t| t.as_str())
+                .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok()),
+            Metadata::MetadataRecord(m) => m.updated(),
+            _ => None,
+        }
+    }
+
+    /// Set the updated timestamp to the current time if it is not known.
+    /// Used by the stores when the data are written, so that the written data are not considered stale
+    /// (see [crate::interpreter::is_stale]), while an explicitly set timestamp is kept.
+    pub fn with_updated_now_if_unknown(&mut self) -> &mut Self {
+        if self.updated().is_none() {
+            match self {
+                Metadata::MetadataRecord(m) => {
+                    m.with_updated_now();
+                }
+                Metadata::LegacyMetadata(serde_json::Value::Object(o)) => {
+                    let now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+                    o.insert("updated".to_owned(), serde_json::Value::String(now));
+                }
+                _ => {}
+            }
+        }
+        self
+    }
+
     pub fn set_extension(&mut self, extension: &str) -> Result<&mut Self, Error> {
         match self {
             Metadata::LegacyMetadata(_) => {
@@ -573,3 +848,116 @@ impl From<MetadataRecord> for Metadata {
         Metadata::MetadataRecord(m)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attributes_roundtrip() -> Result<(), Error> {
+        let mut m = MetadataRecord::new();
+        m.with_attribute("title", Value::String("Report".to_owned()));
+        m.set_attribute("rank", serde_json::json!(1));
+        let json = serde_json::to_string(&m).unwrap();
+        let m2: MetadataRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(m2.attributes().len(), 2);
+        assert_eq!(m2.get_attribute("rank"), Some(&serde_json::json!(1)));
+        let metadata = Metadata::from_json(&json).unwrap();
+        assert_eq!(metadata.get_attribute("title"), Some(serde_json::json!("Report")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_log_filtering() -> Result<(), Error> {
+        let mut m = MetadataRecord::new();
+        let mut old = LogEntry::info("old".to_owned());
+        old.with_custom_timestamp("2024-01-01T00:00:00.000Z".to_owned());
+        m.add_log_entry(old);
+        m.debug("details").warning("careful");
+        let mut failure = LogEntry::error("failed".to_owned());
+        failure.with_timestamp();
+        m.add_log_entry(failure);
+        let messages = |entries: Vec<&LogEntry>| {
+            entries.iter().map(|entry| entry.message.clone()).collect::<Vec<_>>()
+        };
+        let metadata = Metadata::from(m.clone());
+        assert_eq!(messages(metadata.logs_by_level(LogEntryKind::Debug)), vec!["details"]);
+        assert_eq!(
+            messages(metadata.logs_at_least(LogEntryKind::Warning)),
+            vec!["careful", "failed"]
+        );
+        assert_eq!(messages(metadata.errors()), vec!["failed"]);
+        let since = chrono::DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z").unwrap();
+        assert_eq!(messages(metadata.logs_since(&since)), vec!["failed"]);
+        assert!(m.logs_since(&chrono::Utc::now()).is_empty());
+
+        let legacy = Metadata::LegacyMetadata(serde_json::json!({"log": [{"kind": "error"}]}));
+        assert!(legacy.errors().is_empty());
+        assert!(legacy.logs_since(&since).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge() -> Result<(), Error> {
+        let entry = |kind, message: &str, timestamp: &str| {
+            let mut entry = LogEntry::new(kind, message.to_owned());
+            entry.with_custom_timestamp(timestamp.to_owned());
+            entry
+        };
+        let mut m = MetadataRecord::new();
+        m.with_query(parse::parse_query("-R/data.csv/-/filter")?)
+            .with_status(Status::Ready);
+        m.add_log_entry(entry(LogEntryKind::Info, "start", "2025-01-01T00:00:00Z"));
+        m.add_log_entry(entry(LogEntryKind::Info, "done", "2025-01-01T00:00:03Z"));
+        let mut upstream = MetadataRecord::new();
+        upstream.with_query(parse::parse_query("-R/source.csv/-/fetch")?);
+        upstream.dependencies.push("-R/source.csv".to_owned());
+        upstream.add_log_entry(entry(LogEntryKind::Error, "unreachable", "2025-01-01T00:00:02Z"));
+        upstream.with_status(Status::Error);
+        upstream.message = "fetch failed".to_owned();
+
+        m.merge(&upstream).merge(&upstream);
+        let messages = m.log.iter().map(|e| e.message.as_str()).collect::<Vec<_>>();
+        assert_eq!(messages, vec!["start", "unreachable", "unreachable", "done"]);
+        assert_eq!(m.log[1].query, Some(upstream.query.clone()));
+        assert_eq!(m.dependencies, vec!["-R/source.csv/-/fetch", "-R/source.csv"]);
+        assert_eq!(m.status, Status::Error);
+        assert!(m.is_error);
+        assert_eq!(m.message, "fetch failed");
+        let error = m.as_error().unwrap();
+        assert_eq!(error.error_type, ErrorType::ExecutionError);
+        assert_eq!(error.message, "fetch failed");
+        assert_eq!(error.query, Some("-R/data.csv/-/filter".to_owned()));
+        assert!(upstream.as_error().is_some());
+        assert!(MetadataRecord::new().as_error().is_none());
+
+        // A better status does not replace a worse one
+        let mut ready = MetadataRecord::new();
+        ready.with_status(Status::Ready);
+        m.merge(&ready);
+        assert_eq!(m.status, Status::Error);
+        Ok(())
+    }
+
+    #[test]
+    fn test_old_metadata_without_attributes() -> Result<(), Error> {
+        let json = r#"{
+            "log": [],
+            "query": "",
+            "key": null,
+            "status": "Ready",
+            "type_identifier": "text",
+            "message": "",
+            "is_error": false,
+            "media_type": "text/plain",
+            "filename": "hello.txt"
+        }"#;
+        let m: MetadataRecord = serde_json::from_str(json).unwrap();
+        assert!(m.attributes().is_empty());
+        assert!(matches!(
+            Metadata::from_json(json).unwrap(),
+            Metadata::MetadataRecord(_)
+        ));
+        Ok(())
+    }
+}
@@ -10,7 +10,7 @@ use nom::combinator::{eof, not, opt, peek};
 use nom::sequence::{preceded, terminated};
 use nom_locate::LocatedSpan;
 
-use nom::bytes::complete::{tag, take_while, take_while1};
+use nom::bytes::complete::{tag, take, take_while, take_while1, take_while_m_n};
 use nom::character::{is_alphabetic, is_alphanumeric};
 use nom::multi::{many0, many1, separated_list0, separated_list1};
 use nom::*;
@@ -23,6 +23,14 @@ use crate::query::{
 
 type Span<'a> = LocatedSpan<&'a str>;
 
+thread_local! {
+    /// Queries of the link parameters parsed during the current (outermost) [parse_query] call.
+    /// The parser backtracks, so without remembering them a link would be parsed again
+    /// for each tried alternative, i.e. exponentially many times in the nesting depth.
+    static LINK_QUERIES: std::cell::RefCell<Option<std::collections::HashMap<String, Query>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
 #[allow(dead_code)]
 impl<'a> From<Span<'a>> for Position {
     fn from(span: Span<'a>) -> Position {
@@ -56,14 +64,21 @@ fn slash_filename(text: Span) -> IResult<Span, String> {
     Ok((text, fname))
 }
 
+/// Resource name, possibly containing `~` escapes (see [Key::encode_name]).
+/// The name is stored in the canonical encoded form.
 fn resource_name(text: Span) -> IResult<Span, ResourceName> {
     let position: Position = text.into();
-    let (text, a) = take_while1(|c| is_alphanumeric(c as u8) || c == '_' || c == '.')(text)?;
-    let (text, b) =
-        take_while(|c| is_alphanumeric(c as u8) || c == '_' || c == '.' || c == '-')(text)?;
+    let (rest, a) =
+        take_while1(|c| is_alphanumeric(c as u8) || c == '_' || c == '.' || c == '~')(text)?;
+    let (rest, b) = take_while(|c| {
+        is_alphanumeric(c as u8) || c == '_' || c == '.' || c == '-' || c == '~'
+    })(rest)?;
+    let name = Key::decode_name(&format!("{}{}", a, b)).map_err(|_| {
+        nom::Err::Error(nom::error::Error::new(text, nom::error::ErrorKind::Escaped))
+    })?;
     Ok((
-        text,
-        ResourceName::new(format!("{}{}", a, b)).with_position(position),
+        rest,
+        ResourceName::new(Key::encode_name(&name)).with_position(position),
     ))
 }
 fn parameter_text(text: Span) -> IResult<Span, String> {
@@ -113,8 +128,24 @@ fn space_entity(text: Span) -> IResult<Span, String> {
     let (text, _) = tag("~.")(text)?;
     Ok((text, " ".to_owned()))
 }
+/// Characters encoded as `~xHH` entities, i.e. a sequence of UTF-8 bytes in hexadecimal
+fn hex_entities(text: Span) -> IResult<Span, String> {
+    let (rest, hex) = many1(preceded(
+        tag("~x"),
+        take_while_m_n(2, 2, |c: char| c.is_ascii_hexdigit()),
+    ))(text)?;
+    let bytes = hex
+        .iter()
+        .map(|h| u8::from_str_radix(h.fragment(), 16).unwrap())
+        .collect::<Vec<_>>();
+    let decoded = String::from_utf8(bytes).map_err(|_| {
+        nom::Err::Error(nom::error::Error::new(text, nom::error::ErrorKind::Escaped))
+    })?;
+    Ok((rest, decoded))
+}
 fn entities(text: Span) -> IResult<Span, String> {
     alt((
+        hex_entities,
         tilde_entity,
         minus_entity,
         negative_number_entity,
@@ -127,7 +158,7 @@ fn entities(text: Span) -> IResult<Span, String> {
         protocol_entity,
     ))(text)
 }
-fn parameter(text: Span) -> IResult<Span, ActionParameter> {
+fn text_parameter(text: Span) -> IResult<Span, ActionParameter> {
     let position: Position = text.into();
     let (text, par) = many0(alt((parameter_text, entities)))(text)?;
     Ok((
@@ -135,6 +166,103 @@ fn parameter(text: Span) -> IResult<Span, ActionParameter> {
         ActionParameter::new_string(par.join("")).with_position(position),
     ))
 }
+
+/// Maximal nesting depth of link parameters (`~X~query~E`); deeper links are a parse error,
+/// since each level of nesting is parsed recursively
+pub const MAX_LINK_DEPTH: usize = 32;
+
+/// Check that the link parameters in the query are not nested deeper than [MAX_LINK_DEPTH]
+fn check_link_depth(query: &str) -> Result<(), Error> {
+    let bytes = query.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'~' {
+            i += 1;
+        } else if bytes[i..].starts_with(b"~X~") {
+            depth += 1;
+            if depth > MAX_LINK_DEPTH {
+                let position = Position::new(i, 1, query[..i].chars().count() + 1);
+                return Err(Error::query_parse_error(
+                    query,
+                    &format!("Links nested deeper than {} levels", MAX_LINK_DEPTH),
+                    &position,
+                ));
+            }
+            i += 3;
+        } else if bytes[i..].starts_with(b"~E") {
+            depth = depth.saturating_sub(1);
+            i += 2;
+        } else {
+            i += 2;
+        }
+    }
+    Ok(())
+}
+
+/// Length (in characters) of the query of a link parameter up to the terminating `~E`.
+/// Entities (including `~~`) are skipped and nested links are balanced.
+fn link_query_length(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'~' {
+            i += 1;
+        } else if bytes[i..].starts_with(b"~X~") {
+            depth += 1;
+            i += 3;
+        } else if bytes[i..].starts_with(b"~E") {
+            if depth == 0 {
+                return Some(text[..i].chars().count());
+            }
+            depth -= 1;
+            i += 2;
+        } else {
+            i += 2;
+        }
+    }
+    None
+}
+
+/// Link parameter `~X~query~E`, see [ActionParameter::encode]
+fn link_parameter(text: Span) -> IResult<Span, ActionParameter> {
+    let position: Position = text.into();
+    let (text, _) = tag("~X~")(text)?;
+    let length = link_query_length(text.fragment()).ok_or_else(|| {
+        nom::Err::Failure(nom::error::Error::new(text, nom::error::ErrorKind::Escaped))
+    })?;
+    let (rest, query) = take(length)(text)?;
+    let (rest, _) = tag("~E")(rest)?;
+    let query = parse_link_query(query.fragment()).map_err(|_| {
+        nom::Err::Failure(nom::error::Error::new(text, nom::error::ErrorKind::Verify))
+    })?;
+    Ok((rest, ActionParameter::new_link(query).with_position(position)))
+}
+
+/// Parse the query of a link parameter, reusing the result if the link was parsed before (see [LINK_QUERIES])
+fn parse_link_query(text: &str) -> Result<Query, Error> {
+    let parsed = LINK_QUERIES.with(|links| {
+        links
+            .borrow()
+            .as_ref()
+            .and_then(|links| links.get(text).cloned())
+    });
+    if let Some(query) = parsed {
+        return Ok(query);
+    }
+    let query = parse_query(text)?;
+    LINK_QUERIES.with(|links| {
+        if let Some(links) = links.borrow_mut().as_mut() {
+            links.insert(text.to_owned(), query.clone());
+        }
+    });
+    Ok(query)
+}
+
+fn parameter(text: Span) -> IResult<Span, ActionParameter> {
+    alt((link_parameter, text_parameter))(text)
+}
 fn minus_parameter(text: Span) -> IResult<Span, ActionParameter> {
     let (text, _) = tag("-")(text)?;
     parameter(text)
@@ -501,7 +629,26 @@ fn parse_action_path(text: Span) -> IResult<Span, Vec<ActionRequest>> {
 }
 */
 
+/// Parse a query. The [QueryLimits] are not checked, see [parse_query_with_limits] for queries
+/// coming from untrusted sources. The links are parsed only once (see [LINK_QUERIES]).
 pub fn parse_query(query: &str) -> Result<Query, Error> {
+    let outermost = LINK_QUERIES.with(|links| {
+        let mut links = links.borrow_mut();
+        let outermost = links.is_none();
+        if outermost {
+            *links = Some(std::collections::HashMap::new());
+        }
+        outermost
+    });
+    let result = parse_query_text(query);
+    if outermost {
+        LINK_QUERIES.with(|links| links.borrow_mut().take());
+    }
+    result
+}
+
+fn parse_query_text(query: &str) -> Result<Query, Error> {
+    check_link_depth(query)?;
     let (remainder, path) = query_parser(Span::new(query)).map_err(|e| {
         let message = format!("{}", e);
         Error::query_parse_error(query, &message, &Position::unknown())
@@ -518,6 +665,136 @@ pub fn parse_query(query: &str) -> Result<Query, Error> {
     }
 }
 
+/// Limits protecting the parser and interpreter from abusive queries.
+/// Defaults are generous enough for any reasonable hand-written or generated query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryLimits {
+    /// Maximum length of the query string in bytes.
+    pub max_query_length: usize,
+    /// Maximum number of query segments.
+    pub max_segments: usize,
+    /// Maximum total number of actions in all transform segments.
+    pub max_actions: usize,
+    /// Maximum total number of links to resolve (including links nested in links).
+    pub max_links: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        QueryLimits {
+            max_query_length: 16384,
+            max_segments: 256,
+            max_actions: 1024,
+            max_links: 256,
+        }
+    }
+}
+
+impl QueryLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Limits that are never exceeded
+    pub fn unlimited() -> Self {
+        QueryLimits {
+            max_query_length: usize::MAX,
+            max_segments: usize::MAX,
+            max_actions: usize::MAX,
+            max_links: usize::MAX,
+        }
+    }
+    pub fn with_max_query_length(mut self, max_query_length: usize) -> Self {
+        self.max_query_length = max_query_length;
+        self
+    }
+    pub fn with_max_segments(mut self, max_segments: usize) -> Self {
+        self.max_segments = max_segments;
+        self
+    }
+    pub fn with_max_actions(mut self, max_actions: usize) -> Self {
+        self.max_actions = max_actions;
+        self
+    }
+    pub fn with_max_links(mut self, max_links: usize) -> Self {
+        self.max_links = max_links;
+        self
+    }
+
+    /// Check the length of a query string before parsing.
+    pub fn check_length(&self, query: &str) -> Result<(), Error> {
+        if query.len() > self.max_query_length {
+            Err(Error::query_too_long(
+                query,
+                query.len(),
+                self.max_query_length,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check the complexity of a parsed query: the numbers of segments, actions and links.
+    /// The length is a property of the query string, see [QueryLimits::check_length].
+    pub fn check(&self, query: &Query) -> Result<(), Error> {
+        let counts = [
+            ("segments", query.segments.len(), self.max_segments),
+            ("actions", Self::count_actions(query), self.max_actions),
+            ("links", Self::count_links(query), self.max_links),
+        ];
+        for (what, count, max_count) in counts {
+            if count > max_count {
+                // The query is encoded only to be reported
+                return Err(Error::query_too_complex(
+                    &query.encode(),
+                    what,
+                    count,
+                    max_count,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn count_actions(query: &Query) -> usize {
+        query
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                QuerySegment::Transform(tqs) => tqs.query.len(),
+                QuerySegment::Resource(_) => 0,
+            })
+            .sum()
+    }
+
+    fn count_links(query: &Query) -> usize {
+        query
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                QuerySegment::Transform(tqs) => tqs
+                    .query
+                    .iter()
+                    .flat_map(|action| action.parameters.iter())
+                    .map(|parameter| match parameter {
+                        ActionParameter::Link(link, _) => 1 + Self::count_links(link),
+                        ActionParameter::String(_, _) => 0,
+                    })
+                    .sum(),
+                QuerySegment::Resource(_) => 0,
+            })
+            .sum()
+    }
+}
+
+/// Parse query and verify that it is within the specified limits.
+/// The length of the query string is checked before parsing.
+pub fn parse_query_with_limits(query: &str, limits: &QueryLimits) -> Result<Query, Error> {
+    limits.check_length(query)?;
+    let parsed = parse_query(query)?;
+    limits.check(&parsed)?;
+    Ok(parsed)
+}
+
 pub fn parse_key<S: AsRef<str>>(key: S) -> Result<Key, Error> {
     let (remainder, path) = resource_path(Span::new(key.as_ref())).map_err(|e| {
         let em = format!("{}", e);
@@ -1000,4 +1277,63 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn query_within_limits() -> Result<(), Error> {
+        let limits = QueryLimits::new().with_max_segments(3).with_max_actions(4);
+        let q = parse_query_with_limits("-R/a/b/-/c/d/-/e-1", &limits)?;
+        assert_eq!(q.segments.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn query_exceeding_segment_limit() -> Result<(), Error> {
+        let limits = QueryLimits::new().with_max_segments(2);
+        let err = parse_query_with_limits("-R/a/b/-/c/d/-/e-1", &limits).unwrap_err();
+        assert_eq!(err.error_type, ErrorType::QueryTooComplex);
+        assert!(err.message.contains("segments"));
+        Ok(())
+    }
+
+    #[test]
+    fn query_exceeding_length_and_link_limits() -> Result<(), Error> {
+        let limits = QueryLimits::new().with_max_query_length(5);
+        let err = parse_query_with_limits("abc/def/ghi", &limits).unwrap_err();
+        assert_eq!(err.error_type, ErrorType::QueryTooLong);
+
+        // The reported query is truncated; parse_query does not check the limits
+        let long = format!("a-{}", "x".repeat(QueryLimits::default().max_query_length));
+        let err = parse_query_with_limits(&long, &QueryLimits::default()).unwrap_err();
+        assert_eq!(err.error_type, ErrorType::QueryTooLong);
+        let reported = err.query.unwrap();
+        assert!(reported.len() < 300 && reported.ends_with("..."));
+        assert!(parse_query(&long).is_ok());
+        // The complexity of a parsed query is checked regardless of its length
+        let q = parse_query(&long)?;
+        assert!(QueryLimits::new().with_max_query_length(5).check(&q).is_ok());
+
+        let mut q = parse_query("abc-x")?;
+        if let QuerySegment::Transform(tqs) = &mut q.segments[0] {
+            tqs.query[0].parameters[0] = ActionParameter::new_link(parse_query("def")?);
+        }
+        assert!(QueryLimits::new().check(&q).is_ok());
+        let err = QueryLimits::new().with_max_links(0).check(&q).unwrap_err();
+        assert_eq!(err.error_type, ErrorType::QueryTooComplex);
+        Ok(())
+    }
+
+    #[test]
+    fn link_depth() -> Result<(), Error> {
+        let nested = |depth: usize| {
+            format!("{}hello{}", "f-~X~".repeat(depth), "~E".repeat(depth))
+        };
+        assert!(parse_query(&nested(MAX_LINK_DEPTH)).is_ok());
+        let err = parse_query(&nested(MAX_LINK_DEPTH + 1)).unwrap_err();
+        assert_eq!(err.error_type, ErrorType::ParseError);
+        assert!(err.message.contains("nested"));
+        // Too deep to be parsed recursively
+        let err = parse_query(&nested(100_000)).unwrap_err();
+        assert_eq!(err.error_type, ErrorType::ParseError);
+        Ok(())
+    }
 }
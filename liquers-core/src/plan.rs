@@ -16,7 +16,7 @@ use crate::command_metadata::{
 use crate::error::{Error, ErrorType};
 use crate::query::{
     ActionParameter, ActionRequest, Key, Position, Query, QuerySegment, ResourceName,
-    ResourceQuerySegment,
+    ResourceQuerySegment, TransformQuerySegment,
 };
 use crate::value::ValueInterface;
 
@@ -309,6 +309,36 @@ impl ParameterValue {
                     )),
                 }
             }
+            ArgumentType::Duration => {
+                if s.is_empty() {
+                    return Self::from_arginfo(arginfo).to_result(
+                        || format!("Duration argument {} missing", &arginfo.name),
+                        pos,
+                    );
+                }
+                let d = command_metadata::parse_duration(s)
+                    .ok_or_else(|| Error::conversion_error_at_position(s, "duration", pos))?;
+                Ok(ParameterValue::ParameterValue(
+                    arginfo.name.clone(),
+                    d.as_secs_f64().into(),
+                    pos.to_owned(),
+                ))
+            }
+            ArgumentType::DateTime => {
+                if s.is_empty() {
+                    return Self::from_arginfo(arginfo).to_result(
+                        || format!("Datetime argument {} missing", &arginfo.name),
+                        pos,
+                    );
+                }
+                let t = command_metadata::parse_datetime(s)
+                    .ok_or_else(|| Error::conversion_error_at_position(s, "datetime", pos))?;
+                Ok(ParameterValue::ParameterValue(
+                    arginfo.name.clone(),
+                    Value::String(t.to_rfc3339()),
+                    pos.to_owned(),
+                ))
+            }
             ArgumentType::Enum(ref e) => match e.expand_alias(s) {
                 CommandParameterValue::Value(x) => Ok(ParameterValue::ParameterValue(
                     arginfo.name.clone(),
@@ -528,6 +558,14 @@ impl ParameterValue {
     }
 }
 
+/// Queries linked from a parameter value, including the links inside multiple parameters
+fn parameter_links(value: &ParameterValue) -> Vec<Query> {
+    match value.multiple() {
+        Some(values) => values.iter().flat_map(parameter_links).collect(),
+        None => value.link().into_iter().collect(),
+    }
+}
+
 /// ResolvedParameterValues contains the resolved values of all command parameters.
 /// It is used in a Plan to define (resolved) parameters of an action.
 /// Injected parameter values are (of course) not included in ResolvedParameterValues,
@@ -655,7 +693,6 @@ impl<'a> Iterator for ActionParameterIterator<'a> {
 }
 
 // TODO: support cache
-// TODO: support volatile flags
 // TODO: support inline flag
 impl<'c> PlanBuilder<'c> {
     pub fn new(query: Query, command_registry: &'c CommandMetadataRegistry) -> Self {
@@ -679,25 +716,7 @@ impl<'c> PlanBuilder<'c> {
     }
 
     fn get_namespaces(&self, query: &Query) -> Result<Vec<String>, Error> {
-        let mut namespaces = Vec::new();
-        if let Some(ns) = query.last_ns() {
-            for x in ns.iter() {
-                match x {
-                    ActionParameter::String(s, _) => namespaces.push(s.to_string()),
-                    _ => {
-                        return Err(Error::not_supported(
-                            "Only string parameters are supported in ns".into(),
-                        ));
-                    }
-                }
-            }
-        }
-        // TODO: get default namespaces from command registry
-        namespaces.push("".to_string());
-        namespaces.push("root".to_string());
-
-        // TODO: check if the namespaces are registered in command registry
-        Ok(namespaces)
+        query_namespaces(query)
     }
 
     fn get_command_metadata(
@@ -714,6 +733,8 @@ impl<'c> PlanBuilder<'c> {
             &action_request.name,
         ) {
             Ok(command_metadata.clone())
+        } else if let Some(feature) = self.command_registry.required_feature(&action_request.name) {
+            Err(Error::action_requires_feature(action_request, feature))
         } else {
             Err(Error::action_not_registered(action_request, &namespaces))
         }
@@ -731,6 +752,7 @@ impl<'c> PlanBuilder<'c> {
         action_request: &ActionRequest,
     ) -> Result<(), Error> {
         let command_metadata = self.get_command_metadata(query, action_request)?;
+        self.plan.volatile |= command_metadata.volatile;
 
         match &command_metadata.definition {
             command_metadata::CommandDefinition::Registered => {
@@ -751,6 +773,10 @@ impl<'c> PlanBuilder<'c> {
                 head_parameters,
             } => {
                 let original_key = command_metadata.key();
+                self.plan.volatile |= self
+                    .command_registry
+                    .get(command.clone())
+                    .is_some_and(|target| target.volatile);
                 self.plan.steps.push(Step::Info(format!(
                     "Alias command {} to {}",
                     original_key, &command
@@ -769,10 +795,23 @@ impl<'c> PlanBuilder<'c> {
                 });
             }
         }
+        if let Some(Step::Action { parameters, .. }) = self.plan.steps.last() {
+            self.plan.volatile |= self.links_volatile(parameters);
+        }
 
         Ok(())
     }
 
+    /// True if any query linked from the parameters executes a volatile command.
+    /// Links which can't be planned are ignored here, they fail when they are evaluated.
+    fn links_volatile(&self, parameters: &ResolvedParameterValues) -> bool {
+        parameters.0.iter().flat_map(parameter_links).any(|query| {
+            let mut builder = PlanBuilder::new(query, self.command_registry);
+            builder.allow_placeholders = self.allow_placeholders;
+            builder.build().is_ok_and(|plan| plan.is_volatile())
+        })
+    }
+
     fn process_query(&mut self, query: &Query) -> Result<(), Error> {
         //println!("process query {}", query);
         if query.is_empty() || query.is_ns() {
@@ -851,10 +890,166 @@ impl<'c> PlanBuilder<'c> {
     }
 }
 
+/// Namespaces in which the commands of the query are looked up:
+/// the namespaces of the last `ns` action followed by the default namespaces
+pub fn query_namespaces(query: &Query) -> Result<Vec<String>, Error> {
+    namespaces_from_ns(query.last_ns())
+}
+
+/// Namespaces in which the commands are looked up given the parameters of the effective `ns` action (if any),
+/// see [query_namespaces]
+pub fn namespaces_from_ns(ns: Option<Vec<ActionParameter>>) -> Result<Vec<String>, Error> {
+    let mut namespaces = Vec::new();
+    if let Some(ns) = ns {
+        for x in ns.iter() {
+            match x {
+                ActionParameter::String(s, _) => namespaces.push(s.to_string()),
+                _ => {
+                    return Err(Error::not_supported(
+                        "Only string parameters are supported in ns".into(),
+                    ));
+                }
+            }
+        }
+    }
+    // TODO: get default namespaces from command registry
+    namespaces.push("".to_string());
+    namespaces.push("root".to_string());
+
+    // TODO: check if the namespaces are registered in command registry
+    Ok(namespaces)
+}
+
+// Canonical form of the queries (see [Query::canonical]) depends on the command metadata,
+// hence it is implemented here and not in the query module.
+
+impl ActionParameter {
+    /// Parameter without a position; links are canonicalized, see [Query::canonical]
+    pub fn canonical(&self, cmr: &CommandMetadataRegistry) -> Result<Self, Error> {
+        match self {
+            Self::String(s, _) => Ok(Self::new_string(s.clone())),
+            Self::Link(query, _) => Ok(Self::new_link(query.canonical(cmr)?)),
+        }
+    }
+}
+
+impl ActionRequest {
+    /// Action without positions and without trailing parameters equal to the defaults
+    /// of the command arguments, see [Query::canonical].
+    /// Parameters of alias commands and parameters following a multiple argument are kept.
+    pub fn canonical(
+        &self,
+        realm: &str,
+        namespaces: &Vec<String>,
+        cmr: &CommandMetadataRegistry,
+    ) -> Result<Self, Error> {
+        let command = cmr
+            .find_command_in_namespaces(realm, namespaces, &self.name)
+            .ok_or_else(|| Error::action_not_registered(self, namespaces))?;
+        let mut parameters = self
+            .parameters
+            .iter()
+            .map(|parameter| parameter.canonical(cmr))
+            .collect::<Result<Vec<_>, Error>>()?;
+        if let command_metadata::CommandDefinition::Registered = command.definition {
+            let arguments = command
+                .arguments
+                .iter()
+                .filter(|argument| !argument.injected)
+                .collect_vec();
+            while let Some(ActionParameter::String(s, _)) = parameters.last() {
+                let n = parameters.len();
+                if n > arguments.len()
+                    || arguments[..n].iter().any(|argument| argument.multiple)
+                    || !is_default_parameter(arguments[n - 1], s)
+                {
+                    break;
+                }
+                parameters.pop();
+            }
+        }
+        Ok(ActionRequest::new(self.name.clone()).with_parameters(parameters))
+    }
+}
+
+/// True if the parameter text is parsed to the default value of the argument
+fn is_default_parameter(argument: &ArgumentInfo, s: &str) -> bool {
+    let default = match &argument.default {
+        CommandParameterValue::Value(default) => default,
+        _ => return false,
+    };
+    match ParameterValue::from_string(argument, s, &Position::unknown()) {
+        Ok(ParameterValue::ParameterValue(_, value, _)) => &value == default,
+        Ok(ParameterValue::DefaultValue(_, _)) => true,
+        _ => false,
+    }
+}
+
+impl TransformQuerySegment {
+    /// Canonical form of the segment, see [Query::canonical].
+    /// Only the last `ns` action of a segment is effective, so the `ns` actions are replaced
+    /// by a single one (without duplicate namespaces) at the start of the segment.
+    /// Commands are looked up in the namespaces of the segment and the realm given by the segment name.
+    pub fn canonical(&self, cmr: &CommandMetadataRegistry) -> Result<Self, Error> {
+        let ns = self.last_ns();
+        let namespaces = namespaces_from_ns(ns.clone())?;
+        let mut query = Vec::new();
+        if let Some(ns) = ns {
+            let mut parameters: Vec<ActionParameter> = Vec::new();
+            for parameter in ns {
+                let parameter = parameter.canonical(cmr)?;
+                if !parameters.contains(&parameter) {
+                    parameters.push(parameter);
+                }
+            }
+            if !parameters.is_empty() {
+                query.push(ActionRequest::new("ns".to_owned()).with_parameters(parameters));
+            }
+        }
+        let realm = self.name();
+        for action in self.query.iter().filter(|action| !action.is_ns()) {
+            query.push(action.canonical(&realm, &namespaces, cmr)?);
+        }
+        Ok(TransformQuerySegment {
+            header: self.header.clone(),
+            query,
+            filename: self.filename.clone(),
+        })
+    }
+}
+
+impl Query {
+    /// Canonical form of the query, so that equivalent queries are equal, hash identically
+    /// and encode to the same string (e.g. to be used as a cache key).
+    /// Positions are stripped, trailing action parameters equal to the defaults in the command metadata are dropped
+    /// and the namespace (`ns`) actions are normalized (see [TransformQuerySegment::canonical]).
+    /// Resource segments and filenames are kept verbatim.
+    /// Fails if a command is not registered.
+    pub fn canonical(&self, cmr: &CommandMetadataRegistry) -> Result<Query, Error> {
+        let segments = self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                QuerySegment::Resource(_) => Ok(segment.clone()),
+                QuerySegment::Transform(tqs) => tqs.canonical(cmr).map(QuerySegment::Transform),
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Query {
+            segments,
+            absolute: self.absolute,
+            source: self.source.clone(),
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Plan {
     pub query: Query,
     pub steps: Vec<Step>,
+    /// The plan executes a volatile command (see [CommandMetadata::volatile]),
+    /// so its result must be recomputed every time and never cached
+    #[serde(default)]
+    pub volatile: bool,
 }
 
 impl Plan {
@@ -862,6 +1057,7 @@ impl Plan {
         Plan {
             query: Query::new(),
             steps: Vec::new(),
+            volatile: false,
         }
     }
     pub fn info(&mut self, message: String) {
@@ -882,6 +1078,40 @@ impl Plan {
     pub fn len(&self) -> usize {
         self.steps.len()
     }
+    /// Keys of the resources the plan reads, i.e. the resource dependencies of the evaluated query.
+    /// Resources read by the queries linked from the action parameters (see [Query::resource_keys]) are included.
+    pub fn resource_dependencies(&self) -> Vec<Key> {
+        let mut keys: Vec<Key> = Vec::new();
+        let mut add = |key: Key| {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        };
+        for step in self.steps.iter() {
+            match step {
+                Step::GetResource(key)
+                | Step::GetResourceMetadata(key)
+                | Step::GetNamedResource(key)
+                | Step::GetNamedResourceMetadata(key) => add(key.clone()),
+                Step::Plan(plan) => plan.resource_dependencies().into_iter().for_each(&mut add),
+                Step::Action { parameters, .. } => {
+                    for query in parameters.0.iter().flat_map(parameter_links) {
+                        query.resource_keys().into_iter().for_each(&mut add);
+                    }
+                }
+                _ => (),
+            }
+        }
+        keys
+    }
+    /// True if the plan or any of its sub-plans executes a volatile command, see [Plan::volatile]
+    pub fn is_volatile(&self) -> bool {
+        self.volatile
+            || self.steps.iter().any(|step| match step {
+                Step::Plan(plan) => plan.is_volatile(),
+                _ => false,
+            })
+    }
     /// Find index of the last action in the plan
     fn last_action_index(&self) -> Option<usize> {
         for (i, s) in self.steps.iter().enumerate().rev() {
@@ -1024,6 +1254,24 @@ mod tests {
         assert_eq!(pv.value(), Some(Value::String("".to_string())));
     }
     #[test]
+    fn test_duration_and_datetime_parameter_value() {
+        let arginfo = ArgumentInfo::duration_argument("window");
+        let pv = ParameterValue::from_string(&arginfo, "1h30m", &Position::unknown()).unwrap();
+        assert_eq!(pv.value(), Some(serde_json::json!(5400.0)));
+        let pos = Position::new(5, 1, 6);
+        let err = ParameterValue::from_string(&arginfo, "1x", &pos).unwrap_err();
+        assert_eq!(err.error_type, ErrorType::ConversionError);
+        assert_eq!(err.position.offset, 5);
+        assert!(ParameterValue::from_string(&arginfo, "", &pos).is_err());
+
+        let arginfo = ArgumentInfo::datetime_argument("since");
+        let pv = ParameterValue::from_string(&arginfo, "2024-01-31T13:00:00+01:00", &pos).unwrap();
+        assert_eq!(pv.value(), Some(serde_json::json!("2024-01-31T12:00:00+00:00")));
+        let err = ParameterValue::from_string(&arginfo, "yesterday", &pos).unwrap_err();
+        assert_eq!(err.error_type, ErrorType::ConversionError);
+        assert_eq!(err.position.offset, 5);
+    }
+    #[test]
     fn test_pop_parameter_value() -> Result<(), Error> {
         let arginfo = ArgumentInfo::string_argument("test").with_default("default");
         let action = parse_query("hello-testarg-123")?.action().unwrap();
@@ -1050,6 +1298,63 @@ mod tests {
 
         Ok(())
     }
+    #[test]
+    fn test_volatile_plan() -> Result<(), Error> {
+        let mut cmr = CommandMetadataRegistry::new();
+        cmr.add_command(&CommandMetadata::new("stable"));
+        cmr.add_command(CommandMetadata::new("now").with_volatile());
+        let plan = PlanBuilder::new(parse_query("stable")?, &cmr).build()?;
+        assert!(!plan.is_volatile());
+        let plan = PlanBuilder::new(parse_query("now/stable")?, &cmr).build()?;
+        assert!(plan.is_volatile());
+        let plan: Plan = serde_yaml::from_str(&serde_yaml::to_string(&plan).unwrap()).unwrap();
+        assert!(plan.is_volatile());
+
+        // A volatile command in a link parameter makes the plan volatile too
+        let mut cmr = CommandMetadataRegistry::new();
+        let mut f = CommandMetadata::new("f");
+        f.with_argument(ArgumentInfo::any_argument("x"));
+        cmr.add_command(&f);
+        cmr.add_command(CommandMetadata::new("now").with_volatile());
+        let plan = PlanBuilder::new(parse_query("-/f-~X~now~E")?, &cmr).build()?;
+        assert!(plan.is_volatile());
+        let plan = PlanBuilder::new(parse_query("-/f-~X~f-1~E")?, &cmr).build()?;
+        assert!(!plan.is_volatile());
+        Ok(())
+    }
+    #[test]
+    fn test_canonical() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cmr = CommandMetadataRegistry::new();
+        let mut head = CommandMetadata::new("head");
+        head.with_argument(ArgumentInfo::integer_argument("n", false).with_default(10))
+            .with_argument(ArgumentInfo::boolean_argument("strict").false_by_default())
+            .with_argument(ArgumentInfo::argument("context").set_injected());
+        cmr.add_command(&head);
+        let mut upper = CommandMetadata::new("upper");
+        upper.with_namespace("text");
+        cmr.add_command(&upper);
+
+        let canonical = |q: &str| parse_query(q).unwrap().canonical(&cmr).unwrap();
+        assert_eq!(canonical("-/head-10-false").encode(), "-/head");
+        assert_eq!(canonical("-/head-5-f").encode(), "-/head-5");
+        assert_eq!(canonical("-/head-10-true").encode(), "-/head-10-true");
+        assert_eq!(
+            canonical("-/ns-text/upper/head-10/ns-text-text/out.txt").encode(),
+            "-/ns-text/upper/head/out.txt"
+        );
+
+        // Equivalent queries are equal and encode identically, positions are ignored
+        let q1 = canonical("data/a.csv/-/head-10");
+        let q2 = canonical("data/a.csv/-/head");
+        assert_eq!(q1, q2);
+        assert_eq!(q1.encode(), q2.encode());
+        // Resource segments are kept verbatim
+        assert_eq!(q1.segments[0], parse_query("data/a.csv/-/head")?.segments[0]);
+
+        assert!(parse_query("-/upper")?.canonical(&cmr).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_resolved_parameter_values() {
         let mut cm = CommandMetadata::new("testcommand");
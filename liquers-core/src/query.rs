@@ -62,8 +62,29 @@ impl Display for Position {
     }
 }
 
+/// Encode a text of an action parameter, so that it is parsed back to the same text.
+/// ASCII letters, digits, `_`, `+` and `.` are kept, the other characters are escaped by `~` entities:
+/// `~~` (tilde), `~_` (minus), `~.` (space), `~I` (slash)
+/// and `~xHH` for any other character, as a sequence of its UTF-8 bytes in hexadecimal.
 pub fn encode_token<S: AsRef<str>>(text: S) -> String {
-    format!("{}", text.as_ref())
+    let text = text.as_ref();
+    let mut encoded = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '~' => encoded.push_str("~~"),
+            '-' => encoded.push_str("~_"),
+            ' ' => encoded.push_str("~."),
+            '/' => encoded.push_str("~I"),
+            c if c.is_ascii_alphanumeric() || c == '_' || c == '+' || c == '.' => encoded.push(c),
+            c => {
+                let mut buffer = [0u8; 4];
+                for b in c.encode_utf8(&mut buffer).bytes() {
+                    encoded.push_str(&format!("~x{:02X}", b));
+                }
+            }
+        }
+    }
+    encoded
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -209,6 +230,13 @@ impl ResourceName {
             None
         }
     }
+    /// Return the name without the (last) extension; a name starting with a dot is returned unchanged.
+    pub fn base_name(&self) -> &str {
+        match self.name.rsplit_once('.') {
+            Some((base, _)) if !base.is_empty() => base,
+            _ => &self.name,
+        }
+    }
 }
 
 impl PartialEq for ResourceName {
@@ -669,6 +697,69 @@ impl Key {
         key
     }
 
+    /// Encode a name so that it can be used as a key element (see [ResourceName]).
+    /// Names in keys may contain ASCII letters, digits, `_`, `.` and `-` (except at the beginning,
+    /// where `-` starts a segment header). All other characters are reserved and are escaped
+    /// with the same `~` entities as action parameters:
+    /// - `~~` - tilde,
+    /// - `~_` - minus at the beginning of the name,
+    /// - `~.` - space,
+    /// - `~I` - slash (the key separator),
+    /// - `~xHH` - any other character, as a sequence of its UTF-8 bytes in hexadecimal.
+    ///
+    /// The result is canonical, i.e. only the reserved characters are escaped.
+    pub fn encode_name(name: &str) -> String {
+        let mut encoded = String::with_capacity(name.len());
+        for (i, c) in name.chars().enumerate() {
+            match c {
+                '~' => encoded.push_str("~~"),
+                '-' if i == 0 => encoded.push_str("~_"),
+                ' ' => encoded.push_str("~."),
+                '/' => encoded.push_str("~I"),
+                c if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' => encoded.push(c),
+                c => {
+                    let mut buffer = [0u8; 4];
+                    for b in c.encode_utf8(&mut buffer).bytes() {
+                        encoded.push_str(&format!("~x{:02X}", b));
+                    }
+                }
+            }
+        }
+        encoded
+    }
+
+    /// Decode a key element encoded by [Key::encode_name].
+    pub fn decode_name(encoded: &str) -> Result<String, Error> {
+        let error =
+            |message: &str| Error::key_parse_error(encoded, message, &Position::unknown());
+        let mut bytes = Vec::with_capacity(encoded.len());
+        let mut chars = encoded.chars();
+        while let Some(c) = chars.next() {
+            if c != '~' {
+                let mut buffer = [0u8; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                continue;
+            }
+            match chars.next() {
+                Some('~') => bytes.push(b'~'),
+                Some('_') => bytes.push(b'-'),
+                Some('.') => bytes.push(b' '),
+                Some('I') => bytes.push(b'/'),
+                Some('x') => {
+                    let hex = chars.by_ref().take(2).collect::<String>();
+                    let b = u8::from_str_radix(&hex, 16)
+                        .ok()
+                        .filter(|_| hex.len() == 2)
+                        .ok_or_else(|| error(&format!("Invalid escape '~x{}'", hex)))?;
+                    bytes.push(b);
+                }
+                Some(e) => return Err(error(&format!("Unknown escape '~{}'", e))),
+                None => return Err(error("Incomplete escape at the end of the name")),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| error("Escaped bytes are not valid UTF-8"))
+    }
+
     /// Return a parent key - i.e. a key without the last element.
     pub fn parent(&self) -> Self {
         let mut key = Vec::new();
@@ -742,6 +833,118 @@ impl Display for Key {
     }
 }
 
+/// Element of a [KeyPattern]
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum KeyPatternElement {
+    /// `**` - any number (including zero) of key elements
+    AnyPath,
+    /// Pattern of a single key element, `*` matches any (possibly empty) part of the name
+    Glob(String),
+}
+
+/// Pattern matching keys, e.g. `data/*/results/*.csv` or `data/**/*.csv`.
+/// A `*` matches any part of a single key element (it never crosses `/`),
+/// while a `**` element matches any number of key elements (including none).
+/// Elements are matched against the encoded names (see [Key::encode_name]),
+/// so reserved characters are written as `~` entities as in keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPattern(Vec<KeyPatternElement>);
+
+impl KeyPattern {
+    /// Parse a pattern; an empty element (e.g. in `data//x` or `data/`) is an error.
+    /// An empty pattern matches only the empty key.
+    pub fn parse(pattern: &str) -> Result<Self, Error> {
+        if pattern.is_empty() {
+            return Ok(KeyPattern(vec![]));
+        }
+        let mut elements = Vec::new();
+        let mut offset = 0;
+        for element in pattern.split('/') {
+            if element.is_empty() {
+                let position = Position::new(offset, 1, offset + 1);
+                return Err(Error::key_parse_error(
+                    pattern,
+                    "Empty element in key pattern",
+                    &position,
+                ));
+            }
+            elements.push(if element == "**" {
+                KeyPatternElement::AnyPath
+            } else {
+                KeyPatternElement::Glob(element.to_owned())
+            });
+            offset += element.len() + 1;
+        }
+        Ok(KeyPattern(elements))
+    }
+
+    /// Check if the key matches the pattern
+    pub fn matches(&self, key: &Key) -> bool {
+        Self::matches_elements(&self.0, &key.0)
+    }
+
+    fn matches_elements(pattern: &[KeyPatternElement], key: &[ResourceName]) -> bool {
+        match pattern.split_first() {
+            None => key.is_empty(),
+            Some((KeyPatternElement::AnyPath, rest)) => {
+                (0..=key.len()).any(|i| Self::matches_elements(rest, &key[i..]))
+            }
+            Some((KeyPatternElement::Glob(glob), rest)) => {
+                !key.is_empty()
+                    && Self::matches_glob(glob, key[0].encode())
+                    && Self::matches_elements(rest, &key[1..])
+            }
+        }
+    }
+
+    /// Match a name against a glob, where `*` matches any sequence of characters
+    fn matches_glob(glob: &str, name: &str) -> bool {
+        let glob = glob.chars().collect::<Vec<_>>();
+        let name = name.chars().collect::<Vec<_>>();
+        let (mut i, mut j) = (0, 0);
+        // Position of the last star in the glob and of the name character it was matched to
+        let mut star: Option<(usize, usize)> = None;
+        while j < name.len() {
+            if i < glob.len() && glob[i] == '*' {
+                star = Some((i, j));
+                i += 1;
+            } else if i < glob.len() && glob[i] == name[j] {
+                i += 1;
+                j += 1;
+            } else if let Some((star_i, star_j)) = star {
+                // Let the star match one more character
+                i = star_i + 1;
+                j = star_j + 1;
+                star = Some((star_i, j));
+            } else {
+                return false;
+            }
+        }
+        glob[i..].iter().all(|c| *c == '*')
+    }
+}
+
+impl Display for KeyPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let elements = self
+            .0
+            .iter()
+            .map(|element| match element {
+                KeyPatternElement::AnyPath => "**",
+                KeyPatternElement::Glob(glob) => glob.as_str(),
+            })
+            .join("/");
+        write!(f, "{}", elements)
+    }
+}
+
+impl TryFrom<&str> for KeyPattern {
+    type Error = Error;
+    fn try_from(pattern: &str) -> Result<Self, Self::Error> {
+        KeyPattern::parse(pattern)
+    }
+}
+
 /// Query segment representing a resource, i.e. path to a file in a store.
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct ResourceQuerySegment {
@@ -1186,6 +1389,32 @@ impl Query {
         }
     }
 
+    /// Keys of the resources the query reads: keys of the resource segments
+    /// and (recursively) of the queries linked from the action parameters.
+    pub fn resource_keys(&self) -> Vec<Key> {
+        let mut keys: Vec<Key> = Vec::new();
+        let mut add = |key: Key| {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        };
+        for segment in self.segments.iter() {
+            match segment {
+                QuerySegment::Resource(rqs) => add(rqs.key.clone()),
+                QuerySegment::Transform(tqs) => {
+                    for action in tqs.query.iter() {
+                        for parameter in action.parameters.iter() {
+                            if let ActionParameter::Link(query, _) = parameter {
+                                query.resource_keys().into_iter().for_each(&mut add);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        keys
+    }
+
     /// Returns true if the query is a single action request.
     pub fn is_action_request(&self) -> bool {
         self.transform_query()
@@ -1351,6 +1580,33 @@ impl Query {
         }
     }
 
+    /// Query applying an action to the result of this query, e.g. a follow-up action on a displayed result.
+    /// The action is appended to the last transformation, or a new transformation is started
+    /// if the query is a resource (or empty). A filename at the end of the query is dropped,
+    /// since it names the result of the original query.
+    pub fn with_action(&self, action: ActionRequest) -> Query {
+        let mut query = self.clone();
+        match query.segments.last_mut() {
+            Some(QuerySegment::Transform(tqs)) => {
+                tqs.filename = None;
+                tqs.query.push(action);
+            }
+            Some(QuerySegment::Resource(_)) => {
+                // Transformation following a resource needs a header to be distinguished from the key
+                query.segments.push(QuerySegment::Transform(TransformQuerySegment {
+                    header: Some(SegmentHeader::new()),
+                    query: vec![action],
+                    filename: None,
+                }))
+            }
+            None => query.segments.push(QuerySegment::Transform(TransformQuerySegment {
+                query: vec![action],
+                ..Default::default()
+            })),
+        }
+        query
+    }
+
     /// Make a shortened version of the at most n characters of a query for printout purposes
     pub fn short(&self, n: usize) -> String {
         if let (_, Some(r)) = self.predecessor() {
@@ -1387,6 +1643,332 @@ impl Query {
     pub fn len(&self) -> usize {
         self.segments.len()
     }
+
+    /// Compare the query with another (edited) query segment by segment, see [QueryDiff].
+    /// Positions are ignored when comparing.
+    pub fn diff(&self, other: &Query) -> QueryDiff {
+        let common = self
+            .segments
+            .iter()
+            .zip(other.segments.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let mut changes: Vec<SegmentChange> = self.segments[..common]
+            .iter()
+            .cloned()
+            .map(SegmentChange::Unchanged)
+            .collect();
+        let old = &self.segments[common..];
+        let new = &other.segments[common..];
+        for i in 0..old.len().max(new.len()) {
+            match (old.get(i), new.get(i)) {
+                (Some(from), Some(to)) if from == to => {
+                    changes.push(SegmentChange::Unchanged(from.clone()))
+                }
+                (Some(QuerySegment::Transform(from)), Some(QuerySegment::Transform(to))) => {
+                    changes.push(SegmentChange::Modified {
+                        from: old[i].clone(),
+                        to: new[i].clone(),
+                        actions: ActionChange::diff(&from.query, &to.query),
+                    })
+                }
+                (Some(QuerySegment::Resource(_)), Some(QuerySegment::Resource(_))) => {
+                    changes.push(SegmentChange::Modified {
+                        from: old[i].clone(),
+                        to: new[i].clone(),
+                        actions: vec![],
+                    })
+                }
+                // A resource segment is never reported as modified into a transform segment (and vice versa)
+                (from, to) => {
+                    if let Some(from) = from {
+                        changes.push(SegmentChange::Removed(from.clone()));
+                    }
+                    if let Some(to) = to {
+                        changes.push(SegmentChange::Appended(to.clone()));
+                    }
+                }
+            }
+        }
+        QueryDiff {
+            changes,
+            absolute_changed: self.absolute != other.absolute,
+        }
+    }
+}
+
+/// Change of a parameter of an action kept in an edited query (see [ActionChange]).
+/// None means that the parameter is missing in the original or in the edited action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterChange {
+    pub index: usize,
+    pub from: Option<ActionParameter>,
+    pub to: Option<ActionParameter>,
+}
+
+/// Change of an action within a modified transform segment.
+/// None in `from` means an added action, None in `to` a removed action.
+/// If the action name is the same, `parameters` lists the changed parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionChange {
+    pub index: usize,
+    pub from: Option<ActionRequest>,
+    pub to: Option<ActionRequest>,
+    pub parameters: Vec<ParameterChange>,
+}
+
+impl ActionChange {
+    /// Changes between two sequences of actions, compared position by position
+    fn diff(from: &[ActionRequest], to: &[ActionRequest]) -> Vec<ActionChange> {
+        let mut changes = Vec::new();
+        for index in 0..from.len().max(to.len()) {
+            let (a, b) = (from.get(index), to.get(index));
+            if a == b {
+                continue;
+            }
+            let parameters = match (a, b) {
+                (Some(a), Some(b)) if a.name == b.name => (0..a.parameters.len().max(b.parameters.len()))
+                    .filter_map(|i| {
+                        let (p, q) = (a.parameters.get(i), b.parameters.get(i));
+                        (p != q).then(|| ParameterChange {
+                            index: i,
+                            from: p.cloned(),
+                            to: q.cloned(),
+                        })
+                    })
+                    .collect(),
+                _ => vec![],
+            };
+            changes.push(ActionChange {
+                index,
+                from: a.cloned(),
+                to: b.cloned(),
+                parameters,
+            });
+        }
+        changes
+    }
+}
+
+impl Display for ActionChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.from, &self.to) {
+            (Some(from), Some(to)) if from.name == to.name => {
+                let parameters = self
+                    .parameters
+                    .iter()
+                    .map(|p| {
+                        let encode = |x: &Option<ActionParameter>| {
+                            x.as_ref().map_or("(none)".to_owned(), |x| x.encode())
+                        };
+                        format!("#{} {} -> {}", p.index, encode(&p.from), encode(&p.to))
+                    })
+                    .join(", ");
+                write!(f, "{} parameters {}", from.name, parameters)
+            }
+            (Some(from), Some(to)) => write!(f, "{} replaced by {}", from, to),
+            (Some(from), None) => write!(f, "removed {}", from),
+            (None, Some(to)) => write!(f, "added {}", to),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Change of a query segment, see [Query::diff]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SegmentChange {
+    /// Segment equal in both queries; segments of the common prefix are always unchanged,
+    /// so their results (e.g. cached intermediate states) can be reused
+    Unchanged(QuerySegment),
+    Appended(QuerySegment),
+    Removed(QuerySegment),
+    /// Segment replaced by a segment of the same kind (resource or transform);
+    /// for transform segments, `actions` lists the changed actions
+    Modified {
+        from: QuerySegment,
+        to: QuerySegment,
+        actions: Vec<ActionChange>,
+    },
+}
+
+/// Differences between two queries (see [Query::diff]), e.g. to show what changed when a query was edited.
+/// Segments are compared position by position after the common prefix, which is reported as unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryDiff {
+    pub changes: Vec<SegmentChange>,
+    /// The query changed from absolute to relative or vice versa
+    pub absolute_changed: bool,
+}
+
+impl QueryDiff {
+    /// True if the queries are equal (ignoring positions)
+    pub fn is_unchanged(&self) -> bool {
+        !self.absolute_changed
+            && self
+                .changes
+                .iter()
+                .all(|change| matches!(change, SegmentChange::Unchanged(_)))
+    }
+
+    /// Number of leading segments shared by both queries
+    pub fn unchanged_len(&self) -> usize {
+        self.changes
+            .iter()
+            .take_while(|change| matches!(change, SegmentChange::Unchanged(_)))
+            .count()
+    }
+}
+
+impl Display for QueryDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_unchanged() {
+            return write!(f, "no changes");
+        }
+        let mut items = Vec::new();
+        let unchanged = self.unchanged_len();
+        if unchanged > 0 {
+            items.push(format!("{} unchanged", unchanged));
+        }
+        if self.absolute_changed {
+            items.push("absolute changed".to_owned());
+        }
+        for change in self.changes.iter().skip(unchanged) {
+            items.push(match change {
+                SegmentChange::Unchanged(segment) => {
+                    format!("unchanged {}", segment.encode())
+                }
+                SegmentChange::Appended(segment) => {
+                    format!("appended {}", segment.encode())
+                }
+                SegmentChange::Removed(segment) => {
+                    format!("removed {}", segment.encode())
+                }
+                SegmentChange::Modified { from, to, actions } if actions.is_empty() => format!(
+                    "modified {} -> {}",
+                    from.encode(),
+                    to.encode()
+                ),
+                SegmentChange::Modified { actions, .. } => {
+                    format!("modified {}", actions.iter().map(|a| a.to_string()).join(", "))
+                }
+            });
+        }
+        write!(f, "{}", items.join("; "))
+    }
+}
+
+/// Builder of queries constructed programmatically, an alternative to parsing a query string.
+/// Parameter texts are escaped when the query is encoded (see [encode_token]), so the encoded query
+/// is parsed back to the same query.
+///
+/// ```
+/// use liquers_core::query::QueryBuilder;
+/// use liquers_core::parse::{parse_key, parse_query};
+///
+/// let query = QueryBuilder::new()
+///     .resource(&parse_key("data/a.csv").unwrap())
+///     .action("filter")
+///     .arg("a/b")
+///     .unwrap()
+///     .build();
+/// assert_eq!(parse_query(&query.encode()).unwrap(), query);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct QueryBuilder {
+    segments: Vec<QuerySegment>,
+    absolute: bool,
+}
+
+impl QueryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the query absolute
+    pub fn absolute(mut self) -> Self {
+        self.absolute = true;
+        self
+    }
+
+    /// Append a resource segment
+    pub fn resource(mut self, key: &Key) -> Self {
+        let header = SegmentHeader {
+            resource: true,
+            ..SegmentHeader::new()
+        };
+        self.segments.push(QuerySegment::Resource(ResourceQuerySegment {
+            header: Some(header),
+            key: key.clone(),
+        }));
+        self
+    }
+
+    /// Transform segment accepting further actions (i.e. without a filename), appended if needed
+    fn open_transform(&mut self) -> &mut TransformQuerySegment {
+        let open = matches!(
+            self.segments.last(),
+            Some(QuerySegment::Transform(tqs)) if tqs.filename.is_none()
+        );
+        if !open {
+            self.segments.push(QuerySegment::Transform(TransformQuerySegment {
+                header: Some(SegmentHeader::new()),
+                query: vec![],
+                filename: None,
+            }));
+        }
+        match self.segments.last_mut() {
+            Some(QuerySegment::Transform(tqs)) => tqs,
+            _ => unreachable!(),
+        }
+    }
+
+    fn parameter(mut self, parameter: ActionParameter) -> Result<Self, Error> {
+        if let Some(QuerySegment::Transform(tqs)) = self.segments.last_mut() {
+            if let (None, Some(action)) = (&tqs.filename, tqs.query.last_mut()) {
+                action.parameters.push(parameter);
+                return Ok(self);
+            }
+        }
+        Err(Error::general_error(format!(
+            "Query builder: argument '{}' must follow an action",
+            parameter.encode()
+        )))
+    }
+
+    /// Append an action (without parameters)
+    pub fn action(mut self, name: &str) -> Self {
+        self.open_transform()
+            .query
+            .push(ActionRequest::new(name.to_owned()));
+        self
+    }
+
+    /// Append a parameter to the last action.
+    /// Fails if there is no action to append the parameter to (e.g. right after a resource or a filename).
+    pub fn arg<S: Into<String>>(self, value: S) -> Result<Self, Error> {
+        self.parameter(ActionParameter::new_string(value.into()))
+    }
+
+    /// Append a link parameter (a query evaluated to get the parameter value) to the last action.
+    /// Fails if there is no action to append the parameter to, see [QueryBuilder::arg].
+    pub fn link_arg(self, query: Query) -> Result<Self, Error> {
+        self.parameter(ActionParameter::new_link(query))
+    }
+
+    /// Set the filename of the result, which ends the transform segment.
+    /// The name is used verbatim, i.e. it should consist of letters, digits, `_`, `-` and `.`.
+    pub fn filename(mut self, name: &str) -> Self {
+        self.open_transform().filename = Some(ResourceName::new(name.to_owned()));
+        self
+    }
+
+    pub fn build(self) -> Query {
+        Query {
+            segments: self.segments,
+            absolute: self.absolute,
+            source: QuerySource::Unspecified,
+        }
+    }
 }
 
 pub trait TryToQuery{
@@ -1476,7 +2058,7 @@ impl IndexMut<usize> for Query {
 
 #[cfg(test)]
 mod tests {
-    use crate::parse::parse_key;
+    use crate::parse::{parse_key, parse_query};
 
     use super::*;
 
@@ -1490,6 +2072,36 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_with_action() -> Result<(), Error> {
+        use crate::parse::parse_query;
+        let action = ActionRequest::new("greet".to_owned())
+            .with_parameters(vec![ActionParameter::new_string("x".to_owned())]);
+        for (base, expected) in [
+            ("hello", "hello/greet-x"),
+            ("hello/data.txt", "hello/greet-x"),
+            ("-R/dir/data.txt", "-R/dir/data.txt/-/greet-x"),
+            ("-R/dir/data.txt/-/upper", "-R/dir/data.txt/-/upper/greet-x"),
+            ("", "greet-x"),
+        ] {
+            let base = parse_query(base)?;
+            let query = base.with_action(action.clone());
+            assert_eq!(query.encode(), parse_query(expected)?.encode());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_resource_keys() -> Result<(), Error> {
+        let query = parse_query("-R/data/a.txt/-/join-~X~-R/other/b.txt/-/f~E-~X~-R/data/a.txt~E/g")?;
+        assert_eq!(
+            query.resource_keys(),
+            vec![parse_key("data/a.txt")?, parse_key("other/b.txt")?]
+        );
+        assert!(parse_query("hello/world")?.resource_keys().is_empty());
+        Ok(())
+    }
+
     #[test]
     fn encode_link_action_parameter() -> Result<(), Box<dyn std::error::Error>> {
         let q = Query {
@@ -1640,4 +2252,197 @@ mod tests {
         let key = parse_key("arch.tar.gz").unwrap();
         assert_eq!(key.extension(), Some("gz".to_owned()));
     }
+
+    #[test]
+    fn test_key_name_escape_roundtrip() -> Result<(), Error> {
+        for name in [
+            "a~b.txt",
+            "-a-b.txt",
+            "a b.txt",
+            "a/b.txt",
+            "a?b#c%d.txt",
+            "na\u{ef}ve.txt",
+            "plain-name_1.txt",
+        ] {
+            let encoded = Key::encode_name(name);
+            assert_eq!(Key::decode_name(&encoded)?, name);
+            let key = parse_key(format!("dir/{}", encoded))?;
+            assert_eq!(key.len(), 2);
+            assert_eq!(key[1].name, encoded);
+            assert_eq!(Key::decode_name(&key[1].name)?, name);
+        }
+        assert_eq!(Key::encode_name("a-b.txt"), "a-b.txt");
+        assert_eq!(Key::encode_name("-a b/~"), "~_a~.b~I~~");
+        assert_eq!(Key::encode_name("\u{e9}"), "~xC3~xA9");
+        assert!(Key::decode_name("a~").is_err());
+        assert!(Key::decode_name("a~q").is_err());
+        assert!(Key::decode_name("a~xZZ").is_err());
+        assert!(Key::decode_name("a~xC3").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_diff() -> Result<(), Box<dyn std::error::Error>> {
+        let q1 = parse_query("data/a.csv/-/head-10-x/upper")?;
+        let q2 = parse_query("data/a.csv/-/head-20-x/upper/to_json")?;
+        let diff = q1.diff(&q2);
+        assert_eq!(diff.unchanged_len(), 1);
+        assert_eq!(diff.changes.len(), 2);
+        match &diff.changes[1] {
+            SegmentChange::Modified { actions, .. } => {
+                assert_eq!(actions.len(), 2);
+                assert_eq!(actions[0].index, 0);
+                assert_eq!(
+                    actions[0].parameters,
+                    vec![ParameterChange {
+                        index: 0,
+                        from: Some(ActionParameter::new_string("10".to_owned())),
+                        to: Some(ActionParameter::new_string("20".to_owned())),
+                    }]
+                );
+                assert_eq!(actions[1].from, None);
+                assert_eq!(actions[1].to.as_ref().unwrap().name, "to_json");
+            }
+            change => panic!("Modified segment expected, got {:?}", change),
+        }
+        assert_eq!(
+            diff.to_string(),
+            "1 unchanged; modified head parameters #0 10 -> 20, added to_json"
+        );
+
+        // Positions are ignored
+        let mut q3 = parse_query("data/a.csv/-/head-10-x/upper")?;
+        for segment in q3.segments.iter_mut() {
+            if let QuerySegment::Transform(transform) = segment {
+                for action in transform.query.iter_mut() {
+                    action.position = Position::new(action.position.offset + 5, 2, 1);
+                    for parameter in action.parameters.iter_mut() {
+                        if let ActionParameter::String(_, position) = parameter {
+                            *position = Position::new(position.offset + 5, 2, 1);
+                        }
+                    }
+                }
+            }
+        }
+        let offsets = |q: &Query| {
+            q.segments
+                .iter()
+                .filter_map(|segment| match segment {
+                    QuerySegment::Transform(t) => Some(t.query.iter().map(|a| a.position.offset)),
+                    _ => None,
+                })
+                .flatten()
+                .collect::<Vec<_>>()
+        };
+        assert_ne!(offsets(&q1), offsets(&q3));
+        assert!(q1.diff(&q3).is_unchanged());
+        assert_eq!(q1.diff(&q3).to_string(), "no changes");
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_diff_segment_kinds() -> Result<(), Box<dyn std::error::Error>> {
+        let q1 = parse_query("data/a.csv/-/upper")?;
+        let q2 = parse_query("-/upper")?;
+        let diff = q1.diff(&q2);
+        assert_eq!(diff.unchanged_len(), 0);
+        assert!(matches!(diff.changes[0], SegmentChange::Removed(QuerySegment::Resource(_))));
+        assert!(matches!(diff.changes[1], SegmentChange::Appended(QuerySegment::Transform(_))));
+        assert!(matches!(diff.changes[2], SegmentChange::Removed(QuerySegment::Transform(_))));
+        assert!(!diff
+            .changes
+            .iter()
+            .any(|change| matches!(change, SegmentChange::Modified { .. })));
+
+        let diff = q1.diff(&parse_query("data/b.csv/-/upper")?);
+        assert_eq!(diff.to_string(), "modified data/a.csv -> data/b.csv; unchanged -/upper");
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_builder() -> Result<(), Box<dyn std::error::Error>> {
+        let query = QueryBuilder::new().action("foo").arg("a/b")?.build();
+        assert_eq!(query.encode(), "-/foo-a~Ib");
+        assert_eq!(parse_query(&query.encode())?, query);
+
+        let link = QueryBuilder::new()
+            .resource(&parse_key("data/b.csv")?)
+            .action("head")
+            .arg("-5")?
+            .build();
+        let query = QueryBuilder::new()
+            .resource(&parse_key("data/a.csv")?)
+            .action("join")
+            .link_arg(link.clone())?
+            .arg("a-b/c d~e:f ü")?
+            .action("upper")
+            .filename("out.txt")
+            .build();
+        let parsed = parse_query(&query.encode())?;
+        assert_eq!(parsed, query);
+        let actions = match &parsed.segments[1] {
+            QuerySegment::Transform(tqs) => tqs.query.clone(),
+            segment => panic!("Transform segment expected, got {:?}", segment),
+        };
+        assert_eq!(actions[0].parameters[0].link_value(), Some(link.clone()));
+        assert_eq!(
+            actions[0].parameters[1].string_value().as_deref(),
+            Some("a-b/c d~e:f ü")
+        );
+        assert_eq!(parsed.filename().unwrap().encode(), "out.txt");
+
+        // Arguments need an action to belong to
+        assert!(QueryBuilder::new().arg("x").is_err());
+        assert!(QueryBuilder::new()
+            .resource(&parse_key("data/a.csv")?)
+            .arg("x")
+            .is_err());
+        assert!(QueryBuilder::new()
+            .action("foo")
+            .filename("out.txt")
+            .link_arg(link)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_token() {
+        assert_eq!(encode_token("abc_XYZ+0.9"), "abc_XYZ+0.9");
+        assert_eq!(encode_token("~"), "~~");
+        assert_eq!(encode_token("-"), "~_");
+        assert_eq!(encode_token(" "), "~.");
+        assert_eq!(encode_token("/"), "~I");
+        assert_eq!(encode_token("a:b"), "a~x3Ab");
+        assert_eq!(encode_token("ü"), "~xC3~xBC");
+        assert_eq!(encode_token("€"), "~xE2~x82~xAC");
+        assert_eq!(encode_token(""), "");
+    }
+
+    #[test]
+    fn test_key_pattern() -> Result<(), Box<dyn std::error::Error>> {
+        let matches = |pattern: &str, key: &str| {
+            KeyPattern::parse(pattern)
+                .unwrap()
+                .matches(&parse_key(key).unwrap())
+        };
+        assert!(matches("data/*/results/*.csv", "data/a/results/x.csv"));
+        assert!(!matches("data/*/results/*.csv", "data/a/b/results/x.csv"));
+        assert!(!matches("data/*/results/*.csv", "data/a/results/x.txt"));
+        assert!(matches("data/*", "data/a.csv"));
+        assert!(!matches("data/*", "data"));
+        assert!(matches("data/**/*.csv", "data/x.csv"));
+        assert!(matches("data/**/*.csv", "data/a/b/x.csv"));
+        assert!(!matches("data/**/*.csv", "other/a/x.csv"));
+        assert!(matches("**", "a/b/c"));
+        assert!(matches("d*a/a*b*c.txt", "data/abxxbc.txt"));
+        assert!(!matches("a*b", "acb/b"));
+        assert!(matches("", ""));
+        assert_eq!(KeyPattern::parse("data/**/*.csv")?.to_string(), "data/**/*.csv");
+
+        let err = KeyPattern::parse("data//x").unwrap_err();
+        assert_eq!(err.position.offset, 5);
+        assert!(KeyPattern::parse("data/").is_err());
+        assert!(KeyPattern::parse("/data").is_err());
+        Ok(())
+    }
 }
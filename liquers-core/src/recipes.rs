@@ -5,6 +5,8 @@ use serde_json::Value;
 use crate::{
     command_metadata::CommandMetadataRegistry, context::{NGEnvRef, NGEnvironment}, error::Error, parse::parse_query, plan::{Plan, PlanBuilder}, query::{Key, Query, ResourceName}
 };
+#[cfg(feature = "async_store")]
+use crate::{interpreter::NGPlanInterpreter, metadata::MetadataRecord, state::State};
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Recipe {
@@ -13,6 +15,13 @@ pub struct Recipe {
     pub description: String,
     pub arguments: HashMap<String, Value>,
     pub links: HashMap<String, String>,
+    /// Fallback query evaluated instead of the recipe query when the evaluation fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_error: Option<String>,
+    /// Maximal duration of the evaluation of the recipe query in seconds,
+    /// a longer evaluation fails (or falls back to [on_error](Recipe::on_error)), see [Recipe::evaluate]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<f64>,
 }
 
 impl Recipe {
@@ -23,6 +32,8 @@ impl Recipe {
             description,
             arguments: HashMap::new(),
             links: HashMap::new(),
+            on_error: None,
+            timeout: None,
         })
     }
 
@@ -36,10 +47,42 @@ impl Recipe {
         self
     }
 
+    /// Set the fallback query evaluated when the evaluation of the recipe fails
+    pub fn with_on_error(mut self, query: String) -> Result<Self, Error> {
+        self.on_error = Some(parse_query(&query)?.encode());
+        Ok(self)
+    }
+
+    /// Set the maximal duration of the evaluation (see [Recipe::timeout])
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout.as_secs_f64());
+        self
+    }
+
+    /// Maximal duration of the evaluation (see [Recipe::timeout]) if present.
+    /// A negative, infinite or too large timeout is an error.
+    pub fn get_timeout(&self) -> Result<Option<std::time::Duration>, Error> {
+        self.timeout
+            .map(|t| {
+                std::time::Duration::try_from_secs_f64(t).map_err(|e| {
+                    Error::general_error(format!(
+                        "Invalid timeout {} of recipe '{}': {}",
+                        t, self.query, e
+                    ))
+                })
+            })
+            .transpose()
+    }
+
     pub fn get_query(&self) -> Result<Query, Error> {
         parse_query(&self.query)
     }
 
+    /// Fallback query (see [Recipe::on_error]) if present
+    pub fn get_on_error_query(&self) -> Result<Option<Query>, Error> {
+        self.on_error.as_deref().map(parse_query).transpose()
+    }
+
     pub fn filename(&self) -> Result<ResourceName, Error> {
         self.get_query()?.filename().map_or(
             Err(Error::general_error(format!(
@@ -75,6 +118,77 @@ impl Recipe {
         }
         Ok(plan)
     }
+
+    /// Evaluate the recipe on an input state.
+    /// The interpreter evaluates the recipe (on an empty state) when the resource it makes is missing in the store,
+    /// see [DefaultRecipeProvider::get_recipe].
+    /// If the evaluation fails and the recipe has an [on_error](Recipe::on_error) query,
+    /// the fallback query is evaluated on the same input state and its result is used;
+    /// the original error is logged as a warning in the metadata of the result.
+    /// If the fallback fails too, the original error is returned.
+    /// An evaluation exceeding the [timeout](Recipe::timeout) fails with an execution error
+    /// (the fallback is not time limited). The timeout stops waiting for the result, it does not
+    /// interrupt a running command: a blocking command finishes in the background
+    /// (holding its [max_concurrency](crate::command_metadata::CommandMetadata::max_concurrency) permit),
+    /// and a non-blocking synchronous command is only checked for the timeout after it returns.
+    #[cfg(feature = "async_store")]
+    pub async fn evaluate<E: NGEnvironment>(
+        &self,
+        envref: NGEnvRef<E>,
+        input_state: State<E::Value>,
+    ) -> Result<State<E::Value>, Error> {
+        let timeout = self.get_timeout()?;
+        let plan = {
+            let env = envref.0.read().await;
+            self.to_plan(env.get_command_metadata_registry())
+        };
+        let result = match plan {
+            Ok(plan) => {
+                let mut pi = NGPlanInterpreter::new(envref.clone());
+                pi.with_plan(plan);
+                match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, pi.apply_state(input_state.clone()))
+                        .await
+                        .unwrap_or_else(|_| {
+                            Err(Error::execution_error(format!(
+                                "Recipe query '{}' timed out after {:?}",
+                                self.query, timeout
+                            )))
+                        }),
+                    None => pi.apply_state(input_state.clone()).await,
+                }
+            }
+            Err(e) => Err(e),
+        };
+        let error = match result {
+            Ok(state) => return Ok(state),
+            Err(e) => e,
+        };
+        let on_error = match self.get_on_error_query() {
+            Ok(Some(query)) => query,
+            _ => return Err(error),
+        };
+        let mut pi = NGPlanInterpreter::new(envref);
+        if pi.set_query(&on_error).await.is_err() {
+            return Err(error);
+        }
+        match pi.apply_state(input_state).await {
+            Ok(state) => {
+                let mut metadata = state
+                    .metadata
+                    .metadata_record()
+                    .unwrap_or_else(MetadataRecord::new);
+                metadata.warning(&format!(
+                    "Recipe query '{}' failed, fallback '{}' used instead: {}",
+                    self.query,
+                    on_error.encode(),
+                    error
+                ));
+                Ok(state.with_metadata(metadata.into()))
+            }
+            Err(_) => Err(error),
+        }
+    }
 }
 
 
@@ -83,7 +197,8 @@ trait RecipeProvider {
     async fn recipe_plan(&self, key:&Key) -> Result<Plan, Error>;
 }
 
-struct DefaultRecipeProvider<E:NGEnvironment> {
+/// Recipes of the resources in a folder, stored in the `recipes.yaml` file of the folder (see [RecipeList])
+pub struct DefaultRecipeProvider<E:NGEnvironment> {
     envref:NGEnvRef<E>,
 }
 
@@ -97,6 +212,20 @@ impl<E:NGEnvironment> DefaultRecipeProvider<E> {
             |bytes| serde_yaml::from_slice(&bytes).map_err(|e| Error::general_error(format!("Error parsing recipes: {}", e))),
         )
     }
+    /// Recipe of the resource with the key, None if the folder of the key has no recipes
+    /// or there is no recipe for the filename
+    pub async fn get_recipe(&self, key:&Key) -> Result<Option<Recipe>, Error> {
+        let filename = match key.filename() {
+            Some(filename) => filename,
+            None => return Ok(None),
+        };
+        let store = self.envref.get_async_store().await;
+        if !store.contains(&key.parent().join("recipes.yaml")).await? {
+            return Ok(None);
+        }
+        let recipes = self.get_recipes(&key.parent()).await.map_err(|e| e.with_key(key))?;
+        Ok(recipes.get(&filename.name).cloned())
+    }
 }
 
 impl<E:NGEnvironment> RecipeProvider for DefaultRecipeProvider<E> {
@@ -207,4 +336,177 @@ mod test {
             assert!(false);
         }
     }
+
+    #[cfg(feature = "async_store")]
+    mod on_error {
+        use crate::context::{NGEnvRef, NGEnvironment, SimpleNGEnvironment};
+        use crate::error::Error;
+        use crate::metadata::Metadata;
+        use crate::recipes::Recipe;
+        use crate::state::State;
+        use crate::value::{Value, ValueInterface};
+
+        fn envref() -> NGEnvRef<SimpleNGEnvironment<Value>> {
+            let mut env = SimpleNGEnvironment::<Value>::new();
+            crate::library::register_commands(env.get_mut_command_executor()).unwrap();
+            env.to_ref()
+        }
+
+        fn recipe() -> Recipe {
+            Recipe::new("from_json".to_owned(), "title".to_owned(), "".to_owned())
+                .unwrap()
+                .with_on_error("from_yaml".to_owned())
+                .unwrap()
+        }
+
+        fn input(text: &str) -> State<Value> {
+            State::new().with_data(Value::from(text))
+        }
+
+        fn warnings(state: &State<Value>) -> Vec<String> {
+            match &*state.metadata {
+                Metadata::MetadataRecord(m) => m.log.iter().map(|e| e.message.clone()).collect(),
+                _ => vec![],
+            }
+        }
+
+        #[tokio::test]
+        async fn test_primary_success() -> Result<(), Error> {
+            let state = recipe().evaluate(envref(), input(r#"{"a": 1}"#)).await?;
+            assert_eq!(
+                state.read().unwrap().try_into_json_value()?,
+                serde_json::json!({"a": 1})
+            );
+            assert!(!warnings(&state).iter().any(|w| w.contains("fallback")));
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_fallback_success() -> Result<(), Error> {
+            let state = recipe().evaluate(envref(), input("a: 1")).await?;
+            assert_eq!(
+                state.read().unwrap().try_into_json_value()?,
+                serde_json::json!({"a": 1})
+            );
+            assert!(warnings(&state)
+                .iter()
+                .any(|w| w.contains("fallback 'from_yaml'") && w.contains("JSON parse error")));
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_timeout() -> Result<(), Error> {
+            use crate::context::NGContext;
+            use std::time::Duration;
+
+            type Env = SimpleNGEnvironment<Value>;
+            let mut env = Env::new();
+            {
+                let cr = env.get_mut_command_executor();
+                fn slow(_state: &State<Value>, _context: NGContext<Env>) -> Result<Value, Error> {
+                    std::thread::sleep(Duration::from_millis(300));
+                    Ok(Value::from("slow"))
+                }
+                ng_register_command!(cr, slow(state, context));
+                cr.command_metadata_registry
+                    .get_mut("slow")
+                    .unwrap()
+                    .with_blocking()
+                    .with_max_concurrency(1)?;
+                crate::library::register_commands(cr)?;
+            }
+            let envref = env.to_ref();
+            let slow = Recipe::new("slow".to_owned(), "title".to_owned(), "".to_owned())?
+                .with_timeout(Duration::from_millis(50));
+            let err = slow.evaluate(envref.clone(), input("")).await.unwrap_err();
+            assert!(err.message.contains("timed out"));
+            // The command still runs after the timeout and holds its permit
+            let semaphore = envref
+                .0
+                .read()
+                .await
+                .get_command_metadata_registry()
+                .command_semaphore(&crate::command_metadata::CommandKey::new("", "", "slow"))
+                .unwrap();
+            assert_eq!(semaphore.available_permits(), 0);
+
+            let slow = slow.with_on_error("from_yaml".to_owned())?;
+            let state = slow.evaluate(envref.clone(), input("a: 1")).await?;
+            assert_eq!(
+                state.read().unwrap().try_into_json_value()?,
+                serde_json::json!({"a": 1})
+            );
+
+            let slow = Recipe::new("slow".to_owned(), "title".to_owned(), "".to_owned())?
+                .with_timeout(Duration::from_secs(5));
+            let state = slow.evaluate(envref.clone(), input("")).await?;
+            assert_eq!(state.try_into_string()?, "slow");
+
+            let mut invalid = Recipe::new("slow".to_owned(), "title".to_owned(), "".to_owned())?;
+            for timeout in [-1.0, f64::NAN, f64::INFINITY, 1e30] {
+                invalid.timeout = Some(timeout);
+                assert!(invalid.get_timeout().is_err());
+                assert!(invalid.evaluate(envref.clone(), input("")).await.is_err());
+            }
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_recipe_resource() -> Result<(), Error> {
+            use crate::context::NGContext;
+            use crate::interpreter::NGPlanInterpreter;
+            use crate::parse::parse_key;
+            use crate::query::Key;
+            use crate::recipes::RecipeList;
+            use crate::store::{AsyncStoreWrapper, MemoryStore};
+            use std::time::Duration;
+
+            type Env = SimpleNGEnvironment<Value>;
+            let mut env = Env::new();
+            env.with_async_store(Box::new(AsyncStoreWrapper(MemoryStore::new(&Key::new()))));
+            {
+                let cr = env.get_mut_command_executor();
+                fn hello() -> Result<Value, Error> {
+                    Ok(Value::from("Hello"))
+                }
+                fn slow(_state: &State<Value>, _context: NGContext<Env>) -> Result<Value, Error> {
+                    std::thread::sleep(Duration::from_millis(300));
+                    Ok(Value::from("slow"))
+                }
+                ng_register_command!(cr, hello());
+                ng_register_command!(cr, slow(state, context));
+                cr.command_metadata_registry.get_mut("slow").unwrap().with_blocking();
+            }
+            let envref = env.to_ref();
+            let mut recipes = RecipeList::new();
+            recipes.add_recipe(Recipe::new("hello/greeting.txt".to_owned(), "".to_owned(), "".to_owned())?);
+            recipes.add_recipe(
+                Recipe::new("slow/slow.txt".to_owned(), "".to_owned(), "".to_owned())?
+                    .with_timeout(Duration::from_millis(50)),
+            );
+            let yaml = serde_yaml::to_string(&recipes).unwrap();
+            envref
+                .get_async_store()
+                .await
+                .set(&parse_key("data/recipes.yaml")?, yaml.as_bytes(), &Metadata::MetadataRecord(Default::default()))
+                .await?;
+
+            let mut pi = NGPlanInterpreter::new(envref.clone());
+            let state = pi.evaluate("-R/data/greeting.txt").await?;
+            assert_eq!(state.try_into_string()?, "Hello");
+            let err = pi.evaluate("-R/data/slow.txt").await.unwrap_err();
+            assert!(err.message.contains("timed out"));
+            assert!(pi.evaluate("-R/data/missing.txt").await.is_err());
+            Ok(())
+        }
+
+        #[tokio::test]
+        async fn test_fallback_failure() {
+            let err = recipe().evaluate(envref(), input("[1, {")).await.unwrap_err();
+            assert!(err.message.contains("JSON parse error"));
+            let recipe = recipe().with_on_error("nonexistent".to_owned()).unwrap();
+            let err = recipe.evaluate(envref(), input("[1, {")).await.unwrap_err();
+            assert!(err.message.contains("JSON parse error"));
+        }
+    }
 }
@@ -1,15 +1,36 @@
 use std::fs::File;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::{Arc, RwLock};
 
 use async_trait::async_trait;
+#[cfg(feature = "async_store")]
+use futures::stream::{BoxStream, StreamExt};
 
-use crate::error::Error;
+use crate::error::{Error, ErrorType};
 use crate::metadata::{self, Metadata, MetadataRecord};
 use crate::query::Key;
 
+/// Bounds of the byte range from `start` to `end` (exclusive, end of the data if None) within the data of the given length.
+/// The end is clipped to the length; a start beyond the length or an end before the start is not satisfiable.
+pub fn range_bounds(key: &Key, start: u64, end: Option<u64>, length: u64) -> Result<(u64, u64), Error> {
+    let stop = end.map_or(length, |end| end.min(length));
+    if start > length || end.is_some_and(|end| end < start) {
+        return Err(Error::range_not_satisfiable(key, start, end, length));
+    }
+    Ok((start, stop))
+}
+
+/// Slice of the data in the byte range (see [range_bounds]) and the total length of the data
+pub fn slice_range(key: &Key, data: &[u8], start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+    let length = data.len() as u64;
+    let (start, stop) = range_bounds(key, start, end, length)?;
+    Ok((data[start as usize..stop as usize].to_vec(), length))
+}
+
 pub trait Store: Send + Sync {
     /// Get store name
     fn store_name(&self) -> String {
@@ -74,6 +95,36 @@ pub trait Store: Send + Sync {
         Err(Error::key_not_found(key))
     }
 
+    /// Get a reader of the data, so that large data can be read gradually.
+    /// The size of the data is returned too if it is known in advance.
+    /// The default implementation reads all the data into memory.
+    fn get_reader(&self, key: &Key) -> Result<(Box<dyn Read + Send>, Option<u64>), Error> {
+        let data = self.get_bytes(key)?;
+        let size = data.len() as u64;
+        Ok((Box::new(std::io::Cursor::new(data)), Some(size)))
+    }
+
+    /// Get the bytes from `start` to `end` (exclusive, end of the data if None) and the total length of the data.
+    /// A range starting beyond the end of the data is a [ErrorType::RangeNotSatisfiable] error.
+    /// The default implementation reads all the data and slices it.
+    fn get_range(&self, key: &Key, start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+        slice_range(key, &self.get_bytes(key)?, start, end)
+    }
+
+    /// Get a reader of the bytes in the range (see [Store::get_range]), the length of the range
+    /// and the total length of the data, so that a large range can be read gradually.
+    /// The default implementation reads the range into memory.
+    fn get_range_reader(
+        &self,
+        key: &Key,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<(Box<dyn Read + Send>, u64, u64), Error> {
+        let (data, length) = self.get_range(key, start, end)?;
+        let size = data.len() as u64;
+        Ok((Box::new(std::io::Cursor::new(data)), size, length))
+    }
+
     /// Store data and metadata.
     fn set(&self, key: &Key, _data: &[u8], _metadata: &Metadata) -> Result<(), Error> {
         Err(Error::key_not_supported(key, &self.store_name()))
@@ -109,7 +160,10 @@ pub trait Store: Send + Sync {
     /// List or iterator of all keys
     fn keys(&self) -> Result<Vec<Key>, Error> {
         let mut keys = self.listdir_keys_deep(&self.key_prefix())?;
-        keys.push(self.key_prefix().to_owned());
+        // The root of the store is not a key on its own
+        if !self.key_prefix().is_empty() {
+            keys.push(self.key_prefix().to_owned());
+        }
         Ok(keys)
     }
 
@@ -163,6 +217,12 @@ pub trait Store: Send + Sync {
         false
     }
 
+    /// Watch the changes of the key and the keys under it (see [StoreEvent]).
+    /// The default implementation is for stores that can't watch, the watcher never emits an event.
+    fn watch(&self, _key: &Key) -> Result<StoreWatcher, Error> {
+        Ok(StoreWatcher::empty())
+    }
+
     /*
         def on_data_changed(self, key):
             """Event handler called when the data is changed."""
@@ -206,6 +266,167 @@ pub trait Store: Send + Sync {
     */
 }
 
+/// Change of a key in a store, see [Store::watch] and [AsyncStore::watch]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreEvent {
+    Created(Key),
+    Modified(Key),
+    Removed(Key),
+}
+
+impl StoreEvent {
+    /// Key that has changed
+    pub fn key(&self) -> &Key {
+        match self {
+            StoreEvent::Created(key) | StoreEvent::Modified(key) | StoreEvent::Removed(key) => key,
+        }
+    }
+
+    /// The same kind of event for a different key
+    pub fn with_key(&self, key: Key) -> StoreEvent {
+        match self {
+            StoreEvent::Created(_) => StoreEvent::Created(key),
+            StoreEvent::Modified(_) => StoreEvent::Modified(key),
+            StoreEvent::Removed(_) => StoreEvent::Removed(key),
+        }
+    }
+}
+
+/// Number of [StoreEvent]s buffered by a [StoreWatcher].
+/// Events emitted while the buffer is full are dropped, a watcher is expected to keep up with the changes.
+pub const WATCH_CHANNEL_CAPACITY: usize = 1024;
+
+/// Receiver of the [StoreEvent]s of a watched key, see [Store::watch].
+/// The watching stops when the watcher is dropped.
+pub struct StoreWatcher {
+    receiver: tokio::sync::mpsc::Receiver<StoreEvent>,
+    guard: Option<Box<dyn Send>>,
+    map: Option<Box<dyn Fn(StoreEvent) -> Option<StoreEvent> + Send>>,
+}
+
+impl StoreWatcher {
+    pub fn new(receiver: tokio::sync::mpsc::Receiver<StoreEvent>) -> Self {
+        StoreWatcher {
+            receiver,
+            guard: None,
+            map: None,
+        }
+    }
+
+    /// Bounded channel (see [WATCH_CHANNEL_CAPACITY]) and the watcher receiving from it
+    pub fn channel() -> (tokio::sync::mpsc::Sender<StoreEvent>, Self) {
+        let (sender, receiver) = tokio::sync::mpsc::channel(WATCH_CHANNEL_CAPACITY);
+        (sender, Self::new(receiver))
+    }
+
+    /// Watcher that never emits an event
+    pub fn empty() -> Self {
+        Self::channel().1
+    }
+
+    /// Keep a value (e.g. the underlying file system watcher) alive as long as the watcher
+    pub fn with_guard<G: Send + 'static>(mut self, guard: G) -> Self {
+        self.guard = Some(Box::new(guard));
+        self
+    }
+
+    /// Translate the received events, e.g. to the keys of a wrapping store; events mapped to None are skipped
+    pub fn with_map<F>(mut self, map: F) -> Self
+    where
+        F: Fn(StoreEvent) -> Option<StoreEvent> + Send + 'static,
+    {
+        self.map = Some(match self.map.take() {
+            Some(previous) => Box::new(move |event| previous(event).and_then(&map)),
+            None => Box::new(map),
+        });
+        self
+    }
+
+    fn translate(&self, event: StoreEvent) -> Option<StoreEvent> {
+        match self.map.as_ref() {
+            Some(map) => map(event),
+            None => Some(event),
+        }
+    }
+
+    /// Wait for the next event; None if no more events will be emitted
+    pub async fn next_event(&mut self) -> Option<StoreEvent> {
+        loop {
+            let event = self.receiver.recv().await?;
+            if let Some(event) = self.translate(event) {
+                return Some(event);
+            }
+        }
+    }
+
+    /// Next event if already available
+    pub fn try_next_event(&mut self) -> Option<StoreEvent> {
+        loop {
+            let event = self.receiver.try_recv().ok()?;
+            if let Some(event) = self.translate(event) {
+                return Some(event);
+            }
+        }
+    }
+
+    #[cfg(feature = "async_store")]
+    pub fn into_stream(self) -> StoreEventStream {
+        futures::stream::unfold(self, |mut watcher| async move {
+            let event = watcher.next_event().await?;
+            Some((event, watcher))
+        })
+        .boxed()
+    }
+}
+
+/// Stream of the changes in a store, see [AsyncStore::watch]
+#[cfg(feature = "async_store")]
+pub type StoreEventStream = BoxStream<'static, StoreEvent>;
+
+/// Size of the chunks of a [DataStream]
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Stream of data chunks, see [AsyncStore::get_stream]
+#[cfg(feature = "async_store")]
+pub type DataStream = BoxStream<'static, Result<Vec<u8>, Error>>;
+
+/// Stream the data already held in memory in chunks of [STREAM_CHUNK_SIZE]
+#[cfg(feature = "async_store")]
+pub fn buffer_stream(data: Vec<u8>) -> DataStream {
+    let chunks = data
+        .chunks(STREAM_CHUNK_SIZE)
+        .map(|chunk| Ok(chunk.to_vec()))
+        .collect::<Vec<_>>();
+    futures::stream::iter(chunks).boxed()
+}
+
+/// Stream the data from a reader in chunks of [STREAM_CHUNK_SIZE]
+#[cfg(feature = "async_store")]
+pub fn reader_stream(reader: Box<dyn Read + Send>, key: &Key, store_name: &str) -> DataStream {
+    let (key, store_name) = (key.to_owned(), store_name.to_owned());
+    futures::stream::unfold(Some(reader), move |reader| {
+        let (key, store_name) = (key.clone(), store_name.clone());
+        async move {
+            let mut reader = reader?;
+            let mut buffer = vec![0; STREAM_CHUNK_SIZE];
+            loop {
+                match reader.read(&mut buffer) {
+                    Ok(0) => return None,
+                    Ok(n) => {
+                        buffer.truncate(n);
+                        return Some((Ok(buffer), Some(reader)));
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        return Some((Err(Error::key_read_io_error(&key, &store_name, &e)), None))
+                    }
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
 #[cfg(feature = "async_store")]
 #[async_trait]
 pub trait AsyncStore: Send + Sync {
@@ -260,6 +481,38 @@ pub trait AsyncStore: Send + Sync {
         self.get(key).await.map(|(_, metadata)| metadata)
     }
 
+    /// Get data as a stream of chunks, so that large data does not need to be held in memory at once.
+    /// The metadata and the size of the data (if known in advance) are available before the stream is consumed,
+    /// e.g. to set the content type and length of a response.
+    /// The default implementation reads all the data and splits it into chunks.
+    async fn get_stream(&self, key: &Key) -> Result<(DataStream, Metadata, Option<u64>), Error> {
+        let (data, metadata) = self.get(key).await?;
+        let size = data.len() as u64;
+        Ok((buffer_stream(data), metadata, Some(size)))
+    }
+
+    /// Get the bytes from `start` to `end` (exclusive, end of the data if None) and the total length of the data,
+    /// e.g. to serve HTTP range requests.
+    /// A range starting beyond the end of the data is a [ErrorType::RangeNotSatisfiable] error.
+    /// The default implementation reads all the data and slices it.
+    async fn get_range(&self, key: &Key, start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+        slice_range(key, &self.get_bytes(key).await?, start, end)
+    }
+
+    /// Get the bytes in the range (see [AsyncStore::get_range]) as a stream of chunks,
+    /// together with the length of the range and the total length of the data.
+    /// The default implementation reads the range into memory.
+    async fn get_range_stream(
+        &self,
+        key: &Key,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<(DataStream, u64, u64), Error> {
+        let (data, length) = self.get_range(key, start, end).await?;
+        let size = data.len() as u64;
+        Ok((buffer_stream(data), size, length))
+    }
+
     /// Store data and metadata.
     async fn set(&self, key: &Key, _data: &[u8], _metadata: &Metadata) -> Result<(), Error> {
         Err(Error::key_not_supported(key, &self.store_name()))
@@ -293,7 +546,10 @@ pub trait AsyncStore: Send + Sync {
     /// List or iterator of all keys
     async fn keys(&self) -> Result<Vec<Key>, Error> {
         let mut keys = self.listdir_keys_deep(&self.key_prefix()).await?;
-        keys.push(self.key_prefix().to_owned());
+        // The root of the store is not a key on its own
+        if !self.key_prefix().is_empty() {
+            keys.push(self.key_prefix().to_owned());
+        }
         Ok(keys)
     }
 
@@ -327,6 +583,33 @@ pub trait AsyncStore: Send + Sync {
         Ok(keys_deep)
     }
 
+    /// Stream keys inside a directory specified by key, including all the subdirectories.
+    /// Unlike listdir_keys_deep, keys are yielded as they are discovered,
+    /// so the caller does not need to wait for the whole walk to finish.
+    /// The default implementation walks the tree using listdir_keys and is_dir;
+    /// stores capable of streaming listing natively may override it.
+    fn walk<'a>(&'a self, key: &Key) -> BoxStream<'a, Result<Key, Error>> {
+        let start: (Vec<Key>, VecDeque<Key>) = (vec![key.to_owned()], VecDeque::new());
+        futures::stream::unfold(start, move |(mut dirs, mut found)| async move {
+            loop {
+                if let Some(key) = found.pop_front() {
+                    match self.is_dir(&key).await {
+                        Ok(true) => dirs.push(key.clone()),
+                        Ok(false) => {}
+                        Err(e) => return Some((Err(e), (dirs, found))),
+                    }
+                    return Some((Ok(key), (dirs, found)));
+                }
+                let dir = dirs.pop()?;
+                match self.listdir_keys(&dir).await {
+                    Ok(keys) => found.extend(keys),
+                    Err(e) => return Some((Err(e), (dirs, found))),
+                }
+            }
+        })
+        .boxed()
+    }
+
     /// Make a directory
     async fn makedir(&self, key: &Key) -> Result<(), Error> {
         Err(Error::key_not_supported(key, &self.store_name()))
@@ -346,6 +629,12 @@ pub trait AsyncStore: Send + Sync {
     fn is_supported(&self, _key: &Key) -> bool {
         false
     }
+
+    /// Watch the changes of the key and the keys under it (see [StoreEvent]).
+    /// The default implementation is for stores that can't watch, the stream is empty.
+    async fn watch(&self, _key: &Key) -> Result<StoreEventStream, Error> {
+        Ok(futures::stream::empty().boxed())
+    }
 }
 
 #[cfg(feature = "async_store")]
@@ -413,6 +702,29 @@ impl<T: Store + std::marker::Sync> AsyncStore for AsyncStoreWrapper<T> {
         self.0.get_metadata(key)
     }
 
+    /// Stream the data using the reader of the wrapped store.
+    /// Reading from the wrapped store is blocking, as all the other operations of the wrapper.
+    async fn get_stream(&self, key: &Key) -> Result<(DataStream, Metadata, Option<u64>), Error> {
+        let metadata = self.0.get_metadata(key)?;
+        let (reader, size) = self.0.get_reader(key)?;
+        Ok((reader_stream(reader, key, &self.0.store_name()), metadata, size))
+    }
+
+    async fn get_range(&self, key: &Key, start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+        self.0.get_range(key, start, end)
+    }
+
+    /// Stream the range using the range reader of the wrapped store (blocking, see [AsyncStoreWrapper::get_stream])
+    async fn get_range_stream(
+        &self,
+        key: &Key,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<(DataStream, u64, u64), Error> {
+        let (reader, size, length) = self.0.get_range_reader(key, start, end)?;
+        Ok((reader_stream(reader, key, &self.0.store_name()), size, length))
+    }
+
     /// Store data and metadata.
     async fn set(&self, key: &Key, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
         self.0.set(key, data, metadata)
@@ -471,6 +783,10 @@ impl<T: Store + std::marker::Sync> AsyncStore for AsyncStoreWrapper<T> {
         self.0.listdir_keys_deep(key)
     }
 
+    async fn watch(&self, key: &Key) -> Result<StoreEventStream, Error> {
+        Ok(self.0.watch(key)?.into_stream())
+    }
+
     /// Make a directory
     async fn makedir(&self, key: &Key) -> Result<(), Error> {
         Err(Error::key_not_supported(key, &self.store_name()))
@@ -494,6 +810,7 @@ impl<T: Store + std::marker::Sync> AsyncStore for AsyncStoreWrapper<T> {
 
 /// Trivial store unable to store anything.
 /// Used e.g. in the environment as a default value when the store is not available.
+/// Data access fails with a StoreNotConfigured error.
 pub struct NoStore;
 
 impl Clone for NoStore {
@@ -502,10 +819,47 @@ impl Clone for NoStore {
     }
 }
 
-impl Store for NoStore {}
+impl Store for NoStore {
+    fn store_name(&self) -> String {
+        "No store".to_string()
+    }
+
+    fn get(&self, key: &Key) -> Result<(Vec<u8>, Metadata), Error> {
+        Err(Error::store_not_configured(key))
+    }
+
+    fn get_bytes(&self, key: &Key) -> Result<Vec<u8>, Error> {
+        Err(Error::store_not_configured(key))
+    }
+
+    fn get_metadata(&self, key: &Key) -> Result<Metadata, Error> {
+        Err(Error::store_not_configured(key))
+    }
+
+    fn set(&self, key: &Key, _data: &[u8], _metadata: &Metadata) -> Result<(), Error> {
+        Err(Error::store_not_configured(key))
+    }
+
+    fn set_metadata(&self, key: &Key, _metadata: &Metadata) -> Result<(), Error> {
+        Err(Error::store_not_configured(key))
+    }
+
+    fn remove(&self, key: &Key) -> Result<(), Error> {
+        Err(Error::store_not_configured(key))
+    }
+
+    fn removedir(&self, key: &Key) -> Result<(), Error> {
+        Err(Error::store_not_configured(key))
+    }
+
+    fn makedir(&self, key: &Key) -> Result<(), Error> {
+        Err(Error::store_not_configured(key))
+    }
+}
 
 /// Trivial store unable to store anything.
 /// Used e.g. in the environment as a default value when the store is not available.
+/// Data access fails with a StoreNotConfigured error.
 pub struct NoAsyncStore;
 
 impl Clone for NoAsyncStore {
@@ -517,12 +871,32 @@ impl Clone for NoAsyncStore {
 #[cfg(feature = "async_store")]
 #[async_trait]
 impl AsyncStore for NoAsyncStore {
+    fn store_name(&self) -> String {
+        "No store".to_string()
+    }
+
     async fn get(&self, key: &Key) -> Result<(Vec<u8>, Metadata), Error> {
-        Err(Error::key_not_found(key))
+        Err(Error::store_not_configured(key))
     }
 
-    async fn set_metadata(&self, key: &Key, metadata: &Metadata) -> Result<(), Error> {
-        Err(Error::key_not_supported(key, "NoAsyncStore"))
+    async fn set(&self, key: &Key, _data: &[u8], _metadata: &Metadata) -> Result<(), Error> {
+        Err(Error::store_not_configured(key))
+    }
+
+    async fn set_metadata(&self, key: &Key, _metadata: &Metadata) -> Result<(), Error> {
+        Err(Error::store_not_configured(key))
+    }
+
+    async fn remove(&self, key: &Key) -> Result<(), Error> {
+        Err(Error::store_not_configured(key))
+    }
+
+    async fn removedir(&self, key: &Key) -> Result<(), Error> {
+        Err(Error::store_not_configured(key))
+    }
+
+    async fn makedir(&self, key: &Key) -> Result<(), Error> {
+        Err(Error::store_not_configured(key))
     }
 }
 
@@ -534,6 +908,12 @@ pub struct FileStore {
 
 impl FileStore {
     const METADATA: &'static str = ".__metadata__";
+    /// Marker of the temporary files written by [FileStore::set], they are not listed as keys
+    const TEMPORARY: &'static str = ".__temporary__";
+    /// Field of the metadata file with the length and the hash of the data written together with it.
+    /// The data and the metadata are renamed into place one after the other, so after an interrupted write
+    /// they may come from different writes; this is detected by comparing the checksum with the data.
+    const DATA_CHECKSUM: &'static str = "__data_checksum__";
     pub fn new(path: &str, prefix: &Key) -> FileStore {
         FileStore {
             path: PathBuf::from(path),
@@ -552,6 +932,216 @@ impl FileStore {
         path.push(format!("{}{}", key, Self::METADATA));
         path
     }
+
+    /// Write a temporary file next to the path, to be moved into place by [FileStore::commit_temporary].
+    /// The temporary file is removed if the writing fails.
+    fn write_temporary(
+        &self,
+        key: &Key,
+        path: &Path,
+        write: impl FnOnce(&mut File) -> Result<(), Error>,
+    ) -> Result<PathBuf, Error> {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(
+            "{}{}_{}",
+            Self::TEMPORARY,
+            std::process::id(),
+            COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        let temporary = PathBuf::from(name);
+        let result = File::create(&temporary)
+            .map_err(|e| Error::key_write_io_error(key, &self.store_name(), &e))
+            .and_then(|mut file| {
+                write(&mut file)?;
+                file.sync_all()
+                    .map_err(|e| Error::key_write_io_error(key, &self.store_name(), &e))
+            });
+        match result {
+            Ok(()) => Ok(temporary),
+            Err(e) => {
+                let _ = std::fs::remove_file(&temporary);
+                Err(e)
+            }
+        }
+    }
+
+    /// Atomically replace the file at the path by the temporary file, which is removed if this fails.
+    /// The directory is synchronized, so that the rename itself survives a crash.
+    fn commit_temporary(&self, key: &Key, temporary: &Path, path: &Path) -> Result<(), Error> {
+        std::fs::rename(temporary, path).map_err(|e| {
+            let _ = std::fs::remove_file(temporary);
+            Error::key_write_io_error(key, &self.store_name(), &e)
+        })?;
+        // Directories can't be opened as files on some platforms (e.g. Windows), there the rename is not synchronized
+        #[cfg(unix)]
+        if let Some(directory) = path.parent() {
+            File::open(directory)
+                .and_then(|directory| directory.sync_all())
+                .map_err(|e| Error::key_write_io_error(key, &self.store_name(), &e))?;
+        }
+        Ok(())
+    }
+
+    /// Collect the paths (relative to the root) of the data files at or below the path
+    fn collect_files(root: &Path, path: &Path, files: &mut HashSet<PathBuf>) {
+        if path.is_dir() {
+            for entry in std::fs::read_dir(path).into_iter().flatten().flatten() {
+                Self::collect_files(root, &entry.path(), files);
+            }
+        } else if let Ok(path) = path.canonicalize() {
+            let is_data = path.file_name().is_some_and(|name| {
+                let name = name.to_string_lossy();
+                !name.ends_with(Self::METADATA) && !name.contains(Self::TEMPORARY)
+            });
+            if let (true, Ok(relative)) = (is_data, path.strip_prefix(root)) {
+                files.insert(relative.to_path_buf());
+            }
+        }
+    }
+
+    /// Checksum of the data stored in the metadata file (see [FileStore::DATA_CHECKSUM])
+    fn data_checksum(data: &[u8]) -> serde_json::Value {
+        serde_json::json!({
+            "length": data.len(),
+            "blake3": blake3::hash(data).to_hex().to_string(),
+        })
+    }
+
+    fn write_metadata(
+        &self,
+        key: &Key,
+        file: &mut File,
+        metadata: &Metadata,
+        checksum: Option<serde_json::Value>,
+    ) -> Result<(), Error> {
+        let mut value = match metadata {
+            Metadata::MetadataRecord(metadata) => serde_json::to_value(metadata)
+                .map_err(|e| Error::key_write_error(key, &self.store_name(), &e))?,
+            Metadata::LegacyMetadata(metadata) => metadata.clone(),
+        };
+        if let (Some(checksum), Some(fields)) = (checksum, value.as_object_mut()) {
+            fields.insert(Self::DATA_CHECKSUM.to_owned(), checksum);
+        }
+        serde_json::to_writer_pretty(file, &value)
+            .map_err(|e| Error::key_write_error(key, &self.store_name(), &e))
+    }
+
+    /// Read the metadata together with the checksum of the data (see [FileStore::DATA_CHECKSUM]) if present
+    fn read_metadata(&self, key: &Key) -> Result<(Metadata, Option<serde_json::Value>), Error> {
+        let path = self.confine(key, self.key_to_path_metadata(key))?;
+        if path.exists() {
+            let mut file =
+                File::open(path).map_err(|e| Error::key_read_io_error(key, &self.store_name(), &e))?;
+            let mut buffer = Vec::new();
+            file.read_to_end(&mut buffer)
+                .map_err(|e| Error::key_read_io_error(key, &self.store_name(), &e))?;
+            let mut value: serde_json::Value = serde_json::from_slice(&buffer).map_err(|_| {
+                Error::key_read_error(key, &self.store_name(), "Metadata parsing error")
+            })?;
+            let checksum = value
+                .as_object_mut()
+                .and_then(|fields| fields.remove(Self::DATA_CHECKSUM));
+            // TODO: fix metadata, e.g. add the key
+            let metadata = match serde_json::from_value::<MetadataRecord>(value.clone()) {
+                Ok(mut metadata) => {
+                    if metadata.updated().is_none() {
+                        metadata.updated = self.modified(key).unwrap_or_default();
+                    }
+                    Metadata::MetadataRecord(metadata)
+                }
+                Err(_) => Metadata::LegacyMetadata(value),
+            };
+            Ok((metadata, checksum))
+        } else {
+            let path = self.confine(key, self.key_to_path(key))?;
+            if path.exists() {
+                let mut metadata = self.default_metadata(key, path.is_dir());
+                metadata.updated = self.modified(key).unwrap_or_default();
+                Ok((Metadata::MetadataRecord(metadata), None))
+            } else {
+                Err(Error::key_not_found(key))
+            }
+        }
+    }
+
+    /// Modification time of the data file (RFC 3339), used when the metadata do not record
+    /// the updated timestamp, e.g. for files written outside of the store
+    fn modified(&self, key: &Key) -> Option<String> {
+        let path = self.confine(key, self.key_to_path(key)).ok()?;
+        let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+        Some(
+            chrono::DateTime::<chrono::Utc>::from(modified)
+                .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        )
+    }
+
+    /// Check that the data match the checksum read with the metadata.
+    /// Without the data at hand only the length of the data file is compared.
+    fn verify_checksum(
+        &self,
+        key: &Key,
+        checksum: Option<&serde_json::Value>,
+        data: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        let Some(checksum) = checksum else {
+            return Ok(());
+        };
+        let matches = match data {
+            Some(data) => *checksum == Self::data_checksum(data),
+            None => {
+                let length = std::fs::metadata(self.confine(key, self.key_to_path(key))?)
+                    .map_err(|e| Error::key_read_io_error(key, &self.store_name(), &e))?
+                    .len();
+                checksum.get("length").and_then(|length| length.as_u64()) == Some(length)
+            }
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err(Error::key_read_error(
+                key,
+                &self.store_name(),
+                "Data and metadata come from different writes (the last write was interrupted)",
+            ))
+        }
+    }
+
+    /// Check that a path resolved from the key stays inside the root directory of the store.
+    /// Keys are validated by the parser, but this is checked again before touching the filesystem:
+    /// the path must consist of plain names below the root (no `..` or absolute paths)
+    /// and the existing part of the path is canonicalized, so that symbolic links
+    /// pointing outside of the root are rejected too.
+    /// Escaping paths fail with a [crate::error::ErrorType::PermissionDenied] error.
+    fn confine(&self, key: &Key, path: PathBuf) -> Result<PathBuf, Error> {
+        let escapes = |detail: &str| {
+            Error::key_permission_denied(
+                key,
+                &self.store_name(),
+                &format!("path {} {}", path.display(), detail),
+            )
+        };
+        let plain = path.strip_prefix(&self.path).is_ok_and(|relative| {
+            relative
+                .components()
+                .all(|c| matches!(c, std::path::Component::Normal(_)))
+        });
+        if !plain {
+            return Err(escapes("is outside of the store root"));
+        }
+        if let Ok(root) = self.path.canonicalize() {
+            let existing = path
+                .ancestors()
+                .find(|p| p.symlink_metadata().is_ok())
+                .unwrap_or(self.path.as_path());
+            match existing.canonicalize() {
+                Ok(resolved) if resolved.starts_with(&root) => {}
+                Ok(_) => return Err(escapes("resolves outside of the store root")),
+                Err(e) => return Err(Error::key_read_io_error(key, &self.store_name(), &e)),
+            }
+        }
+        Ok(path)
+    }
 }
 
 impl Store for FileStore {
@@ -592,111 +1182,146 @@ impl Store for FileStore {
     ) -> Metadata {
         metadata
     }
+    /// Data with metadata not matching them (see [FileStore::DATA_CHECKSUM]) are an error
     fn get(&self, key: &Key) -> Result<(Vec<u8>, Metadata), Error> {
         let data = self.get_bytes(key)?;
-        match self.get_metadata(key) {
-            Ok(metadata) => Ok((data, metadata)),
+        match self.read_metadata(key) {
+            Ok((metadata, checksum)) => {
+                self.verify_checksum(key, checksum.as_ref(), Some(&data))?;
+                Ok((data, metadata))
+            }
             Err(_) => Ok((data, Metadata::MetadataRecord(MetadataRecord::new()))),
         }
     }
 
     fn get_bytes(&self, key: &Key) -> Result<Vec<u8>, Error> {
-        let path = self.key_to_path(key);
+        let path = self.confine(key, self.key_to_path(key))?;
         if path.exists() {
             let mut file =
-                File::open(path).map_err(|e| Error::key_read_error(key, &self.store_name(), &e))?;
+                File::open(path).map_err(|e| Error::key_read_io_error(key, &self.store_name(), &e))?;
             let mut buffer = Vec::new();
             file.read_to_end(&mut buffer)
-                .map_err(|e| Error::key_read_error(key, &self.store_name(), &e))?;
+                .map_err(|e| Error::key_read_io_error(key, &self.store_name(), &e))?;
             Ok(buffer)
         } else {
             Err(Error::key_not_found(key))
         }
     }
 
-    fn get_metadata(&self, key: &Key) -> Result<Metadata, Error> {
-        let path = self.key_to_path_metadata(key);
-        if path.exists() {
-            let mut file =
-                File::open(path).map_err(|e| Error::key_read_error(key, &self.store_name(), &e))?;
-            let mut buffer = Vec::new();
-            file.read_to_end(&mut buffer)
-                .map_err(|e| Error::key_read_error(key, &self.store_name(), &e))?;
-            if let Ok(metadata) = serde_json::from_reader(&buffer[..]) {
-                // TODO: fix metadata, e.g. add the key
-                return Ok(Metadata::MetadataRecord(metadata));
-            }
-            if let Ok(metadata) = serde_json::from_reader(&buffer[..]) {
-                return Ok(Metadata::LegacyMetadata(metadata));
-            }
-            Err(Error::key_read_error(
-                key,
-                &self.store_name(),
-                "Metadata parsing error",
-            ))
-        } else {
-            let path = self.key_to_path(key);
-            if path.exists() {
-                let metadata = self.default_metadata(key, path.is_dir());   
-                Ok(Metadata::MetadataRecord(metadata))
-            } else {   
-                Err(Error::key_not_found(key))
-            }
+    fn get_reader(&self, key: &Key) -> Result<(Box<dyn Read + Send>, Option<u64>), Error> {
+        let path = self.confine(key, self.key_to_path(key))?;
+        if !path.exists() {
+            return Err(Error::key_not_found(key));
+        }
+        let file =
+            File::open(path).map_err(|e| Error::key_read_io_error(key, &self.store_name(), &e))?;
+        let size = file.metadata().ok().map(|m| m.len());
+        Ok((Box::new(file), size))
+    }
+
+    fn get_range(&self, key: &Key, start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+        let (mut reader, size, length) = self.get_range_reader(key, start, end)?;
+        let mut buffer = Vec::with_capacity(size as usize);
+        reader
+            .read_to_end(&mut buffer)
+            .map_err(|e| Error::key_read_io_error(key, &self.store_name(), &e))?;
+        Ok((buffer, length))
+    }
+
+    /// Reader of the range: the file is positioned at the start and limited to the range,
+    /// nothing is read in advance.
+    fn get_range_reader(
+        &self,
+        key: &Key,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<(Box<dyn Read + Send>, u64, u64), Error> {
+        let path = self.confine(key, self.key_to_path(key))?;
+        if !path.exists() {
+            return Err(Error::key_not_found(key));
         }
+        let read_error = |e: std::io::Error| Error::key_read_io_error(key, &self.store_name(), &e);
+        let mut file = File::open(path).map_err(read_error)?;
+        let length = file.metadata().map_err(read_error)?.len();
+        let (start, stop) = range_bounds(key, start, end, length)?;
+        file.seek(SeekFrom::Start(start)).map_err(read_error)?;
+        Ok((Box::new(file.take(stop - start)), stop - start, length))
+    }
+
+    /// Metadata not matching the length of the data (see [FileStore::DATA_CHECKSUM]) are an error
+    fn get_metadata(&self, key: &Key) -> Result<Metadata, Error> {
+        let (metadata, checksum) = self.read_metadata(key)?;
+        self.verify_checksum(key, checksum.as_ref(), None)?;
+        Ok(metadata)
     }
 
+    /// The data and the metadata are first written into temporary files, which are then renamed into place,
+    /// so that readers never see a partially written file (e.g. when the process crashes during the write).
+    /// The metadata carry a checksum of the data, which detects a crash between the two renames.
+    /// If the updated timestamp is not known, it is set to the time of the write.
     fn set(&self, key: &Key, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
-        let path = self.key_to_path(key);
-        let mut file =
-            File::create(path).map_err(|e| Error::key_write_error(key, &self.store_name(), &e))?;
-        file.write_all(data)
-            .map_err(|e| Error::key_write_error(key, &self.store_name(), &e))?;
-        self.set_metadata(key, metadata)?;
-        Ok(())
+        let path = self.confine(key, self.key_to_path(key))?;
+        let metadata_path = self.confine(key, self.key_to_path_metadata(key))?;
+        let mut metadata = metadata.to_owned();
+        metadata.with_updated_now_if_unknown();
+        let metadata = &metadata;
+        let data_temporary = self.write_temporary(key, &path, |file| {
+            file.write_all(data)
+                .map_err(|e| Error::key_write_io_error(key, &self.store_name(), &e))
+        })?;
+        let metadata_temporary = self
+            .write_temporary(key, &metadata_path, |file| {
+                self.write_metadata(key, file, metadata, Some(Self::data_checksum(data)))
+            })
+            .inspect_err(|_| {
+                let _ = std::fs::remove_file(&data_temporary);
+            })?;
+        self.commit_temporary(key, &data_temporary, &path)
+            .inspect_err(|_| {
+                let _ = std::fs::remove_file(&metadata_temporary);
+            })?;
+        self.commit_temporary(key, &metadata_temporary, &metadata_path)
     }
 
     fn set_metadata(&self, key: &Key, metadata: &Metadata) -> Result<(), Error> {
-        let path = self.key_to_path_metadata(key);
-        let file =
-            File::create(path).map_err(|e| Error::key_write_error(key, &self.store_name(), &e))?;
-        match metadata {
-            Metadata::MetadataRecord(metadata) => serde_json::to_writer_pretty(file, metadata)
-                .map_err(|e| Error::key_write_error(key, &self.store_name(), &e))?,
-            Metadata::LegacyMetadata(metadata) => serde_json::to_writer_pretty(file, metadata)
-                .map_err(|e| Error::key_write_error(key, &self.store_name(), &e))?,
-        };
-        Ok(())
+        let path = self.confine(key, self.key_to_path_metadata(key))?;
+        // The data are not changed, so the checksum of the data is kept
+        let checksum = self.read_metadata(key).ok().and_then(|(_, checksum)| checksum);
+        let temporary = self.write_temporary(key, &path, |file| {
+            self.write_metadata(key, file, metadata, checksum)
+        })?;
+        self.commit_temporary(key, &temporary, &path)
     }
 
     fn remove(&self, key: &Key) -> Result<(), Error> {
-        let path = self.key_to_path(key);
+        let path = self.confine(key, self.key_to_path(key))?;
         if path.exists() {
             std::fs::remove_file(path)
-                .map_err(|e| Error::key_write_error(key, &self.store_name(), &e))?;
+                .map_err(|e| Error::key_write_io_error(key, &self.store_name(), &e))?;
         }
-        let matadata_path = self.key_to_path_metadata(key);
+        let matadata_path = self.confine(key, self.key_to_path_metadata(key))?;
         if matadata_path.exists() {
             std::fs::remove_file(matadata_path)
-                .map_err(|e| Error::key_write_error(key, &self.store_name(), &e))?;
+                .map_err(|e| Error::key_write_io_error(key, &self.store_name(), &e))?;
         }
         Ok(())
     }
 
     fn removedir(&self, key: &Key) -> Result<(), Error> {
-        let path = self.key_to_path(key);
+        let path = self.confine(key, self.key_to_path(key))?;
         if path.exists() {
             std::fs::remove_dir_all(path)
-                .map_err(|e| Error::key_write_error(key, &self.store_name(), &e))?;
+                .map_err(|e| Error::key_write_io_error(key, &self.store_name(), &e))?;
         }
         Ok(())
     }
 
     fn contains(&self, key: &Key) -> Result<bool, Error> {
-        let path = self.key_to_path(key);
+        let path = self.confine(key, self.key_to_path(key))?;
         if path.exists() {
             return Ok(true);
         }
-        let metadata_path = self.key_to_path_metadata(key);
+        let metadata_path = self.confine(key, self.key_to_path_metadata(key))?;
         if metadata_path.exists() {
             return Ok(true);
         }
@@ -704,23 +1329,23 @@ impl Store for FileStore {
     }
 
     fn is_dir(&self, key: &Key) -> Result<bool, Error> {
-        let path = self.key_to_path(key);
+        let path = self.confine(key, self.key_to_path(key))?;
         return Ok(path.is_dir());
     }
 
     fn listdir(&self, key: &Key) -> Result<Vec<String>, Error> {
-        let path = self.key_to_path(key);
+        let path = self.confine(key, self.key_to_path(key))?;
         if path.is_dir() {
             let dir = path
                 .read_dir()
-                .map_err(|e| Error::key_read_error(key, &self.store_name(), &e))?;
+                .map_err(|e| Error::key_read_io_error(key, &self.store_name(), &e))?;
             let names = dir
                 .flat_map(|entry| {
                     entry
                         .ok()
                         .map(|e| e.file_name().to_string_lossy().to_string())
                 })
-                .filter(|name| !name.ends_with(Self::METADATA))
+                .filter(|name| !name.ends_with(Self::METADATA) && !name.contains(Self::TEMPORARY))
                 .collect();
             return Ok(names);
         }
@@ -728,6 +1353,10 @@ impl Store for FileStore {
             if path.exists() {
                 return Ok(vec![]);
             }
+            else if self.prefix.has_key_prefix(key) {
+                // The root of the store (or above) does not exist yet, i.e. the store is empty
+                return Ok(vec![]);
+            }
             else{
                 return Err(Error::key_not_found(key));
             }   
@@ -735,23 +1364,98 @@ impl Store for FileStore {
     }
 
     fn makedir(&self, key: &Key) -> Result<(), Error> {
-        let path = self.key_to_path(key);
+        let path = self.confine(key, self.key_to_path(key))?;
         std::fs::create_dir_all(path)
-            .map_err(|e| Error::key_write_error(key, &self.store_name(), &e))?;
+            .map_err(|e| Error::key_write_io_error(key, &self.store_name(), &e))?;
         Ok(())
     }
 
     fn is_supported(&self, key: &Key) -> bool {
         key.has_key_prefix(&self.prefix)
-            && (!key
-                .filename()
-                .is_some_and(|file_name| file_name.name.ends_with(Self::METADATA)))
+            && (!key.filename().is_some_and(|file_name| {
+                file_name.name.ends_with(Self::METADATA) || file_name.name.contains(Self::TEMPORARY)
+            }))
+    }
+
+    /// Watch the file system (see [notify]); a change of the metadata is reported as a modification of the key,
+    /// as well as the creation of a file by [FileStore::set]. The watched key must exist.
+    fn watch(&self, key: &Key) -> Result<StoreWatcher, Error> {
+        use notify::event::ModifyKind;
+        use notify::{EventKind, RecursiveMode, Watcher};
+
+        let path = self.confine(key, self.key_to_path(key))?;
+        let watch_error = |e: notify::Error| Error::key_read_error(key, &self.store_name(), &e);
+        let root = self
+            .path
+            .canonicalize()
+            .map_err(|e| Error::key_read_io_error(key, &self.store_name(), &e))?;
+        // Files renamed into place are created or modified depending on whether they existed before
+        let mut existing = HashSet::new();
+        Self::collect_files(&root, &path, &mut existing);
+        let (sender, store_watcher) = StoreWatcher::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            for path in event.paths.iter() {
+                let Ok(relative) = path.strip_prefix(&root) else {
+                    continue;
+                };
+                let mut names = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy().to_string())
+                    .collect::<Vec<_>>();
+                if names.last().is_some_and(|name| name.contains(Self::TEMPORARY)) {
+                    continue;
+                }
+                let metadata = names
+                    .last()
+                    .and_then(|name| name.strip_suffix(Self::METADATA))
+                    .map(|name| name.to_owned());
+                if let Some(name) = metadata.as_ref() {
+                    *names.last_mut().unwrap() = name.clone();
+                }
+                let changed = names.iter().fold(Key::new(), |key, name| key.join(name));
+                let store_event = match event.kind {
+                    // The removal of the metadata accompanies the removal of the data
+                    EventKind::Remove(_) if metadata.is_some() => continue,
+                    EventKind::Create(_) | EventKind::Modify(_) if metadata.is_some() => {
+                        StoreEvent::Modified(changed)
+                    }
+                    EventKind::Create(_) => {
+                        existing.insert(relative.to_path_buf());
+                        StoreEvent::Created(changed)
+                    }
+                    // Files are written by renaming a temporary file into place (see [FileStore::set])
+                    EventKind::Modify(ModifyKind::Name(_)) if !path.exists() => {
+                        existing.remove(relative);
+                        StoreEvent::Removed(changed)
+                    }
+                    EventKind::Modify(ModifyKind::Name(_)) if existing.insert(relative.to_path_buf()) => {
+                        StoreEvent::Created(changed)
+                    }
+                    EventKind::Modify(_) => StoreEvent::Modified(changed),
+                    EventKind::Remove(_) => {
+                        existing.remove(relative);
+                        StoreEvent::Removed(changed)
+                    }
+                    _ => continue,
+                };
+                let _ = sender.try_send(store_event);
+            }
+        })
+        .map_err(watch_error)?;
+        watcher
+            .watch(&path, RecursiveMode::Recursive)
+            .map_err(watch_error)?;
+        Ok(store_watcher.with_guard(watcher))
     }
 }
 
 pub struct MemoryStore {
     data: Arc<RwLock<std::collections::HashMap<Key, (Vec<u8>, Metadata)>>>,
     prefix: Key,
+    watchers: Arc<RwLock<Vec<(Key, tokio::sync::mpsc::Sender<StoreEvent>)>>>,
 }
 
 impl MemoryStore {
@@ -759,8 +1463,19 @@ impl MemoryStore {
         MemoryStore {
             data: Arc::new(RwLock::new(std::collections::HashMap::new())),
             prefix: prefix.to_owned(),
+            watchers: Arc::new(RwLock::new(Vec::new())),
         }
     }
+
+    /// Send the event to the watchers of the key, dropping the watchers that are gone
+    fn notify(&self, event: StoreEvent) {
+        use tokio::sync::mpsc::error::TrySendError;
+        let mut watchers = self.watchers.write().unwrap();
+        watchers.retain(|(key, sender)| {
+            !event.key().has_key_prefix(key)
+                || !matches!(sender.try_send(event.clone()), Err(TrySendError::Closed(_)))
+        });
+    }
 }
 
 impl Store for MemoryStore {
@@ -821,34 +1536,52 @@ impl Store for MemoryStore {
     }
 
     fn set(&self, key: &Key, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
-        let mut mem = self.data.write().unwrap();
-
-        mem.insert(key.to_owned(), (data.to_owned(), metadata.to_owned()));
+        let mut metadata = metadata.to_owned();
+        metadata.with_updated_now_if_unknown();
+        let previous = {
+            let mut mem = self.data.write().unwrap();
+            mem.insert(key.to_owned(), (data.to_owned(), metadata))
+        };
+        self.notify(match previous {
+            Some(_) => StoreEvent::Modified(key.to_owned()),
+            None => StoreEvent::Created(key.to_owned()),
+        });
         Ok(())
     }
 
     fn set_metadata(&self, key: &Key, metadata: &Metadata) -> Result<(), Error> {
         let res = self.get(key)?;
-        let mut mem = self.data.write().unwrap();
-        mem.insert(key.to_owned(), (res.0, metadata.to_owned()));
+        {
+            let mut mem = self.data.write().unwrap();
+            mem.insert(key.to_owned(), (res.0, metadata.to_owned()));
+        }
+        self.notify(StoreEvent::Modified(key.to_owned()));
         Ok(())
     }
 
     fn remove(&self, key: &Key) -> Result<(), Error> {
-        let mut mem = self.data.write().unwrap();
-        mem.remove(key);
+        let removed = self.data.write().unwrap().remove(key);
+        if removed.is_some() {
+            self.notify(StoreEvent::Removed(key.to_owned()));
+        }
         Ok(())
     }
 
     fn removedir(&self, key: &Key) -> Result<(), Error> {
-        let mut mem = self.data.write().unwrap();
-        let keys = mem
-            .keys()
-            .filter(|k| k.has_key_prefix(key))
-            .cloned()
-            .collect::<Vec<_>>();
+        let keys = {
+            let mut mem = self.data.write().unwrap();
+            let keys = mem
+                .keys()
+                .filter(|k| k.has_key_prefix(key))
+                .cloned()
+                .collect::<Vec<_>>();
+            for k in keys.iter() {
+                mem.remove(k);
+            }
+            keys
+        };
         for k in keys {
-            mem.remove(&k);
+            self.notify(StoreEvent::Removed(k));
         }
         Ok(())
     }
@@ -881,17 +1614,23 @@ impl Store for MemoryStore {
 
     fn listdir(&self, key: &Key) -> Result<Vec<String>, Error> {
         let keys = self.listdir_keys(key)?;
-        Ok(keys.iter().map(|x| x.to_string()).collect())
+        Ok(keys
+            .iter()
+            .filter_map(|x| x.filename().map(|name| name.to_string()))
+            .collect())
     }
 
+    /// Keys directly inside the directory, including the (implicit) subdirectories of deeper keys
     fn listdir_keys(&self, key: &Key) -> Result<Vec<Key>, Error> {
         let mem = self.data.read().unwrap();
         let n = key.len() + 1;
-        let keys = mem
+        let mut keys = mem
             .keys()
-            .filter(|k| k.has_key_prefix(key) && k.len() == n)
-            .cloned()
+            .filter(|k| k.has_key_prefix(key) && k.len() >= n)
+            .map(|k| Key(k.0[..n].to_vec()))
             .collect::<Vec<_>>();
+        keys.sort_by_key(|k| k.encode());
+        keys.dedup();
         Ok(keys)
     }
 
@@ -913,6 +1652,13 @@ impl Store for MemoryStore {
     fn is_supported(&self, _key: &Key) -> bool {
         true
     }
+
+    /// The events are emitted by the modifications of the store
+    fn watch(&self, key: &Key) -> Result<StoreWatcher, Error> {
+        let (sender, watcher) = StoreWatcher::channel();
+        self.watchers.write().unwrap().push((key.to_owned(), sender));
+        Ok(watcher)
+    }
 }
 
 /// Store that routes requests to multiple stores.
@@ -1006,8 +1752,29 @@ impl Store for StoreRouter {
             })
     }
 
-    fn set(&self, key: &Key, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
-        self.find_store(key).map_or(
+    fn get_reader(&self, key: &Key) -> Result<(Box<dyn Read + Send>, Option<u64>), Error> {
+        self.find_store(key)
+            .map_or(Err(Error::key_not_found(key)), |store| store.get_reader(key))
+    }
+
+    fn get_range(&self, key: &Key, start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+        self.find_store(key).map_or(Err(Error::key_not_found(key)), |store| {
+            store.get_range(key, start, end)
+        })
+    }
+    fn get_range_reader(
+        &self,
+        key: &Key,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<(Box<dyn Read + Send>, u64, u64), Error> {
+        self.find_store(key).map_or(Err(Error::key_not_found(key)), |store| {
+            store.get_range_reader(key, start, end)
+        })
+    }
+
+    fn set(&self, key: &Key, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
+        self.find_store(key).map_or(
             Err(Error::key_not_supported(key, "store router")),
             |store| store.set(key, data, metadata),
         )
@@ -1057,7 +1824,10 @@ impl Store for StoreRouter {
 
     fn keys(&self) -> Result<Vec<Key>, Error> {
         let mut keys = self.listdir_keys_deep(&self.key_prefix())?;
-        keys.push(self.key_prefix().to_owned());
+        // The root of the store is not a key on its own
+        if !self.key_prefix().is_empty() {
+            keys.push(self.key_prefix().to_owned());
+        }
         Ok(keys)
     }
 
@@ -1068,12 +1838,14 @@ impl Store for StoreRouter {
                 let names = store.listdir(&key)?;
                 list.extend(names);
             }
-            if store.key_prefix().has_key_prefix(key) {
+            let prefix = store.key_prefix();
+            if prefix.len() > key.len() && prefix.has_key_prefix(key) {
                 // key is a prefix of store prefix, but smaller - hence it is a directory
-                list.push(store.key_prefix()[key.len()].to_string());
+                list.push(prefix[key.len()].to_string());
             }
         }
-
+        let mut seen = HashSet::new();
+        list.retain(|name| seen.insert(name.clone()));
         Ok(list)
     }
 
@@ -1105,6 +1877,220 @@ impl Store for StoreRouter {
         self.find_store(key)
             .map_or(false, |store| store.is_supported(key))
     }
+
+    fn watch(&self, key: &Key) -> Result<StoreWatcher, Error> {
+        self.find_store(key)
+            .ok_or_else(|| Error::key_not_supported(key, &self.store_name()))?
+            .watch(key)
+    }
+}
+
+/// Store expanding key aliases, shortcuts for longer locations in the wrapped store.
+/// A key starting with an alias element `~name` (written as `~~name` in a query, see [Key::encode_name])
+/// is resolved by replacing the alias element with the prefix configured for the alias,
+/// e.g. `~~data/table.csv` may stand for `projects/2024/data/table.csv`.
+/// Aliases are opt-in and purely a mapping of keys - they have nothing to do with home directories
+/// and never access the file system. Other keys are passed to the wrapped store unchanged.
+pub struct AliasStore<S: Store> {
+    inner: S,
+    aliases: BTreeMap<String, Key>,
+}
+
+impl<S: Store> AliasStore<S> {
+    pub fn new(inner: S) -> Self {
+        AliasStore {
+            inner,
+            aliases: BTreeMap::new(),
+        }
+    }
+
+    /// Add an alias (without the leading `~`) of a key prefix.
+    /// The alias must be a non-empty name of ASCII letters, digits and `_`,
+    /// the prefix must be a non-empty key not starting with an alias.
+    pub fn with_alias(mut self, alias: &str, prefix: &Key) -> Result<Self, Error> {
+        if alias.is_empty() || !alias.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(Error::general_error(format!(
+                "Invalid key alias '{}': only ASCII letters, digits and '_' are allowed",
+                alias
+            )));
+        }
+        if prefix.is_empty() || Self::alias_name(prefix).is_some() {
+            return Err(Error::general_error(format!(
+                "Invalid prefix '{}' of the key alias '{}': the prefix must be a non-empty key without an alias",
+                prefix, alias
+            )));
+        }
+        self.aliases.insert(alias.to_owned(), prefix.clone());
+        Ok(self)
+    }
+
+    /// The wrapped store
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Name of the alias the key starts with, if any
+    fn alias_name(key: &Key) -> Option<String> {
+        let first = key.iter().next()?;
+        Key::decode_name(&first.name)
+            .ok()?
+            .strip_prefix('~')
+            .map(|alias| alias.to_owned())
+    }
+
+    /// Expand the alias at the beginning of the key; an unknown alias is an error
+    pub fn resolve_key(&self, key: &Key) -> Result<Key, Error> {
+        match Self::alias_name(key) {
+            Some(alias) => {
+                let prefix = self.aliases.get(&alias).ok_or_else(|| {
+                    Error::new(
+                        ErrorType::KeyNotFound,
+                        format!("Unknown key alias '~{}' in key '{}'", alias, key),
+                    )
+                    .with_key(key)
+                })?;
+                let mut resolved = prefix.clone();
+                resolved.0.extend(key.iter().skip(1).cloned());
+                Ok(resolved)
+            }
+            None => Ok(key.clone()),
+        }
+    }
+}
+
+impl<S: Store> Store for AliasStore<S> {
+    fn store_name(&self) -> String {
+        format!("{} with key aliases", self.inner.store_name())
+    }
+
+    fn key_prefix(&self) -> Key {
+        self.inner.key_prefix()
+    }
+
+    fn default_metadata(&self, key: &Key, is_dir: bool) -> MetadataRecord {
+        let key = self.resolve_key(key).unwrap_or_else(|_| key.clone());
+        self.inner.default_metadata(&key, is_dir)
+    }
+
+    fn finalize_metadata(
+        &self,
+        metadata: Metadata,
+        key: &Key,
+        data: &[u8],
+        update: bool,
+    ) -> Metadata {
+        let key = self.resolve_key(key).unwrap_or_else(|_| key.clone());
+        self.inner.finalize_metadata(metadata, &key, data, update)
+    }
+
+    fn finalize_metadata_empty(
+        &self,
+        metadata: Metadata,
+        key: &Key,
+        is_dir: bool,
+        update: bool,
+    ) -> Metadata {
+        let key = self.resolve_key(key).unwrap_or_else(|_| key.clone());
+        self.inner
+            .finalize_metadata_empty(metadata, &key, is_dir, update)
+    }
+
+    fn get(&self, key: &Key) -> Result<(Vec<u8>, Metadata), Error> {
+        self.inner.get(&self.resolve_key(key)?)
+    }
+
+    fn get_bytes(&self, key: &Key) -> Result<Vec<u8>, Error> {
+        self.inner.get_bytes(&self.resolve_key(key)?)
+    }
+
+    fn get_metadata(&self, key: &Key) -> Result<Metadata, Error> {
+        self.inner.get_metadata(&self.resolve_key(key)?)
+    }
+
+    fn get_reader(&self, key: &Key) -> Result<(Box<dyn Read + Send>, Option<u64>), Error> {
+        self.inner.get_reader(&self.resolve_key(key)?)
+    }
+
+    fn get_range(&self, key: &Key, start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+        self.inner.get_range(&self.resolve_key(key)?, start, end)
+    }
+    fn get_range_reader(
+        &self,
+        key: &Key,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<(Box<dyn Read + Send>, u64, u64), Error> {
+        self.inner.get_range_reader(&self.resolve_key(key)?, start, end)
+    }
+
+    fn set(&self, key: &Key, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
+        self.inner.set(&self.resolve_key(key)?, data, metadata)
+    }
+
+    fn set_metadata(&self, key: &Key, metadata: &Metadata) -> Result<(), Error> {
+        self.inner.set_metadata(&self.resolve_key(key)?, metadata)
+    }
+
+    fn remove(&self, key: &Key) -> Result<(), Error> {
+        self.inner.remove(&self.resolve_key(key)?)
+    }
+
+    fn removedir(&self, key: &Key) -> Result<(), Error> {
+        self.inner.removedir(&self.resolve_key(key)?)
+    }
+
+    fn contains(&self, key: &Key) -> Result<bool, Error> {
+        self.inner.contains(&self.resolve_key(key)?)
+    }
+
+    fn is_dir(&self, key: &Key) -> Result<bool, Error> {
+        self.inner.is_dir(&self.resolve_key(key)?)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>, Error> {
+        self.inner.keys()
+    }
+
+    fn listdir(&self, key: &Key) -> Result<Vec<String>, Error> {
+        self.inner.listdir(&self.resolve_key(key)?)
+    }
+
+    fn listdir_keys(&self, key: &Key) -> Result<Vec<Key>, Error> {
+        self.inner.listdir_keys(&self.resolve_key(key)?)
+    }
+
+    fn listdir_keys_deep(&self, key: &Key) -> Result<Vec<Key>, Error> {
+        self.inner.listdir_keys_deep(&self.resolve_key(key)?)
+    }
+
+    fn makedir(&self, key: &Key) -> Result<(), Error> {
+        self.inner.makedir(&self.resolve_key(key)?)
+    }
+
+    fn is_supported(&self, key: &Key) -> bool {
+        self.resolve_key(key)
+            .map_or(false, |key| self.inner.is_supported(&key))
+    }
+
+    /// The events of a key watched through an alias carry the keys starting with the alias
+    fn watch(&self, key: &Key) -> Result<StoreWatcher, Error> {
+        let resolved = self.resolve_key(key)?;
+        let watcher = self.inner.watch(&resolved)?;
+        let (Some(alias), Some(prefix)) = (
+            key.iter().next().cloned(),
+            Self::alias_name(key).and_then(|name| self.aliases.get(&name).cloned()),
+        ) else {
+            return Ok(watcher);
+        };
+        Ok(watcher.with_map(move |event| {
+            let changed = event.key();
+            changed.has_key_prefix(&prefix).then(|| {
+                let mut outer = Key(vec![alias.clone()]);
+                outer.0.extend(changed.iter().skip(prefix.len()).cloned());
+                event.with_key(outer)
+            })
+        }))
+    }
 }
 
 /// Asunchronous store that routes requests to multiple (asynchronous) stores.
@@ -1209,6 +2195,34 @@ impl AsyncStore for AsyncStoreRouter {
         }
     }
 
+    async fn get_stream(&self, key: &Key) -> Result<(DataStream, Metadata, Option<u64>), Error> {
+        if let Some(store) = self.find_store(key) {
+            store.get_stream(key).await
+        } else {
+            Err(Error::key_not_found(key))
+        }
+    }
+
+    async fn get_range(&self, key: &Key, start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+        if let Some(store) = self.find_store(key) {
+            store.get_range(key, start, end).await
+        } else {
+            Err(Error::key_not_found(key))
+        }
+    }
+    async fn get_range_stream(
+        &self,
+        key: &Key,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<(DataStream, u64, u64), Error> {
+        if let Some(store) = self.find_store(key) {
+            store.get_range_stream(key, start, end).await
+        } else {
+            Err(Error::key_not_found(key))
+        }
+    }
+
     /// Store data and metadata.
     async fn set(&self, key: &Key, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
         if let Some(store) = self.find_store(key) {
@@ -1276,7 +2290,10 @@ impl AsyncStore for AsyncStoreRouter {
     /// List or iterator of all keys
     async fn keys(&self) -> Result<Vec<Key>, Error> {
         let mut keys = self.listdir_keys_deep(&self.key_prefix()).await?;
-        keys.push(self.key_prefix().to_owned());
+        // The root of the store is not a key on its own
+        if !self.key_prefix().is_empty() {
+            keys.push(self.key_prefix().to_owned());
+        }
         Ok(keys)
     }
 
@@ -1290,12 +2307,14 @@ impl AsyncStore for AsyncStoreRouter {
                 let names = store.listdir(&key).await?;
                 list.extend(names);
             }
-            if store.key_prefix().has_key_prefix(key) {
+            let prefix = store.key_prefix();
+            if prefix.len() > key.len() && prefix.has_key_prefix(key) {
                 // key is a prefix of store prefix, but smaller - hence it is a directory
-                list.push(store.key_prefix()[key.len()].to_string());
+                list.push(prefix[key.len()].to_string());
             }
         }
-
+        let mut seen = HashSet::new();
+        list.retain(|name| seen.insert(name.clone()));
         Ok(list)
     }
 
@@ -1349,38 +2368,1973 @@ impl AsyncStore for AsyncStoreRouter {
             false
         }
     }
+
+    async fn watch(&self, key: &Key) -> Result<StoreEventStream, Error> {
+        if let Some(store) = self.find_store(key) {
+            store.watch(key).await
+        } else {
+            Err(Error::key_not_supported(key, "store router"))
+        }
+    }
 }
 
-// Unittests
-#[cfg(test)]
-mod tests {
-    //    use crate::query::Key;
+/// Default number of buffered bytes triggering a flush of a [BatchingStore]
+#[cfg(feature = "async_store")]
+pub const DEFAULT_BATCH_MAX_BYTES: usize = 1024 * 1024;
 
-    use super::*;
+/// Default age of the oldest buffered write triggering a flush of a [BatchingStore]
+#[cfg(feature = "async_store")]
+pub const DEFAULT_BATCH_MAX_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
 
-    use crate::parse::parse_key;
+/// Buffered write: data (None for a metadata-only write) and metadata
+#[cfg(feature = "async_store")]
+struct PendingWrite {
+    key: Key,
+    data: Option<Vec<u8>>,
+    metadata: Metadata,
+}
 
-    #[test]
-    fn test_simple_store() -> Result<(), Error> {
-        let store = MemoryStore::new(&Key::new());
-        let key = parse_key("a/b/c").unwrap();
-        let data = b"test data".to_vec();
-        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+#[cfg(feature = "async_store")]
+#[derive(Default)]
+struct PendingWrites {
+    writes: Vec<PendingWrite>,
+    bytes: usize,
+    since: Option<std::time::Instant>,
+}
 
-        assert!(!store.contains(&key)?);
-        assert!(store.keys().unwrap().is_empty());
-        assert!(!store.is_dir(&parse_key("a/b")?)?);
+#[cfg(feature = "async_store")]
+impl PendingWrites {
+    fn find(&self, key: &Key) -> Option<&PendingWrite> {
+        self.writes.iter().find(|w| &w.key == key)
+    }
+}
 
-        store.set(&key, &data, &metadata)?;
-        assert!(store.contains(&key)?);
-        assert!(store.keys()?.contains(&key));
-        assert!(store.is_dir(&parse_key("a/b")?)?);
-        assert_eq!(store.keys().unwrap().len(), 1);
+/// Async store wrapper buffering the writes and passing them to the inner store in batches,
+/// to avoid a round-trip to the inner store for each of many small writes.
+///
+/// Writes ([AsyncStore::set] and [AsyncStore::set_metadata]) are kept in memory and flushed
+/// when the buffered data exceed `max_bytes`, when the oldest buffered write is older
+/// than `max_delay` (checked when writing, there is no background timer),
+/// or by an explicit [BatchingStore::flush]. Reads see the buffered writes;
+/// directory operations (listing, [AsyncStore::is_dir], [AsyncStore::removedir], ...)
+/// flush the buffer first and are delegated to the inner store.
+///
+/// Durability: until flushed, the writes exist only in the memory of the process.
+/// They are lost if the process ends or crashes before the flush, and a successful `set`
+/// does not mean that the data are persisted. Dropping the store does not flush it;
+/// call [BatchingStore::flush] whenever the data need to be durable.
+/// Errors of the inner store are reported by the write (or flush) triggering the flush.
+/// A write returning an error has not been accepted: it is neither buffered nor written,
+/// while the buffered writes that could not be flushed stay buffered.
+#[cfg(feature = "async_store")]
+pub struct BatchingStore<S: AsyncStore> {
+    inner: S,
+    pending: tokio::sync::Mutex<PendingWrites>,
+    max_bytes: usize,
+    max_delay: std::time::Duration,
+}
 
-        let (data2, _metadata2) = store.get(&key).unwrap();
-        assert_eq!(data, data2);
-        store.remove(&key).unwrap();
-        assert!(!store.contains(&key)?);
+#[cfg(feature = "async_store")]
+impl<S: AsyncStore> BatchingStore<S> {
+    pub fn new(inner: S) -> Self {
+        BatchingStore {
+            inner,
+            pending: tokio::sync::Mutex::new(PendingWrites::default()),
+            max_bytes: DEFAULT_BATCH_MAX_BYTES,
+            max_delay: DEFAULT_BATCH_MAX_DELAY,
+        }
+    }
+
+    /// Flush when the buffered data exceed the size
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Flush when the oldest buffered write is older than the delay
+    pub fn with_max_delay(mut self, max_delay: std::time::Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// The wrapped store
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Number of buffered writes not flushed yet
+    pub async fn pending(&self) -> usize {
+        self.pending.lock().await.writes.len()
+    }
+
+    /// Write all the buffered writes to the inner store.
+    /// On an error, the failed write and the writes after it stay buffered.
+    pub async fn flush(&self) -> Result<(), Error> {
+        let mut pending = self.pending.lock().await;
+        Self::flush_pending(&self.inner, &mut pending).await
+    }
+
+    async fn flush_pending(inner: &S, pending: &mut PendingWrites) -> Result<(), Error> {
+        while !pending.writes.is_empty() {
+            let write = &pending.writes[0];
+            match &write.data {
+                Some(data) => inner.set(&write.key, data, &write.metadata).await?,
+                None => inner.set_metadata(&write.key, &write.metadata).await?,
+            }
+            let write = pending.writes.remove(0);
+            pending.bytes -= write.data.map_or(0, |d| d.len());
+        }
+        pending.since = None;
+        Ok(())
+    }
+
+    async fn buffer(&self, key: &Key, data: Option<&[u8]>, metadata: &Metadata) -> Result<(), Error> {
+        let mut pending = self.pending.lock().await;
+        let data = match (data, pending.find(key)) {
+            (Some(data), _) => Some(data.to_vec()),
+            // Metadata update of a buffered write keeps its data
+            (None, Some(write)) => write.data.clone(),
+            (None, None) => None,
+        };
+        let replaced = match pending.writes.iter().position(|w| &w.key == key) {
+            Some(position) => {
+                let old = pending.writes.remove(position);
+                pending.bytes -= old.data.as_ref().map_or(0, |d| d.len());
+                Some((position, old))
+            }
+            None => None,
+        };
+        pending.bytes += data.as_ref().map_or(0, |d| d.len());
+        pending.writes.push(PendingWrite {
+            key: key.clone(),
+            data,
+            metadata: metadata.clone(),
+        });
+        let since = *pending.since.get_or_insert_with(std::time::Instant::now);
+        if pending.bytes > self.max_bytes || since.elapsed() >= self.max_delay {
+            let before = pending.writes.len();
+            if let Err(e) = Self::flush_pending(&self.inner, &mut pending).await {
+                // The flush stopped at a failed write, so this write (the last one) was not written;
+                // it is withdrawn and the write it replaced is restored
+                let flushed = before - pending.writes.len();
+                if let Some(write) = pending.writes.pop() {
+                    pending.bytes -= write.data.map_or(0, |d| d.len());
+                }
+                if let Some((position, old)) = replaced {
+                    pending.bytes += old.data.as_ref().map_or(0, |d| d.len());
+                    let position = position.saturating_sub(flushed).min(pending.writes.len());
+                    pending.writes.insert(position, old);
+                }
+                if pending.writes.is_empty() {
+                    pending.since = None;
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async_store")]
+#[async_trait]
+impl<S: AsyncStore> AsyncStore for BatchingStore<S> {
+    fn store_name(&self) -> String {
+        self.inner.store_name()
+    }
+
+    fn key_prefix(&self) -> Key {
+        self.inner.key_prefix()
+    }
+
+    fn default_metadata(&self, key: &Key, is_dir: bool) -> MetadataRecord {
+        self.inner.default_metadata(key, is_dir)
+    }
+
+    fn finalize_metadata(
+        &self,
+        metadata: Metadata,
+        key: &Key,
+        data: &[u8],
+        update: bool,
+    ) -> Metadata {
+        self.inner.finalize_metadata(metadata, key, data, update)
+    }
+
+    fn finalize_metadata_empty(
+        &self,
+        metadata: Metadata,
+        key: &Key,
+        is_dir: bool,
+        update: bool,
+    ) -> Metadata {
+        self.inner.finalize_metadata_empty(metadata, key, is_dir, update)
+    }
+
+    async fn get(&self, key: &Key) -> Result<(Vec<u8>, Metadata), Error> {
+        // The buffered write is copied, so that the lock is not held while reading the inner store
+        let buffered = self
+            .pending
+            .lock()
+            .await
+            .find(key)
+            .map(|write| (write.data.clone(), write.metadata.clone()));
+        match buffered {
+            Some((Some(data), metadata)) => Ok((data, metadata)),
+            Some((None, metadata)) => {
+                let data = self.inner.get_bytes(key).await?;
+                Ok((data, metadata))
+            }
+            None => self.inner.get(key).await,
+        }
+    }
+
+    async fn get_metadata(&self, key: &Key) -> Result<Metadata, Error> {
+        let buffered = self
+            .pending
+            .lock()
+            .await
+            .find(key)
+            .map(|write| write.metadata.clone());
+        match buffered {
+            Some(metadata) => Ok(metadata),
+            None => self.inner.get_metadata(key).await,
+        }
+    }
+
+    async fn get_stream(&self, key: &Key) -> Result<(DataStream, Metadata, Option<u64>), Error> {
+        if self.pending.lock().await.find(key).is_some() {
+            let (data, metadata) = self.get(key).await?;
+            let size = data.len() as u64;
+            Ok((buffer_stream(data), metadata, Some(size)))
+        } else {
+            self.inner.get_stream(key).await
+        }
+    }
+
+    async fn get_range(&self, key: &Key, start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+        if self.pending.lock().await.find(key).is_some() {
+            slice_range(key, &self.get_bytes(key).await?, start, end)
+        } else {
+            self.inner.get_range(key, start, end).await
+        }
+    }
+    async fn get_range_stream(
+        &self,
+        key: &Key,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<(DataStream, u64, u64), Error> {
+        if self.pending.lock().await.find(key).is_some() {
+            let (data, length) = self.get_range(key, start, end).await?;
+            let size = data.len() as u64;
+            Ok((buffer_stream(data), size, length))
+        } else {
+            self.inner.get_range_stream(key, start, end).await
+        }
+    }
+
+    async fn set(&self, key: &Key, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
+        self.buffer(key, Some(data), metadata).await
+    }
+
+    async fn set_metadata(&self, key: &Key, metadata: &Metadata) -> Result<(), Error> {
+        self.buffer(key, None, metadata).await
+    }
+
+    async fn remove(&self, key: &Key) -> Result<(), Error> {
+        let mut pending = self.pending.lock().await;
+        if let Some(position) = pending.writes.iter().position(|w| &w.key == key) {
+            let write = pending.writes.remove(position);
+            pending.bytes -= write.data.map_or(0, |d| d.len());
+            if !self.inner.contains(key).await? {
+                return Ok(());
+            }
+        }
+        self.inner.remove(key).await
+    }
+
+    async fn removedir(&self, key: &Key) -> Result<(), Error> {
+        self.flush().await?;
+        self.inner.removedir(key).await
+    }
+
+    async fn contains(&self, key: &Key) -> Result<bool, Error> {
+        if self.pending.lock().await.find(key).is_some() {
+            return Ok(true);
+        }
+        self.inner.contains(key).await
+    }
+
+    async fn is_dir(&self, key: &Key) -> Result<bool, Error> {
+        self.flush().await?;
+        self.inner.is_dir(key).await
+    }
+
+    async fn keys(&self) -> Result<Vec<Key>, Error> {
+        self.flush().await?;
+        self.inner.keys().await
+    }
+
+    async fn listdir(&self, key: &Key) -> Result<Vec<String>, Error> {
+        self.flush().await?;
+        self.inner.listdir(key).await
+    }
+
+    async fn makedir(&self, key: &Key) -> Result<(), Error> {
+        self.inner.makedir(key).await
+    }
+
+    fn is_supported(&self, key: &Key) -> bool {
+        self.inner.is_supported(key)
+    }
+
+    /// Pending writes are reported when they are flushed to the wrapped store
+    async fn watch(&self, key: &Key) -> Result<StoreEventStream, Error> {
+        self.inner.watch(key).await
+    }
+}
+
+/// Async store wrapper rooting the wrapped store at a key prefix,
+/// e.g. to give a command library access only to a subtree of a larger store.
+/// Every key is prepended with the prefix before it is passed to the wrapped store
+/// and the prefix is stripped from the listed keys.
+/// The `.` and `..` elements are interpreted; keys that would escape the prefix are rejected
+/// with [ErrorType::PermissionDenied].
+#[cfg(feature = "async_store")]
+pub struct PrefixStore<S: AsyncStore> {
+    inner: S,
+    prefix: Key,
+}
+
+#[cfg(feature = "async_store")]
+impl<S: AsyncStore> PrefixStore<S> {
+    pub fn new(inner: S, prefix: &Key) -> Self {
+        PrefixStore {
+            inner,
+            prefix: prefix.to_owned(),
+        }
+    }
+
+    /// The wrapped store
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// The prefix at which the wrapped store is rooted
+    pub fn prefix(&self) -> &Key {
+        &self.prefix
+    }
+
+    /// Key in the wrapped store; an error if the key escapes the prefix
+    pub fn inner_key(&self, key: &Key) -> Result<Key, Error> {
+        let mut relative = Vec::new();
+        for name in key.iter() {
+            if name.is_cwd() {
+                continue;
+            }
+            if name.is_parent() {
+                if relative.pop().is_none() {
+                    return Err(Error::key_permission_denied(
+                        key,
+                        &self.store_name(),
+                        "key is outside of the store prefix",
+                    ));
+                }
+                continue;
+            }
+            relative.push(name.clone());
+        }
+        let mut inner_key = self.prefix.clone();
+        inner_key.0.extend(relative);
+        Ok(inner_key)
+    }
+
+    /// Key relative to the prefix, None if the key of the wrapped store is not under the prefix
+    pub fn outer_key(&self, inner_key: &Key) -> Option<Key> {
+        inner_key
+            .has_key_prefix(&self.prefix)
+            .then(|| Key(inner_key.0[self.prefix.len()..].to_vec()))
+    }
+
+    fn outer_keys(&self, inner_keys: Vec<Key>) -> Vec<Key> {
+        inner_keys
+            .iter()
+            .filter_map(|key| self.outer_key(key))
+            .filter(|key| !key.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(feature = "async_store")]
+#[async_trait]
+impl<S: AsyncStore> AsyncStore for PrefixStore<S> {
+    fn store_name(&self) -> String {
+        format!("{} at {}", self.inner.store_name(), self.prefix)
+    }
+
+    fn key_prefix(&self) -> Key {
+        Key::new()
+    }
+
+    fn default_metadata(&self, key: &Key, is_dir: bool) -> MetadataRecord {
+        let key = self.inner_key(key).unwrap_or_else(|_| key.clone());
+        self.inner.default_metadata(&key, is_dir)
+    }
+
+    fn finalize_metadata(
+        &self,
+        metadata: Metadata,
+        key: &Key,
+        data: &[u8],
+        update: bool,
+    ) -> Metadata {
+        let key = self.inner_key(key).unwrap_or_else(|_| key.clone());
+        self.inner.finalize_metadata(metadata, &key, data, update)
+    }
+
+    fn finalize_metadata_empty(
+        &self,
+        metadata: Metadata,
+        key: &Key,
+        is_dir: bool,
+        update: bool,
+    ) -> Metadata {
+        let key = self.inner_key(key).unwrap_or_else(|_| key.clone());
+        self.inner.finalize_metadata_empty(metadata, &key, is_dir, update)
+    }
+
+    async fn get(&self, key: &Key) -> Result<(Vec<u8>, Metadata), Error> {
+        self.inner.get(&self.inner_key(key)?).await
+    }
+
+    async fn get_bytes(&self, key: &Key) -> Result<Vec<u8>, Error> {
+        self.inner.get_bytes(&self.inner_key(key)?).await
+    }
+
+    async fn get_metadata(&self, key: &Key) -> Result<Metadata, Error> {
+        self.inner.get_metadata(&self.inner_key(key)?).await
+    }
+
+    async fn get_stream(&self, key: &Key) -> Result<(DataStream, Metadata, Option<u64>), Error> {
+        self.inner.get_stream(&self.inner_key(key)?).await
+    }
+
+    async fn get_range(&self, key: &Key, start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+        self.inner.get_range(&self.inner_key(key)?, start, end).await
+    }
+    async fn get_range_stream(
+        &self,
+        key: &Key,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<(DataStream, u64, u64), Error> {
+        self.inner.get_range_stream(&self.inner_key(key)?, start, end).await
+    }
+
+    async fn set(&self, key: &Key, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
+        self.inner.set(&self.inner_key(key)?, data, metadata).await
+    }
+
+    async fn set_metadata(&self, key: &Key, metadata: &Metadata) -> Result<(), Error> {
+        self.inner.set_metadata(&self.inner_key(key)?, metadata).await
+    }
+
+    async fn remove(&self, key: &Key) -> Result<(), Error> {
+        self.inner.remove(&self.inner_key(key)?).await
+    }
+
+    async fn removedir(&self, key: &Key) -> Result<(), Error> {
+        self.inner.removedir(&self.inner_key(key)?).await
+    }
+
+    async fn contains(&self, key: &Key) -> Result<bool, Error> {
+        self.inner.contains(&self.inner_key(key)?).await
+    }
+
+    async fn is_dir(&self, key: &Key) -> Result<bool, Error> {
+        self.inner.is_dir(&self.inner_key(key)?).await
+    }
+
+    async fn keys(&self) -> Result<Vec<Key>, Error> {
+        self.listdir_keys_deep(&Key::new()).await
+    }
+
+    async fn listdir(&self, key: &Key) -> Result<Vec<String>, Error> {
+        self.inner.listdir(&self.inner_key(key)?).await
+    }
+
+    async fn listdir_keys(&self, key: &Key) -> Result<Vec<Key>, Error> {
+        let keys = self.inner.listdir_keys(&self.inner_key(key)?).await?;
+        Ok(self.outer_keys(keys))
+    }
+
+    async fn listdir_keys_deep(&self, key: &Key) -> Result<Vec<Key>, Error> {
+        let keys = self.inner.listdir_keys_deep(&self.inner_key(key)?).await?;
+        Ok(self.outer_keys(keys))
+    }
+
+    async fn makedir(&self, key: &Key) -> Result<(), Error> {
+        self.inner.makedir(&self.inner_key(key)?).await
+    }
+
+    fn is_supported(&self, key: &Key) -> bool {
+        self.inner_key(key)
+            .map_or(false, |key| self.inner.is_supported(&key))
+    }
+
+    async fn watch(&self, key: &Key) -> Result<StoreEventStream, Error> {
+        let prefix = self.prefix.clone();
+        let events = self.inner.watch(&self.inner_key(key)?).await?;
+        Ok(events
+            .filter_map(move |event| {
+                let key = event
+                    .key()
+                    .has_key_prefix(&prefix)
+                    .then(|| Key(event.key().0[prefix.len()..].to_vec()));
+                futures::future::ready(key.map(|key| event.with_key(key)))
+            })
+            .boxed())
+    }
+}
+
+/// Async store wrapper allowing only reading, e.g. to serve published data.
+/// All the modifications ([AsyncStore::set], [AsyncStore::set_metadata], [AsyncStore::remove],
+/// [AsyncStore::removedir] and [AsyncStore::makedir]) fail with [ErrorType::ReadOnlyStore],
+/// reading is delegated to the wrapped store.
+#[cfg(feature = "async_store")]
+pub struct ReadOnlyStore<S: AsyncStore> {
+    inner: S,
+}
+
+#[cfg(feature = "async_store")]
+impl<S: AsyncStore> ReadOnlyStore<S> {
+    pub fn new(inner: S) -> Self {
+        ReadOnlyStore { inner }
+    }
+
+    /// The wrapped store
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+#[cfg(feature = "async_store")]
+#[async_trait]
+impl<S: AsyncStore> AsyncStore for ReadOnlyStore<S> {
+    fn store_name(&self) -> String {
+        format!("{} (read-only)", self.inner.store_name())
+    }
+
+    fn key_prefix(&self) -> Key {
+        self.inner.key_prefix()
+    }
+
+    fn default_metadata(&self, key: &Key, is_dir: bool) -> MetadataRecord {
+        self.inner.default_metadata(key, is_dir)
+    }
+
+    async fn get(&self, key: &Key) -> Result<(Vec<u8>, Metadata), Error> {
+        self.inner.get(key).await
+    }
+
+    async fn get_bytes(&self, key: &Key) -> Result<Vec<u8>, Error> {
+        self.inner.get_bytes(key).await
+    }
+
+    async fn get_metadata(&self, key: &Key) -> Result<Metadata, Error> {
+        self.inner.get_metadata(key).await
+    }
+
+    async fn get_stream(&self, key: &Key) -> Result<(DataStream, Metadata, Option<u64>), Error> {
+        self.inner.get_stream(key).await
+    }
+
+    async fn get_range(&self, key: &Key, start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+        self.inner.get_range(key, start, end).await
+    }
+    async fn get_range_stream(
+        &self,
+        key: &Key,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<(DataStream, u64, u64), Error> {
+        self.inner.get_range_stream(key, start, end).await
+    }
+
+    async fn set(&self, key: &Key, _data: &[u8], _metadata: &Metadata) -> Result<(), Error> {
+        Err(Error::read_only_store(key, &self.store_name()))
+    }
+
+    async fn set_metadata(&self, key: &Key, _metadata: &Metadata) -> Result<(), Error> {
+        Err(Error::read_only_store(key, &self.store_name()))
+    }
+
+    async fn remove(&self, key: &Key) -> Result<(), Error> {
+        Err(Error::read_only_store(key, &self.store_name()))
+    }
+
+    async fn removedir(&self, key: &Key) -> Result<(), Error> {
+        Err(Error::read_only_store(key, &self.store_name()))
+    }
+
+    async fn contains(&self, key: &Key) -> Result<bool, Error> {
+        self.inner.contains(key).await
+    }
+
+    async fn is_dir(&self, key: &Key) -> Result<bool, Error> {
+        self.inner.is_dir(key).await
+    }
+
+    async fn keys(&self) -> Result<Vec<Key>, Error> {
+        self.inner.keys().await
+    }
+
+    async fn listdir(&self, key: &Key) -> Result<Vec<String>, Error> {
+        self.inner.listdir(key).await
+    }
+
+    async fn listdir_keys(&self, key: &Key) -> Result<Vec<Key>, Error> {
+        self.inner.listdir_keys(key).await
+    }
+
+    async fn listdir_keys_deep(&self, key: &Key) -> Result<Vec<Key>, Error> {
+        self.inner.listdir_keys_deep(key).await
+    }
+
+    fn walk<'a>(&'a self, key: &Key) -> BoxStream<'a, Result<Key, Error>> {
+        self.inner.walk(key)
+    }
+
+    async fn makedir(&self, key: &Key) -> Result<(), Error> {
+        Err(Error::read_only_store(key, &self.store_name()))
+    }
+
+    fn is_supported(&self, key: &Key) -> bool {
+        self.inner.is_supported(key)
+    }
+
+    async fn watch(&self, key: &Key) -> Result<StoreEventStream, Error> {
+        self.inner.watch(key).await
+    }
+}
+
+/// Number of the most recent secondary errors kept by [MirrorStore]
+#[cfg(feature = "async_store")]
+pub const MAX_SECONDARY_ERRORS: usize = 100;
+
+/// What [MirrorStore] does when a write to the secondary store fails
+#[cfg(feature = "async_store")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecondaryWritePolicy {
+    /// The write fails with the error of the secondary store (the primary store is already written)
+    Fail,
+    /// The write succeeds, the error is kept and can be obtained by [MirrorStore::take_secondary_errors]
+    Record,
+}
+
+/// Async store writing to two stores, e.g. to migrate data from one store to another without downtime.
+/// All the writes go to the primary store and then to the secondary store;
+/// failures of the secondary store are handled according to the [SecondaryWritePolicy].
+/// Reads are served by the primary store and fall back to the secondary store when the key is not found.
+/// With back-filling enabled, data found only in the secondary store are copied to the primary store.
+/// Listing ([AsyncStore::contains], [AsyncStore::listdir], [AsyncStore::keys], ...) combines both stores.
+#[cfg(feature = "async_store")]
+pub struct MirrorStore<A: AsyncStore, B: AsyncStore> {
+    primary: A,
+    secondary: B,
+    policy: SecondaryWritePolicy,
+    backfill: bool,
+    /// The most recent secondary errors and the count of all of them
+    secondary_errors: std::sync::Mutex<(VecDeque<Error>, usize)>,
+}
+
+#[cfg(feature = "async_store")]
+impl<A: AsyncStore, B: AsyncStore> MirrorStore<A, B> {
+    pub fn new(primary: A, secondary: B) -> Self {
+        MirrorStore {
+            primary,
+            secondary,
+            policy: SecondaryWritePolicy::Fail,
+            backfill: false,
+            secondary_errors: std::sync::Mutex::new((VecDeque::new(), 0)),
+        }
+    }
+
+    /// Set how the failed writes to the secondary store are handled
+    pub fn with_secondary_write_policy(mut self, policy: SecondaryWritePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Copy the data read from the secondary store to the primary store.
+    /// Failures of the copy do not fail the read, they are kept as the secondary errors.
+    pub fn with_backfill(mut self, backfill: bool) -> Self {
+        self.backfill = backfill;
+        self
+    }
+
+    /// The primary store
+    pub fn primary(&self) -> &A {
+        &self.primary
+    }
+
+    /// The secondary store
+    pub fn secondary(&self) -> &B {
+        &self.secondary
+    }
+
+    /// Errors of the secondary writes (with [SecondaryWritePolicy::Record]) and of back-filling
+    /// since the last call; only the last [MAX_SECONDARY_ERRORS] errors are kept
+    pub fn take_secondary_errors(&self) -> Vec<Error> {
+        let mut secondary_errors = self.secondary_errors.lock().unwrap();
+        secondary_errors.1 = 0;
+        std::mem::take(&mut secondary_errors.0).into()
+    }
+
+    /// Number of the secondary errors since the last [MirrorStore::take_secondary_errors],
+    /// including the errors that were not kept
+    pub fn secondary_error_count(&self) -> usize {
+        self.secondary_errors.lock().unwrap().1
+    }
+
+    fn record(&self, error: Error) {
+        let mut secondary_errors = self.secondary_errors.lock().unwrap();
+        if secondary_errors.0.len() >= MAX_SECONDARY_ERRORS {
+            secondary_errors.0.pop_front();
+        }
+        secondary_errors.0.push_back(error);
+        secondary_errors.1 += 1;
+    }
+
+    fn secondary_result(&self, result: Result<(), Error>) -> Result<(), Error> {
+        match (result, self.policy) {
+            (Err(e), SecondaryWritePolicy::Record) => {
+                self.record(e);
+                Ok(())
+            }
+            (result, _) => result,
+        }
+    }
+
+    fn is_not_found<T>(result: &Result<T, Error>) -> bool {
+        matches!(result, Err(e) if e.error_type == ErrorType::KeyNotFound)
+    }
+
+    /// Union of the listings without duplicates; a listing missing in one of the stores is empty
+    fn union<T: Eq + Hash + Clone>(
+        primary: Result<Vec<T>, Error>,
+        secondary: Result<Vec<T>, Error>,
+    ) -> Result<Vec<T>, Error> {
+        match (primary, secondary) {
+            (Ok(mut items), Ok(other)) => {
+                let mut seen = items.iter().cloned().collect::<HashSet<_>>();
+                items.extend(other.into_iter().filter(|item| seen.insert(item.clone())));
+                Ok(items)
+            }
+            (Ok(items), Err(e)) | (Err(e), Ok(items)) if e.error_type == ErrorType::KeyNotFound => {
+                Ok(items)
+            }
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        }
+    }
+}
+
+#[cfg(feature = "async_store")]
+#[async_trait]
+impl<A: AsyncStore, B: AsyncStore> AsyncStore for MirrorStore<A, B> {
+    fn store_name(&self) -> String {
+        format!(
+            "{} mirrored to {}",
+            self.primary.store_name(),
+            self.secondary.store_name()
+        )
+    }
+
+    fn key_prefix(&self) -> Key {
+        self.primary.key_prefix()
+    }
+
+    fn default_metadata(&self, key: &Key, is_dir: bool) -> MetadataRecord {
+        self.primary.default_metadata(key, is_dir)
+    }
+
+    async fn get(&self, key: &Key) -> Result<(Vec<u8>, Metadata), Error> {
+        let result = self.primary.get(key).await;
+        if !Self::is_not_found(&result) {
+            return result;
+        }
+        let (data, metadata) = self.secondary.get(key).await?;
+        if self.backfill {
+            if let Err(e) = self.primary.set(key, &data, &metadata).await {
+                self.record(e);
+            }
+        }
+        Ok((data, metadata))
+    }
+
+    async fn get_metadata(&self, key: &Key) -> Result<Metadata, Error> {
+        let result = self.primary.get_metadata(key).await;
+        if Self::is_not_found(&result) {
+            self.secondary.get_metadata(key).await
+        } else {
+            result
+        }
+    }
+
+    async fn get_stream(&self, key: &Key) -> Result<(DataStream, Metadata, Option<u64>), Error> {
+        let result = self.primary.get_stream(key).await;
+        if !Self::is_not_found(&result) {
+            return result;
+        }
+        if self.backfill {
+            let (data, metadata) = self.get(key).await?;
+            let size = data.len() as u64;
+            Ok((buffer_stream(data), metadata, Some(size)))
+        } else {
+            self.secondary.get_stream(key).await
+        }
+    }
+
+    async fn get_range(&self, key: &Key, start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+        let result = self.primary.get_range(key, start, end).await;
+        if Self::is_not_found(&result) {
+            self.secondary.get_range(key, start, end).await
+        } else {
+            result
+        }
+    }
+    async fn get_range_stream(
+        &self,
+        key: &Key,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<(DataStream, u64, u64), Error> {
+        let result = self.primary.get_range_stream(key, start, end).await;
+        if Self::is_not_found(&result) {
+            self.secondary.get_range_stream(key, start, end).await
+        } else {
+            result
+        }
+    }
+
+    async fn set(&self, key: &Key, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
+        self.primary.set(key, data, metadata).await?;
+        self.secondary_result(self.secondary.set(key, data, metadata).await)
+    }
+
+    async fn set_metadata(&self, key: &Key, metadata: &Metadata) -> Result<(), Error> {
+        self.primary.set_metadata(key, metadata).await?;
+        self.secondary_result(self.secondary.set_metadata(key, metadata).await)
+    }
+
+    async fn remove(&self, key: &Key) -> Result<(), Error> {
+        let result = self.primary.remove(key).await;
+        if !Self::is_not_found(&result) {
+            result?;
+        }
+        let result = self.secondary.remove(key).await;
+        if Self::is_not_found(&result) {
+            return Ok(());
+        }
+        self.secondary_result(result)
+    }
+
+    async fn removedir(&self, key: &Key) -> Result<(), Error> {
+        let result = self.primary.removedir(key).await;
+        if !Self::is_not_found(&result) {
+            result?;
+        }
+        let result = self.secondary.removedir(key).await;
+        if Self::is_not_found(&result) {
+            return Ok(());
+        }
+        self.secondary_result(result)
+    }
+
+    async fn contains(&self, key: &Key) -> Result<bool, Error> {
+        Ok(self.primary.contains(key).await? || self.secondary.contains(key).await?)
+    }
+
+    async fn is_dir(&self, key: &Key) -> Result<bool, Error> {
+        Ok(self.primary.is_dir(key).await? || self.secondary.is_dir(key).await?)
+    }
+
+    async fn keys(&self) -> Result<Vec<Key>, Error> {
+        Self::union(self.primary.keys().await, self.secondary.keys().await)
+    }
+
+    async fn listdir(&self, key: &Key) -> Result<Vec<String>, Error> {
+        Self::union(
+            self.primary.listdir(key).await,
+            self.secondary.listdir(key).await,
+        )
+    }
+
+    async fn makedir(&self, key: &Key) -> Result<(), Error> {
+        self.primary.makedir(key).await?;
+        self.secondary_result(self.secondary.makedir(key).await)
+    }
+
+    fn is_supported(&self, key: &Key) -> bool {
+        self.primary.is_supported(key)
+    }
+
+    /// Changes of both stores are reported, so a write may be reported twice
+    async fn watch(&self, key: &Key) -> Result<StoreEventStream, Error> {
+        let primary = self.primary.watch(key).await?;
+        let secondary = self.secondary.watch(key).await?;
+        Ok(futures::stream::select(primary, secondary).boxed())
+    }
+}
+
+/// Default key under which a [DedupStore] keeps the blobs in the wrapped store
+#[cfg(feature = "async_store")]
+pub const DEFAULT_DEDUP_BLOB_PREFIX: &str = "_blobs";
+
+/// Index of a [DedupStore]: content hash of each key and the number of keys referring to each blob
+#[cfg(feature = "async_store")]
+#[derive(Default)]
+struct DedupIndex {
+    hashes: std::collections::HashMap<Key, String>,
+    references: std::collections::HashMap<String, usize>,
+}
+
+#[cfg(feature = "async_store")]
+impl DedupIndex {
+    /// Point the key to the hash; returns the hash of a blob not referenced anymore
+    fn insert(&mut self, key: &Key, hash: &str) -> Option<String> {
+        if self.hashes.get(key).is_some_and(|h| h == hash) {
+            return None;
+        }
+        let released = self.remove(key);
+        *self.references.entry(hash.to_owned()).or_insert(0) += 1;
+        self.hashes.insert(key.clone(), hash.to_owned());
+        released
+    }
+
+    /// Remove the key; returns the hash of a blob not referenced anymore
+    fn remove(&mut self, key: &Key) -> Option<String> {
+        let hash = self.hashes.remove(key)?;
+        let count = self.references.get_mut(&hash)?;
+        *count -= 1;
+        if *count == 0 {
+            self.references.remove(&hash);
+            Some(hash)
+        } else {
+            None
+        }
+    }
+}
+
+/// Async store wrapper deduplicating the data by their content.
+/// The data are stored once as a blob under their blake3 hash (below the blob prefix, see [DEFAULT_DEDUP_BLOB_PREFIX]),
+/// the key itself holds only the hash and the metadata of the key, so the metadata stay per key.
+/// A blob is removed when the last key referring to it is removed or overwritten.
+///
+/// The index of the hashes and reference counts is kept in memory; it is built from the wrapped store
+/// at the first access. The modifications are serialized by the index lock, so that concurrent writes
+/// of the same content (to the same or different keys) store the blob once and count the references correctly.
+/// Reads hold the index lock (shared) from reading the hash until the blob is read or its stream is opened,
+/// so that the blob can't be removed by a concurrent write in between.
+/// The blob prefix is not visible through the wrapper and can't be written to.
+#[cfg(feature = "async_store")]
+pub struct DedupStore<S: AsyncStore> {
+    inner: S,
+    blob_prefix: Key,
+    index: tokio::sync::RwLock<Option<DedupIndex>>,
+}
+
+#[cfg(feature = "async_store")]
+impl<S: AsyncStore> DedupStore<S> {
+    pub fn new(inner: S) -> Self {
+        DedupStore {
+            inner,
+            blob_prefix: Key::new().join(DEFAULT_DEDUP_BLOB_PREFIX),
+            index: tokio::sync::RwLock::new(None),
+        }
+    }
+
+    /// Keep the blobs under a different key in the wrapped store
+    pub fn with_blob_prefix(mut self, blob_prefix: &Key) -> Self {
+        self.blob_prefix = blob_prefix.to_owned();
+        self
+    }
+
+    /// The wrapped store
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Content hash of the data (hexadecimal blake3 hash)
+    pub fn content_hash(data: &[u8]) -> String {
+        blake3::hash(data).to_hex().to_string()
+    }
+
+    /// Key of the blob with the given hash in the wrapped store
+    pub fn blob_key(&self, hash: &str) -> Key {
+        self.blob_prefix.join(hash)
+    }
+
+    fn is_blob_key(&self, key: &Key) -> bool {
+        key.has_key_prefix(&self.blob_prefix)
+    }
+
+    fn check_key(&self, key: &Key) -> Result<(), Error> {
+        if self.is_blob_key(key) {
+            Err(Error::key_not_supported(key, &self.store_name()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Hash stored under the key
+    async fn hash_of(&self, key: &Key) -> Result<(String, Metadata), Error> {
+        self.check_key(key)?;
+        let (pointer, metadata) = self.inner.get(key).await?;
+        let hash = String::from_utf8(pointer)
+            .map_err(|e| Error::key_read_error(key, &self.store_name(), &e))?;
+        Ok((hash, metadata))
+    }
+
+    /// Locked index for a modification, built from the wrapped store if not loaded yet
+    async fn index(&self) -> Result<tokio::sync::RwLockWriteGuard<'_, Option<DedupIndex>>, Error> {
+        let mut index = self.index.write().await;
+        if index.is_none() {
+            let mut loaded = DedupIndex::default();
+            for key in self.inner.keys().await? {
+                if self.is_blob_key(&key) || self.inner.is_dir(&key).await? {
+                    continue;
+                }
+                match self.hash_of(&key).await {
+                    Ok((hash, _)) if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+                        loaded.insert(&key, &hash);
+                    }
+                    _ => {}
+                }
+            }
+            *index = Some(loaded);
+        }
+        Ok(index)
+    }
+
+    async fn remove_blob(&self, hash: Option<String>) -> Result<(), Error> {
+        match hash {
+            Some(hash) => self.inner.remove(&self.blob_key(&hash)).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "async_store")]
+#[async_trait]
+impl<S: AsyncStore> AsyncStore for DedupStore<S> {
+    fn store_name(&self) -> String {
+        format!("{} deduplicated", self.inner.store_name())
+    }
+
+    fn key_prefix(&self) -> Key {
+        self.inner.key_prefix()
+    }
+
+    fn default_metadata(&self, key: &Key, is_dir: bool) -> MetadataRecord {
+        self.inner.default_metadata(key, is_dir)
+    }
+
+    async fn get(&self, key: &Key) -> Result<(Vec<u8>, Metadata), Error> {
+        let _index = self.index.read().await;
+        let (hash, metadata) = self.hash_of(key).await?;
+        let data = self.inner.get_bytes(&self.blob_key(&hash)).await?;
+        Ok((data, metadata))
+    }
+
+    async fn get_metadata(&self, key: &Key) -> Result<Metadata, Error> {
+        self.check_key(key)?;
+        self.inner.get_metadata(key).await
+    }
+
+    async fn get_stream(&self, key: &Key) -> Result<(DataStream, Metadata, Option<u64>), Error> {
+        let _index = self.index.read().await;
+        let (hash, metadata) = self.hash_of(key).await?;
+        let (stream, _, size) = self.inner.get_stream(&self.blob_key(&hash)).await?;
+        Ok((stream, metadata, size))
+    }
+
+    async fn get_range(&self, key: &Key, start: u64, end: Option<u64>) -> Result<(Vec<u8>, u64), Error> {
+        let _index = self.index.read().await;
+        let (hash, _) = self.hash_of(key).await?;
+        self.inner.get_range(&self.blob_key(&hash), start, end).await
+    }
+    async fn get_range_stream(
+        &self,
+        key: &Key,
+        start: u64,
+        end: Option<u64>,
+    ) -> Result<(DataStream, u64, u64), Error> {
+        let _index = self.index.read().await;
+        let (hash, _) = self.hash_of(key).await?;
+        self.inner.get_range_stream(&self.blob_key(&hash), start, end).await
+    }
+
+    async fn set(&self, key: &Key, data: &[u8], metadata: &Metadata) -> Result<(), Error> {
+        self.check_key(key)?;
+        let hash = Self::content_hash(data);
+        let mut index = self.index().await?;
+        let index = index.get_or_insert_with(DedupIndex::default);
+        let new_blob = !index.references.contains_key(&hash);
+        if new_blob {
+            let blob_metadata = Metadata::MetadataRecord(MetadataRecord::new());
+            self.inner
+                .set(&self.blob_key(&hash), data, &blob_metadata)
+                .await?;
+        }
+        if let Err(e) = self.inner.set(key, hash.as_bytes(), metadata).await {
+            // Nothing refers to the new blob
+            if new_blob {
+                let _ = self.remove_blob(Some(hash)).await;
+            }
+            return Err(e);
+        }
+        let released = index.insert(key, &hash);
+        self.remove_blob(released).await
+    }
+
+    async fn set_metadata(&self, key: &Key, metadata: &Metadata) -> Result<(), Error> {
+        self.check_key(key)?;
+        self.inner.set_metadata(key, metadata).await
+    }
+
+    async fn remove(&self, key: &Key) -> Result<(), Error> {
+        self.check_key(key)?;
+        let mut index = self.index().await?;
+        let index = index.get_or_insert_with(DedupIndex::default);
+        self.inner.remove(key).await?;
+        let released = index.remove(key);
+        self.remove_blob(released).await
+    }
+
+    async fn removedir(&self, key: &Key) -> Result<(), Error> {
+        self.check_key(key)?;
+        let mut index = self.index().await?;
+        let index = index.get_or_insert_with(DedupIndex::default);
+        self.inner.removedir(key).await?;
+        let removed = index
+            .hashes
+            .keys()
+            .filter(|k| k.has_key_prefix(key))
+            .cloned()
+            .collect::<Vec<_>>();
+        for k in removed {
+            let released = index.remove(&k);
+            self.remove_blob(released).await?;
+        }
+        Ok(())
+    }
+
+    async fn contains(&self, key: &Key) -> Result<bool, Error> {
+        if self.is_blob_key(key) {
+            return Ok(false);
+        }
+        self.inner.contains(key).await
+    }
+
+    async fn is_dir(&self, key: &Key) -> Result<bool, Error> {
+        if self.is_blob_key(key) {
+            return Ok(false);
+        }
+        self.inner.is_dir(key).await
+    }
+
+    async fn keys(&self) -> Result<Vec<Key>, Error> {
+        let keys = self.inner.keys().await?;
+        Ok(keys.into_iter().filter(|k| !self.is_blob_key(k)).collect())
+    }
+
+    async fn listdir(&self, key: &Key) -> Result<Vec<String>, Error> {
+        if self.is_blob_key(key) {
+            return Ok(vec![]);
+        }
+        let names = self.inner.listdir(key).await?;
+        Ok(names
+            .into_iter()
+            .filter(|name| !self.is_blob_key(&key.join(name)))
+            .collect())
+    }
+
+    async fn makedir(&self, key: &Key) -> Result<(), Error> {
+        self.check_key(key)?;
+        self.inner.makedir(key).await
+    }
+
+    fn is_supported(&self, key: &Key) -> bool {
+        !self.is_blob_key(key) && self.inner.is_supported(key)
+    }
+
+    /// Changes of the blobs are not reported
+    async fn watch(&self, key: &Key) -> Result<StoreEventStream, Error> {
+        let blob_prefix = self.blob_prefix.clone();
+        let events = self.inner.watch(key).await?;
+        Ok(events
+            .filter(move |event| futures::future::ready(!event.key().has_key_prefix(&blob_prefix)))
+            .boxed())
+    }
+}
+
+// Unittests
+#[cfg(test)]
+mod tests {
+    //    use crate::query::Key;
+
+    use super::*;
+
+    use crate::parse::parse_key;
+
+    #[test]
+    fn test_simple_store() -> Result<(), Error> {
+        let store = MemoryStore::new(&Key::new());
+        let key = parse_key("a/b/c").unwrap();
+        let data = b"test data".to_vec();
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+
+        assert!(!store.contains(&key)?);
+        assert!(store.keys().unwrap().is_empty());
+        assert!(!store.is_dir(&parse_key("a/b")?)?);
+
+        store.set(&key, &data, &metadata)?;
+        assert!(store.contains(&key)?);
+        assert!(store.keys()?.contains(&key));
+        assert!(store.is_dir(&parse_key("a/b")?)?);
+        assert_eq!(store.keys().unwrap().len(), 1);
+
+        let (data2, _metadata2) = store.get(&key).unwrap();
+        assert_eq!(data, data2);
+        store.remove(&key).unwrap();
+        assert!(!store.contains(&key)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_alias_store() -> Result<(), Error> {
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+        let store = AliasStore::new(MemoryStore::new(&Key::new()))
+            .with_alias("data", &parse_key("projects/2024/data")?)?;
+        store.set(&parse_key("~~data/table.csv")?, b"a,b", &metadata)?;
+        assert!(store.contains(&parse_key("projects/2024/data/table.csv")?)?);
+        assert_eq!(store.get_bytes(&parse_key("~~data/table.csv")?)?, b"a,b");
+        assert_eq!(
+            store.resolve_key(&parse_key("~~data")?)?,
+            parse_key("projects/2024/data")?
+        );
+        assert_eq!(store.listdir(&parse_key("~~data")?)?, vec!["table.csv".to_owned()]);
+        // Keys without an alias are not changed
+        assert_eq!(store.resolve_key(&parse_key("a/~~b")?)?, parse_key("a/~~b")?);
+
+        let err = store.get_bytes(&parse_key("~~other/table.csv")?).unwrap_err();
+        assert!(err.message.contains("Unknown key alias '~other'"));
+
+        // Events of a key watched through the alias are reported with the alias
+        let mut watcher = store.watch(&parse_key("~~data")?)?;
+        let mut resolved_watcher = store.watch(&parse_key("projects/2024")?)?;
+        store.set(&parse_key("projects/2024/data/new.csv")?, b"c,d", &metadata)?;
+        assert_eq!(
+            watcher.try_next_event(),
+            Some(StoreEvent::Created(parse_key("~~data/new.csv")?))
+        );
+        assert_eq!(
+            resolved_watcher.try_next_event(),
+            Some(StoreEvent::Created(parse_key("projects/2024/data/new.csv")?))
+        );
+
+        let store = AliasStore::new(MemoryStore::new(&Key::new()));
+        assert!(store.with_alias("bad name", &parse_key("x")?).is_err());
+        let store = AliasStore::new(MemoryStore::new(&Key::new()));
+        assert!(store.with_alias("loop", &parse_key("~~data/x")?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_store_listing() -> Result<(), Error> {
+        let root = Key::new();
+        let store = MemoryStore::new(&root);
+        assert!(store.keys()?.is_empty());
+        assert!(store.listdir(&root)?.is_empty());
+        assert!(store.listdir_keys_deep(&root)?.is_empty());
+
+        let path = std::env::temp_dir().join(format!("liquers_empty_test_{}", std::process::id()));
+        let file_store = FileStore::new(path.to_str().unwrap(), &root);
+        assert!(file_store.keys()?.is_empty());
+        assert!(file_store.listdir(&root)?.is_empty());
+        assert!(file_store.listdir_keys_deep(&root)?.is_empty());
+
+        let mut router = StoreRouter::new();
+        router.add_store(Box::new(MemoryStore::new(&root)));
+        assert!(router.keys()?.is_empty());
+        assert!(router.listdir(&root)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_level_store_listing() -> Result<(), Error> {
+        let root = Key::new();
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+        let store = MemoryStore::new(&root);
+        store.set(&parse_key("a.txt")?, b"a", &metadata)?;
+        store.set(&parse_key("b.txt")?, b"b", &metadata)?;
+        assert_eq!(store.listdir(&root)?, vec!["a.txt", "b.txt"]);
+        assert_eq!(store.listdir_keys_deep(&root)?.len(), 2);
+        assert_eq!(store.keys()?.len(), 2);
+
+        // Deeper keys make implicit directories
+        store.set(&parse_key("dir/sub/c.txt")?, b"c", &metadata)?;
+        assert_eq!(store.listdir(&root)?, vec!["a.txt", "b.txt", "dir"]);
+        assert_eq!(store.listdir(&parse_key("dir")?)?, vec!["sub"]);
+
+        let path = std::env::temp_dir().join(format!("liquers_top_test_{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let file_store = FileStore::new(path.to_str().unwrap(), &root);
+        file_store.set(&parse_key("a.txt")?, b"a", &metadata)?;
+        let keys = file_store.keys()?;
+        let deep = file_store.listdir_keys_deep(&root)?;
+        std::fs::remove_dir_all(&path).unwrap();
+        assert_eq!(keys, vec![parse_key("a.txt")?]);
+        assert_eq!(deep, vec![parse_key("a.txt")?]);
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_walk() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("liquers_walk_test_{}", std::process::id()));
+        std::fs::create_dir_all(path.join("a/b")).unwrap();
+        let store = AsyncStoreWrapper(FileStore::new(path.to_str().unwrap(), &Key::new()));
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+        for key in ["x.txt", "a/y.txt", "a/b/z.txt"] {
+            store.set(&parse_key(key)?, b"test", &metadata).await?;
+        }
+
+        let mut walked = store
+            .walk(&Key::new())
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut listed = store.listdir_keys_deep(&Key::new()).await?;
+        std::fs::remove_dir_all(&path).unwrap();
+
+        walked.sort_by_key(|k| k.encode());
+        listed.sort_by_key(|k| k.encode());
+        assert_eq!(walked, listed);
+        assert_eq!(walked.len(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_store_error_types() {
+        use crate::error::ErrorType;
+
+        let store = MemoryStore::new(&Key::new());
+        let key = parse_key("a/b.txt").unwrap();
+        let err = store.get(&key).unwrap_err();
+        assert_eq!(err.error_type, ErrorType::KeyNotFound);
+    }
+
+    #[test]
+    fn test_file_store_error_types() {
+        use crate::error::ErrorType;
+
+        let path = std::env::temp_dir().join(format!("liquers_errors_test_{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let store = FileStore::new(path.to_str().unwrap(), &Key::new());
+        let key = parse_key("x.txt").unwrap();
+        let missing = store.get(&key).unwrap_err();
+        store
+            .set(&key, b"test", &Metadata::MetadataRecord(MetadataRecord::new()))
+            .unwrap();
+        let write_under_file = store
+            .set(
+                &parse_key("x.txt/y.txt").unwrap(),
+                b"test",
+                &Metadata::MetadataRecord(MetadataRecord::new()),
+            )
+            .unwrap_err();
+        std::fs::remove_dir_all(&path).unwrap();
+
+        assert_eq!(missing.error_type, ErrorType::KeyNotFound);
+        assert_eq!(write_under_file.error_type, ErrorType::NotADirectory);
+
+        // Permission checks can't be relied on in tests (e.g. when running as root),
+        // so the classification of the I/O error kinds is tested directly
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert_eq!(
+            Error::key_read_io_error(&key, "test", &denied).error_type,
+            ErrorType::PermissionDenied
+        );
+        let other = std::io::Error::from(std::io::ErrorKind::Other);
+        assert_eq!(
+            Error::key_write_io_error(&key, "test", &other).error_type,
+            ErrorType::KeyWriteError
+        );
+    }
+
+    #[test]
+    fn test_file_store_atomic_set() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("liquers_atomic_test_{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let store = FileStore::new(path.to_str().unwrap(), &Key::new());
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+        let key = parse_key("data.txt")?;
+        store.set(&key, b"first", &metadata)?;
+        store.set(&key, b"second", &metadata)?;
+        store.set_metadata(&key, &metadata)?;
+        let failed = store.set(&parse_key("missing/data.txt")?, b"data", &metadata);
+        let mut files = std::fs::read_dir(&path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        files.sort();
+        // A leftover of an interrupted write is not a key
+        std::fs::write(path.join("data.txt.__temporary__1_0"), b"partial").unwrap();
+        let listed = store.listdir(&Key::new())?;
+        let (data, _) = store.get(&key)?;
+        let metadata_only = store.get_metadata(&key);
+        // Data renamed into place without the metadata of the same write
+        std::fs::write(path.join("data.txt"), b"third!!").unwrap();
+        let mismatched = store.get(&key).err();
+        let mismatched_metadata = store.get_metadata(&key).err();
+        std::fs::remove_dir_all(&path).unwrap();
+
+        assert!(failed.is_err());
+        assert_eq!(files, vec!["data.txt", "data.txt.__metadata__"]);
+        assert_eq!(listed, vec!["data.txt"]);
+        assert_eq!(data, b"second");
+        assert!(metadata_only.is_ok());
+        assert_eq!(mismatched.unwrap().error_type, ErrorType::KeyReadError);
+        assert_eq!(mismatched_metadata.unwrap().error_type, ErrorType::KeyReadError);
+        assert!(!store.is_supported(&parse_key("data.txt.__temporary__1_0")?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_store_updated() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("liquers_updated_test_{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let store = FileStore::new(path.to_str().unwrap(), &Key::new());
+        let before = chrono::Utc::now() - chrono::Duration::seconds(1);
+        // Written through the store without a known timestamp
+        let written = parse_key("written.txt")?;
+        store.set(&written, b"data", &Metadata::MetadataRecord(MetadataRecord::new()))?;
+        let written_updated = store.get_metadata(&written)?.updated();
+        // Explicit timestamp is kept
+        let mut explicit = MetadataRecord::new();
+        explicit.updated = "2020-01-01T00:00:00.000Z".to_owned();
+        let kept = parse_key("kept.txt")?;
+        store.set(&kept, b"data", &Metadata::MetadataRecord(explicit))?;
+        let kept_updated = store.get_metadata(&kept)?.updated();
+        // Written outside of the store, without metadata
+        std::fs::write(path.join("external.txt"), b"data").unwrap();
+        let external_updated = store.get_metadata(&parse_key("external.txt")?)?.updated();
+        std::fs::remove_dir_all(&path).unwrap();
+
+        assert!(written_updated.unwrap() >= before);
+        assert_eq!(kept_updated.unwrap().to_rfc3339(), "2020-01-01T00:00:00+00:00");
+        assert!(external_updated.unwrap() >= before);
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_store_confinement() {
+        use crate::error::ErrorType;
+        use crate::query::ResourceName;
+
+        let path = std::env::temp_dir().join(format!("liquers_confine_test_{}", std::process::id()));
+        let root = path.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(path.join("secret.txt"), b"secret").unwrap();
+        let store = FileStore::new(root.to_str().unwrap(), &Key::new());
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+
+        let traversal = Key(vec![
+            ResourceName::new("..".to_owned()),
+            ResourceName::new("secret.txt".to_owned()),
+        ]);
+        let absolute = Key(vec![ResourceName::new(
+            path.join("secret.txt").to_str().unwrap().to_owned(),
+        )]);
+        let mut errors = vec![
+            store.get_bytes(&traversal).unwrap_err(),
+            store.set(&traversal, b"x", &metadata).unwrap_err(),
+            store.contains(&traversal).unwrap_err(),
+            store.get_bytes(&absolute).unwrap_err(),
+        ];
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&path, root.join("link")).unwrap();
+            errors.push(store.get_bytes(&parse_key("link/secret.txt").unwrap()).unwrap_err());
+        }
+        store.set(&parse_key("a.txt").unwrap(), b"a", &metadata).unwrap();
+        let inside = store.get_bytes(&parse_key("a.txt").unwrap());
+        let secret = std::fs::read(path.join("secret.txt")).unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+
+        for e in errors {
+            assert_eq!(e.error_type, ErrorType::PermissionDenied);
+        }
+        assert_eq!(inside.unwrap(), b"a");
+        assert_eq!(secret, b"secret");
+    }
+
+    #[test]
+    fn test_get_range() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("liquers_range_test_{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let file_store = FileStore::new(path.to_str().unwrap(), &Key::new());
+        let memory_store = MemoryStore::new(&Key::new());
+        let key = parse_key("data.txt")?;
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+        let stores: [&dyn Store; 2] = [&file_store, &memory_store];
+        let mut results = Vec::new();
+        for store in stores {
+            store.set(&key, b"0123456789", &metadata)?;
+            results.push((
+                store.get_range(&key, 2, Some(5)),
+                store.get_range(&key, 7, None),
+                store.get_range(&key, 8, Some(100)),
+                store.get_range(&key, 10, None),
+                store.get_range(&key, 11, None).err(),
+                store.get_range(&key, 5, Some(3)).err(),
+            ));
+        }
+        std::fs::remove_dir_all(&path).unwrap();
+
+        for (middle, tail, clipped, empty, beyond, reversed) in results {
+            assert_eq!(middle?, (b"234".to_vec(), 10));
+            assert_eq!(tail?, (b"789".to_vec(), 10));
+            assert_eq!(clipped?, (b"89".to_vec(), 10));
+            assert_eq!(empty?, (Vec::new(), 10));
+            assert_eq!(beyond.unwrap().error_type, ErrorType::RangeNotSatisfiable);
+            assert_eq!(reversed.unwrap().error_type, ErrorType::RangeNotSatisfiable);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_get_stream() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("liquers_stream_test_{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        let store = AsyncStoreWrapper(FileStore::new(path.to_str().unwrap(), &Key::new()));
+        let data = (0..3 * STREAM_CHUNK_SIZE + 100)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let key = parse_key("large.bin")?;
+        let mut metadata = MetadataRecord::new();
+        metadata.with_media_type("application/octet-stream".to_owned());
+        store
+            .set(&key, &data, &Metadata::MetadataRecord(metadata))
+            .await?;
+        let (stream, metadata, size) = store.get_stream(&key).await?;
+        let chunks = stream.collect::<Vec<_>>().await;
+        let missing = store.get_stream(&parse_key("missing.bin")?).await.err();
+        let (start, stop) = (STREAM_CHUNK_SIZE as u64 - 10, 2 * STREAM_CHUNK_SIZE as u64 + 10);
+        let (range_stream, range_size, length) =
+            store.get_range_stream(&key, start, Some(stop)).await?;
+        let range_chunks = range_stream.collect::<Vec<_>>().await;
+        std::fs::remove_dir_all(&path).unwrap();
+
+        assert_eq!((range_size, length), (stop - start, data.len() as u64));
+        assert_eq!(range_chunks.len(), 2);
+        let streamed_range = range_chunks
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        assert_eq!(streamed_range, data[start as usize..stop as usize]);
+
+        assert_eq!(size, Some(data.len() as u64));
+        assert_eq!(metadata.get_media_type(), "application/octet-stream");
+        assert_eq!(chunks.len(), 4);
+        let streamed = chunks
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        assert_eq!(streamed, data);
+        assert_eq!(
+            missing.unwrap().error_type,
+            crate::error::ErrorType::KeyNotFound
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_prefix_store() -> Result<(), Error> {
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+        let memory = MemoryStore::new(&Key::new());
+        memory.set(&parse_key("a/x/1.txt")?, b"1", &metadata)?;
+        memory.set(&parse_key("a/2.txt")?, b"2", &metadata)?;
+        memory.set(&parse_key("b/3.txt")?, b"3", &metadata)?;
+        let store = PrefixStore::new(AsyncStoreWrapper(memory), &parse_key("a")?);
+
+        assert_eq!(store.get_bytes(&parse_key("x/1.txt")?).await?, b"1");
+        assert_eq!(store.get_bytes(&parse_key("x/../2.txt")?).await?, b"2");
+        store.set(&parse_key("y/4.txt")?, b"4", &metadata).await?;
+        assert_eq!(store.inner().0.get_bytes(&parse_key("a/y/4.txt")?)?, b"4");
+        let mut keys = store.keys().await?.iter().map(|k| k.encode()).collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec!["2.txt", "x/1.txt", "y/4.txt"]);
+        let mut listed = store.listdir_keys(&Key::new()).await?;
+        listed.sort_by_key(|k| k.encode());
+        assert_eq!(listed, vec![parse_key("2.txt")?, parse_key("x")?, parse_key("y")?]);
+
+        for escaping in ["../b/3.txt", "x/../../b/3.txt", ".."] {
+            let err = store.get_bytes(&parse_key(escaping)?).await.unwrap_err();
+            assert_eq!(err.error_type, ErrorType::PermissionDenied);
+        }
+        assert!(!store.contains(&parse_key("b/3.txt")?).await?);
+
+        let path = std::env::temp_dir().join(format!("liquers_prefix_test_{}", std::process::id()));
+        std::fs::create_dir_all(path.join("dataset")).unwrap();
+        let file_store = PrefixStore::new(
+            AsyncStoreWrapper(FileStore::new(path.to_str().unwrap(), &Key::new())),
+            &parse_key("dataset")?,
+        );
+        file_store.set(&parse_key("table.csv")?, b"a,b", &metadata).await?;
+        let written = std::fs::read(path.join("dataset").join("table.csv"));
+        let read = file_store.get_bytes(&parse_key("table.csv")?).await;
+        std::fs::remove_dir_all(&path).unwrap();
+        assert_eq!(written.unwrap(), b"a,b");
+        assert_eq!(read?, b"a,b");
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_read_only_store() -> Result<(), Error> {
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+        let memory = MemoryStore::new(&Key::new());
+        memory.set(&parse_key("a/data.txt")?, b"data", &metadata)?;
+        let store = ReadOnlyStore::new(AsyncStoreWrapper(memory));
+        let key = parse_key("a/data.txt")?;
+
+        assert_eq!(store.get_bytes(&key).await?, b"data");
+        assert!(store.contains(&key).await?);
+        assert_eq!(store.listdir(&parse_key("a")?).await?, vec!["data.txt".to_owned()]);
+
+        let errors = vec![
+            store.set(&key, b"new", &metadata).await.unwrap_err(),
+            store.set_metadata(&key, &metadata).await.unwrap_err(),
+            store.remove(&key).await.unwrap_err(),
+            store.removedir(&parse_key("a")?).await.unwrap_err(),
+            store.makedir(&parse_key("b")?).await.unwrap_err(),
+        ];
+        for e in errors {
+            assert_eq!(e.error_type, ErrorType::ReadOnlyStore);
+            assert!(e.query.is_some());
+        }
+        assert_eq!(
+            store.remove(&key).await.unwrap_err().query.as_deref(),
+            Some("a/data.txt")
+        );
+        assert_eq!(store.get_bytes(&key).await?, b"data");
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_store_watch() -> Result<(), Error> {
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+        let store = MemoryStore::new(&Key::new());
+        let key = parse_key("a/data.txt")?;
+        let mut watcher = store.watch(&parse_key("a")?)?;
+        store.set(&key, b"data", &metadata)?;
+        store.set(&key, b"new", &metadata)?;
+        store.set_metadata(&key, &metadata)?;
+        store.set(&parse_key("b/other.txt")?, b"other", &metadata)?;
+        store.removedir(&parse_key("a")?)?;
+        store.remove(&key)?;
+        let mut events = Vec::new();
+        while let Some(event) = watcher.try_next_event() {
+            events.push(event);
+        }
+        assert_eq!(
+            events,
+            vec![
+                StoreEvent::Created(key.clone()),
+                StoreEvent::Modified(key.clone()),
+                StoreEvent::Modified(key.clone()),
+                StoreEvent::Removed(key.clone()),
+            ]
+        );
+
+        // Events beyond the capacity are dropped, the watcher is kept
+        for _ in 0..WATCH_CHANNEL_CAPACITY + 10 {
+            store.set(&key, b"data", &metadata)?;
+        }
+        let mut count = 0;
+        while watcher.try_next_event().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, WATCH_CHANNEL_CAPACITY);
+        store.set(&key, b"data", &metadata)?;
+        assert_eq!(watcher.try_next_event(), Some(StoreEvent::Modified(key.clone())));
+
+        // Dropped watchers are not notified anymore
+        drop(watcher);
+        store.set(&key, b"data", &metadata)?;
+        assert!(store.watchers.read().unwrap().is_empty());
+        assert!(StoreWatcher::empty().try_next_event().is_none());
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_watch() -> Result<(), Error> {
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+        let memory = AsyncStoreWrapper(MemoryStore::new(&Key::new()));
+        let store = PrefixStore::new(memory, &parse_key("dataset")?);
+        let mut events = store.watch(&parse_key("a")?).await?;
+        store.set(&parse_key("a/data.txt")?, b"data", &metadata).await?;
+        store.remove(&parse_key("a/data.txt")?).await?;
+        assert_eq!(events.next().await, Some(StoreEvent::Created(parse_key("a/data.txt")?)));
+        assert_eq!(events.next().await, Some(StoreEvent::Removed(parse_key("a/data.txt")?)));
+        // Stores that can't watch have no events
+        assert_eq!(NoAsyncStore.watch(&Key::new()).await?.next().await, None);
+
+        let path = std::env::temp_dir().join(format!("liquers_watch_test_{}", std::process::id()));
+        std::fs::create_dir_all(path.join("a")).unwrap();
+        let file_store = FileStore::new(path.to_str().unwrap(), &Key::new());
+        let mut watcher = file_store.watch(&parse_key("a")?)?;
+        let key = parse_key("a/data.txt")?;
+        file_store.set(&key, b"data", &metadata)?;
+        file_store.set(&key, b"new data", &metadata)?;
+        file_store.remove(&key)?;
+        // Events until the removal: the first write creates the file, the second one modifies it
+        let events = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            let mut events = Vec::new();
+            while let Some(event) = watcher.next_event().await {
+                let removed = event == StoreEvent::Removed(key.clone());
+                events.push(event);
+                if removed {
+                    break;
+                }
+            }
+            events
+        })
+        .await
+        .unwrap_or_default();
+        std::fs::remove_dir_all(&path).unwrap();
+        assert_eq!(events.first(), Some(&StoreEvent::Created(key.clone())));
+        assert_eq!(events.last(), Some(&StoreEvent::Removed(key.clone())));
+        assert!(events[1..events.len() - 1]
+            .iter()
+            .all(|event| *event == StoreEvent::Modified(key.clone())));
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_mirror_store() -> Result<(), Error> {
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+        let old = MemoryStore::new(&Key::new());
+        old.set(&parse_key("a/old.txt")?, b"old", &metadata)?;
+        let store = MirrorStore::new(
+            AsyncStoreWrapper(MemoryStore::new(&Key::new())),
+            AsyncStoreWrapper(old),
+        )
+        .with_backfill(true);
+
+        store.set(&parse_key("a/new.txt")?, b"new", &metadata).await?;
+        assert_eq!(store.secondary().0.get_bytes(&parse_key("a/new.txt")?)?, b"new");
+        assert!(!store.primary().0.contains(&parse_key("a/old.txt")?)?);
+        assert_eq!(store.get_bytes(&parse_key("a/old.txt")?).await?, b"old");
+        // Back-filled to the primary store
+        assert_eq!(store.primary().0.get_bytes(&parse_key("a/old.txt")?)?, b"old");
+        assert!(store.contains(&parse_key("a/new.txt")?).await?);
+        let mut names = store.listdir(&parse_key("a")?).await?;
+        names.sort();
+        assert_eq!(names, vec!["new.txt".to_owned(), "old.txt".to_owned()]);
+        assert_eq!(store.keys().await?.len(), 2);
+        assert_eq!(
+            store.get_bytes(&parse_key("missing")?).await.unwrap_err().error_type,
+            ErrorType::KeyNotFound
+        );
+
+        // Failing secondary writes
+        let key = parse_key("b.txt")?;
+        let failing = MirrorStore::new(
+            AsyncStoreWrapper(MemoryStore::new(&Key::new())),
+            ReadOnlyStore::new(AsyncStoreWrapper(MemoryStore::new(&Key::new()))),
+        );
+        let err = failing.set(&key, b"b", &metadata).await.unwrap_err();
+        assert_eq!(err.error_type, ErrorType::ReadOnlyStore);
+        let recording = failing.with_secondary_write_policy(SecondaryWritePolicy::Record);
+        recording.set(&key, b"b", &metadata).await?;
+        assert_eq!(recording.get_bytes(&key).await?, b"b");
+        let errors = recording.take_secondary_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].error_type, ErrorType::ReadOnlyStore);
+        assert!(recording.take_secondary_errors().is_empty());
+        // Only the last errors are kept
+        for _ in 0..MAX_SECONDARY_ERRORS + 5 {
+            recording.set(&key, b"b", &metadata).await?;
+        }
+        assert_eq!(recording.secondary_error_count(), MAX_SECONDARY_ERRORS + 5);
+        assert_eq!(recording.take_secondary_errors().len(), MAX_SECONDARY_ERRORS);
+        assert_eq!(recording.secondary_error_count(), 0);
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_dedup_store() -> Result<(), Error> {
+        let store = DedupStore::new(AsyncStoreWrapper(MemoryStore::new(&Key::new())));
+        let blobs = parse_key(DEFAULT_DEDUP_BLOB_PREFIX)?;
+        let mut metadata_a = MetadataRecord::new();
+        metadata_a.with_media_type("text/plain".to_owned());
+        let metadata_a = Metadata::MetadataRecord(metadata_a);
+        let metadata_b = Metadata::MetadataRecord(MetadataRecord::new());
+        let (a, b) = (parse_key("a.txt")?, parse_key("dir/b.txt")?);
+
+        // Concurrent writes of the same content, including the same key twice
+        let (ra, rb, ra2) = tokio::join!(
+            store.set(&a, b"same content", &metadata_a),
+            store.set(&b, b"same content", &metadata_b),
+            store.set(&a, b"same content", &metadata_a),
+        );
+        ra?;
+        rb?;
+        ra2?;
+        assert_eq!(store.inner().0.listdir(&blobs)?.len(), 1);
+        assert_eq!(store.get_bytes(&b).await?, b"same content");
+        assert_eq!(store.get_metadata(&a).await?.get_media_type(), "text/plain");
+        let mut keys = store.keys().await?;
+        keys.sort_by_key(|k| k.encode());
+        assert_eq!(keys, vec![a.clone(), b.clone()]);
+        assert!(!store.listdir(&Key::new()).await?.contains(&DEFAULT_DEDUP_BLOB_PREFIX.to_owned()));
+
+        // The blob is kept while referenced
+        store.remove(&a).await?;
+        assert_eq!(store.get_bytes(&b).await?, b"same content");
+        store.set(&b, b"other content", &metadata_b).await?;
+        assert_eq!(store.inner().0.listdir(&blobs)?.len(), 1);
+        store.remove(&b).await?;
+        assert!(store.inner().0.listdir(&blobs)?.is_empty());
+
+        assert!(store.set(&blobs.join("x"), b"x", &metadata_b).await.is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_dedup_store_index_loaded() -> Result<(), Error> {
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+        let store = DedupStore::new(AsyncStoreWrapper(MemoryStore::new(&Key::new())));
+        store.set(&parse_key("a")?, b"data", &metadata).await?;
+        store.set(&parse_key("b")?, b"data", &metadata).await?;
+        // A new wrapper of the same data builds the index from the stored hashes
+        let store = DedupStore::new(store.inner);
+        store.remove(&parse_key("a")?).await?;
+        assert_eq!(store.get_bytes(&parse_key("b")?).await?, b"data");
+        store.remove(&parse_key("b")?).await?;
+        let blobs = parse_key(DEFAULT_DEDUP_BLOB_PREFIX)?;
+        assert!(store.inner().0.listdir(&blobs)?.is_empty());
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_dedup_store_failed_write() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("liquers_dedup_test_{}", std::process::id()));
+        std::fs::create_dir_all(path.join(DEFAULT_DEDUP_BLOB_PREFIX)).unwrap();
+        let metadata = Metadata::MetadataRecord(MetadataRecord::new());
+        let store = DedupStore::new(AsyncStoreWrapper(FileStore::new(
+            path.to_str().unwrap(),
+            &Key::new(),
+        )));
+        // The blob is written, but the key can't be (its directory does not exist)
+        let failed = store.set(&parse_key("missing/a.txt")?, b"data", &metadata).await;
+        let blobs = std::fs::read_dir(path.join(DEFAULT_DEDUP_BLOB_PREFIX)).unwrap().count();
+        std::fs::remove_dir_all(&path).unwrap();
+
+        assert!(failed.is_err());
+        assert_eq!(blobs, 0);
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_batching_store_reads_buffered_writes() -> Result<(), Error> {
+        let store = BatchingStore::new(AsyncStoreWrapper(MemoryStore::new(&Key::new())));
+        let key = parse_key("a/b.txt")?;
+        let mut metadata = MetadataRecord::new();
+        metadata.with_message("first".to_owned());
+        store.set(&key, b"hello", &metadata.clone().into()).await?;
+
+        assert_eq!(store.pending().await, 1);
+        assert!(!store.inner().contains(&key).await?);
+        assert!(store.contains(&key).await?);
+        assert_eq!(store.get_bytes(&key).await?, b"hello");
+
+        // Metadata update keeps the buffered data
+        metadata.with_message("second".to_owned());
+        store.set_metadata(&key, &metadata.into()).await?;
+        assert_eq!(store.pending().await, 1);
+        let (data, metadata) = store.get(&key).await?;
+        assert_eq!(data, b"hello");
+        assert_eq!(metadata.metadata_record().unwrap().message, "second");
+
+        // Removing a buffered write drops it
+        store.remove(&key).await?;
+        assert_eq!(store.pending().await, 0);
+        assert!(!store.contains(&key).await?);
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_batching_store_flush() -> Result<(), Error> {
+        let store = BatchingStore::new(AsyncStoreWrapper(MemoryStore::new(&Key::new())))
+            .with_max_bytes(10)
+            .with_max_delay(std::time::Duration::from_secs(3600));
+        let (a, b, c) = (parse_key("a.txt")?, parse_key("b.txt")?, parse_key("c.txt")?);
+        let metadata: Metadata = MetadataRecord::new().into();
+        store.set(&a, b"1234", &metadata).await?;
+        store.set(&b, b"5678", &metadata).await?;
+        assert_eq!(store.pending().await, 2);
+
+        // Explicit flush persists the writes to the inner store
+        store.flush().await?;
+        assert_eq!(store.pending().await, 0);
+        assert_eq!(store.inner().get_bytes(&a).await?, b"1234");
+        assert_eq!(store.inner().get_bytes(&b).await?, b"5678");
+
+        // Size threshold triggers the flush
+        store.set(&a, b"123456", &metadata).await?;
+        assert!(!store.inner().contains(&c).await?);
+        store.set(&c, b"789012", &metadata).await?;
+        assert_eq!(store.pending().await, 0);
+        assert_eq!(store.inner().get_bytes(&a).await?, b"123456");
+        assert_eq!(store.inner().get_bytes(&c).await?, b"789012");
+        Ok(())
+    }
+
+    #[cfg(feature = "async_store")]
+    #[tokio::test]
+    async fn test_batching_store_failed_flush() -> Result<(), Error> {
+        let store = BatchingStore::new(ReadOnlyStore::new(AsyncStoreWrapper(MemoryStore::new(&Key::new()))))
+            .with_max_bytes(10)
+            .with_max_delay(std::time::Duration::from_secs(3600));
+        let (a, b) = (parse_key("a.txt")?, parse_key("b.txt")?);
+        let metadata: Metadata = MetadataRecord::new().into();
+        store.set(&a, b"1234", &metadata).await?;
+
+        // The write triggering a failed flush is not accepted
+        assert!(store.set(&b, b"5678901", &metadata).await.is_err());
+        assert_eq!(store.pending().await, 1);
+        assert!(!store.contains(&b).await?);
+        // A rejected write keeps the buffered write it would replace
+        assert!(store.set(&a, b"12345678901", &metadata).await.is_err());
+        assert_eq!(store.pending().await, 1);
+        assert_eq!(store.get_bytes(&a).await?, b"1234");
+        assert!(store.flush().await.is_err());
+        assert_eq!(store.pending().await, 1);
         Ok(())
     }
 }
@@ -3,7 +3,7 @@
 
 use serde_json;
 
-use std::{borrow::Cow, collections::BTreeMap, result::Result};
+use std::{borrow::Cow, collections::BTreeMap, collections::HashMap, result::Result, sync::Arc};
 
 use crate::error::{Error, ErrorType};
 use std::convert::{TryFrom, TryInto};
@@ -37,6 +37,12 @@ pub trait ValueInterface: core::fmt::Debug + Clone + Sized + DefaultValueSeriali
     /// Test if value is empty
     fn is_none(&self) -> bool;
 
+    /// Number of items of a collection (e.g. elements of an array or rows of a table),
+    /// None if the value is not a collection
+    fn collection_len(&self) -> Option<usize> {
+        None
+    }
+
     /// From string
     fn new(txt: &str) -> Self;
 
@@ -190,6 +196,55 @@ pub trait ValueInterface: core::fmt::Debug + Clone + Sized + DefaultValueSeriali
             )),
         }
     }
+
+    /// Get the part of the value referenced by a JSON pointer (RFC 6901), e.g. `/items/0/name`.
+    /// The default implementation works on the JSON representation of the value (see [ValueInterface::try_into_json_value]).
+    fn get_pointer(&self, pointer: &str) -> Result<Self, Error> {
+        let json = self.try_into_json_value()?;
+        Self::try_from_json_value(resolve_json_pointer(&json, pointer)?)
+    }
+}
+
+/// Resolve a JSON pointer (RFC 6901) in a JSON value.
+/// A missing object member or an array index out of range is a [ErrorType::KeyNotFound] error
+/// mentioning the pointer.
+pub fn resolve_json_pointer<'a>(
+    value: &'a serde_json::Value,
+    pointer: &str,
+) -> Result<&'a serde_json::Value, Error> {
+    if pointer.is_empty() {
+        return Ok(value);
+    }
+    let Some(tokens) = pointer.strip_prefix('/') else {
+        return Err(Error::general_error(format!(
+            "Invalid JSON pointer '{}', it must be empty or start with '/'",
+            pointer
+        )));
+    };
+    let not_found = |what: String| {
+        Error::new(
+            ErrorType::KeyNotFound,
+            format!("JSON pointer '{}': {}", pointer, what),
+        )
+    };
+    tokens.split('/').try_fold(value, |value, token| {
+        let token = token.replace("~1", "/").replace("~0", "~");
+        match value {
+            serde_json::Value::Object(o) => o
+                .get(&token)
+                .ok_or_else(|| not_found(format!("member '{}' not found", token))),
+            serde_json::Value::Array(a) => {
+                let index = (token == "0" || !token.starts_with('0'))
+                    .then(|| token.parse::<usize>().ok())
+                    .flatten()
+                    .ok_or_else(|| not_found(format!("'{}' is not an array index", token)))?;
+                a.get(index).ok_or_else(|| {
+                    not_found(format!("index {} out of range (length {})", index, a.len()))
+                })
+            }
+            _ => Err(not_found(format!("'{}' can't be looked up in a scalar value", token))),
+        }
+    })
 }
 
 impl ValueInterface for Value {
@@ -204,6 +259,14 @@ impl ValueInterface for Value {
         }
     }
 
+    fn collection_len(&self) -> Option<usize> {
+        match self {
+            Value::Array(a) => Some(a.len()),
+            Value::Object(o) => Some(o.len()),
+            _ => None,
+        }
+    }
+
     fn new(txt: &str) -> Self {
         Value::Text(txt.to_owned())
     }
@@ -519,6 +582,101 @@ impl From<&str> for Value {
     }
 }
 
+impl TryFrom<Value> for std::time::Duration {
+    type Error = Error;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let duration = match &value {
+            Value::I32(x) => u64::try_from(*x).ok().map(std::time::Duration::from_secs),
+            Value::I64(x) => u64::try_from(*x).ok().map(std::time::Duration::from_secs),
+            Value::F64(x) => std::time::Duration::try_from_secs_f64(*x).ok(),
+            Value::Text(x) => crate::command_metadata::parse_duration(x),
+            _ => None,
+        };
+        duration.ok_or_else(|| Error::conversion_error(value.type_name(), "duration"))
+    }
+}
+
+impl TryFrom<Value> for chrono::DateTime<chrono::Utc> {
+    type Error = Error;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        let datetime = match &value {
+            Value::Text(x) => crate::command_metadata::parse_datetime(x),
+            _ => None,
+        };
+        datetime.ok_or_else(|| Error::conversion_error(value.type_name(), "datetime"))
+    }
+}
+
+impl TryFrom<Value> for Vec<Value> {
+    type Error = Error;
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Array(x) => Ok(x),
+            _ => Ok(vec![value]),
+        }
+    }
+}
+
+/// Macro to implement the conversion of a value to an optional value; [Value::None] is None
+macro_rules! impl_try_from_value_option {
+    ($t:ty) => {
+        impl TryFrom<Value> for Option<$t> {
+            type Error = Error;
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                match value {
+                    Value::None => Ok(None),
+                    _ => <$t>::try_from(value).map(Some),
+                }
+            }
+        }
+    };
+}
+
+/// Macro to implement the conversion of an array value to a vector; a non-array value is a single element
+macro_rules! impl_try_from_value_vec {
+    ($t:ty) => {
+        impl TryFrom<Value> for Vec<$t> {
+            type Error = Error;
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                match value {
+                    Value::Array(x) => x.into_iter().map(<$t>::try_from).collect(),
+                    _ => Ok(vec![<$t>::try_from(value)?]),
+                }
+            }
+        }
+    };
+}
+
+/// Macro to implement the conversion of an array value with a matching length to a tuple
+macro_rules! impl_try_from_value_tuple {
+    ($n:expr; $($t:ident),+) => {
+        impl<$($t: TryFrom<Value, Error = Error>),+> TryFrom<Value> for ($($t,)+) {
+            type Error = Error;
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                match value {
+                    Value::Array(x) if x.len() == $n => {
+                        let mut x = x.into_iter();
+                        Ok(($($t::try_from(x.next().unwrap())?,)+))
+                    }
+                    _ => Err(Error::conversion_error(
+                        value.type_name(),
+                        concat!("tuple of ", stringify!($n)),
+                    )),
+                }
+            }
+        }
+    };
+}
+
+impl_try_from_value_option!(i64);
+impl_try_from_value_option!(f64);
+impl_try_from_value_vec!(String);
+impl_try_from_value_vec!(i64);
+impl_try_from_value_vec!(f64);
+impl_try_from_value_vec!(bool);
+impl_try_from_value_tuple!(2; A, B);
+impl_try_from_value_tuple!(3; A, B, C);
+
 // TODO: Turn this into a separate object to make it configurable
 pub trait DefaultValueSerializer
 where
@@ -527,6 +685,23 @@ where
     fn as_bytes(&self, format: &str) -> Result<Vec<u8>, Error>;
     fn deserialize_from_bytes(b: &[u8], type_identifier: &str, format: &str)
         -> Result<Self, Error>;
+
+    /// Deserialize only the given columns of a table.
+    /// Formats supporting projection may read only the requested columns;
+    /// by default the whole value is deserialized and then projected by [DefaultValueSerializer::project].
+    fn deserialize_projected(
+        b: &[u8],
+        type_identifier: &str,
+        format: &str,
+        columns: &[String],
+    ) -> Result<Self, Error> {
+        Self::deserialize_from_bytes(b, type_identifier, format)?.project(columns)
+    }
+
+    /// Keep only the given columns of a table; values that are not tables are returned unchanged
+    fn project(self, _columns: &[String]) -> Result<Self, Error> {
+        Ok(self)
+    }
 }
 
 impl DefaultValueSerializer for Value {
@@ -572,6 +747,116 @@ impl DefaultValueSerializer for Value {
             )),
         }
     }
+    /// Tables are arrays of records (projected record by record) or objects of columns
+    /// (see the `to_columns` command). A requested column missing in the whole table is an error.
+    fn project(self, columns: &[String]) -> Result<Self, Error> {
+        let missing = |present: &dyn Fn(&String) -> bool| {
+            columns.iter().find(|c| !present(c)).map(|c| {
+                Error::general_error(format!("Column '{}' not found in the projected table", c))
+            })
+        };
+        match self {
+            Value::Array(records) if records.iter().all(|r| matches!(r, Value::Object(_))) => {
+                let present = |c: &String| {
+                    records
+                        .iter()
+                        .any(|r| matches!(r, Value::Object(o) if o.contains_key(c)))
+                };
+                if let Some(e) = missing(&present) {
+                    return Err(e);
+                }
+                Ok(Value::Array(
+                    records
+                        .into_iter()
+                        .map(|r| match r {
+                            Value::Object(mut o) => {
+                                o.retain(|k, _| columns.contains(k));
+                                Value::Object(o)
+                            }
+                            r => r,
+                        })
+                        .collect(),
+                ))
+            }
+            Value::Object(mut o) => {
+                if let Some(e) = missing(&|c| o.contains_key(c)) {
+                    return Err(e);
+                }
+                o.retain(|k, _| columns.contains(k));
+                Ok(Value::Object(o))
+            }
+            value => Ok(value),
+        }
+    }
+}
+
+/// Deserializer of a registered value type: creates a value from bytes in a format (file extension)
+pub type ValueDeserializer<V> = Arc<dyn Fn(&[u8], &str) -> Result<V, Error> + Send + Sync>;
+
+/// Deserializers of value types registered at runtime, by type identifier.
+/// Allows downstream crates (e.g. plugins) to deserialize value types
+/// unknown to [DefaultValueSerializer::deserialize_from_bytes] without changing the value type.
+/// A registered deserializer takes precedence over the built-in deserialization.
+pub struct ValueDeserializerRegistry<V> {
+    deserializers: HashMap<String, ValueDeserializer<V>>,
+}
+
+impl<V: DefaultValueSerializer> ValueDeserializerRegistry<V> {
+    pub fn new() -> Self {
+        ValueDeserializerRegistry {
+            deserializers: HashMap::new(),
+        }
+    }
+
+    /// Register a deserializer for a type identifier, replacing a previously registered one
+    pub fn register<F>(&mut self, type_identifier: &str, deserializer: F) -> &mut Self
+    where
+        F: Fn(&[u8], &str) -> Result<V, Error> + Send + Sync + 'static,
+    {
+        self.deserializers
+            .insert(type_identifier.to_owned(), Arc::new(deserializer));
+        self
+    }
+
+    /// Deserializer registered for a type identifier
+    pub fn get(&self, type_identifier: &str) -> Option<ValueDeserializer<V>> {
+        self.deserializers.get(type_identifier).cloned()
+    }
+
+    /// Deserialize bytes with the deserializer registered for the type identifier,
+    /// or with [DefaultValueSerializer::deserialize_from_bytes] if there is none
+    pub fn deserialize_from_bytes(
+        &self,
+        b: &[u8],
+        type_identifier: &str,
+        format: &str,
+    ) -> Result<V, Error> {
+        match self.deserializers.get(type_identifier) {
+            Some(deserializer) => deserializer(b, format),
+            None => V::deserialize_from_bytes(b, type_identifier, format),
+        }
+    }
+
+    /// Deserialize only the given columns of a table (see [DefaultValueSerializer::deserialize_projected]).
+    /// Registered deserializers don't support projection, their result is projected afterwards.
+    pub fn deserialize_projected(
+        &self,
+        b: &[u8],
+        type_identifier: &str,
+        format: &str,
+        columns: &[String],
+    ) -> Result<V, Error> {
+        match self.deserializers.get(type_identifier) {
+            Some(deserializer) => deserializer(b, format)?.project(columns),
+            None => V::deserialize_projected(b, type_identifier, format, columns),
+        }
+    }
+}
+
+impl<V: DefaultValueSerializer> Default for ValueDeserializerRegistry<V> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[cfg(test)]
@@ -589,6 +874,42 @@ mod tests {
         Ok(())
     }
     #[test]
+    fn test_get_pointer() -> Result<(), Box<dyn std::error::Error>> {
+        let v = Value::try_from_json_value(&serde_json::json!(
+            {"items": [{"name": "a"}, {"name": "b"}], "a/b": 1, "m~n": 2, "": 3}
+        ))?;
+        assert_eq!(v.get_pointer("/items/1/name")?, Value::Text("b".to_owned()));
+        assert_eq!(
+            v.get_pointer("/items/0")?,
+            Value::try_from_json_value(&serde_json::json!({"name": "a"}))?
+        );
+        assert_eq!(v.get_pointer("/a~1b")?, Value::I64(1));
+        assert_eq!(v.get_pointer("/m~0n")?, Value::I64(2));
+        assert_eq!(v.get_pointer("/")?, Value::I64(3));
+        assert_eq!(v.get_pointer("")?, v);
+        for pointer in ["/items/2", "/items/01", "/items/-", "/missing", "/items/0/name/x"] {
+            let e = v.get_pointer(pointer).unwrap_err();
+            assert_eq!(e.error_type, ErrorType::KeyNotFound);
+            assert!(e.message.contains(pointer));
+        }
+        assert!(v.get_pointer("items").is_err());
+        Ok(())
+    }
+    #[test]
+    fn test_deserialize_projected() -> Result<(), Box<dyn std::error::Error>> {
+        let columns = vec!["a".to_owned(), "c".to_owned()];
+        let b = br#"[{"a":1,"b":2,"c":3},{"a":4,"b":5}]"#;
+        let v = Value::deserialize_projected(b, "generic", "json", &columns)?;
+        assert_eq!(v, serde_json::from_str::<Value>(r#"[{"a":1,"c":3},{"a":4}]"#)?);
+        let b = br#"{"a":[1,4],"b":[2,5],"c":[3,null]}"#;
+        let v = Value::deserialize_projected(b, "generic", "json", &columns)?;
+        assert_eq!(v, serde_json::from_str::<Value>(r#"{"a":[1,4],"c":[3,null]}"#)?);
+        let missing = vec!["z".to_owned()];
+        assert!(Value::deserialize_projected(b, "generic", "json", &missing).is_err());
+        assert_eq!(Value::deserialize_projected(b"1", "generic", "json", &missing)?, Value::I32(1));
+        Ok(())
+    }
+    #[test]
     fn test_convert_int() -> Result<(), Box<dyn std::error::Error>> {
         let v = Value::I32(123);
         let x: i32 = v.try_into()?;
@@ -596,6 +917,17 @@ mod tests {
         Ok(())
     }
     #[test]
+    fn test_convert_option() -> Result<(), Box<dyn std::error::Error>> {
+        let x: Option<f64> = Value::I32(2).try_into()?;
+        assert_eq!(x, Some(2.0));
+        let x: Option<f64> = Value::None.try_into()?;
+        assert_eq!(x, None);
+        let x: Option<i64> = Value::I64(3).try_into()?;
+        assert_eq!(x, Some(3));
+        assert!(Option::<i64>::try_from(Value::from("abc")).is_err());
+        Ok(())
+    }
+    #[test]
     fn test_convert_text() -> Result<(), Box<dyn std::error::Error>> {
         let v = Value::from("abc");
         assert_eq!(v, Value::Text("abc".to_owned()));
@@ -24,6 +24,13 @@ pub enum ErrorType {
     KeyWriteError,
     UnexpectedError,
     ExecutionError,
+    QueryTooLong,
+    QueryTooComplex,
+    StoreNotConfigured,
+    PermissionDenied,
+    NotADirectory,
+    RangeNotSatisfiable,
+    ReadOnlyStore,
 }
 
 impl From<ErrorType> for liquers_core::error::ErrorType {
@@ -50,6 +57,13 @@ impl From<ErrorType> for liquers_core::error::ErrorType {
             ErrorType::KeyWriteError => liquers_core::error::ErrorType::KeyWriteError,
             ErrorType::UnexpectedError => liquers_core::error::ErrorType::UnexpectedError,
             ErrorType::ExecutionError => liquers_core::error::ErrorType::ExecutionError,
+            ErrorType::QueryTooLong => liquers_core::error::ErrorType::QueryTooLong,
+            ErrorType::QueryTooComplex => liquers_core::error::ErrorType::QueryTooComplex,
+            ErrorType::StoreNotConfigured => liquers_core::error::ErrorType::StoreNotConfigured,
+            ErrorType::PermissionDenied => liquers_core::error::ErrorType::PermissionDenied,
+            ErrorType::NotADirectory => liquers_core::error::ErrorType::NotADirectory,
+            ErrorType::RangeNotSatisfiable => liquers_core::error::ErrorType::RangeNotSatisfiable,
+            ErrorType::ReadOnlyStore => liquers_core::error::ErrorType::ReadOnlyStore,
         }
     }
 }
@@ -78,6 +92,13 @@ impl From<liquers_core::error::ErrorType> for ErrorType {
             liquers_core::error::ErrorType::KeyWriteError => ErrorType::KeyWriteError,
             liquers_core::error::ErrorType::UnexpectedError => ErrorType::UnexpectedError,
             liquers_core::error::ErrorType::ExecutionError => ErrorType::ExecutionError,
+            liquers_core::error::ErrorType::QueryTooLong => ErrorType::QueryTooLong,
+            liquers_core::error::ErrorType::QueryTooComplex => ErrorType::QueryTooComplex,
+            liquers_core::error::ErrorType::StoreNotConfigured => ErrorType::StoreNotConfigured,
+            liquers_core::error::ErrorType::PermissionDenied => ErrorType::PermissionDenied,
+            liquers_core::error::ErrorType::NotADirectory => ErrorType::NotADirectory,
+            liquers_core::error::ErrorType::RangeNotSatisfiable => ErrorType::RangeNotSatisfiable,
+            liquers_core::error::ErrorType::ReadOnlyStore => ErrorType::ReadOnlyStore,
         }
     }
 }
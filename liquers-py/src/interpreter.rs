@@ -1,8 +1,18 @@
-use std::{env, sync::Arc};
+use std::{env, panic::{self, AssertUnwindSafe}, sync::{Arc, Condvar, Mutex}};
 
 use pyo3::{exceptions::PyException, prelude::*};
 use crate::{commands::CommandRegistry, context::{EnvRefDef, Environment}, error::Error, state::State};
-use liquers_core::interpreter::PlanInterpreter;
+use liquers_core::{interpreter::PlanInterpreter, metadata::Status, parse::{parse_query_with_limits, QueryLimits}};
+
+type EvaluationResult =
+    Result<liquers_core::state::State<crate::value::Value>, liquers_core::error::Error>;
+/// Result of a background evaluation, set once and signalled to the waiting threads
+type EvaluationSlot = (Mutex<Option<EvaluationResult>>, Condvar);
+
+/// Parse a query passed from Python, checking the default [QueryLimits]
+fn parse_query(query: &str) -> PyResult<liquers_core::query::Query> {
+    Ok(parse_query_with_limits(query, &QueryLimits::default()).map_err(Error)?)
+}
 
 #[pyfunction]
 pub fn evaluate(query:String) -> PyResult<State> {
@@ -14,7 +24,7 @@ pub fn evaluate(query:String) -> PyResult<State> {
     let envref = liquers_core::context::ArcEnvRef(Arc::new(env));
 
     let mut pi = PlanInterpreter::new(envref);
-    let state = pi.evaluate(query).map_err(|e| Error(e))?;
+    let state = pi.evaluate(parse_query(&query)?).map_err(|e| Error(e))?;
     Ok(State(state))
 }
 
@@ -29,6 +39,139 @@ pub fn evaluate_with_cmr(query:String, cmr:&crate::command_metadata::CommandMeta
     let envref = liquers_core::context::ArcEnvRef(Arc::new(env));
 
     let mut pi = PlanInterpreter::new(envref);
-    let state = pi.evaluate(query).map_err(|e| Error(e))?;
+    let state = pi.evaluate(parse_query(&query)?).map_err(|e| Error(e))?;
     Ok(State(state))
 }
+
+/// Evaluate a query in a background thread, so that Python is not blocked while the query is evaluated.
+/// The returned [Evaluation] is awaitable (`state = await evaluate_async(query)` in a notebook);
+/// it can as well be polled for the status and the state, or waited for without an event loop.
+#[pyfunction]
+#[pyo3(signature = (query, cmr=None))]
+pub fn evaluate_async(py: Python, query:String, cmr:Option<&crate::command_metadata::CommandMetadataRegistry>) -> PyResult<Evaluation> {
+    let mut env = Environment::new();
+    let cr = CommandRegistry::new()?;
+    env.command_registry = cr.0;
+    if let Some(cmr) = cmr {
+        env.set_cmr(cmr);
+    }
+    let envref = liquers_core::context::ArcEnvRef(Arc::new(env));
+    let parsed = parse_query(&query)?;
+
+    let result: Arc<EvaluationSlot> = Arc::new((Mutex::new(None), Condvar::new()));
+    let future: PyObject = PyModule::import(py, "concurrent.futures")?
+        .getattr("Future")?
+        .call0()?
+        .into();
+    {
+        let result = result.clone();
+        let future = future.clone_ref(py);
+        let query = query.clone();
+        std::thread::spawn(move || {
+            let state = panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut pi = PlanInterpreter::new(envref);
+                pi.evaluate(parsed)
+            }))
+            .unwrap_or_else(|payload| {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                Err(liquers_core::error::Error::unexpected_error(format!(
+                    "Evaluation of '{query}' panicked: {message}"
+                )))
+            });
+            {
+                let (lock, finished) = &*result;
+                *lock.lock().unwrap() = Some(state.clone());
+                finished.notify_all();
+            }
+            Python::with_gil(|py| {
+                // Fails only if the future was cancelled, then nobody waits for the result
+                let _ = match state {
+                    Ok(state) => future.call_method1(py, "set_result", (State(state),)),
+                    Err(e) => {
+                        let error: PyErr = Error(e).into();
+                        future.call_method1(py, "set_exception", (error.into_value(py),))
+                    }
+                };
+            });
+        });
+    }
+    Ok(Evaluation { query, result, future })
+}
+
+/// Query evaluation running in a background thread, see [evaluate_async]
+#[pyclass]
+pub struct Evaluation {
+    query: String,
+    result: Arc<EvaluationSlot>,
+    /// `concurrent.futures.Future` receiving the state (or the error) when the evaluation is finished
+    future: PyObject,
+}
+
+impl Evaluation {
+    fn to_state(&self, result: &Option<EvaluationResult>) -> PyResult<Option<State>> {
+        match result {
+            None => Ok(None),
+            Some(Ok(state)) => Ok(Some(State(state.clone()))),
+            Some(Err(e)) => Err(Error(e.clone()).into()),
+        }
+    }
+}
+
+#[pymethods]
+impl Evaluation {
+    #[getter]
+    pub fn query(&self) -> String {
+        self.query.clone()
+    }
+
+    /// Status of the evaluation: `Evaluation` while it is running, then `Ready` or `Error`
+    pub fn status(&self) -> String {
+        let status = match &*self.result.0.lock().unwrap() {
+            None => Status::Evaluation,
+            Some(Ok(state)) if !state.is_error().unwrap_or(false) => Status::Ready,
+            Some(_) => Status::Error,
+        };
+        format!("{:?}", status)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.result.0.lock().unwrap().is_some()
+    }
+
+    /// The resulting state when the evaluation is finished, None while it is running.
+    /// The error is raised if the evaluation failed.
+    pub fn poll_state(&self) -> PyResult<Option<State>> {
+        self.to_state(&self.result.0.lock().unwrap())
+    }
+
+    /// Wait (without holding the GIL) until the evaluation is finished and return the resulting state.
+    /// Several threads may wait for the same evaluation.
+    pub fn wait(&self, py: Python) -> PyResult<State> {
+        let result = py.allow_threads(|| {
+            let (lock, finished) = &*self.result;
+            finished
+                .wait_while(lock.lock().unwrap(), |result| result.is_none())
+                .unwrap()
+                .clone()
+        });
+        self.to_state(&result)?.ok_or_else(|| {
+            PyException::new_err(format!("Evaluation of '{}' did not finish", self.query))
+        })
+    }
+
+    /// Await the resulting state in an asyncio event loop
+    pub fn __await__(&self, py: Python) -> PyResult<PyObject> {
+        let future = PyModule::import(py, "asyncio")?
+            .getattr("wrap_future")?
+            .call1((self.future.clone_ref(py),))?;
+        Ok(future.call_method0("__await__")?.into())
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!("Evaluation(query={:?}, status={})", self.query, self.status())
+    }
+}
@@ -64,6 +64,8 @@ fn liquers_py(py: Python, m: &PyModule) -> PyResult<()> {
 
     m.add_function(wrap_pyfunction!(crate::interpreter::evaluate, m)?)?;
     m.add_function(wrap_pyfunction!(crate::interpreter::evaluate_with_cmr, m)?)?;
+    m.add_function(wrap_pyfunction!(crate::interpreter::evaluate_async, m)?)?;
+    m.add_class::<crate::interpreter::Evaluation>()?;
 
     Ok(())
 }
@@ -606,7 +606,8 @@ impl Query {
 
 #[pyfunction]
 pub fn parse(query: &str) -> PyResult<Query> {
-    match liquers_core::parse::parse_query(query) {
+    let limits = liquers_core::parse::QueryLimits::default();
+    match liquers_core::parse::parse_query_with_limits(query, &limits) {
         Ok(q) => Ok(Query(q)),
         Err(e) => Err(PyErr::new::<pyo3::exceptions::PyException, _>(
             e.to_string(),
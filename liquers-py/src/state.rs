@@ -25,7 +25,7 @@ impl State {
             Err(PyException::new_err("ERROR".to_string()))
         }
         else{
-            Ok((*self.0.data).clone())
+            Ok(self.0.data.read().unwrap().clone())
         }
     }
 
@@ -35,17 +35,17 @@ impl State {
         }
         else{
             Python::with_gil(|py|{
-                (*self.0.data).as_pyobject(py)
+                self.0.data.read().unwrap().as_pyobject(py)
             })
         }
     }
 
     pub fn __str__(&self) -> PyResult<String> {
-        self.0.data.__str__()
+        self.0.data.read().unwrap().__str__()
     }
 
     pub fn __repr__(&self) -> PyResult<String> {        
-        Ok(format!("State(data={}, metadata={:?})", self.0.data.__repr__()?, *self.0.metadata))
+        Ok(format!("State(data={}, metadata={:?})", self.0.data.read().unwrap().__repr__()?, *self.0.metadata))
     }
 
 }
@@ -0,0 +1,64 @@
+#!/usr/bin/python
+# -*- coding: utf-8 -*-
+"""
+Unit tests for the background evaluation of queries.
+"""
+import asyncio
+import sys
+import threading
+import types
+
+import pytest
+from liquers_py import *
+
+
+class TestEvaluation:
+    def test_wait_for_blocked_evaluation(self):
+        released = threading.Event()
+        helper = types.ModuleType("liquers_evaluation_helper")
+        helper.blocked = lambda: released.wait(10) and "done"
+        sys.modules[helper.__name__] = helper
+
+        evaluation = evaluate_async("pycall-liquers_evaluation_helper-blocked-no")
+        assert evaluation.query == "pycall-liquers_evaluation_helper-blocked-no"
+        assert evaluation.status() == "Evaluation"
+        assert not evaluation.is_finished()
+        assert evaluation.poll_state() is None
+
+        released.set()
+        state = evaluation.wait()
+        assert state.get() == "done"
+        assert evaluation.status() == "Ready"
+        assert evaluation.poll_state().get() == "done"
+        assert evaluation.wait().get() == "done"
+
+    def test_wait_for_failed_evaluation(self):
+        evaluation = evaluate_async("undefined_command")
+        with pytest.raises(Exception):
+            evaluation.wait()
+        assert evaluation.status() == "Error"
+        assert evaluation.is_finished()
+        with pytest.raises(Exception):
+            evaluation.poll_state()
+
+    def test_await_evaluation(self):
+        helper = types.ModuleType("liquers_await_helper")
+        helper.answer = lambda: "awaited"
+        sys.modules[helper.__name__] = helper
+
+        async def main():
+            state = await evaluate_async("pycall-liquers_await_helper-answer-no")
+            return state.get()
+
+        assert asyncio.run(main()) == "awaited"
+
+    def test_await_failed_evaluation(self):
+        async def main():
+            await evaluate_async("undefined_command")
+
+        with pytest.raises(Exception):
+            asyncio.run(main())
+
+    def test_evaluate_too_long_query(self):
+        with pytest.raises(Exception):
+            evaluate_async("a-" + "x" * 20000)
@@ -285,3 +285,7 @@ class TestQueryElements:
         action = parse("name-1").action()
         assert action.name == "name"
         assert action.encode() == "name-1"
+
+    def test_parse_too_long_query(self):
+        with pytest.raises(Exception):
+            parse("a-" + "x" * 20000)
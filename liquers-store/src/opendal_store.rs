@@ -7,6 +7,19 @@ use bytes::Buf;
 
 use async_trait::async_trait;
 
+/// Convert an OpenDAL error to a store error classified by the error kind:
+/// missing object (when reading), permission denied and not a directory have their own error types,
+/// anything else is a read or write error.
+fn map_opendal_error(key: &Key, store_name: &str, e: opendal::Error, write: bool) -> Error {
+    match e.kind() {
+        opendal::ErrorKind::NotFound if !write => Error::key_not_found(key),
+        opendal::ErrorKind::PermissionDenied => Error::key_permission_denied(key, store_name, &e),
+        opendal::ErrorKind::NotADirectory => Error::key_not_a_directory(key, store_name),
+        _ if write => Error::key_write_error(key, store_name, &format!("{e} (OpenDAL Write Error)")),
+        _ => Error::key_read_error(key, store_name, &format!("{e} (OpenDAL Read Error)")),
+    }
+}
+
 pub struct OpenDALStore {
     op: BlockingOperator,
     prefix: Key,
@@ -27,10 +40,10 @@ impl OpenDALStore {
         format!("{}{}", key.encode(), Self::METADATA)
     }
     fn map_read_error<T>(&self, key:&Key, res:opendal::Result<T>)->Result<T, liquers_core::error::Error> {
-        res.map_err(|e| liquers_core::error::Error::key_read_error(key, &self.store_name(), &format!("{e} (OpenDAL Read Error)")))
+        res.map_err(|e| map_opendal_error(key, &self.store_name(), e, false))
     }
     fn map_write_error<T>(&self, key:&Key, res:opendal::Result<T>)->Result<T, liquers_core::error::Error> {
-        res.map_err(|e| liquers_core::error::Error::key_write_error(key, &self.store_name(), &format!("{e} (OpenDAL Write Error)")))
+        res.map_err(|e| map_opendal_error(key, &self.store_name(), e, true))
     }
 
 }
@@ -104,7 +117,9 @@ impl Store for OpenDALStore {
         let path = self.key_to_path(key);
         let buffer = Buffer::from_iter(data.iter().copied());
         self.map_write_error(key, self.op.write(&path, buffer))?;
-        self.set_metadata(key, metadata)?;
+        let mut metadata = metadata.to_owned();
+        metadata.with_updated_now_if_unknown();
+        self.set_metadata(key, &metadata)?;
         Ok(())
     }
     
@@ -232,10 +247,10 @@ impl AsyncOpenDALStore {
         format!("{}{}", key.encode(), Self::METADATA)
     }
     fn map_read_error<T>(&self, key:&Key, res:opendal::Result<T>)->Result<T, liquers_core::error::Error> {
-        res.map_err(|e| liquers_core::error::Error::key_read_error(key, &self.store_name(), &format!("{e} (OpenDAL Read Error)")))
+        res.map_err(|e| map_opendal_error(key, &self.store_name(), e, false))
     }
     fn map_write_error<T>(&self, key:&Key, res:opendal::Result<T>)->Result<T, liquers_core::error::Error> {
-        res.map_err(|e| liquers_core::error::Error::key_write_error(key, &self.store_name(), &format!("{e} (OpenDAL Write Error)")))
+        res.map_err(|e| map_opendal_error(key, &self.store_name(), e, true))
     }
 }
 
@@ -320,7 +335,9 @@ impl AsyncStore for AsyncOpenDALStore{
         let path = self.key_to_path(key);
         let buffer = Buffer::from_iter(data.iter().copied());
         self.map_write_error(key, self.op.write(&path, buffer).await)?;
-        self.set_metadata(key, metadata).await?;
+        let mut metadata = metadata.to_owned();
+        metadata.with_updated_now_if_unknown();
+        self.set_metadata(key, &metadata).await?;
         Ok(())
     }
 
@@ -449,4 +466,33 @@ impl AsyncStore for AsyncOpenDALStore{
                 .is_some_and(|file_name| file_name.name.ends_with(Self::METADATA)))
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use liquers_core::error::ErrorType;
+    use liquers_core::parse::parse_key;
+
+    #[test]
+    fn test_error_types() {
+        let op = Operator::new(opendal::services::Memory::default())
+            .unwrap()
+            .finish()
+            .blocking();
+        let store = OpenDALStore::new(op, Key::new());
+        let key = parse_key("a/b.txt").unwrap();
+        let err = store.get_bytes(&key).unwrap_err();
+        assert_eq!(err.error_type, ErrorType::KeyNotFound);
+
+        let denied = opendal::Error::new(opendal::ErrorKind::PermissionDenied, "denied");
+        let err = map_opendal_error(&key, "test", denied, false);
+        assert_eq!(err.error_type, ErrorType::PermissionDenied);
+        let not_dir = opendal::Error::new(opendal::ErrorKind::NotADirectory, "file");
+        let err = map_opendal_error(&key, "test", not_dir, false);
+        assert_eq!(err.error_type, ErrorType::NotADirectory);
+        let other = opendal::Error::new(opendal::ErrorKind::Unexpected, "failed");
+        let err = map_opendal_error(&key, "test", other, true);
+        assert_eq!(err.error_type, ErrorType::KeyWriteError);
+    }
+}